@@ -195,17 +195,26 @@ main() {
       \"mcp__rust-docs__cache_crate_from_cratesio\",
       \"mcp__rust-docs__cache_crate_from_github\",
       \"mcp__rust-docs__cache_crate_from_local\",
+      \"mcp__rust-docs__cache_crates_from_vendor\",
+      \"mcp__rust-docs__get_prefetch_status\",
       \"mcp__rust-docs__remove_crate\",
       \"mcp__rust-docs__list_cached_crates\",
       \"mcp__rust-docs__list_crate_versions\",
       \"mcp__rust-docs__get_crates_metadata\",
+      \"mcp__rust-docs__gc_cache\",
+      \"mcp__rust-docs__pin_crate\",
+      \"mcp__rust-docs__register_project\",
+      \"mcp__rust-docs__verify_cache\",
       \"mcp__rust-docs__list_crate_items\",
       \"mcp__rust-docs__search_items\",
       \"mcp__rust-docs__search_items_preview\",
+      \"mcp__rust-docs__search_all_crates\",
       \"mcp__rust-docs__get_item_details\",
       \"mcp__rust-docs__get_item_docs\",
       \"mcp__rust-docs__get_item_source\",
       \"mcp__rust-docs__get_dependencies\",
+      \"mcp__rust-docs__get_reverse_dependencies\",
+      \"mcp__rust-docs__get_dependency_report\",
       \"mcp__rust-docs__structure\"${NC}"
             else
                 warn "Failed to add rust-docs-mcp to Claude Code"
@@ -1,6 +1,5 @@
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 use rmcp::schemars;
 use serde::{Deserialize, Serialize};
@@ -27,7 +26,7 @@ pub struct AnalyzeCrateStructureParams {
     pub version: String,
 
     #[schemars(
-        description = "For workspace crates, specify the member path (e.g., 'crates/rmcp')"
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp')"
     )]
     pub member: Option<String>,
 
@@ -80,16 +79,16 @@ pub struct AnalyzeCrateStructureParams {
 
 #[derive(Debug, Clone)]
 pub struct AnalysisTools {
-    cache: Arc<Mutex<CrateCache>>,
+    cache: Arc<CrateCache>,
 }
 
 impl AnalysisTools {
-    pub fn new(cache: Arc<Mutex<CrateCache>>) -> Self {
+    pub fn new(cache: Arc<CrateCache>) -> Self {
         Self { cache }
     }
 
     pub async fn structure(&self, params: AnalyzeCrateStructureParams) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
 
         // Ensure the crate source is available (without requiring docs)
         match cache
@@ -106,13 +105,19 @@ impl AnalysisTools {
                 // (either the crate root or the member directory)
                 let manifest_path = source_path.join("Cargo.toml");
 
-                // Extract the package name for workspace members
-                let package = params
-                    .member
-                    .as_ref()
-                    .map(|member| member.split('/').next_back().unwrap_or(member).to_string());
-
-                drop(cache); // Release the lock before the blocking operation
+                // Workspace members are analyzed by their package name
+                let package = match params.member.as_deref() {
+                    Some(member) => match cache
+                        .resolve_member(&params.crate_name, &params.version, member)
+                        .await
+                    {
+                        Ok(member) => Some(member.name),
+                        Err(e) => {
+                            return format!(r#"{{"error": "Failed to resolve member: {e}"}}"#);
+                        }
+                    },
+                    None => None,
+                };
 
                 // Run the analysis
                 analyze_with_cargo_modules(manifest_path, package, params).await
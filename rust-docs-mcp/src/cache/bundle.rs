@@ -0,0 +1,466 @@
+//! Portable bundles of cached crates
+//!
+//! A bundle is a gzip-compressed tar archive that carries complete cache
+//! entries from one machine to another, e.g. onto build machines without
+//! network access. It starts with `manifest.json`, which records the bundle
+//! format version and the crate versions inside, followed by each entry's
+//! directory under `crates/{name}/{version}/`: source, docs, docs index,
+//! dependency information, metadata and workspace members. Build artifacts are
+//! left out.
+//!
+//! `dependencies.json` holds absolute paths into the exporting cache, so they
+//! are rewritten to the importing cache's source directory on import, see
+//! [`CacheStorage::rewrite_dependency_paths`].
+
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::cache::storage::CacheStorage;
+
+/// Version of the bundle layout, bumped on incompatible changes
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Identifies bundles among other archives
+const BUNDLE_FORMAT: &str = "rust-docs-mcp-bundle";
+
+/// Path of the manifest inside a bundle
+const MANIFEST_PATH: &str = "manifest.json";
+
+/// Source recorded for imported crates, which are sandboxed like crates.io crates
+const BUNDLE_SOURCE: &str = "bundle";
+
+/// Description of a bundle's contents, stored as its first entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub crates: Vec<BundleEntry>,
+}
+
+/// A crate version inside a bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleEntry {
+    pub name: String,
+    pub version: String,
+    /// Source directory of the entry in the exporting cache
+    pub source_path: PathBuf,
+}
+
+impl BundleEntry {
+    /// Path of the entry's directory inside the bundle
+    fn archive_path(&self) -> PathBuf {
+        Path::new("crates").join(&self.name).join(&self.version)
+    }
+}
+
+/// What to do with bundled crates that are already cached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnConflict {
+    /// Keep the cached copy
+    Skip,
+    /// Replace the cached copy with the bundled one
+    Replace,
+    /// Keep whichever copy was cached more recently
+    Newer,
+}
+
+/// Outcome of an export
+#[derive(Debug, Serialize)]
+pub struct ExportReport {
+    pub path: PathBuf,
+    /// Exported crate versions as `name-version`
+    pub crates: Vec<String>,
+    /// Requested crate versions that are not cached and could not be fetched
+    pub missing: Vec<String>,
+    pub size_bytes: u64,
+}
+
+/// Outcome of an import, listing crate versions as `name-version`
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    /// Crate versions that were not cached before
+    pub imported: Vec<String>,
+    /// Cached crate versions replaced by the bundled copy
+    pub replaced: Vec<String>,
+    /// Bundled crate versions that were already cached and kept
+    pub skipped: Vec<String>,
+}
+
+/// Writes a bundle, one crate version at a time
+pub struct BundleWriter {
+    builder: tar::Builder<GzEncoder<tempfile::NamedTempFile>>,
+    output: PathBuf,
+}
+
+impl BundleWriter {
+    /// Start a bundle at `output` containing the given crate versions
+    ///
+    /// The file only appears at `output` once [`finish`](Self::finish) succeeds.
+    pub fn create(output: &Path, manifest: &BundleManifest) -> Result<Self> {
+        let parent = output
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let file = tempfile::NamedTempFile::new_in(parent)
+            .with_context(|| format!("Failed to create temporary file in {}", parent.display()))?;
+
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        builder.follow_symlinks(false);
+
+        let json = serde_json::to_vec_pretty(manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(manifest.created_at.timestamp().max(0) as u64);
+        builder
+            .append_data(&mut header, MANIFEST_PATH, json.as_slice())
+            .context("Failed to write bundle manifest")?;
+
+        Ok(Self {
+            builder,
+            output: output.to_path_buf(),
+        })
+    }
+
+    /// Add a cached crate version, leaving out its build artifacts
+    ///
+    /// Bundled crates are snapshots, so a link to a local directory is left out too.
+    /// The caller must hold the crate lock.
+    pub fn append(&mut self, storage: &CacheStorage, entry: &BundleEntry) -> Result<()> {
+        let entry_dir = storage.crate_path(&entry.name, &entry.version);
+        if !entry_dir.exists() {
+            bail!("{}-{} is no longer cached", entry.name, entry.version);
+        }
+        let skip = [
+            storage.build_dir(&entry.name, &entry.version),
+            storage.link_path(&entry.name, &entry.version),
+        ];
+        self.append_dir(&entry_dir, &entry.archive_path(), &skip)
+            .with_context(|| format!("Failed to bundle {}-{}", entry.name, entry.version))
+    }
+
+    fn append_dir(&mut self, dir: &Path, archive_path: &Path, skip: &[PathBuf]) -> Result<()> {
+        self.builder.append_dir(archive_path, dir)?;
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = archive_path.join(entry.file_name());
+            if skip.contains(&path) {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                self.append_dir(&path, &name, skip)?;
+            } else {
+                self.builder.append_path_with_name(&path, &name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Complete the archive and move it to its final location
+    pub fn finish(self) -> Result<u64> {
+        let file = self
+            .builder
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .context("Failed to finish bundle")?;
+        file.as_file().sync_all()?;
+        let size = file.as_file().metadata()?.len();
+        file.persist(&self.output)
+            .with_context(|| format!("Failed to write {}", self.output.display()))?;
+        Ok(size)
+    }
+}
+
+/// Unpack a bundle into a staging directory of the cache
+///
+/// Returns the manifest and the staging directory, which holds each entry under
+/// `crates/{name}/{version}/` and is deleted when dropped.
+pub fn unpack(
+    storage: &CacheStorage,
+    bundle: &Path,
+) -> Result<(BundleManifest, tempfile::TempDir)> {
+    let file =
+        File::open(bundle).with_context(|| format!("Failed to open {}", bundle.display()))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut entries = archive.entries().context("Failed to read bundle")?;
+
+    let manifest = match entries.next() {
+        Some(entry) => {
+            let mut entry = entry.context("Failed to read bundle")?;
+            if entry.path()?.as_ref() != Path::new(MANIFEST_PATH) {
+                bail!("{} is not a cache bundle", bundle.display());
+            }
+            let mut json = Vec::new();
+            entry.read_to_end(&mut json)?;
+            parse_manifest(&json)?
+        }
+        None => bail!("{} is empty", bundle.display()),
+    };
+
+    let staging = storage.create_temp_dir("import-")?;
+    for entry in entries {
+        let mut entry = entry.context("Failed to read bundle")?;
+        let path = entry.path()?.into_owned();
+        if !path.starts_with("crates") {
+            bail!("Unexpected file in bundle: {}", path.display());
+        }
+        // Links could point an entry's files anywhere, exports never contain them
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            bail!("Bundle entry {} is not a file or directory", path.display());
+        }
+        // Refuses paths that would escape the staging directory
+        entry
+            .unpack_in(staging.path())
+            .with_context(|| format!("Failed to unpack {}", path.display()))?;
+    }
+
+    for entry in &manifest.crates {
+        let staged = staged_entry(staging.path(), entry);
+        if !staged.is_dir() {
+            bail!(
+                "Bundle is missing {}-{} listed in its manifest",
+                entry.name,
+                entry.version
+            );
+        }
+        mark_imported(&staged)
+            .with_context(|| format!("Failed to import {}-{}", entry.name, entry.version))?;
+    }
+    Ok((manifest, staging))
+}
+
+/// Record a staged entry's source as [`BUNDLE_SOURCE`]
+///
+/// The metadata comes from another machine, so its source can't be trusted to
+/// decide whether the crate is built in the sandbox.
+fn mark_imported(staged: &Path) -> Result<()> {
+    let path = staged.join("metadata.json");
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut metadata: serde_json::Value = serde_json::from_slice(&fs::read(&path)?)
+        .with_context(|| format!("Invalid metadata {}", path.display()))?;
+    metadata["source"] = serde_json::json!(BUNDLE_SOURCE);
+    fs::write(&path, serde_json::to_vec_pretty(&metadata)?)?;
+    Ok(())
+}
+
+/// Staged directory of a bundled entry
+pub fn staged_entry(staging: &Path, entry: &BundleEntry) -> PathBuf {
+    staging.join(entry.archive_path())
+}
+
+fn parse_manifest(json: &[u8]) -> Result<BundleManifest> {
+    let manifest: BundleManifest =
+        serde_json::from_slice(json).context("Invalid bundle manifest")?;
+    if manifest.format != BUNDLE_FORMAT {
+        bail!("Not a cache bundle: unknown format {}", manifest.format);
+    }
+    if manifest.version > BUNDLE_VERSION {
+        bail!(
+            "Bundle version {} was created by a newer rust-docs-mcp, this one reads up to version {}",
+            manifest.version,
+            BUNDLE_VERSION
+        );
+    }
+    // Names and versions become cache paths, they must not leave their directory
+    for entry in &manifest.crates {
+        if !is_valid_name(&entry.name) {
+            bail!("Invalid crate name in bundle manifest: {:?}", entry.name);
+        }
+        if !is_valid_version(&entry.version) {
+            bail!(
+                "Invalid version of {} in bundle manifest: {:?}",
+                entry.name,
+                entry.version
+            );
+        }
+    }
+    Ok(manifest)
+}
+
+/// Whether a crate name only uses the characters crates.io allows
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Whether a version is a single relative path component
+fn is_valid_version(version: &str) -> bool {
+    !version.is_empty()
+        && !version.contains(['/', '\\'])
+        && !version.contains("..")
+        && !Path::new(version).is_absolute()
+}
+
+/// Create the manifest for a bundle of the given crate versions
+pub fn manifest(storage: &CacheStorage, crates: &[(String, String)]) -> BundleManifest {
+    BundleManifest {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        created_at: chrono::Utc::now(),
+        crates: crates
+            .iter()
+            .map(|(name, version)| BundleEntry {
+                name: name.clone(),
+                version: version.clone(),
+                source_path: storage.source_path(name, version),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cache_entry(storage: &CacheStorage, name: &str, version: &str) -> Result<()> {
+        let source = storage.source_path(name, version);
+        fs::create_dir_all(source.join("src"))?;
+        fs::create_dir_all(storage.build_dir(name, version).join("debug"))?;
+        fs::write(
+            source.join("Cargo.toml"),
+            format!("[package]\nname = \"{name}\"\n"),
+        )?;
+        fs::write(source.join("src").join("lib.rs"), "pub fn f() {}")?;
+        fs::write(
+            storage
+                .build_dir(name, version)
+                .join("debug")
+                .join("lib.rlib"),
+            "x",
+        )?;
+        fs::write(
+            storage.dependencies_path(name, version),
+            serde_json::to_vec(&serde_json::json!({ "workspace_root": source }))?,
+        )?;
+        storage.save_metadata(name, version)
+    }
+
+    #[test]
+    fn test_bundle_round_trip() -> Result<()> {
+        let export_dir = TempDir::new()?;
+        let exporting = CacheStorage::new(Some(export_dir.path().join("cache")))?;
+        cache_entry(&exporting, "demo", "1.0.0")?;
+
+        let crates = [("demo".to_string(), "1.0.0".to_string())];
+        let manifest = manifest(&exporting, &crates);
+        let bundle = export_dir.path().join("bundle.tar.gz");
+        let mut writer = BundleWriter::create(&bundle, &manifest)?;
+        writer.append(&exporting, &manifest.crates[0])?;
+        writer.finish()?;
+
+        let import_dir = TempDir::new()?;
+        let importing = CacheStorage::new(Some(import_dir.path().to_path_buf()))?;
+        let (read, staging) = unpack(&importing, &bundle)?;
+        assert_eq!(read.version, BUNDLE_VERSION);
+        assert_eq!(read.crates.len(), 1);
+
+        let staged = staged_entry(staging.path(), &read.crates[0]);
+        assert!(staged.join("source").join("src").join("lib.rs").exists());
+        let metadata: serde_json::Value =
+            serde_json::from_slice(&fs::read(staged.join("metadata.json"))?)?;
+        assert_eq!(metadata["source"], BUNDLE_SOURCE);
+        // Build artifacts stay behind
+        assert!(!staged.join("source").join("target").exists());
+
+        let new_source = importing.source_path("demo", "1.0.0");
+        CacheStorage::rewrite_dependency_paths(&staged, &read.crates[0].source_path, &new_source)?;
+        let deps: serde_json::Value =
+            serde_json::from_slice(&fs::read(staged.join("dependencies.json"))?)?;
+        assert_eq!(deps["workspace_root"], serde_json::json!(new_source));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_newer_bundles() {
+        let mut manifest = manifest(&CacheStorage::new(Some(std::env::temp_dir())).unwrap(), &[]);
+        manifest.version = BUNDLE_VERSION + 1;
+        let json = serde_json::to_vec(&manifest).unwrap();
+        assert!(parse_manifest(&json).is_err());
+
+        manifest.version = BUNDLE_VERSION;
+        manifest.format = "something-else".to_string();
+        let json = serde_json::to_vec(&manifest).unwrap();
+        assert!(parse_manifest(&json).is_err());
+    }
+
+    #[test]
+    fn test_rejects_paths_outside_the_cache() -> Result<()> {
+        let dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(dir.path().join("cache")))?;
+        let victim = dir.path().join("victim");
+        fs::create_dir_all(victim.join("v"))?;
+
+        for (name, version) in [
+            (victim.to_string_lossy().to_string(), "v".to_string()),
+            ("../../victim".to_string(), "v".to_string()),
+            ("demo".to_string(), "../../victim".to_string()),
+            ("demo".to_string(), "..".to_string()),
+            ("demo".to_string(), "1.0.0/x".to_string()),
+            ("".to_string(), "1.0.0".to_string()),
+        ] {
+            let mut manifest = manifest(&storage, &[]);
+            manifest.crates.push(BundleEntry {
+                name: name.clone(),
+                version: version.clone(),
+                source_path: PathBuf::from("/src"),
+            });
+            assert!(
+                parse_manifest(&serde_json::to_vec(&manifest)?).is_err(),
+                "accepted {name} {version}"
+            );
+
+            // A crafted bundle holding nothing but that manifest
+            let bundle = dir.path().join("crafted.tar.gz");
+            BundleWriter::create(&bundle, &manifest)?.finish()?;
+            assert!(unpack(&storage, &bundle).is_err());
+        }
+        assert!(victim.join("v").is_dir());
+
+        let mut manifest = manifest(&storage, &[]);
+        manifest.crates.push(BundleEntry {
+            name: "serde_json-ng".to_string(),
+            version: "1.0.0-rc.1+build.5".to_string(),
+            source_path: PathBuf::from("/src"),
+        });
+        parse_manifest(&serde_json::to_vec(&manifest)?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_links() -> Result<()> {
+        let dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(dir.path().join("cache")))?;
+        let crates = [("demo".to_string(), "1.0.0".to_string())];
+        let manifest = manifest(&storage, &crates);
+
+        for entry_type in [tar::EntryType::Symlink, tar::EntryType::Link] {
+            let bundle = dir.path().join("crafted.tar.gz");
+            let mut writer = BundleWriter::create(&bundle, &manifest)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(0);
+            writer
+                .builder
+                .append_link(&mut header, "crates/demo/1.0.0/source", "/")?;
+            writer.finish()?;
+
+            let error = unpack(&storage, &bundle).unwrap_err();
+            assert!(error.to_string().contains("not a file or directory"));
+        }
+        Ok(())
+    }
+}
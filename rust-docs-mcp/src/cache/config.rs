@@ -0,0 +1,30 @@
+//! Runtime configuration for the crate cache
+//!
+//! This module collects the user-configurable settings that influence how crates
+//! are downloaded, built and stored.
+
+use crate::cache::gc::GcQuota;
+use crate::cache::sandbox::SandboxConfig;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Configuration for [`CrateCache`](crate::cache::CrateCache)
+#[derive(Debug, Clone, Default)]
+pub struct CacheConfig {
+    /// Which crate sources are documented inside the build sandbox
+    pub sandbox: SandboxConfig,
+    /// Maximum time a single cargo invocation may take before it is killed
+    pub build_timeout: Option<Duration>,
+    /// Memory budget in bytes for parsed documentation kept between queries, 0 disables it
+    pub doc_cache_bytes: u64,
+    /// Directory holding the target directories shared between builds, defaults to
+    /// `targets/` in the cache directory
+    pub shared_target_dir: Option<PathBuf>,
+    /// Size limit in bytes for the shared target directories, 0 builds every crate
+    /// in its own target directory
+    pub shared_target_max_bytes: u64,
+    /// Limits enforced by garbage collection after new crates are cached
+    pub quota: GcQuota,
+    /// Read-only cache directories searched in order for crates missing from the cache
+    pub read_only_layers: Vec<PathBuf>,
+}
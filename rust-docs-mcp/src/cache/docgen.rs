@@ -3,28 +3,211 @@
 //! This module handles running `cargo rustdoc` to generate JSON documentation
 //! for both regular crates and workspace members.
 
-use crate::cache::storage::CacheStorage;
-use crate::cache::workspace::WorkspaceHandler;
-use anyhow::{Context, Result, bail};
+use crate::cache::config::CacheConfig;
+use crate::cache::process::run_command;
+use crate::cache::sandbox::{Sandbox, SandboxMounts, SourceKind};
+use crate::cache::storage::{CacheStorage, DOCS_FILE_NAME, LEGACY_DOCS_FILE_NAME};
+use crate::cache::target_dir::{SharedTargets, TargetDirGuard};
+use crate::cache::transaction::PartialEntryGuard;
+use crate::cache::utils::{compress_atomic, read_docs, write_atomic};
+use crate::cache::workspace::WorkspaceMember;
+use crate::docs::DocIndex;
+use anyhow::{Context, Result, anyhow, bail};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+
+/// Feature flags every documentation build is run with
+const RUSTDOC_FEATURES: &str = "--all-features";
+
+/// Allowance for file systems that store modification times with coarse precision
+const MTIME_PRECISION: Duration = Duration::from_secs(2);
 
 /// Service for generating documentation from Rust crates
 #[derive(Debug, Clone)]
 pub struct DocGenerator {
     storage: CacheStorage,
+    config: CacheConfig,
+    shared_targets: Option<SharedTargets>,
 }
 
 impl DocGenerator {
     /// Create a new documentation generator
-    pub fn new(storage: CacheStorage) -> Self {
-        Self { storage }
+    pub fn new(storage: CacheStorage, config: CacheConfig) -> Self {
+        let shared_targets = (config.shared_target_max_bytes > 0).then(|| {
+            let root = config
+                .shared_target_dir
+                .clone()
+                .unwrap_or_else(|| storage.targets_dir());
+            SharedTargets::new(root, config.shared_target_max_bytes)
+        });
+
+        Self {
+            storage,
+            config,
+            shared_targets,
+        }
+    }
+
+    /// Check whether a cached crate is built inside the sandbox
+    fn is_sandboxed(&self, name: &str, version: &str) -> bool {
+        // Crates without metadata are treated as crates.io crates
+        let source_kind = self
+            .storage
+            .load_metadata(name, version)
+            .map(|metadata| SourceKind::from_metadata_source(&metadata.source))
+            .unwrap_or(SourceKind::CratesIo);
+
+        self.config.sandbox.is_sandboxed(source_kind)
+    }
+
+    /// Get the sandbox to build a cached crate in, if its source is not trusted
+    ///
+    /// The sandbox may only write to `target_dir`.
+    fn sandbox_for(&self, name: &str, version: &str, target_dir: &Path) -> Option<Sandbox> {
+        if !self.is_sandboxed(name, version) {
+            return None;
+        }
+
+        let source_path = self.storage.source_path(name, version);
+        let sandbox_dir = self.storage.sandbox_dir();
+        Some(Sandbox::new(
+            self.config.sandbox.limits().clone(),
+            SandboxMounts {
+                writable_dirs: vec![target_dir.to_path_buf()],
+                source_dir: source_path,
+                cargo_home: sandbox_dir.join("cargo-home"),
+                home_dir: sandbox_dir.join("home"),
+            },
+        ))
+    }
+
+    /// Get the time limit for a cargo command, taking the sandbox wall-time limit into account
+    fn timeout_for(&self, sandbox: Option<&Sandbox>) -> Option<Duration> {
+        let sandbox_limit = sandbox.and(self.config.sandbox.limits().wall_time);
+        match (self.config.build_timeout, sandbox_limit) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Fetch dependencies ahead of a sandboxed build, which has no network access
+    async fn prepare_build(&self, name: &str, version: &str) -> Result<()> {
+        let build_dir = self.storage.build_dir(name, version);
+        let Some(sandbox) = self.sandbox_for(name, version, &build_dir) else {
+            return Ok(());
+        };
+
+        tracing::info!(
+            "Fetching dependencies for sandboxed build of {}-{}",
+            name,
+            version
+        );
+
+        let output = run_command(sandbox.fetch_command()?, self.config.build_timeout)
+            .await
+            .context("Failed to run cargo fetch")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to fetch dependencies: {}", stderr);
+        }
+
+        Ok(())
+    }
+
+    /// Lock a shared target directory for building a crate, if sharing is enabled
+    ///
+    /// Falls back to the crate's own target directory when no shared one is available.
+    async fn shared_target(&self, name: &str, version: &str) -> Option<TargetDirGuard> {
+        let shared_targets = self.shared_targets.as_ref()?;
+        let sandboxed = self.is_sandboxed(name, version);
+        match shared_targets.acquire(RUSTDOC_FEATURES, sandboxed).await {
+            Ok(guard) => Some(guard),
+            Err(e) => {
+                tracing::warn!(
+                    "Building {}-{} without a shared target directory: {}",
+                    name,
+                    version,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Release a shared target directory, pruning the shared directories to their size limit
+    async fn release_target(&self, target: Option<TargetDirGuard>) {
+        let (Some(shared_targets), Some(target)) = (self.shared_targets.clone(), target) else {
+            return;
+        };
+
+        let result = tokio::task::spawn_blocking(move || shared_targets.prune(&target)).await;
+        match result {
+            Ok(Ok(0)) => {}
+            Ok(Ok(freed)) => tracing::info!("Pruned {} bytes of shared build artifacts", freed),
+            Ok(Err(e)) => tracing::warn!("Failed to prune shared target directories: {}", e),
+            Err(e) => tracing::warn!("Target directory pruning task failed: {}", e),
+        }
+    }
+
+    /// Run `cargo rustdoc` with JSON output, writing build artifacts to `target_dir`
+    async fn run_rustdoc(
+        &self,
+        name: &str,
+        version: &str,
+        package: Option<&str>,
+        target_dir: &Path,
+    ) -> Result<Output> {
+        let target_dir_arg = target_dir.to_string_lossy();
+        let mut args = vec!["+nightly", "rustdoc"];
+        if let Some(package) = package {
+            args.extend(["-p", package]);
+        }
+        args.extend([
+            RUSTDOC_FEATURES,
+            "--target-dir",
+            &target_dir_arg,
+            "--",
+            "--output-format",
+            "json",
+            "-Z",
+            "unstable-options",
+        ]);
+
+        self.run_cargo(name, version, &args, target_dir).await
+    }
+
+    /// Run a cargo command for a cached crate, isolated if its source is not trusted
+    async fn run_cargo(
+        &self,
+        name: &str,
+        version: &str,
+        args: &[&str],
+        target_dir: &Path,
+    ) -> Result<Output> {
+        let sandbox = self.sandbox_for(name, version, target_dir);
+        let timeout = self.timeout_for(sandbox.as_ref());
+
+        let command = match sandbox {
+            Some(sandbox) => sandbox.cargo_command(args)?,
+            None => {
+                let mut command = Command::new("cargo");
+                command
+                    .args(args)
+                    .current_dir(self.storage.source_path(name, version));
+                command
+            }
+        };
+
+        run_command(command, timeout).await
     }
 
     /// Generate JSON documentation for a crate
     pub async fn generate_docs(&self, name: &str, version: &str) -> Result<PathBuf> {
         let source_path = self.storage.source_path(name, version);
-        let docs_path = self.storage.docs_path(name, version);
+        let entry_dir = self.storage.crate_path(name, version);
+        let docs_path = entry_dir.join(DOCS_FILE_NAME);
 
         if !source_path.exists() {
             bail!(
@@ -36,20 +219,18 @@ impl DocGenerator {
 
         tracing::info!("Generating documentation for {}-{}", name, version);
 
+        self.prepare_build(name, version).await?;
+
         // Run cargo rustdoc with JSON output
-        let output = Command::new("cargo")
-            .args([
-                "+nightly",
-                "rustdoc",
-                "--all-features",
-                "--",
-                "--output-format",
-                "json",
-                "-Z",
-                "unstable-options",
-            ])
-            .current_dir(&source_path)
-            .output()
+        let target = self.shared_target(name, version).await;
+        let target_dir = match &target {
+            Some(target) => target.path().to_path_buf(),
+            None => self.storage.build_dir(name, version),
+        };
+        let build_started = SystemTime::now();
+        let output = self
+            .run_rustdoc(name, version, None, &target_dir)
+            .await
             .context("Failed to run cargo rustdoc")?;
 
         if !output.status.success() {
@@ -58,14 +239,27 @@ impl DocGenerator {
         }
 
         // Find the generated JSON file in target/doc
-        let doc_dir = source_path.join("target").join("doc");
-        let json_file = self.find_json_doc(&doc_dir, name)?;
-
-        // Copy the JSON file to our cache location
-        std::fs::copy(&json_file, &docs_path).context("Failed to copy documentation to cache")?;
+        let doc_dir = target_dir.join("doc");
+        let json_file = self.find_json_doc(&doc_dir, name, build_started)?;
+
+        // Remove the docs again if anything below fails or the build is cancelled,
+        // so that a half-written entry is never mistaken for generated docs
+        let index_path = self.storage.docs_index_path(name, version);
+        let guard = PartialEntryGuard::new([
+            docs_path.clone(),
+            index_path.clone(),
+            self.storage.dependencies_path(name, version),
+        ]);
+
+        // Store a compressed copy of the JSON file in our cache location
+        compress_atomic(&json_file, &docs_path).context("Failed to copy documentation to cache")?;
+        self.release_target(target).await;
+        remove_legacy_docs(&entry_dir)?;
+        self.build_docs_index(&docs_path, &index_path).await;
 
         // Generate and save dependency information
         self.generate_dependencies(name, version).await?;
+        guard.complete();
 
         // Update metadata to reflect that docs are now generated
         self.storage.save_metadata(name, version)?;
@@ -79,14 +273,16 @@ impl DocGenerator {
     }
 
     /// Generate JSON documentation for a workspace member
+    ///
+    /// The documentation is stored under the member's package name.
     pub async fn generate_workspace_member_docs(
         &self,
         name: &str,
         version: &str,
-        member_path: &str,
+        member: &WorkspaceMember,
     ) -> Result<PathBuf> {
         let source_path = self.storage.source_path(name, version);
-        let member_full_path = source_path.join(member_path);
+        let member_full_path = source_path.join(&member.path);
 
         if !source_path.exists() {
             bail!(
@@ -103,38 +299,28 @@ impl DocGenerator {
             );
         }
 
-        // Get the actual package name from the member's Cargo.toml
-        let member_cargo_toml = member_full_path.join("Cargo.toml");
-        let package_name = WorkspaceHandler::get_package_name(&member_cargo_toml)?;
-
-        // Extract the member name from the path (last directory)
-        let member_name = WorkspaceHandler::extract_member_name(member_path);
-        let docs_path = self.storage.member_docs_path(name, version, member_name);
+        let member_name = member.name.as_str();
 
         tracing::info!(
             "Generating documentation for workspace member {} (package: {}) in {}-{}",
-            member_path,
-            package_name,
+            member.path,
+            member.name,
             name,
             version
         );
 
+        self.prepare_build(name, version).await?;
+
         // Run cargo rustdoc with JSON output for the specific package
-        let output = Command::new("cargo")
-            .args([
-                "+nightly",
-                "rustdoc",
-                "-p",
-                &package_name,
-                "--all-features",
-                "--",
-                "--output-format",
-                "json",
-                "-Z",
-                "unstable-options",
-            ])
-            .current_dir(&source_path)
-            .output()
+        let target = self.shared_target(name, version).await;
+        let target_dir = match &target {
+            Some(target) => target.path().to_path_buf(),
+            None => self.storage.build_dir(name, version),
+        };
+        let build_started = SystemTime::now();
+        let output = self
+            .run_rustdoc(name, version, Some(member_name), &target_dir)
+            .await
             .context("Failed to run cargo rustdoc")?;
 
         if !output.status.success() {
@@ -143,57 +329,244 @@ impl DocGenerator {
         }
 
         // Find the generated JSON file in target/doc
-        let doc_dir = source_path.join("target").join("doc");
-        let json_file = self.find_json_doc(&doc_dir, &package_name)?;
+        let doc_dir = target_dir.join("doc");
+        let lib_name = member.lib_name.as_deref().unwrap_or(member_name);
+        let json_file = self.find_json_doc(&doc_dir, lib_name, build_started)?;
+        let docs_path = self
+            .store_member_docs(name, version, member, &json_file)
+            .await?;
+        self.release_target(target).await;
 
-        // Ensure the member directory exists in cache
-        if let Some(parent) = docs_path.parent() {
-            self.storage.ensure_dir(parent)?;
-        } else {
+        tracing::info!(
+            "Successfully generated documentation for workspace member {} in {}-{}",
+            member.path,
+            name,
+            version
+        );
+        Ok(docs_path)
+    }
+
+    /// Generate JSON documentation for several library members of a workspace in one build
+    ///
+    /// A single `cargo doc` documents all of them, so their shared dependencies are
+    /// built once and the members don't compete for the build directory. A member
+    /// failing to build doesn't keep the others from being stored, the result holds
+    /// the outcome of every member in order.
+    pub async fn generate_workspace_docs(
+        &self,
+        name: &str,
+        version: &str,
+        members: &[WorkspaceMember],
+    ) -> Result<Vec<Result<PathBuf>>> {
+        if !self.storage.source_path(name, version).exists() {
             bail!(
-                "Invalid docs path: no parent directory for {}",
-                docs_path.display()
+                "Source not found for {}-{}. Download it first.",
+                name,
+                version
             );
         }
 
-        // Copy the JSON file to our cache location
-        std::fs::copy(&json_file, &docs_path)
-            .context("Failed to copy workspace member documentation to cache")?;
+        tracing::info!(
+            "Generating documentation for {} workspace members of {}-{}",
+            members.len(),
+            name,
+            version
+        );
 
-        // Generate and save dependency information for the member
-        self.generate_workspace_member_dependencies(name, version, member_path)
-            .await?;
+        self.prepare_build(name, version).await?;
+
+        let target = self.shared_target(name, version).await;
+        let target_dir = match &target {
+            Some(target) => target.path().to_path_buf(),
+            None => self.storage.build_dir(name, version),
+        };
+        let target_dir_arg = target_dir.to_string_lossy();
+
+        // Cargo doesn't rewrite up-to-date JSON, which the mtime check below would
+        // then miss, e.g. after an earlier build stored only some of the members.
+        // Cleaning the members makes cargo document them again.
+        let mut clean_args = vec!["+nightly", "clean", "--target-dir", &target_dir_arg];
+        for member in members {
+            clean_args.extend(["-p", member.name.as_str()]);
+        }
+        match self
+            .run_cargo(name, version, &clean_args, &target_dir)
+            .await
+        {
+            Ok(output) if !output.status.success() => tracing::warn!(
+                "Failed to clean workspace members of {}-{}: {}",
+                name,
+                version,
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::warn!(
+                "Failed to clean workspace members of {}-{}: {}",
+                name,
+                version,
+                e
+            ),
+        }
+
+        let mut args = vec![
+            "+nightly",
+            "doc",
+            "--lib",
+            "--no-deps",
+            "--keep-going",
+            RUSTDOC_FEATURES,
+        ];
+        for member in members {
+            args.extend(["-p", member.name.as_str()]);
+        }
+        args.extend([
+            "--target-dir",
+            &target_dir_arg,
+            "-Z",
+            "unstable-options",
+            "--output-format",
+            "json",
+        ]);
+
+        let build_started = SystemTime::now();
+        let output = self
+            .run_cargo(name, version, &args, &target_dir)
+            .await
+            .context("Failed to run cargo doc")?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let doc_dir = target_dir.join("doc");
+        let mut results = Vec::new();
+        for member in members {
+            let json_file = member
+                .lib_name
+                .as_deref()
+                .and_then(|lib_name| built_json_doc(&doc_dir, lib_name, build_started));
+            let result = match json_file {
+                Some(json_file) => {
+                    self.store_member_docs(name, version, member, &json_file)
+                        .await
+                }
+                None if output.status.success() => Err(anyhow!(
+                    "No JSON documentation found for workspace member {}",
+                    member.name
+                )),
+                None => Err(anyhow!("Failed to generate documentation: {}", stderr)),
+            };
+            results.push(result);
+        }
+        self.release_target(target).await;
 
+        let documented = results.iter().filter(|result| result.is_ok()).count();
         tracing::info!(
-            "Successfully generated documentation for workspace member {} in {}-{}",
-            member_path,
+            "Generated documentation for {} of {} workspace members of {}-{}",
+            documented,
+            members.len(),
             name,
             version
         );
+        Ok(results)
+    }
+
+    /// Store a member's freshly built rustdoc JSON with its index and dependency information
+    async fn store_member_docs(
+        &self,
+        name: &str,
+        version: &str,
+        member: &WorkspaceMember,
+        json_file: &Path,
+    ) -> Result<PathBuf> {
+        let member_name = member.name.as_str();
+        let entry_dir = self.storage.member_path(name, version, member_name);
+        let docs_path = entry_dir.join(DOCS_FILE_NAME);
+
+        // Ensure the member directory exists in cache
+        self.storage.ensure_dir(&entry_dir)?;
+
+        let index_path = self
+            .storage
+            .member_docs_index_path(name, version, member_name);
+        let guard = PartialEntryGuard::new([
+            docs_path.clone(),
+            index_path.clone(),
+            self.storage
+                .member_dependencies_path(name, version, member_name),
+        ]);
+
+        // Store a compressed copy of the JSON file in our cache location
+        compress_atomic(json_file, &docs_path)
+            .context("Failed to copy workspace member documentation to cache")?;
+        remove_legacy_docs(&entry_dir)?;
+        self.build_docs_index(&docs_path, &index_path).await;
+
+        // Generate and save dependency information for the member
+        self.generate_workspace_member_dependencies(name, version, member)
+            .await?;
+        guard.complete();
+        self.storage.save_member_info(name, version, member)?;
         Ok(docs_path)
     }
 
-    /// Find the JSON documentation file for a crate in the target/doc directory
-    fn find_json_doc(&self, doc_dir: &Path, crate_name: &str) -> Result<PathBuf> {
-        // The JSON file is typically named after the crate, with hyphens replaced by underscores
-        let json_name = crate_name.replace('-', "_");
-        let json_file = doc_dir.join(format!("{json_name}.json"));
+    /// Build the binary query index for freshly stored documentation
+    ///
+    /// The index only speeds up queries, so a failure is logged and queries fall
+    /// back to the JSON documentation.
+    async fn build_docs_index(&self, docs_path: &Path, index_path: &Path) {
+        let (docs_path, index_path) = (docs_path.to_path_buf(), index_path.to_path_buf());
+        let result = tokio::task::spawn_blocking(move || {
+            DocIndex::write(&docs_path, &index_path).inspect_err(|_| {
+                // Never leave an index of previous documentation behind
+                let _ = std::fs::remove_file(&index_path);
+            })
+        })
+        .await;
+
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to build documentation index: {}", e),
+            Err(e) => tracing::warn!("Documentation index task failed: {}", e),
+        }
+    }
 
-        if json_file.exists() {
+    /// Find the JSON documentation file for a crate in the target/doc directory
+    ///
+    /// Shared target directories also hold the docs of previously built crates, so
+    /// only files written since `built_after` are considered.
+    fn find_json_doc(
+        &self,
+        doc_dir: &Path,
+        crate_name: &str,
+        built_after: SystemTime,
+    ) -> Result<PathBuf> {
+        if let Some(json_file) = built_json_doc(doc_dir, crate_name, built_after) {
             return Ok(json_file);
         }
-
-        // If not found, try to find any .json file in the directory
+        let built_after = built_after - MTIME_PRECISION;
+        let modified_since_build = |path: &Path| {
+            std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .filter(|modified| *modified >= built_after)
+        };
+
+        // If not found, fall back to the most recently written .json file in the directory
         let entries = std::fs::read_dir(doc_dir)
             .with_context(|| format!("Failed to read doc directory: {}", doc_dir.display()))?;
 
+        let mut newest = None;
         for entry in entries {
             let entry = entry?;
             let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                return Ok(path);
+            if path.extension().and_then(|s| s.to_str()) == Some("json")
+                && let Some(modified) = modified_since_build(&path)
+                && newest.as_ref().is_none_or(|(newest, _)| modified > *newest)
+            {
+                newest = Some((modified, path));
             }
         }
+        if let Some((_, path)) = newest {
+            return Ok(path);
+        }
 
         bail!(
             "No JSON documentation file found for crate '{}' in {}",
@@ -203,17 +576,20 @@ impl DocGenerator {
     }
 
     /// Generate and save dependency information for a crate
-    async fn generate_dependencies(&self, name: &str, version: &str) -> Result<()> {
-        let source_path = self.storage.source_path(name, version);
+    pub async fn generate_dependencies(&self, name: &str, version: &str) -> Result<()> {
         let deps_path = self.storage.dependencies_path(name, version);
 
         tracing::info!("Generating dependency information for {}-{}", name, version);
 
         // Run cargo metadata to get dependency information
-        let output = Command::new("cargo")
-            .args(["metadata", "--format-version", "1"])
-            .current_dir(&source_path)
-            .output()
+        let output = self
+            .run_cargo(
+                name,
+                version,
+                &["metadata", "--format-version", "1"],
+                &self.storage.build_dir(name, version),
+            )
+            .await
             .context("Failed to run cargo metadata")?;
 
         if !output.status.success() {
@@ -222,46 +598,49 @@ impl DocGenerator {
         }
 
         // Save the raw metadata output
-        tokio::fs::write(&deps_path, &output.stdout)
-            .await
+        write_atomic(&deps_path, &output.stdout)
             .context("Failed to write dependencies to cache")?;
 
         Ok(())
     }
 
     /// Generate and save dependency information for a workspace member
-    async fn generate_workspace_member_dependencies(
+    pub async fn generate_workspace_member_dependencies(
         &self,
         name: &str,
         version: &str,
-        member_path: &str,
+        member: &WorkspaceMember,
     ) -> Result<()> {
         let source_path = self.storage.source_path(name, version);
-        let member_name = WorkspaceHandler::extract_member_name(member_path);
         let deps_path = self
             .storage
-            .member_dependencies_path(name, version, member_name);
+            .member_dependencies_path(name, version, &member.name);
 
         tracing::info!(
             "Generating dependency information for workspace member {} in {}-{}",
-            member_path,
+            member.path,
             name,
             version
         );
 
         // Path to the member's Cargo.toml
-        let member_cargo_toml = source_path.join(member_path).join("Cargo.toml");
+        let member_cargo_toml = source_path.join(&member.path).join("Cargo.toml");
 
         // Run cargo metadata with --manifest-path for the specific member
-        let output = Command::new("cargo")
-            .args([
-                "metadata",
-                "--format-version",
-                "1",
-                "--manifest-path",
-                &member_cargo_toml.to_string_lossy(),
-            ])
-            .output()
+        let output = self
+            .run_cargo(
+                name,
+                version,
+                &[
+                    "metadata",
+                    "--format-version",
+                    "1",
+                    "--manifest-path",
+                    &member_cargo_toml.to_string_lossy(),
+                ],
+                &self.storage.build_dir(name, version),
+            )
+            .await
             .context("Failed to run cargo metadata")?;
 
         if !output.status.success() {
@@ -280,8 +659,7 @@ impl DocGenerator {
         }
 
         // Save the raw metadata output
-        tokio::fs::write(&deps_path, &output.stdout)
-            .await
+        write_atomic(&deps_path, &output.stdout)
             .context("Failed to write dependencies to cache")?;
 
         Ok(())
@@ -305,6 +683,36 @@ impl DocGenerator {
         Ok(deps)
     }
 
+    /// Load dependency information of a workspace member from cache
+    pub async fn load_member_dependencies(
+        &self,
+        name: &str,
+        version: &str,
+        member_name: &str,
+    ) -> Result<serde_json::Value> {
+        let deps_path = self
+            .storage
+            .member_dependencies_path(name, version, member_name);
+
+        if !deps_path.exists() {
+            bail!(
+                "Dependencies not found for workspace member {} in {}-{}",
+                member_name,
+                name,
+                version
+            );
+        }
+
+        let json_string = tokio::fs::read_to_string(&deps_path)
+            .await
+            .context("Failed to read dependencies file")?;
+
+        let deps: serde_json::Value =
+            serde_json::from_str(&json_string).context("Failed to parse dependencies JSON")?;
+
+        Ok(deps)
+    }
+
     /// Load documentation from cache
     pub async fn load_docs(&self, name: &str, version: &str) -> Result<serde_json::Value> {
         let docs_path = self.storage.docs_path(name, version);
@@ -313,12 +721,13 @@ impl DocGenerator {
             bail!("Documentation not found for {}-{}", name, version);
         }
 
-        let json_string = tokio::fs::read_to_string(&docs_path)
+        let json = tokio::task::spawn_blocking(move || read_docs(&docs_path))
             .await
+            .context("Documentation read task panicked")?
             .context("Failed to read documentation file")?;
 
         let docs: serde_json::Value =
-            serde_json::from_str(&json_string).context("Failed to parse documentation JSON")?;
+            serde_json::from_slice(&json).context("Failed to parse documentation JSON")?;
 
         Ok(docs)
     }
@@ -341,17 +750,39 @@ impl DocGenerator {
             );
         }
 
-        let json_string = tokio::fs::read_to_string(&docs_path)
+        let json = tokio::task::spawn_blocking(move || read_docs(&docs_path))
             .await
+            .context("Documentation read task panicked")?
             .context("Failed to read member documentation file")?;
 
-        let docs: serde_json::Value = serde_json::from_str(&json_string)
-            .context("Failed to parse member documentation JSON")?;
+        let docs: serde_json::Value =
+            serde_json::from_slice(&json).context("Failed to parse member documentation JSON")?;
 
         Ok(docs)
     }
 }
 
+/// Find the JSON documentation of a crate written since `built_after`
+///
+/// The file is named after the crate, with hyphens replaced by underscores.
+fn built_json_doc(doc_dir: &Path, crate_name: &str, built_after: SystemTime) -> Option<PathBuf> {
+    let json_file = doc_dir.join(format!("{}.json", crate_name.replace('-', "_")));
+    let modified = std::fs::metadata(&json_file)
+        .and_then(|m| m.modified())
+        .ok()?;
+    (modified >= built_after - MTIME_PRECISION).then_some(json_file)
+}
+
+/// Remove the uncompressed documentation an older version left in an entry directory
+fn remove_legacy_docs(entry_dir: &Path) -> Result<()> {
+    let legacy = entry_dir.join(LEGACY_DOCS_FILE_NAME);
+    if legacy.exists() {
+        std::fs::remove_file(&legacy)
+            .with_context(|| format!("Failed to remove {}", legacy.display()))?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -362,7 +793,7 @@ mod tests {
     fn test_docgen_creation() {
         let temp_dir = TempDir::new().unwrap();
         let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf())).unwrap();
-        let docgen = DocGenerator::new(storage);
+        let docgen = DocGenerator::new(storage, CacheConfig::default());
 
         // Just verify it was created successfully
         assert!(format!("{docgen:?}").contains("DocGenerator"));
@@ -372,12 +803,12 @@ mod tests {
     fn test_find_json_doc_not_found() {
         let temp_dir = TempDir::new().unwrap();
         let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf())).unwrap();
-        let docgen = DocGenerator::new(storage);
+        let docgen = DocGenerator::new(storage, CacheConfig::default());
 
         let doc_dir = temp_dir.path().join("doc");
         fs::create_dir_all(&doc_dir).unwrap();
 
-        let result = docgen.find_json_doc(&doc_dir, "nonexistent");
+        let result = docgen.find_json_doc(&doc_dir, "nonexistent", SystemTime::UNIX_EPOCH);
         assert!(result.is_err());
     }
 
@@ -385,7 +816,7 @@ mod tests {
     fn test_find_json_doc_found() {
         let temp_dir = TempDir::new().unwrap();
         let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf())).unwrap();
-        let docgen = DocGenerator::new(storage);
+        let docgen = DocGenerator::new(storage, CacheConfig::default());
 
         let doc_dir = temp_dir.path().join("doc");
         fs::create_dir_all(&doc_dir).unwrap();
@@ -394,15 +825,47 @@ mod tests {
         let json_file = doc_dir.join("test_crate.json");
         fs::write(&json_file, "{}").unwrap();
 
-        let result = docgen.find_json_doc(&doc_dir, "test_crate").unwrap();
+        let result = docgen
+            .find_json_doc(&doc_dir, "test_crate", SystemTime::UNIX_EPOCH)
+            .unwrap();
         assert_eq!(result, json_file);
     }
 
+    #[test]
+    fn test_find_json_doc_ignores_docs_of_earlier_builds() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf())).unwrap();
+        let docgen = DocGenerator::new(storage, CacheConfig::default());
+
+        let doc_dir = temp_dir.path().join("doc");
+        fs::create_dir_all(&doc_dir).unwrap();
+
+        // A shared target directory still holds the docs of another crate
+        let stale = doc_dir.join("other_crate.json");
+        fs::write(&stale, "{}").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+        let result = docgen.find_json_doc(&doc_dir, "renamed-lib", SystemTime::now());
+        assert!(result.is_err());
+
+        // The docs written by this build are found even under a different name
+        let fresh = doc_dir.join("renamed_lib_name.json");
+        fs::write(&fresh, "{}").unwrap();
+        let result = docgen
+            .find_json_doc(&doc_dir, "renamed-lib", SystemTime::now())
+            .unwrap();
+        assert_eq!(result, fresh);
+    }
+
     #[test]
     fn test_find_json_doc_with_underscore_conversion() {
         let temp_dir = TempDir::new().unwrap();
         let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf())).unwrap();
-        let docgen = DocGenerator::new(storage);
+        let docgen = DocGenerator::new(storage, CacheConfig::default());
 
         let doc_dir = temp_dir.path().join("doc");
         fs::create_dir_all(&doc_dir).unwrap();
@@ -411,7 +874,80 @@ mod tests {
         let json_file = doc_dir.join("test_crate.json");
         fs::write(&json_file, "{}").unwrap();
 
-        let result = docgen.find_json_doc(&doc_dir, "test-crate").unwrap();
+        let result = docgen
+            .find_json_doc(&doc_dir, "test-crate", SystemTime::UNIX_EPOCH)
+            .unwrap();
         assert_eq!(result, json_file);
     }
+
+    /// Write a workspace of two library members `a` and `b` as a cached local crate
+    fn local_workspace(storage: &CacheStorage) -> Vec<WorkspaceMember> {
+        let source = storage.source_path("ws", "0.1.0");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(
+            source.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"a\", \"b\"]\nresolver = \"2\"\n",
+        )
+        .unwrap();
+        let members = ["a", "b"]
+            .iter()
+            .map(|member| {
+                let dir = source.join(member);
+                fs::create_dir_all(dir.join("src")).unwrap();
+                fs::write(
+                    dir.join("Cargo.toml"),
+                    format!(
+                        "[package]\nname = \"{member}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n"
+                    ),
+                )
+                .unwrap();
+                fs::write(dir.join("src").join("lib.rs"), "pub fn f() {}\n").unwrap();
+                WorkspaceMember {
+                    name: member.to_string(),
+                    id: format!("path+file://{}#0.1.0", dir.display()),
+                    version: "0.1.0".to_string(),
+                    path: member.to_string(),
+                    target_kinds: vec!["lib".to_string()],
+                    lib_name: Some(member.to_string()),
+                }
+            })
+            .collect();
+        storage
+            .save_metadata_with_source("ws", "0.1.0", "local", None, &[])
+            .unwrap();
+        members
+    }
+
+    #[tokio::test]
+    async fn test_workspace_docs_rebuilt_after_deleting_member_docs() -> Result<()> {
+        if !Command::new("cargo")
+            .args(["+nightly", "--version"])
+            .output()
+            .is_ok_and(|output| output.status.success())
+        {
+            // Documentation is generated with the nightly toolchain
+            return Ok(());
+        }
+
+        let temp_dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf()))?;
+        let members = local_workspace(&storage);
+        let docgen = DocGenerator::new(storage.clone(), CacheConfig::default());
+
+        let results = docgen
+            .generate_workspace_docs("ws", "0.1.0", &members)
+            .await?;
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        // The target output of `b` is up to date but older than the next build,
+        // its cached docs are gone
+        tokio::time::sleep(MTIME_PRECISION + Duration::from_secs(1)).await;
+        fs::remove_dir_all(storage.member_path("ws", "0.1.0", "b"))?;
+        let results = docgen
+            .generate_workspace_docs("ws", "0.1.0", &members[1..])
+            .await?;
+        assert!(results[0].is_ok(), "{:?}", results[0]);
+        assert!(storage.has_member_docs("ws", "0.1.0", "b"));
+        Ok(())
+    }
 }
@@ -3,17 +3,22 @@
 //! This module handles downloading crates from various sources including
 //! crates.io, GitHub repositories, and local filesystem paths.
 
+use crate::cache::filter::{self, SourceFiles};
+use crate::cache::prefetch::PrefetchOptions;
 use crate::cache::source::{GitReference, SourceDetector, SourceType};
 use crate::cache::storage::CacheStorage;
 use crate::cache::tools::{
     CacheCrateFromCratesIOParams, CacheCrateFromGitHubParams, CacheCrateFromLocalParams,
 };
+use crate::cache::transaction::PartialEntryGuard;
 use crate::cache::utils::copy_directory_contents;
+use crate::deps::graph::DependencyKind;
 use anyhow::{Context, Result, bail};
 use flate2::read::GzDecoder;
 use futures::StreamExt;
 use git2::Repository;
-use std::fs::{self, File};
+use serde::Serialize;
+use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use tar::Archive;
@@ -26,6 +31,51 @@ pub enum CrateSource {
     LocalPath(CacheCrateFromLocalParams),
 }
 
+impl CrateSource {
+    /// Whether all library members of a workspace should be documented
+    pub fn all_members(&self) -> bool {
+        let all_members = match self {
+            CrateSource::CratesIO(params) => params.all_members,
+            CrateSource::GitHub(params) => params.all_members,
+            CrateSource::LocalPath(params) => params.all_members,
+        };
+        all_members.unwrap_or(false)
+    }
+
+    /// Which dependencies to prefetch once the crate is cached, if any
+    pub fn prefetch(&self) -> Result<Option<PrefetchOptions>> {
+        let (prefetch, max_depth, kinds) = match self {
+            CrateSource::CratesIO(params) => (
+                params.prefetch_dependencies,
+                params.prefetch_depth,
+                &params.prefetch_kinds,
+            ),
+            CrateSource::GitHub(params) => (
+                params.prefetch_dependencies,
+                params.prefetch_depth,
+                &params.prefetch_kinds,
+            ),
+            CrateSource::LocalPath(params) => (
+                params.prefetch_dependencies,
+                params.prefetch_depth,
+                &params.prefetch_kinds,
+            ),
+        };
+        if prefetch != Some(true) {
+            return Ok(None);
+        }
+
+        let kinds = match kinds {
+            Some(kinds) => kinds
+                .iter()
+                .map(|kind| DependencyKind::parse(kind))
+                .collect::<Result<Vec<_>>>()?,
+            None => vec![DependencyKind::Normal],
+        };
+        Ok(Some(PrefetchOptions { max_depth, kinds }))
+    }
+}
+
 /// Service for downloading crates from various sources
 #[derive(Debug, Clone)]
 pub struct CrateDownloader {
@@ -51,7 +101,11 @@ impl CrateDownloader {
     ) -> Result<PathBuf> {
         let source_type = SourceDetector::detect(source);
 
-        match source_type {
+        // A download that fails or is cancelled must not leave a half-extracted crate behind
+        let crate_path = self.storage.crate_path(name, version);
+        let guard = PartialEntryGuard::new((!crate_path.exists()).then_some(crate_path));
+
+        let source_path = match source_type {
             SourceType::CratesIo => self.download_crate(name, version).await,
             SourceType::GitHub {
                 url,
@@ -67,7 +121,10 @@ impl CrateDownloader {
                     .await
             }
             SourceType::Local { path } => self.copy_from_local(name, version, &path).await,
-        }
+        }?;
+
+        guard.complete();
+        Ok(source_path)
     }
 
     /// Download a crate from crates.io
@@ -92,8 +149,12 @@ impl CrateDownloader {
             );
         }
 
-        // Save to a temporary file first
-        let temp_file_path = std::env::temp_dir().join(format!("{name}-{version}.tar.gz"));
+        // Download and extract into a uniquely named staging directory first, so
+        // concurrent downloads never share temporary files
+        let staging = self
+            .storage
+            .create_temp_dir(&format!("{name}-{version}-"))?;
+        let temp_file_path = staging.path().join("crate.tar.gz");
         let mut temp_file = File::create(&temp_file_path)
             .with_context(|| format!("Failed to create temporary file for {name}-{version}"))?;
 
@@ -106,34 +167,10 @@ impl CrateDownloader {
         }
 
         // Extract the crate
-        let source_path = self.storage.source_path(name, version);
-        self.storage.ensure_dir(&source_path)?;
-
-        let tar_gz = File::open(&temp_file_path).context("Failed to open downloaded file")?;
-        let tar = GzDecoder::new(tar_gz);
-        let mut archive = Archive::new(tar);
-
-        // Extract with proper path handling
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?;
-
-            // Skip the top-level directory (crate-version/)
-            let components: Vec<_> = path.components().collect();
-            if components.len() > 1 {
-                let relative_path: PathBuf = components[1..].iter().collect();
-                let dest_path = source_path.join(relative_path);
-
-                if let Some(parent) = dest_path.parent() {
-                    std::fs::create_dir_all(parent)?;
-                }
+        let staged_source = unpack_crate_archive(&temp_file_path, &staging.path().join("source"))?;
 
-                entry.unpack(&dest_path)?;
-            }
-        }
-
-        // Clean up temp file
-        std::fs::remove_file(&temp_file_path).ok();
+        // Move the extracted crate into the cache
+        let source_path = self.storage.install_source(name, version, &staged_source)?;
 
         // Save metadata for the cached crate
         self.storage.save_metadata(name, version)?;
@@ -157,12 +194,11 @@ impl CrateDownloader {
             repo_url
         );
 
-        let temp_dir = std::env::temp_dir().join(format!("rust-docs-mcp-git-{name}-{version}"));
-
-        // Clean up any existing temp directory
-        if temp_dir.exists() {
-            fs::remove_dir_all(&temp_dir).context("Failed to clean temp directory")?;
-        }
+        // Clone into a uniquely named staging directory, removed again when dropped
+        let staging = self
+            .storage
+            .create_temp_dir(&format!("{name}-{version}-"))?;
+        let temp_dir = staging.path().join("repo");
 
         // Clone the repository
         let repo = Repository::clone(repo_url, &temp_dir)
@@ -215,22 +251,24 @@ impl CrateDownloader {
         }
 
         // Copy to cache location
-        let source_path = self.storage.source_path(name, version);
-        self.storage.ensure_dir(&source_path)?;
-
-        copy_directory_contents(&repo_source_path, &source_path)
+        let staged_source = staging.path().join("source");
+        let copied = filter::copy_source_tree(&repo_source_path, &staged_source)
             .context("Failed to copy repository contents")?;
-
-        // Clean up temp directory
-        fs::remove_dir_all(&temp_dir).ok();
+        log_skipped(name, version, &copied);
+        let source_path = self.storage.install_source(name, version, &staged_source)?;
 
         // Save metadata with source information
         let source_info = match repo_path {
             Some(path) => format!("{repo_url}#{path}"),
             None => repo_url.to_string(),
         };
-        self.storage
-            .save_metadata_with_source(name, version, "github", Some(&source_info))?;
+        self.storage.save_metadata_with_source(
+            name,
+            version,
+            "github",
+            Some(&source_info),
+            &skipped_paths(&copied),
+        )?;
 
         tracing::info!(
             "Successfully downloaded and extracted {}-{} from GitHub",
@@ -263,6 +301,11 @@ impl CrateDownloader {
         if !source_path_input.exists() {
             bail!("Local path does not exist: {}", source_path_input.display());
         }
+        if source_path_input.is_file() {
+            return self
+                .unpack_local_archive(name, version, source_path_input, local_path)
+                .await;
+        }
 
         let cargo_toml = source_path_input.join("Cargo.toml");
         if !cargo_toml.exists() {
@@ -273,24 +316,306 @@ impl CrateDownloader {
         }
 
         // Copy to cache location
-        let source_path = self.storage.source_path(name, version);
-        self.storage.ensure_dir(&source_path)?;
-
-        copy_directory_contents(source_path_input, &source_path)
+        let staging = self
+            .storage
+            .create_temp_dir(&format!("{name}-{version}-"))?;
+        let staged_source = staging.path().join("source");
+        let copied = filter::copy_source_tree(source_path_input, &staged_source)
             .context("Failed to copy local directory contents")?;
+        log_skipped(name, version, &copied);
+        let source_path = self.storage.install_source(name, version, &staged_source)?;
 
         // Save metadata with source information
-        self.storage
-            .save_metadata_with_source(name, version, "local", Some(local_path))?;
+        self.storage.save_metadata_with_source(
+            name,
+            version,
+            "local",
+            Some(local_path),
+            &skipped_paths(&copied),
+        )?;
 
         tracing::info!("Successfully copied {}-{} from local path", name, version);
         Ok(source_path)
     }
+
+    /// Unpack a local `.crate` file or gzipped tarball, e.g. one written by `cargo package`
+    ///
+    /// Archives are often third-party code, their metadata source `archive` is
+    /// treated like crates.io when deciding whether to sandbox builds.
+    async fn unpack_local_archive(
+        &self,
+        name: &str,
+        version: &str,
+        archive: &Path,
+        local_path: &str,
+    ) -> Result<PathBuf> {
+        let file_name = archive
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if !ARCHIVE_EXTENSIONS
+            .iter()
+            .any(|extension| file_name.ends_with(extension))
+        {
+            bail!(
+                "Unsupported file {}, expected a directory or one of {:?}",
+                archive.display(),
+                ARCHIVE_EXTENSIONS
+            );
+        }
+
+        let staging = self
+            .storage
+            .create_temp_dir(&format!("{name}-{version}-"))?;
+        let staged_source = unpack_crate_archive(archive, &staging.path().join("source"))?;
+        check_archive_package(&staged_source, name, version).with_context(|| {
+            format!(
+                "Archive {} doesn't hold {name}-{version}",
+                archive.display()
+            )
+        })?;
+        let source_path = self.storage.install_source(name, version, &staged_source)?;
+        self.storage
+            .save_metadata_with_source(name, version, "archive", Some(local_path), &[])?;
+
+        tracing::info!(
+            "Successfully unpacked {}-{} from {}",
+            name,
+            version,
+            local_path
+        );
+        Ok(source_path)
+    }
+
+    /// Copy a crate of a `cargo vendor` directory into the cache
+    ///
+    /// Vendored crates are third-party code, their metadata source `vendor` is
+    /// treated like crates.io when deciding whether to sandbox builds.
+    pub async fn copy_vendored(&self, vendored: &VendoredCrate) -> Result<PathBuf> {
+        let (name, version) = (vendored.name.as_str(), vendored.version.as_str());
+        let crate_path = self.storage.crate_path(name, version);
+        let guard = PartialEntryGuard::new((!crate_path.exists()).then_some(crate_path));
+
+        let staging = self
+            .storage
+            .create_temp_dir(&format!("{name}-{version}-"))?;
+        let staged_source = staging.path().join("source");
+        copy_directory_contents(&vendored.path, &staged_source)
+            .context("Failed to copy vendored crate")?;
+        let source_path = self.storage.install_source(name, version, &staged_source)?;
+        self.storage.save_metadata_with_source(
+            name,
+            version,
+            "vendor",
+            Some(&vendored.path.to_string_lossy()),
+            &[],
+        )?;
+        self.storage
+            .set_checksum(name, version, &vendored.checksum)?;
+
+        guard.complete();
+        Ok(source_path)
+    }
+}
+
+/// File name endings of crate archives accepted as local paths
+const ARCHIVE_EXTENSIONS: [&str; 3] = [".crate", ".tar.gz", ".tgz"];
+
+/// Unpack a `.crate` file or gzipped tarball into `dest` and find the crate in it
+///
+/// Crate archives hold a single `{name}-{version}/` directory, other tarballs may
+/// also have the crate at their top level. Returns the directory containing the
+/// crate's `Cargo.toml`.
+fn unpack_crate_archive(archive: &Path, dest: &Path) -> Result<PathBuf> {
+    let file = File::open(archive)
+        .with_context(|| format!("Failed to open archive: {}", archive.display()))?;
+    // Unlike unpacking entries one by one, this refuses paths outside of `dest`
+    Archive::new(GzDecoder::new(file))
+        .unpack(dest)
+        .with_context(|| format!("Failed to unpack archive: {}", archive.display()))?;
+
+    if dest.join("Cargo.toml").is_file() {
+        return Ok(dest.to_path_buf());
+    }
+    let mut dirs = std::fs::read_dir(dest)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir());
+    match (dirs.next(), dirs.next()) {
+        (Some(dir), None) if dir.join("Cargo.toml").is_file() => Ok(dir),
+        _ => bail!("No Cargo.toml found in archive: {}", archive.display()),
+    }
+}
+
+/// Check that the package unpacked from an archive is the requested crate version
+///
+/// Tarballs of a virtual workspace have no package to compare.
+fn check_archive_package(source: &Path, name: &str, version: &str) -> Result<()> {
+    let manifest = read_manifest(&source.join("Cargo.toml"))?;
+    if manifest.get("package").is_none() {
+        return Ok(());
+    }
+
+    let package_name = package_field(&manifest, "name").unwrap_or_default();
+    if package_name != name {
+        bail!("The archive holds the package {package_name}");
+    }
+    // Cargo defaults a missing version to 0.0.0
+    let package_version = match manifest
+        .get("package")
+        .and_then(|package| package.get("version"))
+    {
+        None => "0.0.0",
+        Some(package_version) => package_version
+            .as_str()
+            .context("The archive's package version is inherited from a workspace")?,
+    };
+    if package_version != version {
+        bail!("The archive holds version {package_version} of {name}");
+    }
+    Ok(())
+}
+
+/// Outcome of importing a `cargo vendor` directory, listing crate versions as `name-version`
+#[derive(Debug, Default, Serialize)]
+pub struct VendorReport {
+    /// Crate versions that were not cached before
+    pub imported: Vec<String>,
+    /// Vendored crate versions that were already cached and kept
+    pub skipped: Vec<String>,
+    /// Vendored crates not from a registry, e.g. git dependencies, with the reason
+    pub ignored: Vec<String>,
+    /// Crate versions or directories that couldn't be imported, with the reason
+    pub failed: Vec<String>,
+}
+
+/// A registry crate of a `cargo vendor` directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VendoredCrate {
+    pub name: String,
+    pub version: String,
+    /// Directory of the crate in the vendor directory
+    pub path: PathBuf,
+    /// Checksum of the `.crate` file the crate was unpacked from
+    pub checksum: String,
+}
+
+/// Crates found in a `cargo vendor` directory
+#[derive(Debug, Default)]
+pub struct VendorScan {
+    pub crates: Vec<VendoredCrate>,
+    /// Crates not from a registry, with the reason they are left out
+    pub ignored: Vec<String>,
+    /// Directories whose crate couldn't be read, with the reason
+    pub failed: Vec<String>,
+}
+
+/// Find the crates of a `cargo vendor` directory
+///
+/// Every subdirectory with a `Cargo.toml` and the `.cargo-checksum.json` written
+/// by `cargo vendor` is a crate, named and versioned by its manifest. Git
+/// dependencies have no package checksum. They may differ from the crates.io
+/// release of the same name and version, which they would shadow in the cache,
+/// so they are left out.
+pub fn scan_vendor_dir(vendor_dir: &Path) -> Result<VendorScan> {
+    let entries = std::fs::read_dir(vendor_dir)
+        .with_context(|| format!("Failed to read vendor directory: {}", vendor_dir.display()))?;
+
+    let mut scan = VendorScan::default();
+    for entry in entries {
+        let path = entry?.path();
+        if !path.join(".cargo-checksum.json").is_file() || !path.join("Cargo.toml").is_file() {
+            continue;
+        }
+
+        let dir_name = path.file_name().unwrap_or_default().to_string_lossy();
+        match read_vendored_crate(&path) {
+            Ok((name, version, Some(checksum))) => scan.crates.push(VendoredCrate {
+                name,
+                version,
+                path,
+                checksum,
+            }),
+            Ok((name, version, None)) => scan.ignored.push(format!(
+                "{name}-{version}: not from a registry, e.g. a git dependency"
+            )),
+            Err(e) => scan.failed.push(format!("{dir_name}: {e:#}")),
+        }
+    }
+
+    scan.crates
+        .sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+    scan.ignored.sort();
+    scan.failed.sort();
+    Ok(scan)
+}
+
+/// Read the name, version and package checksum of a vendored crate
+fn read_vendored_crate(path: &Path) -> Result<(String, String, Option<String>)> {
+    let manifest_path = path.join("Cargo.toml");
+    let checksum_path = path.join(".cargo-checksum.json");
+
+    let manifest = read_manifest(&manifest_path)?;
+    let package = |key: &str| {
+        package_field(&manifest, key)
+            .map(String::from)
+            .with_context(|| format!("No package.{key} in {}", manifest_path.display()))
+    };
+    let checksums: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(&checksum_path)
+            .with_context(|| format!("Failed to read {}", checksum_path.display()))?,
+    )
+    .with_context(|| format!("Failed to parse {}", checksum_path.display()))?;
+
+    Ok((
+        package("name")?,
+        package("version")?,
+        checksums["package"].as_str().map(String::from),
+    ))
+}
+
+fn read_manifest(manifest_path: &Path) -> Result<toml::Value> {
+    std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?
+        .parse()
+        .with_context(|| format!("Failed to parse {}", manifest_path.display()))
+}
+
+/// A string field of a manifest's `[package]` table
+fn package_field<'a>(manifest: &'a toml::Value, key: &str) -> Option<&'a str> {
+    manifest
+        .get("package")
+        .and_then(|package| package.get(key))
+        .and_then(|value| value.as_str())
+}
+
+/// Paths left out when copying a source tree, as recorded in the crate metadata
+fn skipped_paths(copied: &SourceFiles) -> Vec<String> {
+    copied
+        .skipped
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect()
+}
+
+fn log_skipped(name: &str, version: &str, copied: &SourceFiles) {
+    if !copied.skipped.is_empty() {
+        tracing::info!(
+            "Copied {} files of {}-{}, skipped {} ignored or excluded paths: {:?}",
+            copied.files.len(),
+            name,
+            version,
+            copied.skipped.len(),
+            copied.skipped
+        );
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cache::utils::test_support::write;
     use tempfile::TempDir;
 
     #[test]
@@ -302,4 +627,106 @@ mod tests {
         // Just verify it was created successfully
         assert!(format!("{downloader:?}").contains("CrateDownloader"));
     }
+
+    /// Write a package laid out like the output of `cargo package` into a `.crate` file
+    fn crate_archive(dir: &Path, manifest: &str) -> Result<PathBuf> {
+        let package = dir.join("package");
+        write(&package, "Cargo.toml", manifest);
+        write(&package, "src/lib.rs", "pub fn demo() {}");
+
+        let archive_path = dir.join("demo-0.1.0.crate");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&archive_path)?,
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        builder.append_dir_all("demo-0.1.0", &package)?;
+        builder.into_inner()?.finish()?;
+        Ok(archive_path)
+    }
+
+    #[tokio::test]
+    async fn test_cache_from_crate_archive() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let archive_path = crate_archive(
+            temp_dir.path(),
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n",
+        )?;
+        let archive = archive_path.to_string_lossy();
+
+        let storage = CacheStorage::new(Some(temp_dir.path().join("cache")))?;
+        let downloader = CrateDownloader::new(storage.clone());
+        let source_path = downloader
+            .download_or_copy_crate("demo", "0.1.0", Some(&archive))
+            .await?;
+        assert_eq!(
+            std::fs::read_to_string(source_path.join("src/lib.rs"))?,
+            "pub fn demo() {}"
+        );
+        assert_eq!(storage.load_metadata("demo", "0.1.0")?.source, "archive");
+
+        // The archive must hold the requested crate version
+        for (name, version) in [("serde", "0.1.0"), ("demo", "1.0.0")] {
+            assert!(
+                downloader
+                    .download_or_copy_crate(name, version, Some(&archive))
+                    .await
+                    .is_err()
+            );
+            assert!(!storage.is_cached(name, version));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_vendored_crates() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let vendor = temp_dir.path().join("vendor");
+        write(
+            &vendor,
+            "serde/Cargo.toml",
+            "[package]\nname = \"serde\"\nversion = \"1.0.200\"\n",
+        );
+        write(
+            &vendor,
+            "serde/.cargo-checksum.json",
+            r#"{"files": {}, "package": "abc123"}"#,
+        );
+        write(&vendor, "serde/src/lib.rs", "");
+        write(
+            &vendor,
+            "from-git/Cargo.toml",
+            "[package]\nname = \"from-git\"\nversion = \"0.2.0\"\n",
+        );
+        write(
+            &vendor,
+            "from-git/.cargo-checksum.json",
+            r#"{"files": {}, "package": null}"#,
+        );
+        write(&vendor, "broken/Cargo.toml", "[package");
+        write(&vendor, "broken/.cargo-checksum.json", r#"{"files": {}}"#);
+        // Not written by cargo vendor
+        write(&vendor, "notes/Cargo.toml", "[package]\nname = \"notes\"\n");
+
+        let scan = scan_vendor_dir(&vendor)?;
+        let found: Vec<_> = scan
+            .crates
+            .iter()
+            .map(|c| (c.name.as_str(), c.version.as_str(), c.checksum.as_str()))
+            .collect();
+        assert_eq!(found, [("serde", "1.0.200", "abc123")]);
+        assert_eq!(scan.ignored.len(), 1);
+        assert!(scan.ignored[0].starts_with("from-git-0.2.0"));
+        assert_eq!(scan.failed.len(), 1);
+        assert!(scan.failed[0].starts_with("broken"));
+
+        let storage = CacheStorage::new(Some(temp_dir.path().join("cache")))?;
+        let downloader = CrateDownloader::new(storage.clone());
+        let source_path = downloader.copy_vendored(&scan.crates[0]).await?;
+        assert!(source_path.join("src/lib.rs").exists());
+        let metadata = storage.load_metadata("serde", "1.0.200")?;
+        assert_eq!(metadata.source, "vendor");
+        assert_eq!(metadata.checksum.as_deref(), Some("abc123"));
+        Ok(())
+    }
 }
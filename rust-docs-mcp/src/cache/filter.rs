@@ -0,0 +1,456 @@
+//! Selection of the files of local sources worth caching
+//!
+//! Local directories and repositories are copied without the files
+//! `cargo package` wouldn't ship: version control directories, build
+//! directories, files ignored by `.gitignore` or `.ignore` files and files left
+//! out by a package's `include` or `exclude` list. Patterns use the gitignore
+//! syntax, matched by the `ignore` crate like cargo does, and are relative to
+//! the directory of the file or manifest declaring them. Packages nested in another package's directory, like workspace members,
+//! only follow their own `include` and `exclude`.
+
+use anyhow::{Context, Result};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories never copied, at any depth
+const VCS_DIRS: [&str; 3] = [".git", ".svn", ".hg"];
+
+/// Files listing ignored paths, later files take precedence
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// File cargo writes into every build directory, whatever its name
+const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+
+/// Files of a source tree selected for caching, relative to its root
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SourceFiles {
+    pub files: Vec<PathBuf>,
+    /// Files and directories left out, without the contents of skipped directories
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Select the files of the source tree at `root`
+pub fn scan(root: &Path) -> Result<SourceFiles> {
+    let mut walk = Walk {
+        root,
+        workspace_package: read_manifest(&root.join("Cargo.toml"))
+            .and_then(|manifest| manifest.get("workspace")?.get("package").cloned()),
+        ignores: Vec::new(),
+        packages: Vec::new(),
+        selected: SourceFiles::default(),
+    };
+    walk.dir(Path::new(""))?;
+    walk.selected.files.sort();
+    walk.selected.skipped.sort();
+    Ok(walk.selected)
+}
+
+/// Copy the files of the source tree at `src` selected by [`scan`] to `dest`
+pub fn copy_source_tree(src: &Path, dest: &Path) -> Result<SourceFiles> {
+    if !src.exists() {
+        anyhow::bail!("Source directory does not exist: {}", src.display());
+    }
+
+    let selected = scan(src)?;
+    fs::create_dir_all(dest)
+        .with_context(|| format!("Failed to create directory: {}", dest.display()))?;
+    for file in &selected.files {
+        let (from, to) = (src.join(file), dest.join(file));
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        fs::copy(&from, &to).with_context(|| {
+            format!(
+                "Failed to copy file from {} to {}",
+                from.display(),
+                to.display()
+            )
+        })?;
+    }
+    Ok(selected)
+}
+
+/// State of a walk through a source tree
+struct Walk<'a> {
+    root: &'a Path,
+    /// `[workspace.package]` of the root manifest, which packages can inherit lists from
+    workspace_package: Option<toml::Value>,
+    /// Patterns of the ignore files of the current directory and its ancestors
+    ignores: Vec<PatternList>,
+    /// Packages whose directories contain the current directory, innermost last
+    packages: Vec<Package>,
+    selected: SourceFiles,
+}
+
+impl Walk<'_> {
+    fn dir(&mut self, relative: &Path) -> Result<()> {
+        let dir = self.root.join(relative);
+        let manifest = dir.join("Cargo.toml");
+        let package = manifest
+            .is_file()
+            .then(|| Package::read(&manifest, relative, self.workspace_package.as_ref()))
+            .flatten();
+        let ignores = IGNORE_FILES
+            .iter()
+            .filter_map(|file| PatternList::read(&dir.join(file), relative))
+            .collect::<Vec<_>>();
+        let (pushed_ignores, pushed_package) = (ignores.len(), package.is_some());
+        self.ignores.extend(ignores);
+        self.packages.extend(package);
+
+        let mut entries = fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        let mut result = Ok(());
+        for entry in entries {
+            let path = relative.join(entry.file_name());
+            // Follow symbolic links, skipping broken ones
+            let Ok(metadata) = fs::metadata(entry.path()) else {
+                continue;
+            };
+            let is_dir = metadata.is_dir();
+            if self.skips(&path, is_dir, manifest.is_file()) {
+                self.selected.skipped.push(path);
+            } else if is_dir {
+                result = self.dir(&path);
+                if result.is_err() {
+                    break;
+                }
+            } else if metadata.is_file() {
+                self.selected.files.push(path);
+            }
+        }
+
+        self.ignores.truncate(self.ignores.len() - pushed_ignores);
+        if pushed_package {
+            self.packages.pop();
+        }
+        result
+    }
+
+    /// Whether a path is left out, `in_package_root` if its directory has a manifest
+    fn skips(&self, path: &Path, is_dir: bool, in_package_root: bool) -> bool {
+        let name = path.file_name().unwrap_or_default();
+        if is_dir {
+            if VCS_DIRS.iter().any(|vcs| name == *vcs)
+                || (in_package_root && name == "target")
+                || self.root.join(path).join(CACHEDIR_TAG).exists()
+            {
+                return true;
+            }
+        } else if name == "Cargo.toml" {
+            // Manifests are needed to build the package, whatever the patterns say
+            return false;
+        }
+
+        let ignored = self
+            .ignores
+            .iter()
+            .rev()
+            .find_map(|list| list.decide(path, is_dir));
+        if ignored == Some(true) {
+            return true;
+        }
+        self.packages
+            .last()
+            .is_some_and(|package| package.leaves_out(path, is_dir))
+    }
+}
+
+/// The `include` and `exclude` lists of a package
+struct Package {
+    include: Option<PatternList>,
+    exclude: PatternList,
+}
+
+impl Package {
+    /// Read the lists of the manifest of the package in `base`, `None` if it has none
+    fn read(manifest: &Path, base: &Path, workspace_package: Option<&toml::Value>) -> Option<Self> {
+        let manifest = read_manifest(manifest)?;
+        let package = manifest.get("package")?;
+        let list = |key: &str| {
+            let value = package.get(key)?;
+            // `include.workspace = true` inherits the list of the workspace
+            let value = match value.get("workspace").and_then(|w| w.as_bool()) {
+                Some(true) => workspace_package?.get(key)?,
+                _ => value,
+            };
+            let lines = value.as_array()?.iter().filter_map(|v| v.as_str());
+            Some(PatternList::parse(lines, base))
+        };
+        Some(Self {
+            include: list("include").filter(|include| !include.is_empty()),
+            exclude: list("exclude").unwrap_or_default(),
+        })
+    }
+
+    /// Whether the package's lists leave out a path
+    ///
+    /// As in cargo, `exclude` is only used without an `include` list. Directories
+    /// are never left out by `include`, which may list files inside them.
+    fn leaves_out(&self, path: &Path, is_dir: bool) -> bool {
+        match &self.include {
+            Some(_) if is_dir => false,
+            Some(include) => {
+                let included = include.decide(path, false).or_else(|| {
+                    path.ancestors()
+                        .skip(1)
+                        .take_while(|ancestor| ancestor.starts_with(&include.base))
+                        .find_map(|ancestor| include.decide(ancestor, true))
+                });
+                included != Some(true)
+            }
+            None => self.exclude.decide(path, is_dir) == Some(true),
+        }
+    }
+}
+
+/// Parse a manifest, `None` if it is missing or malformed
+fn read_manifest(path: &Path) -> Option<toml::Value> {
+    let content = fs::read_to_string(path).ok()?;
+    match toml::from_str(&content) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            tracing::debug!("Failed to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Gitignore patterns of one file or list, relative to a directory
+#[derive(Debug)]
+struct PatternList {
+    /// Directory the patterns are relative to, relative to the root
+    base: PathBuf,
+    matcher: Gitignore,
+}
+
+impl Default for PatternList {
+    fn default() -> Self {
+        Self {
+            base: PathBuf::new(),
+            matcher: Gitignore::empty(),
+        }
+    }
+}
+
+impl PatternList {
+    fn read(path: &Path, base: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        Some(Self::parse(content.lines(), base))
+    }
+
+    fn parse<'a>(lines: impl IntoIterator<Item = &'a str>, base: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new(base);
+        for line in lines {
+            // Like git, skip malformed patterns instead of rejecting the whole list
+            if let Err(e) = builder.add_line(None, line) {
+                tracing::debug!("Skipping pattern {:?} in {}: {}", line, base.display(), e);
+            }
+        }
+        let matcher = builder.build().unwrap_or_else(|e| {
+            tracing::debug!("Failed to build patterns of {}: {}", base.display(), e);
+            Gitignore::empty()
+        });
+        Self {
+            base: base.to_path_buf(),
+            matcher,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.matcher.is_empty()
+    }
+
+    /// Whether the last pattern matching a path ignores it, `None` if none matches
+    fn decide(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        path.strip_prefix(&self.base).ok()?;
+        match self.matcher.matched(path, is_dir) {
+            Match::None => None,
+            Match::Ignore(_) => Some(true),
+            Match::Whitelist(_) => Some(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::utils::test_support::write;
+    use tempfile::TempDir;
+
+    fn paths(paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    fn decide(lines: &[&str], base: &str, path: &str, is_dir: bool) -> Option<bool> {
+        PatternList::parse(lines.iter().copied(), Path::new(base)).decide(Path::new(path), is_dir)
+    }
+
+    #[test]
+    fn test_pattern_list() {
+        assert_eq!(decide(&["*.log"], "", "logs/debug.log", false), Some(true));
+        assert_eq!(decide(&["/*.log"], "", "logs/debug.log", false), None);
+        assert_eq!(
+            decide(&["*.log", "!keep.log"], "", "keep.log", false),
+            Some(false)
+        );
+        assert_eq!(decide(&["build/"], "", "build", false), None);
+        assert_eq!(decide(&["build/"], "", "build", true), Some(true));
+        assert_eq!(decide(&["file?.[ch]"], "", "file1.c", false), Some(true));
+        assert_eq!(decide(&["file?.[!ch]"], "", "file1.c", false), None);
+        assert_eq!(decide(&["\\#notes"], "", "#notes", false), Some(true));
+
+        // Patterns are relative to the directory declaring them
+        assert_eq!(decide(&["/out"], "sub", "sub/out", true), Some(true));
+        assert_eq!(decide(&["/out"], "sub", "out", true), None);
+        assert_eq!(decide(&["/out"], "sub", "sub/x/out", true), None);
+        assert_eq!(decide(&["/sub"], "sub", "sub/sub", true), Some(true));
+    }
+
+    #[test]
+    fn test_double_star_patterns() {
+        assert_eq!(decide(&["**/*.log"], "", "debug.log", false), Some(true));
+        assert_eq!(
+            decide(&["**/*.log"], "", "a/b/debug.log", false),
+            Some(true)
+        );
+        assert_eq!(decide(&["**/fixtures"], "", "fixtures", true), Some(true));
+        assert_eq!(
+            decide(&["**/fixtures"], "", "a/b/fixtures", true),
+            Some(true)
+        );
+        assert_eq!(decide(&["docs/**"], "", "docs/a/b.md", false), Some(true));
+        assert_eq!(decide(&["docs/**"], "", "docs", true), None);
+        assert_eq!(decide(&["a/**/b"], "", "a/b", false), Some(true));
+        assert_eq!(decide(&["a/**/b"], "", "a/x/y/b", false), Some(true));
+        assert_eq!(decide(&["a/**/b"], "", "a/xb", false), None);
+        assert_eq!(decide(&["a/**/b"], "", "x/a/b", false), None);
+    }
+
+    #[test]
+    fn test_many_wildcards_do_not_backtrack() {
+        // A naive recursive matcher takes exponential time on this
+        let pattern = "*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b";
+        let name = "a".repeat(200);
+        assert_eq!(
+            decide(&[pattern, "**/**/**/**/**/**/x"], "", &name, false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_scan_honors_ignore_files() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        write(root, "Cargo.toml", "[package]\nname = \"demo\"\n");
+        write(root, ".gitignore", "*.log\n/node_modules/\n!keep.log\n");
+        write(root, "src/lib.rs", "");
+        write(root, "src/debug.log", "");
+        write(root, "keep.log", "");
+        write(root, "node_modules/pkg/index.js", "");
+        write(root, "target/debug/out", "");
+        write(root, "build-out/CACHEDIR.TAG", "");
+        write(root, "build-out/debug/out", "");
+        write(root, ".git/HEAD", "");
+        write(root, "assets/.ignore", "big.bin\n");
+        write(root, "assets/big.bin", "");
+        write(root, "assets/small.txt", "");
+
+        let selected = scan(root)?;
+        assert_eq!(
+            paths(&selected.files),
+            [
+                ".gitignore",
+                "Cargo.toml",
+                "assets/.ignore",
+                "assets/small.txt",
+                "keep.log",
+                "src/lib.rs"
+            ]
+        );
+        assert_eq!(
+            paths(&selected.skipped),
+            [
+                ".git",
+                "assets/big.bin",
+                "build-out",
+                "node_modules",
+                "src/debug.log",
+                "target"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_negation_inside_excluded_directory() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        write(root, "Cargo.toml", "[package]\nname = \"demo\"\n");
+        // As in git, files can't be re-included once their directory is excluded,
+        // only when the directory's contents are
+        write(
+            root,
+            ".gitignore",
+            "/build/\n!/build/keep.txt\n/dist/*\n!/dist/keep.txt\n",
+        );
+        write(root, "build/keep.txt", "");
+        write(root, "dist/keep.txt", "");
+        write(root, "dist/bundle.js", "");
+
+        let selected = scan(root)?;
+        assert_eq!(
+            paths(&selected.files),
+            [".gitignore", "Cargo.toml", "dist/keep.txt"]
+        );
+        assert_eq!(paths(&selected.skipped), ["build", "dist/bundle.js"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_honors_package_lists() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\"]\n\n[workspace.package]\nexclude = [\"fixtures/\"]\n\n\
+             [package]\nname = \"demo\"\ninclude = [\"src/\", \"README.md\"]\n",
+        );
+        write(root, "src/lib.rs", "");
+        write(root, "README.md", "");
+        write(root, "notes.txt", "");
+        write(
+            root,
+            "crates/core/Cargo.toml",
+            "[package]\nname = \"core\"\nexclude.workspace = true\n",
+        );
+        write(root, "crates/core/src/lib.rs", "");
+        write(root, "crates/core/fixtures/huge.json", "");
+
+        let selected = scan(root)?;
+        assert_eq!(
+            paths(&selected.files),
+            [
+                "Cargo.toml",
+                "README.md",
+                "crates/core/Cargo.toml",
+                "crates/core/src/lib.rs",
+                "src/lib.rs"
+            ]
+        );
+        assert_eq!(
+            paths(&selected.skipped),
+            ["crates/core/fixtures", "notes.txt"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+//! Garbage collection of cached crates
+//!
+//! The cache can be limited in total size and in the number of cached crate
+//! versions. When a quota is exceeded, the least recently used crate versions
+//! are evicted until it is met again. Pinned crates and crates referenced by
+//! the lockfile of a registered project are never evicted, but they still count
+//! towards the quota.
+//!
+//! Usage is tracked in each crate's metadata. Recording every single query
+//! would rewrite the metadata file on each tool call, so uses are counted in
+//! memory and written at most once per [`ACCESS_FLUSH_INTERVAL`] and crate.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cache::storage::CrateMetadata;
+
+/// Minimum time between two writes of a crate's usage statistics
+pub const ACCESS_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Limits on the size of the cache, `0` meaning unlimited
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GcQuota {
+    /// Maximum total disk usage of all cached crates in bytes
+    pub max_bytes: u64,
+    /// Maximum number of cached crate versions
+    pub max_crates: usize,
+}
+
+impl GcQuota {
+    /// Whether no limit is configured
+    pub fn is_unlimited(&self) -> bool {
+        self.max_bytes == 0 && self.max_crates == 0
+    }
+
+    /// Whether a cache of this size exceeds the quota
+    pub fn is_exceeded(&self, total_bytes: u64, total_crates: usize) -> bool {
+        (self.max_bytes > 0 && total_bytes > self.max_bytes)
+            || (self.max_crates > 0 && total_crates > self.max_crates)
+    }
+}
+
+/// A cached crate version considered by garbage collection
+#[derive(Debug, Clone, Serialize)]
+pub struct GcEntry {
+    pub name: String,
+    pub version: String,
+    pub size_bytes: u64,
+    pub last_used: chrono::DateTime<chrono::Utc>,
+    pub access_count: u64,
+}
+
+impl From<&CrateMetadata> for GcEntry {
+    fn from(metadata: &CrateMetadata) -> Self {
+        Self {
+            name: metadata.name.clone(),
+            version: metadata.version.clone(),
+            size_bytes: metadata.size_bytes,
+            last_used: metadata.last_used(),
+            access_count: metadata.access_count,
+        }
+    }
+}
+
+/// Crate versions to evict to bring the cache within a quota
+#[derive(Debug, Default)]
+pub struct GcPlan {
+    /// Eviction candidates, least recently used first
+    pub evict: Vec<GcEntry>,
+    /// Number of pinned crate versions
+    pub pinned: usize,
+    /// Number of unpinned crate versions referenced by registered projects
+    pub referenced: usize,
+    pub total_bytes: u64,
+    pub total_crates: usize,
+}
+
+/// Plan which crate versions to evict, least recently used first
+///
+/// `referenced` holds the `(name, version)` pairs used by registered projects,
+/// `keep` those a caller just cached and is about to read. Neither is evicted.
+pub fn plan(
+    crates: &[CrateMetadata],
+    referenced: &HashSet<(String, String)>,
+    keep: &HashSet<(String, String)>,
+    quota: GcQuota,
+) -> GcPlan {
+    let mut plan = GcPlan {
+        total_bytes: crates.iter().map(|c| c.size_bytes).sum(),
+        total_crates: crates.len(),
+        ..GcPlan::default()
+    };
+
+    let mut candidates = Vec::new();
+    for metadata in crates {
+        let key = (metadata.name.clone(), metadata.version.clone());
+        if metadata.pinned {
+            plan.pinned += 1;
+        } else if referenced.contains(&key) {
+            plan.referenced += 1;
+        } else if !keep.contains(&key) {
+            candidates.push(GcEntry::from(metadata));
+        }
+    }
+    candidates.sort_by_key(|entry| entry.last_used);
+
+    let (mut bytes, mut count) = (plan.total_bytes, plan.total_crates);
+    for entry in candidates {
+        if !quota.is_exceeded(bytes, count) {
+            break;
+        }
+        bytes = bytes.saturating_sub(entry.size_bytes);
+        count -= 1;
+        plan.evict.push(entry);
+    }
+    plan
+}
+
+/// Outcome of a garbage collection run
+#[derive(Debug, Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub quota: GcQuota,
+    /// Crate versions evicted, or that would be evicted in a dry run
+    pub evicted: Vec<GcEntry>,
+    /// Eviction candidates skipped because they were in use
+    pub skipped_in_use: Vec<GcEntry>,
+    pub pinned: usize,
+    pub referenced_by_projects: usize,
+    pub total_bytes_before: u64,
+    pub total_bytes_after: u64,
+    pub crates_before: usize,
+    pub crates_after: usize,
+    /// Whether the cache is within the quota afterwards
+    pub quota_met: bool,
+}
+
+/// Uses of a crate that have not been written to its metadata yet
+#[derive(Debug)]
+struct PendingAccess {
+    hits: u64,
+    flushed_at: Option<Instant>,
+}
+
+/// In-memory counter of crate uses, batching metadata writes
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    pending: Mutex<HashMap<(String, String), PendingAccess>>,
+}
+
+impl AccessLog {
+    /// Count a use of a crate version
+    ///
+    /// Returns the number of uses to record when they are due to be written,
+    /// which is on the first use and then at most once per [`ACCESS_FLUSH_INTERVAL`].
+    pub fn hit(&self, name: &str, version: &str) -> Option<u64> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = pending
+            .entry((name.to_string(), version.to_string()))
+            .or_insert(PendingAccess {
+                hits: 0,
+                flushed_at: None,
+            });
+        entry.hits += 1;
+
+        let due = entry
+            .flushed_at
+            .is_none_or(|flushed_at| flushed_at.elapsed() >= ACCESS_FLUSH_INTERVAL);
+        if !due {
+            return None;
+        }
+        entry.flushed_at = Some(Instant::now());
+        Some(std::mem::take(&mut entry.hits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::storage::CacheSizes;
+    use chrono::{TimeDelta, Utc};
+
+    fn metadata(name: &str, size_bytes: u64, idle_days: i64, pinned: bool) -> CrateMetadata {
+        let now = Utc::now();
+        CrateMetadata {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            cached_at: now - TimeDelta::days(365),
+            doc_generated: true,
+            size_bytes,
+            source: "crates.io".to_string(),
+            source_path: None,
+            sizes: CacheSizes::default(),
+            last_accessed: Some(now - TimeDelta::days(idle_days)),
+            access_count: 1,
+            pinned,
+            skipped_paths: Vec::new(),
+            checksum: None,
+        }
+    }
+
+    fn names(entries: &[GcEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_plan_evicts_least_recently_used_first() {
+        let crates = [
+            metadata("recent", 100, 1, false),
+            metadata("stale", 100, 30, false),
+            metadata("old", 100, 10, false),
+        ];
+        let quota = GcQuota {
+            max_bytes: 150,
+            max_crates: 0,
+        };
+
+        let by_size = plan(&crates, &HashSet::new(), &HashSet::new(), quota);
+        assert_eq!(names(&by_size.evict), ["stale", "old"]);
+        assert_eq!(by_size.total_bytes, 300);
+
+        let quota = GcQuota {
+            max_bytes: 0,
+            max_crates: 2,
+        };
+        let by_count = plan(&crates, &HashSet::new(), &HashSet::new(), quota);
+        assert_eq!(names(&by_count.evict), ["stale"]);
+
+        let unlimited = plan(
+            &crates,
+            &HashSet::new(),
+            &HashSet::new(),
+            GcQuota::default(),
+        );
+        assert!(unlimited.evict.is_empty());
+    }
+
+    #[test]
+    fn test_plan_never_evicts_pinned_or_referenced_crates() {
+        let crates = [
+            metadata("pinned", 100, 50, true),
+            metadata("locked", 100, 40, false),
+            metadata("unused", 100, 1, false),
+        ];
+        let referenced = HashSet::from([("locked".to_string(), "1.0.0".to_string())]);
+        let quota = GcQuota {
+            max_bytes: 1,
+            max_crates: 0,
+        };
+
+        let plan = plan(&crates, &referenced, &HashSet::new(), quota);
+        assert_eq!(names(&plan.evict), ["unused"]);
+        assert_eq!(plan.pinned, 1);
+        assert_eq!(plan.referenced, 1);
+    }
+
+    #[test]
+    fn test_plan_never_evicts_kept_crates() {
+        let crates = [
+            metadata("old", 100, 30, false),
+            metadata("new", 1000, 0, false),
+        ];
+        let keep = HashSet::from([("new".to_string(), "1.0.0".to_string())]);
+        let quota = GcQuota {
+            max_bytes: 500,
+            max_crates: 0,
+        };
+
+        // The crate just cached is larger than the whole quota on its own
+        let plan = plan(&crates, &HashSet::new(), &keep, quota);
+        assert_eq!(names(&plan.evict), ["old"]);
+    }
+
+    #[test]
+    fn test_access_log_batches_writes() {
+        let log = AccessLog::default();
+        assert_eq!(log.hit("serde", "1.0.0"), Some(1));
+        assert_eq!(log.hit("serde", "1.0.0"), None);
+        assert_eq!(log.hit("serde", "1.0.0"), None);
+        assert_eq!(log.hit("tokio", "1.0.0"), Some(1));
+
+        // Once the interval has passed, the uses counted meanwhile are written together
+        log.pending
+            .lock()
+            .unwrap()
+            .get_mut(&("serde".to_string(), "1.0.0".to_string()))
+            .unwrap()
+            .flushed_at = Some(Instant::now() - ACCESS_FLUSH_INTERVAL);
+        assert_eq!(log.hit("serde", "1.0.0"), Some(3));
+    }
+}
@@ -0,0 +1,481 @@
+//! Linked local crates
+//!
+//! A crate cached from a local path is normally a snapshot. A linked crate
+//! additionally records the directory it was copied from and a fingerprint of
+//! every file in it. Before the crate is queried, the directory is compared with
+//! the fingerprints: files whose size or modification time changed are hashed,
+//! so touching a file without changing it doesn't cause a rebuild. Changed files
+//! are copied into the cached source and only the documentation they affect is
+//! regenerated: the workspace members containing them, including the root
+//! package, and the members depending on those through path dependencies.
+//!
+//! Checks walk the whole directory, so they run at most once per
+//! [`LINK_CHECK_INTERVAL`] and crate.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cache::filter;
+use crate::cache::utils::write_atomic;
+
+/// Minimum time between two checks of a linked crate for changes
+pub const LINK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// File name of the link state in a cache entry
+pub const LINK_FILE_NAME: &str = "link.json";
+
+/// Files of the workspace root whose changes affect every member
+const WORKSPACE_FILES: [&str; 2] = ["Cargo.toml", "Cargo.lock"];
+
+/// The original directory of a linked crate and the state of its files when last synced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkState {
+    /// Absolute path of the linked directory
+    pub path: PathBuf,
+    /// Fingerprints of all files, by path relative to the directory
+    files: BTreeMap<PathBuf, FileFingerprint>,
+}
+
+/// Size, modification time and content hash of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileFingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+    crc: u32,
+}
+
+/// Files that differ between a linked directory and the cached source, relative to both
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LinkChanges {
+    /// Added or modified files
+    pub changed: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl LinkChanges {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changed.len() + self.removed.len()
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.changed.iter().chain(&self.removed)
+    }
+
+    /// Copy the changes from the linked directory into the cached source
+    pub fn apply(&self, from: &Path, to: &Path) -> Result<()> {
+        for path in &self.changed {
+            let dest = to.join(path);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(from.join(path), &dest)
+                .with_context(|| format!("Failed to copy {}", from.join(path).display()))?;
+        }
+        for path in &self.removed {
+            let dest = to.join(path);
+            if dest.exists() {
+                fs::remove_file(&dest)
+                    .with_context(|| format!("Failed to remove {}", dest.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LinkState {
+    /// Fingerprint every file of a local crate, given as the user wrote its path
+    pub fn capture(local_path: &str) -> Result<Self> {
+        let expanded = shellexpand::full(local_path)
+            .with_context(|| format!("Failed to expand path: {local_path}"))?;
+        let path = std::path::absolute(expanded.as_ref())
+            .with_context(|| format!("Invalid path: {local_path}"))?;
+        let files = scan(&path, &BTreeMap::new())?;
+        Ok(Self { path, files })
+    }
+
+    /// Compare the linked directory with the recorded state
+    ///
+    /// Returns the new state and the files that changed since the recorded one.
+    pub fn rescan(&self) -> Result<(Self, LinkChanges)> {
+        let files = scan(&self.path, &self.files)?;
+
+        let mut changes = LinkChanges::default();
+        for (path, fingerprint) in &files {
+            if self.files.get(path).is_none_or(|previous| {
+                previous.crc != fingerprint.crc || previous.len != fingerprint.len
+            }) {
+                changes.changed.push(path.clone());
+            }
+        }
+        changes.removed = self
+            .files
+            .keys()
+            .filter(|path| !files.contains_key(*path))
+            .cloned()
+            .collect();
+
+        let state = Self {
+            path: self.path.clone(),
+            files,
+        };
+        Ok((state, changes))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, serde_json::to_vec(self)?.as_slice())
+    }
+}
+
+/// Fingerprint the files of `root` worth caching, reusing `previous` fingerprints of unmodified files
+///
+/// The files are selected like when the crate was copied, see [`filter::scan`].
+fn scan(
+    root: &Path,
+    previous: &BTreeMap<PathBuf, FileFingerprint>,
+) -> Result<BTreeMap<PathBuf, FileFingerprint>> {
+    if !root.join("Cargo.toml").is_file() {
+        anyhow::bail!("No Cargo.toml found at path: {}", root.display());
+    }
+
+    let mut files = BTreeMap::new();
+    for path in filter::scan(root)?.files {
+        let full_path = root.join(&path);
+        let metadata = fs::metadata(&full_path)
+            .with_context(|| format!("Failed to read {}", full_path.display()))?;
+        let (len, modified) = (metadata.len(), metadata.modified().ok());
+        let fingerprint = match previous.get(&path) {
+            Some(known) if known.len == len && known.modified == modified => *known,
+            _ => FileFingerprint {
+                len,
+                modified,
+                crc: crc_of(&full_path)?,
+            },
+        };
+        files.insert(path, fingerprint);
+    }
+    Ok(files)
+}
+
+fn crc_of(path: &Path) -> Result<u32> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut crc = flate2::Crc::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(crc.sum());
+        }
+        crc.update(&buffer[..read]);
+    }
+}
+
+/// Select the workspace members whose documentation is affected by changes
+///
+/// `members` are the member paths relative to the workspace root `source`,
+/// including `.` for the root package of a non-virtual workspace, which owns
+/// the files outside of every other member. A change to the workspace manifest
+/// or lockfile, or outside of every member of a virtual workspace, affects all
+/// of them.
+pub fn affected_members(source: &Path, members: &[String], changes: &LinkChanges) -> Vec<String> {
+    let dir = |member: &String| normalize(Path::new(member));
+    let owner = |path: &Path| {
+        members
+            .iter()
+            .filter(|member| path.starts_with(dir(member)))
+            .max_by_key(|member| dir(member).as_os_str().len())
+    };
+
+    let mut affected = BTreeSet::new();
+    for path in changes.paths() {
+        if WORKSPACE_FILES.iter().any(|file| path == Path::new(file)) {
+            return members.to_vec();
+        }
+        match owner(path) {
+            Some(member) => {
+                affected.insert(member.clone());
+            }
+            None => return members.to_vec(),
+        }
+    }
+
+    // Members re-export and document items of the members they depend on
+    let dependencies: HashMap<&String, Vec<PathBuf>> = members
+        .iter()
+        .map(|member| (member, path_dependencies(source, member)))
+        .collect();
+    loop {
+        let dependents: Vec<String> = members
+            .iter()
+            .filter(|member| !affected.contains(*member))
+            .filter(|member| {
+                dependencies[member]
+                    .iter()
+                    .any(|dependency| affected.iter().any(|a| *dependency == dir(a)))
+            })
+            .cloned()
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        affected.extend(dependents);
+    }
+
+    members
+        .iter()
+        .filter(|member| affected.contains(*member))
+        .cloned()
+        .collect()
+}
+
+/// Get the paths, relative to the workspace root, of a member's path dependencies
+fn path_dependencies(source: &Path, member: &str) -> Vec<PathBuf> {
+    let manifest = |path: &Path| {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| toml::from_str::<toml::Value>(&content).ok())
+    };
+    let Some(member_manifest) = manifest(&source.join(member).join("Cargo.toml")) else {
+        return Vec::new();
+    };
+    let workspace_manifest = manifest(&source.join("Cargo.toml"));
+    let workspace_dependencies = workspace_manifest
+        .as_ref()
+        .and_then(|m| m.get("workspace"))
+        .and_then(|w| w.get("dependencies"));
+
+    let mut tables: Vec<&toml::Value> = member_manifest.get("dependencies").into_iter().collect();
+    if let Some(targets) = member_manifest.get("target").and_then(|t| t.as_table()) {
+        tables.extend(
+            targets
+                .values()
+                .filter_map(|target| target.get("dependencies")),
+        );
+    }
+
+    let mut paths = Vec::new();
+    for (name, dependency) in tables.iter().filter_map(|t| t.as_table()).flatten() {
+        let from_workspace = dependency.get("workspace").and_then(|w| w.as_bool()) == Some(true);
+        let path = if from_workspace {
+            workspace_dependencies
+                .and_then(|deps| deps.get(name))
+                .and_then(|dep| dep.get("path"))
+                .and_then(|path| path.as_str())
+                .map(PathBuf::from)
+        } else {
+            dependency
+                .get("path")
+                .and_then(|path| path.as_str())
+                .map(|path| Path::new(member).join(path))
+        };
+        paths.extend(path.map(|path| normalize(&path)));
+    }
+    paths
+}
+
+/// Resolve `.` and `..` components without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Rate limit for checking linked crates for changes
+#[derive(Debug, Default)]
+pub struct LinkChecks {
+    last_checked: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl LinkChecks {
+    /// Whether a linked crate is due to be checked, counting this call as a check if so
+    pub fn due(&self, name: &str, version: &str) -> bool {
+        let mut last_checked = self.last_checked.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (name.to_string(), version.to_string());
+        if last_checked
+            .get(&key)
+            .is_some_and(|checked| checked.elapsed() < LINK_CHECK_INTERVAL)
+        {
+            return false;
+        }
+        last_checked.insert(key, Instant::now());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::utils::test_support::write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rescan_detects_content_changes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = &temp_dir.path().join("demo");
+        write(root, "Cargo.toml", "[package]\nname = \"demo\"\n");
+        write(root, "src/lib.rs", "pub fn a() {}");
+        write(root, "src/old.rs", "");
+        write(root, "target/debug/out", "ignored");
+        write(root, ".gitignore", "*.log\n");
+        write(root, "build.log", "ignored");
+
+        let state = LinkState::capture(&root.to_string_lossy())?;
+        assert_eq!(state.rescan()?.1, LinkChanges::default());
+
+        // Rewriting a file with the same content is not a change
+        write(root, "src/lib.rs", "pub fn a() {}");
+        write(root, "target/debug/out", "still ignored");
+        write(root, "build.log", "still ignored");
+        assert!(state.rescan()?.1.is_empty());
+
+        write(root, "src/lib.rs", "pub fn b() {}");
+        write(root, "src/new.rs", "");
+        fs::remove_file(root.join("src/old.rs"))?;
+        let (state, changes) = state.rescan()?;
+        assert_eq!(
+            changes.changed,
+            [PathBuf::from("src/lib.rs"), PathBuf::from("src/new.rs")]
+        );
+        assert_eq!(changes.removed, [PathBuf::from("src/old.rs")]);
+
+        let cached = temp_dir.path().join("cached");
+        write(&cached, "src/old.rs", "");
+        changes.apply(root, &cached)?;
+        assert_eq!(
+            fs::read_to_string(cached.join("src/lib.rs"))?,
+            "pub fn b() {}"
+        );
+        assert!(!cached.join("src/old.rs").exists());
+
+        assert!(state.rescan()?.1.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_affected_members_follow_path_dependencies() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\"]\n\n[workspace.dependencies]\ncore = { path = \"crates/core\" }\n",
+        );
+        write(
+            root,
+            "crates/core/Cargo.toml",
+            "[package]\nname = \"core\"\n",
+        );
+        write(
+            root,
+            "crates/api/Cargo.toml",
+            "[package]\nname = \"api\"\n\n[dependencies]\ncore.workspace = true\n",
+        );
+        write(
+            root,
+            "crates/cli/Cargo.toml",
+            "[package]\nname = \"cli\"\n\n[dependencies]\napi = { path = \"../api\" }\n",
+        );
+        write(
+            root,
+            "crates/other/Cargo.toml",
+            "[package]\nname = \"other\"\n",
+        );
+        let members: Vec<String> = ["crates/core", "crates/api", "crates/cli", "crates/other"]
+            .map(String::from)
+            .to_vec();
+
+        let changes = |path: &str| LinkChanges {
+            changed: vec![PathBuf::from(path)],
+            removed: Vec::new(),
+        };
+        assert_eq!(
+            affected_members(root, &members, &changes("crates/core/src/lib.rs")),
+            ["crates/core", "crates/api", "crates/cli"]
+        );
+        assert_eq!(
+            affected_members(root, &members, &changes("crates/other/src/lib.rs")),
+            ["crates/other"]
+        );
+        assert_eq!(
+            affected_members(root, &members, &changes("Cargo.lock")),
+            members
+        );
+    }
+
+    #[test]
+    fn test_affected_members_include_root_package() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[package]\nname = \"app\"\n\n[dependencies]\ncore = { path = \"crates/core\" }\n\n\
+             [workspace]\nmembers = [\"crates/*\"]\n",
+        );
+        write(
+            root,
+            "crates/core/Cargo.toml",
+            "[package]\nname = \"core\"\n",
+        );
+        write(
+            root,
+            "crates/plugin/Cargo.toml",
+            "[package]\nname = \"plugin\"\n\n[dependencies]\napp = { path = \"../..\" }\n",
+        );
+        write(
+            root,
+            "crates/other/Cargo.toml",
+            "[package]\nname = \"other\"\n",
+        );
+        let members: Vec<String> = ["crates/core", "crates/other", "crates/plugin", "."]
+            .map(String::from)
+            .to_vec();
+
+        let changes = |path: &str| LinkChanges {
+            changed: vec![PathBuf::from(path)],
+            removed: Vec::new(),
+        };
+        // Edits inside an unrelated member leave the root package alone
+        assert_eq!(
+            affected_members(root, &members, &changes("crates/other/src/lib.rs")),
+            ["crates/other"]
+        );
+        // The root package depends on core and plugin depends on the root package
+        assert_eq!(
+            affected_members(root, &members, &changes("crates/core/src/lib.rs")),
+            ["crates/core", "crates/plugin", "."]
+        );
+        assert_eq!(
+            affected_members(root, &members, &changes("src/main.rs")),
+            ["crates/plugin", "."]
+        );
+        assert_eq!(
+            affected_members(root, &members, &changes("Cargo.toml")),
+            members
+        );
+    }
+}
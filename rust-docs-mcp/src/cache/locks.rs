@@ -0,0 +1,239 @@
+//! Per crate-version locking for cache operations
+//!
+//! Instead of serializing every tool call behind one global lock, each cached
+//! crate version gets its own async lock. Operations that download, build or
+//! remove an entry take that lock, while queries against entries that are
+//! already complete never wait. Callers re-check the cache after acquiring the
+//! lock, so concurrent identical builds are de-duplicated: the first caller
+//! builds, the others wait and then reuse its result.
+//!
+//! Several `rust-docs-mcp` processes may share one cache directory, so the
+//! in-process lock is backed by an advisory file lock in `locks/`, which
+//! coordinates with other processes as well.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// Guard holding the lock of a single crate version
+///
+/// Both the in-process and the cross-process lock are released when dropped.
+#[derive(Debug)]
+pub struct CrateLockGuard {
+    _file: File,
+    _guard: OwnedMutexGuard<()>,
+}
+
+/// Registry of async locks keyed by crate name and version
+#[derive(Debug, Clone)]
+pub struct CrateLocks {
+    lock_dir: PathBuf,
+    locks: Arc<Mutex<HashMap<String, Weak<AsyncMutex<()>>>>>,
+}
+
+impl CrateLocks {
+    /// Create an empty lock registry storing its lock files in `lock_dir`
+    pub fn new(lock_dir: PathBuf) -> Self {
+        Self {
+            lock_dir,
+            locks: Arc::default(),
+        }
+    }
+
+    /// Acquire the lock for a crate version, waiting for any operation in progress
+    /// in this or another process
+    pub async fn lock(&self, name: &str, version: &str) -> Result<CrateLockGuard> {
+        let key = lock_key(name, version);
+        let guard = self.entry(&key).lock_owned().await;
+
+        let path = self.lock_dir.join(format!("{key}.lock"));
+        let file = tokio::task::spawn_blocking(move || lock_file(&path))
+            .await
+            .context("Lock task panicked")??;
+
+        Ok(CrateLockGuard {
+            _file: file,
+            _guard: guard,
+        })
+    }
+
+    /// Acquire the lock for a crate version without waiting
+    ///
+    /// Returns `None` if an operation on the crate is in progress in this or another process.
+    pub fn try_lock(&self, name: &str, version: &str) -> Result<Option<CrateLockGuard>> {
+        let key = lock_key(name, version);
+        let Ok(guard) = self.entry(&key).try_lock_owned() else {
+            return Ok(None);
+        };
+
+        let path = self.lock_dir.join(format!("{key}.lock"));
+        Ok(try_lock_file(&path)?.map(|file| CrateLockGuard {
+            _file: file,
+            _guard: guard,
+        }))
+    }
+
+    /// Get or create the lock for a crate version
+    fn entry(&self, key: &str) -> Arc<AsyncMutex<()>> {
+        let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Drop entries nobody holds anymore so the map does not grow forever
+        locks.retain(|_, lock| lock.strong_count() > 0);
+
+        if let Some(lock) = locks.get(key).and_then(Weak::upgrade) {
+            return lock;
+        }
+
+        let lock = Arc::new(AsyncMutex::new(()));
+        locks.insert(key.to_string(), Arc::downgrade(&lock));
+        lock
+    }
+}
+
+/// Build the key of a crate version, usable as a file name
+fn lock_key(name: &str, version: &str) -> String {
+    // GitHub versions are branch or tag names, which may contain slashes
+    format!("{name}-{version}").replace(['/', '\\'], "_")
+}
+
+/// Open a lock file and take an exclusive lock on it, blocking until it is available
+pub(crate) fn lock_file(path: &Path) -> Result<File> {
+    let file = open_lock_file(path)?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            tracing::info!("Waiting for another process to release {}", path.display());
+            file.lock()
+                .with_context(|| format!("Failed to lock {}", path.display()))?;
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+        }
+    }
+
+    Ok(file)
+}
+
+/// Take an exclusive lock on a lock file without waiting, `None` if it is held elsewhere
+pub(crate) fn try_lock_file(path: &Path) -> Result<Option<File>> {
+    let file = open_lock_file(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+        }
+    }
+}
+
+/// Open or create a lock file and its parent directory
+fn open_lock_file(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open lock file: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_same_crate_is_serialized() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let locks = CrateLocks::new(temp_dir.path().to_path_buf());
+        let guard = locks.lock("serde", "1.0.0").await?;
+
+        // A second lock on the same crate version must wait
+        let waiting = tokio::time::timeout(Duration::from_millis(50), locks.lock("serde", "1.0.0"));
+        assert!(waiting.await.is_err());
+
+        drop(guard);
+        let relocked =
+            tokio::time::timeout(Duration::from_millis(50), locks.lock("serde", "1.0.0"));
+        assert!(relocked.await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_different_crates_do_not_block() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let locks = CrateLocks::new(temp_dir.path().to_path_buf());
+        let _serde = locks.lock("serde", "1.0.0").await?;
+
+        let other_version =
+            tokio::time::timeout(Duration::from_millis(50), locks.lock("serde", "2.0.0"));
+        assert!(other_version.await.is_ok());
+
+        let other_crate =
+            tokio::time::timeout(Duration::from_millis(50), locks.lock("tokio", "1.0.0"));
+        assert!(other_crate.await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lock_file_excludes_other_registries() -> Result<()> {
+        // Two registries sharing a lock directory behave like two processes
+        // sharing a cache directory
+        let temp_dir = TempDir::new()?;
+        let first = CrateLocks::new(temp_dir.path().to_path_buf());
+        let second = CrateLocks::new(temp_dir.path().to_path_buf());
+
+        let guard = first.lock("serde", "1.0.0").await?;
+        assert!(temp_dir.path().join("serde-1.0.0.lock").exists());
+
+        let waiting = tokio::spawn(async move { second.lock("serde", "1.0.0").await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(guard);
+        let relocked = tokio::time::timeout(Duration::from_secs(5), waiting).await;
+        assert!(relocked.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_try_lock_skips_busy_crates() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let first = CrateLocks::new(temp_dir.path().to_path_buf());
+        let second = CrateLocks::new(temp_dir.path().to_path_buf());
+
+        let guard = first.lock("serde", "1.0.0").await?;
+        assert!(first.try_lock("serde", "1.0.0")?.is_none());
+        assert!(second.try_lock("serde", "1.0.0")?.is_none());
+        assert!(second.try_lock("tokio", "1.0.0")?.is_some());
+
+        drop(guard);
+        assert!(second.try_lock("serde", "1.0.0")?.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_released_locks_are_pruned() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let locks = CrateLocks::new(temp_dir.path().to_path_buf());
+        drop(locks.lock("serde", "1.0.0").await?);
+        drop(locks.lock("tokio", "1.0.0").await?);
+
+        assert!(locks.locks.lock().unwrap().len() <= 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_key_is_a_file_name() {
+        assert_eq!(lock_key("foo", "feature/bar"), "foo-feature_bar");
+    }
+}
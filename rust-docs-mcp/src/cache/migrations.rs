@@ -0,0 +1,392 @@
+//! Cache format versioning and migrations
+//!
+//! The layout of the cache directory is versioned by `cache-format.json` in its
+//! root. On startup, caches written in an older format are upgraded in place by
+//! running the migrations between their version and [`CACHE_FORMAT_VERSION`] in
+//! order, recording the new version after each one so an interrupted upgrade
+//! resumes where it stopped. Caches that were written by a newer version in a
+//! format this version can't read are refused.
+//!
+//! Caches created before the format was versioned have no `cache-format.json`
+//! and are treated as version 1. Read-only layers are never migrated; older
+//! layers are read through the fallbacks for older layouts, newer incompatible
+//! ones are refused.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::cache::locks::lock_file;
+use crate::cache::storage::{CacheStorage, DOCS_FILE_NAME, LEGACY_DOCS_FILE_NAME};
+use crate::cache::utils::{compress_atomic, write_atomic};
+use crate::cache::workspace::WorkspaceHandler;
+
+/// Current version of the cache layout
+pub const CACHE_FORMAT_VERSION: u32 = 4;
+
+/// Version of caches created before the format was recorded
+const UNVERSIONED_FORMAT_VERSION: u32 = 1;
+
+/// File name of the format description in the cache root
+pub const FORMAT_FILE_NAME: &str = "cache-format.json";
+
+/// Contents of `cache-format.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheFormat {
+    /// Version of the layout the cache was written in
+    pub version: u32,
+    /// Oldest format version whose readers can use the cache
+    pub min_reader_version: u32,
+    /// Version of rust-docs-mcp that last wrote the format
+    pub written_by: String,
+}
+
+impl CacheFormat {
+    fn new(version: u32) -> Self {
+        Self {
+            version,
+            min_reader_version: version,
+            written_by: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// An upgrade of the cache layout to the next format version
+struct Migration {
+    /// Format version after the migration
+    to: u32,
+    description: &'static str,
+    run: fn(&CacheStorage) -> Result<()>,
+}
+
+/// All migrations, ordered by version
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        to: 2,
+        description: "compress uncompressed documentation",
+        run: compress_legacy_docs,
+    },
+    Migration {
+        to: 3,
+        description: "record the disk usage breakdown of every crate",
+        run: record_sizes,
+    },
+    Migration {
+        to: 4,
+        description: "store workspace members under their package name",
+        run: key_members_by_package,
+    },
+];
+
+/// Check the format of the cache and its read-only layers, upgrading the cache if needed
+///
+/// Fails if the cache or a layer was written in a format this version can't read.
+pub fn prepare(storage: &CacheStorage) -> Result<()> {
+    for layer in storage.read_only_layers() {
+        match read_format(&layer.join(FORMAT_FILE_NAME))? {
+            Some(format) => {
+                check_compatible(&format, layer)?;
+                if format.version < CACHE_FORMAT_VERSION {
+                    tracing::warn!(
+                        "Read-only cache layer {} uses the older format version {}",
+                        layer.display(),
+                        format.version
+                    );
+                }
+            }
+            None => tracing::warn!(
+                "Read-only cache layer {} predates cache format versioning",
+                layer.display()
+            ),
+        }
+    }
+
+    let format_path = storage.format_path();
+    // Processes starting at the same time must not migrate concurrently
+    let _lock = lock_file(&format_path.with_extension("lock"))?;
+
+    let version = match read_format(&format_path)? {
+        Some(format) => {
+            check_compatible(&format, storage.cache_dir())?;
+            format.version
+        }
+        None if !writable_entries(storage)?.is_empty() => UNVERSIONED_FORMAT_VERSION,
+        // A new cache starts out in the current format
+        None => CACHE_FORMAT_VERSION,
+    };
+
+    for migration in MIGRATIONS.iter().filter(|m| m.to > version) {
+        tracing::info!(
+            "Migrating cache to format version {}: {}",
+            migration.to,
+            migration.description
+        );
+        (migration.run)(storage).with_context(|| {
+            format!("Failed to migrate cache to format version {}", migration.to)
+        })?;
+        write_format(&format_path, &CacheFormat::new(migration.to))?;
+    }
+
+    if !format_path.exists() {
+        write_format(&format_path, &CacheFormat::new(CACHE_FORMAT_VERSION))?;
+    }
+    Ok(())
+}
+
+/// Fail if a cache was written in a format this version can't read
+fn check_compatible(format: &CacheFormat, cache_dir: &Path) -> Result<()> {
+    if format.min_reader_version > CACHE_FORMAT_VERSION {
+        bail!(
+            "The cache at {} was written by rust-docs-mcp {} in format version {}, which this \
+             version ({}, format version {}) can't read. Upgrade rust-docs-mcp or use a \
+             different cache directory.",
+            cache_dir.display(),
+            format.written_by,
+            format.version,
+            env!("CARGO_PKG_VERSION"),
+            CACHE_FORMAT_VERSION
+        );
+    }
+    Ok(())
+}
+
+fn read_format(path: &Path) -> Result<Option<CacheFormat>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let format =
+        serde_json::from_str(&json).with_context(|| format!("Invalid {}", path.display()))?;
+    Ok(Some(format))
+}
+
+fn write_format(path: &Path, format: &CacheFormat) -> Result<()> {
+    write_atomic(path, serde_json::to_string_pretty(format)?.as_bytes())
+}
+
+/// Get the cache entries a migration upgrades, skipping those of read-only layers
+fn writable_entries(storage: &CacheStorage) -> Result<Vec<(String, String)>> {
+    let mut entries = storage.list_entries()?;
+    entries.retain(|(name, version)| !storage.is_read_only(name, version));
+    Ok(entries)
+}
+
+/// Version 2: documentation is stored gzip-compressed
+///
+/// Replaces the plain `docs.json` of crates and workspace members with `docs.json.gz`.
+fn compress_legacy_docs(storage: &CacheStorage) -> Result<()> {
+    for (name, version) in writable_entries(storage)? {
+        let mut dirs = vec![storage.crate_path(&name, &version)];
+        for member in storage.list_workspace_members(&name, &version)? {
+            dirs.push(storage.member_path(&name, &version, &member));
+        }
+
+        for dir in dirs {
+            let legacy = dir.join(LEGACY_DOCS_FILE_NAME);
+            if !legacy.exists() {
+                continue;
+            }
+            let compressed = dir.join(DOCS_FILE_NAME);
+            if !compressed.exists() {
+                compress_atomic(&legacy, &compressed)?;
+            }
+            fs::remove_file(&legacy)
+                .with_context(|| format!("Failed to remove {}", legacy.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Version 3: metadata records the disk usage split into source, docs and build artifacts
+fn record_sizes(storage: &CacheStorage) -> Result<()> {
+    for (name, version) in writable_entries(storage)? {
+        // Entries without metadata are reported by `verify`, there is nothing to update
+        if storage.metadata_path(&name, &version).exists() {
+            storage.update_sizes(&name, &version)?;
+        }
+    }
+    Ok(())
+}
+
+/// Version 4: workspace members are stored under their package name with a `member.json`
+///
+/// Members used to be stored under the last segment of their path. Directories
+/// whose segment matches several members can't be attributed, as these members
+/// overwrote each other's docs, and are removed to be regenerated on use.
+/// Entries whose workspace can't be resolved are left as they are.
+fn key_members_by_package(storage: &CacheStorage) -> Result<()> {
+    for (name, version) in writable_entries(storage)? {
+        let legacy: Vec<String> = storage
+            .list_workspace_members(&name, &version)?
+            .into_iter()
+            .filter(|member| !storage.member_info_path(&name, &version, member).exists())
+            .collect();
+        if legacy.is_empty() {
+            continue;
+        }
+
+        let workspace = match WorkspaceHandler::resolve(&storage.source_path(&name, &version)) {
+            Ok(Some(workspace)) => workspace,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!(
+                    "Leaving the members of {}-{} as they are, their workspace can't be resolved: {}",
+                    name,
+                    version,
+                    e
+                );
+                continue;
+            }
+        };
+
+        for dir_name in legacy {
+            let dir = storage.member_path(&name, &version, &dir_name);
+            let candidates: Vec<_> = workspace
+                .members
+                .iter()
+                .filter(|m| m.path.rsplit('/').next() == Some(dir_name.as_str()))
+                .collect();
+            let member = match candidates.as_slice() {
+                [member] => *member,
+                // Not a member anymore, `verify` reports the directory
+                [] => continue,
+                _ => {
+                    fs::remove_dir_all(&dir)
+                        .with_context(|| format!("Failed to remove {}", dir.display()))?;
+                    continue;
+                }
+            };
+
+            let target = storage.member_path(&name, &version, &member.name);
+            if target != dir {
+                if target.exists() {
+                    continue;
+                }
+                fs::rename(&dir, &target).with_context(|| {
+                    format!("Failed to move {} to {}", dir.display(), target.display())
+                })?;
+            }
+            storage.save_member_info(&name, &version, member)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_new_cache_gets_current_format() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf()))?;
+        prepare(&storage)?;
+
+        let format = read_format(&storage.format_path())?.unwrap();
+        assert_eq!(format.version, CACHE_FORMAT_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unversioned_cache_is_migrated() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf()))?;
+        let entry_dir = storage.crate_path("serde", "1.0.0");
+        let member_dir = storage.member_path("serde", "1.0.0", "serde_derive");
+        fs::create_dir_all(storage.source_path("serde", "1.0.0"))?;
+        fs::create_dir_all(&member_dir)?;
+        fs::write(entry_dir.join(LEGACY_DOCS_FILE_NAME), r#"{"root": 0}"#)?;
+        fs::write(member_dir.join(LEGACY_DOCS_FILE_NAME), r#"{"root": 1}"#)?;
+        fs::write(
+            storage.metadata_path("serde", "1.0.0"),
+            r#"{"name": "serde", "version": "1.0.0", "cached_at": "2024-01-01T00:00:00Z",
+                "doc_generated": true, "size_bytes": 0}"#,
+        )?;
+
+        prepare(&storage)?;
+
+        for dir in [&entry_dir, &member_dir] {
+            assert!(!dir.join(LEGACY_DOCS_FILE_NAME).exists());
+            assert!(dir.join(DOCS_FILE_NAME).exists());
+        }
+        let metadata = storage.load_metadata("serde", "1.0.0")?;
+        assert!(metadata.size_bytes > 0);
+        assert!(metadata.sizes.docs_bytes > 0);
+        assert_eq!(
+            read_format(&storage.format_path())?.unwrap().version,
+            CACHE_FORMAT_VERSION
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_members_are_keyed_by_package() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf()))?;
+        let source = storage.source_path("mono", "1.0.0");
+        fs::create_dir_all(&source)?;
+        fs::write(
+            source.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\", \"libs/core\"]\n",
+        )?;
+        for (path, package) in [
+            ("crates/api", "mono-api"),
+            ("crates/core", "mono-core"),
+            ("libs/core", "libs-core"),
+        ] {
+            fs::create_dir_all(source.join(path).join("src"))?;
+            fs::write(
+                source.join(path).join("Cargo.toml"),
+                format!("[package]\nname = \"{package}\"\nversion = \"1.0.0\"\n"),
+            )?;
+            fs::write(source.join(path).join("src").join("lib.rs"), "")?;
+        }
+        for dir in ["api", "core"] {
+            let member_dir = storage.member_path("mono", "1.0.0", dir);
+            fs::create_dir_all(&member_dir)?;
+            fs::write(member_dir.join(DOCS_FILE_NAME), "docs")?;
+        }
+        write_format(&storage.format_path(), &CacheFormat::new(3))?;
+
+        prepare(&storage)?;
+
+        // `core` can't be told apart between crates/core and libs/core
+        assert_eq!(
+            storage.list_workspace_members("mono", "1.0.0")?,
+            ["mono-api"]
+        );
+        let member = storage.find_member("mono", "1.0.0", "crates/api").unwrap();
+        assert_eq!(member.name, "mono-api");
+        assert!(
+            storage
+                .member_path("mono", "1.0.0", "mono-api")
+                .join(DOCS_FILE_NAME)
+                .exists()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_refuses_newer_incompatible_format() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf()))?;
+
+        // A newer format that older readers can still use is accepted as it is
+        let compatible = CacheFormat {
+            version: CACHE_FORMAT_VERSION + 1,
+            min_reader_version: CACHE_FORMAT_VERSION,
+            written_by: "99.0.0".to_string(),
+        };
+        write_format(&storage.format_path(), &compatible)?;
+        prepare(&storage)?;
+        assert_eq!(read_format(&storage.format_path())?, Some(compatible));
+
+        let incompatible = CacheFormat::new(CACHE_FORMAT_VERSION + 1);
+        write_format(&storage.format_path(), &incompatible)?;
+        assert!(prepare(&storage).is_err());
+        Ok(())
+    }
+}
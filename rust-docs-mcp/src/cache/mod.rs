@@ -5,25 +5,54 @@
 //! ## Key Components
 //!
 //! - [`service`] - Main caching service that coordinates all cache operations
+//! - [`bundle`] - Portable archives of cached crates for export and import
+//! - [`config`] - Runtime configuration for the cache
 //! - [`storage`] - Low-level storage operations for cached crates
 //! - [`downloader`] - Downloads crates from various sources (crates.io, GitHub, local)
 //! - [`docgen`] - Generates JSON documentation using cargo rustdoc
+//! - [`filter`] - Selection of the files of local sources worth caching
+//! - [`gc`] - Garbage collection of least recently used crates within a quota
+//! - [`link`] - Linked local crates kept in sync with their original directory
+//! - [`locks`] - Per crate-version locks for concurrent cache operations
+//! - [`migrations`] - Cache format version and in-place upgrades of older caches
+//! - [`prefetch`] - Background caching of a crate's dependencies
+//! - [`process`] - Async command execution with timeouts and process-tree cleanup
+//! - [`projects`] - Registered projects whose lockfiles protect crates from eviction
+//! - [`sandbox`] - Isolated execution of cargo commands for untrusted crates
+//! - [`search_index`] - Persistent search index over all cached crates
 //! - [`source`] - Source type detection and parsing (crates.io, GitHub, local paths)
+//! - [`target_dir`] - Target directories shared between documentation builds
 //! - [`tools`] - MCP tool implementations for cache operations
 //! - [`transaction`] - Transactional updates with automatic rollback
 //! - [`types`] - Type definitions for improved type safety
 //! - [`utils`] - Common utilities including response formatting
+//! - [`verify`] - Integrity checks and repairs of cache entries
 //! - [`workspace`] - Workspace crate handling
 
+pub mod bundle;
+pub mod config;
 pub mod docgen;
 pub mod downloader;
+pub mod filter;
+pub mod gc;
+pub mod link;
+pub mod locks;
+pub mod migrations;
+pub mod prefetch;
+pub mod process;
+pub mod projects;
+pub mod sandbox;
+pub mod search_index;
 pub mod service;
 pub mod source;
 pub mod storage;
+pub mod target_dir;
 pub mod tools;
 pub mod transaction;
 pub mod types;
 pub mod utils;
+pub mod verify;
 pub mod workspace;
 
+pub use config::CacheConfig;
 pub use service::CrateCache;
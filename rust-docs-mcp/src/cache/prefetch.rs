@@ -0,0 +1,314 @@
+//! Background prefetching of a cached crate's dependencies
+//!
+//! Agents following a type into a dependency need that dependency's docs too.
+//! A prefetch walks the resolved dependency graph stored in a crate's
+//! `dependencies.json` and caches the docs of every reachable crates.io package
+//! at its resolved version, one at a time in the background. The progress of the
+//! latest prefetch of each crate version is kept in memory for status queries.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use crate::deps::graph::{DependencyGraph, DependencyKind};
+
+/// Source ids cargo uses for crates.io, through the git and the sparse index
+const CRATES_IO_SOURCES: [&str; 2] = [
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// Which dependencies a prefetch follows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefetchOptions {
+    /// Maximum distance from the crate, 1 for direct dependencies only, `None` for all
+    pub max_depth: Option<usize>,
+    /// Kinds of dependencies to follow
+    pub kinds: Vec<DependencyKind>,
+}
+
+/// A package reachable from a crate through its dependencies
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PrefetchTarget {
+    pub name: String,
+    pub version: String,
+    /// Distance from the crate, 1 for direct dependencies
+    pub depth: usize,
+    /// Where cargo resolved the package from
+    #[serde(skip)]
+    pub source: String,
+}
+
+impl PrefetchTarget {
+    /// Whether the package can be downloaded from crates.io
+    pub fn is_crates_io(&self) -> bool {
+        is_crates_io_source(&self.source)
+    }
+}
+
+/// Whether a package source of `cargo metadata` is crates.io
+pub fn is_crates_io_source(source: &str) -> bool {
+    CRATES_IO_SOURCES.contains(&source)
+}
+
+/// Find the packages reachable from the local package `root`
+///
+/// Packages are returned in breadth-first order, each once at its smallest depth.
+/// Path dependencies, like other members of the same workspace, are walked
+/// through but not returned, their sources are already cached.
+pub fn dependency_targets(
+    graph: &DependencyGraph,
+    root: &str,
+    options: &PrefetchOptions,
+) -> Result<Vec<PrefetchTarget>> {
+    let root = graph
+        .local_package(root)
+        .with_context(|| format!("Package {root} not found in dependency metadata"))?;
+
+    let mut targets = Vec::new();
+    let mut seen = HashSet::from([root]);
+    let mut queue = VecDeque::from([(root, 0)]);
+    while let Some((index, depth)) = queue.pop_front() {
+        if options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            continue;
+        }
+        for edge in graph.dependencies(index) {
+            if !edge.has_kind(&options.kinds) || !seen.insert(edge.to) {
+                continue;
+            }
+
+            let package = graph.package(edge.to);
+            if let Some(source) = &package.source {
+                targets.push(PrefetchTarget {
+                    name: package.name.clone(),
+                    version: package.version.clone(),
+                    depth: depth + 1,
+                    source: source.clone(),
+                });
+            }
+            queue.push_back((edge.to, depth + 1));
+        }
+    }
+    Ok(targets)
+}
+
+/// Progress of one dependency of a prefetch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum PrefetchStatus {
+    Pending,
+    Caching,
+    Cached,
+    Skipped { reason: String },
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefetchEntry {
+    #[serde(flatten)]
+    pub target: PrefetchTarget,
+    #[serde(flatten)]
+    pub status: PrefetchStatus,
+}
+
+/// Progress of the latest prefetch of a crate version
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefetchJob {
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished: bool,
+    pub dependencies: Vec<PrefetchEntry>,
+}
+
+impl PrefetchJob {
+    /// Number of dependencies in each status
+    pub fn summary(&self) -> HashMap<&'static str, usize> {
+        let mut summary = HashMap::new();
+        for entry in &self.dependencies {
+            let status = match entry.status {
+                PrefetchStatus::Pending => "pending",
+                PrefetchStatus::Caching => "caching",
+                PrefetchStatus::Cached => "cached",
+                PrefetchStatus::Skipped { .. } => "skipped",
+                PrefetchStatus::Failed { .. } => "failed",
+            };
+            *summary.entry(status).or_default() += 1;
+        }
+        summary
+    }
+}
+
+/// Prefetches of all crate versions, by `name-version`
+#[derive(Debug, Default)]
+pub struct PrefetchJobs {
+    jobs: Mutex<HashMap<String, PrefetchJob>>,
+}
+
+impl PrefetchJobs {
+    /// Record a new prefetch, replacing the record of a finished one
+    ///
+    /// Dependencies not on crates.io are skipped right away. Returns `false`
+    /// without recording anything while an earlier prefetch is still running.
+    pub fn start(&self, key: &str, targets: Vec<PrefetchTarget>) -> bool {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if jobs.get(key).is_some_and(|job| !job.finished) {
+            return false;
+        }
+
+        let dependencies = targets
+            .into_iter()
+            .map(|target| {
+                let status = if target.is_crates_io() {
+                    PrefetchStatus::Pending
+                } else {
+                    PrefetchStatus::Skipped {
+                        reason: format!("Not a crates.io package: {}", target.source),
+                    }
+                };
+                PrefetchEntry { target, status }
+            })
+            .collect();
+        let job = PrefetchJob {
+            started_at: chrono::Utc::now(),
+            finished: false,
+            dependencies,
+        };
+        jobs.insert(key.to_string(), job);
+        true
+    }
+
+    /// Dependencies of a prefetch that still have to be cached
+    pub fn pending(&self, key: &str) -> Vec<(usize, PrefetchTarget)> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.get(key)
+            .into_iter()
+            .flat_map(|job| job.dependencies.iter().enumerate())
+            .filter(|(_, entry)| entry.status == PrefetchStatus::Pending)
+            .map(|(index, entry)| (index, entry.target.clone()))
+            .collect()
+    }
+
+    pub fn set_status(&self, key: &str, index: usize, status: PrefetchStatus) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = jobs
+            .get_mut(key)
+            .and_then(|job| job.dependencies.get_mut(index))
+        {
+            entry.status = status;
+        }
+    }
+
+    pub fn finish(&self, key: &str) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.get_mut(key) {
+            job.finished = true;
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<PrefetchJob> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.get(key).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deps::graph::test_support::{CRATES_IO, dep, package};
+
+    /// `app` depends on `hyper` and, through the path dependency `util`, on
+    /// `bytes`; `hyper` depends on `http`, which depends on `bytes` too
+    fn graph() -> DependencyGraph {
+        let metadata = serde_json::json!({
+            "packages": [
+                package("app", "app", "0.1.0", None),
+                package("util", "util", "0.1.0", None),
+                package("hyper", "hyper", "1.4.1", Some(CRATES_IO)),
+                package("http", "http", "1.1.0", Some(CRATES_IO)),
+                package("bytes", "bytes", "1.7.1", Some(CRATES_IO)),
+                package("cc", "cc", "1.1.0", Some(CRATES_IO)),
+                package("tokio-test", "tokio-test", "0.4.4", Some("git+https://example.com/tokio")),
+            ],
+            "resolve": {
+                "nodes": [
+                    { "id": "app", "deps": [
+                        dep("hyper", "hyper", None),
+                        dep("util", "util", None),
+                        dep("cc", "cc", Some("build")),
+                        dep("tokio-test", "tokio-test", Some("dev")),
+                    ]},
+                    { "id": "util", "deps": [dep("bytes", "bytes", None)] },
+                    { "id": "hyper", "deps": [dep("http", "http", None)] },
+                    { "id": "http", "deps": [dep("bytes", "bytes", None)] },
+                    { "id": "bytes", "deps": [] },
+                    { "id": "cc", "deps": [] },
+                    { "id": "tokio-test", "deps": [] },
+                ]
+            }
+        });
+        DependencyGraph::from_metadata(&metadata).unwrap()
+    }
+
+    fn names(targets: &[PrefetchTarget]) -> Vec<(&str, usize)> {
+        targets
+            .iter()
+            .map(|target| (target.name.as_str(), target.depth))
+            .collect()
+    }
+
+    #[test]
+    fn test_dependency_targets() -> Result<()> {
+        let normal = PrefetchOptions {
+            max_depth: None,
+            kinds: vec![DependencyKind::Normal],
+        };
+        let targets = dependency_targets(&graph(), "app", &normal)?;
+        assert_eq!(names(&targets), [("hyper", 1), ("http", 2), ("bytes", 2)]);
+
+        let direct = PrefetchOptions {
+            max_depth: Some(1),
+            kinds: vec![
+                DependencyKind::Normal,
+                DependencyKind::Build,
+                DependencyKind::Dev,
+            ],
+        };
+        let targets = dependency_targets(&graph(), "app", &direct)?;
+        assert_eq!(
+            names(&targets),
+            [("hyper", 1), ("cc", 1), ("tokio-test", 1)]
+        );
+        assert!(!targets[2].is_crates_io());
+
+        assert!(dependency_targets(&graph(), "missing", &normal).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jobs_track_progress() -> Result<()> {
+        let jobs = PrefetchJobs::default();
+        let options = PrefetchOptions {
+            max_depth: Some(1),
+            kinds: vec![DependencyKind::Normal, DependencyKind::Dev],
+        };
+        let targets = dependency_targets(&graph(), "app", &options)?;
+        assert!(jobs.start("app-0.1.0", targets.clone()));
+        assert!(!jobs.start("app-0.1.0", targets.clone()));
+
+        // The git dependency is skipped right away
+        let pending = jobs.pending("app-0.1.0");
+        assert_eq!(pending.len(), 1);
+        jobs.set_status("app-0.1.0", pending[0].0, PrefetchStatus::Cached);
+        jobs.finish("app-0.1.0");
+
+        let job = jobs.get("app-0.1.0").unwrap();
+        assert!(job.finished);
+        assert_eq!(job.summary()["cached"], 1);
+        assert_eq!(job.summary()["skipped"], 1);
+        assert!(jobs.start("app-0.1.0", targets));
+        Ok(())
+    }
+}
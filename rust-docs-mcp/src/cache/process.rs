@@ -0,0 +1,101 @@
+//! Asynchronous execution of external commands
+//!
+//! Documentation builds can take minutes. This module runs them on the tokio
+//! runtime with an optional timeout, and makes sure the whole process tree is
+//! killed when the build times out or the calling future is dropped (for example
+//! because the MCP request was cancelled).
+
+use anyhow::{Context, Result, bail};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+/// Run a command to completion, killing its process tree on timeout or cancellation
+pub async fn run_command(command: Command, timeout: Option<Duration>) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().to_string();
+
+    let mut command = tokio::process::Command::from(command);
+    // Never let a child read from our stdin, which carries the MCP transport
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    // Run the child in its own process group so that every process it spawns
+    // (rustc, build scripts, proc-macro servers) can be killed together
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let child = command
+        .spawn()
+        .with_context(|| format!("Failed to spawn {program}"))?;
+    let mut guard = ProcessTreeGuard::new(child.id());
+
+    let output = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => output,
+            Err(_) => bail!("{program} timed out after {} seconds", timeout.as_secs()),
+        },
+        None => child.wait_with_output().await,
+    }
+    .with_context(|| format!("Failed to wait for {program}"))?;
+
+    guard.disarm();
+    Ok(output)
+}
+
+/// Kills the process group of a child when dropped, unless disarmed
+struct ProcessTreeGuard {
+    pid: Option<u32>,
+}
+
+impl ProcessTreeGuard {
+    fn new(pid: Option<u32>) -> Self {
+        Self { pid }
+    }
+
+    /// The child exited normally, nothing needs to be killed
+    fn disarm(&mut self) {
+        self.pid = None;
+    }
+}
+
+impl Drop for ProcessTreeGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid.take() {
+            use nix::sys::signal::{Signal, killpg};
+            use nix::unistd::Pid;
+
+            tracing::warn!("Killing process group {} of an unfinished command", pid);
+            // The group may already be gone, which is fine
+            let _ = killpg(Pid::from_raw(pid as i32), Signal::SIGKILL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_command_success() -> Result<()> {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo hello"]);
+
+        let output = run_command(command, Some(Duration::from_secs(10))).await?;
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hello");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_command_timeout() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30"]);
+
+        let result = run_command(command, Some(Duration::from_millis(100))).await;
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("timed out"), "unexpected error: {error}");
+    }
+}
@@ -0,0 +1,219 @@
+//! Projects registered with the cache
+//!
+//! A registered project is a local Cargo project whose `Cargo.lock` keeps crates
+//! alive: garbage collection never evicts a crate version that a registered
+//! lockfile refers to. Registrations are stored in `projects.json` in the cache
+//! directory and shared by every process using it.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache::locks::lock_file;
+use crate::cache::utils::write_atomic;
+
+/// Contents of `projects.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProjectList {
+    /// Lockfiles of the registered projects
+    lockfiles: Vec<PathBuf>,
+}
+
+/// The parts of a `Cargo.lock` needed to find the crates it uses
+#[derive(Debug, Deserialize)]
+struct CargoLock {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+/// A package pinned by a `Cargo.lock`
+#[derive(Debug, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// Where the package comes from, `None` for packages of the project itself
+    pub source: Option<String>,
+}
+
+impl LockedPackage {
+    /// Whether the package is downloaded from crates.io
+    pub fn is_crates_io(&self) -> bool {
+        self.source.as_deref().is_some_and(|source| {
+            source == "registry+https://github.com/rust-lang/crates.io-index"
+                || source == "sparse+https://index.crates.io/"
+        })
+    }
+}
+
+/// Registry of the projects whose lockfiles protect crates from eviction
+#[derive(Debug, Clone)]
+pub struct ProjectRegistry {
+    path: PathBuf,
+}
+
+impl ProjectRegistry {
+    /// Create a registry stored in the given `projects.json`
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Lockfiles of all registered projects
+    pub fn lockfiles(&self) -> Result<Vec<PathBuf>> {
+        Ok(self.load()?.lockfiles)
+    }
+
+    /// Register a project by its directory or `Cargo.lock`, returning the lockfile path
+    pub fn register(&self, project: &Path) -> Result<PathBuf> {
+        let lockfile = resolve_lockfile(project)?;
+        if !lockfile.is_file() {
+            bail!(
+                "No Cargo.lock found at {}. Run `cargo generate-lockfile` in the project first.",
+                lockfile.display()
+            );
+        }
+
+        self.modify(|list| {
+            if !list.lockfiles.contains(&lockfile) {
+                list.lockfiles.push(lockfile.clone());
+            }
+        })?;
+        Ok(lockfile)
+    }
+
+    /// Unregister a project, returning whether it was registered
+    pub fn unregister(&self, project: &Path) -> Result<bool> {
+        let lockfile = resolve_lockfile(project)?;
+        let mut removed = false;
+        self.modify(|list| {
+            let before = list.lockfiles.len();
+            list.lockfiles.retain(|registered| registered != &lockfile);
+            removed = list.lockfiles.len() != before;
+        })?;
+        Ok(removed)
+    }
+
+    /// All crate versions referenced by the lockfiles of registered projects
+    ///
+    /// Lockfiles that can no longer be read are skipped with a warning, so a deleted
+    /// project stops protecting its crates.
+    pub fn locked_crates(&self) -> Result<HashSet<(String, String)>> {
+        let mut crates = HashSet::new();
+        for lockfile in self.lockfiles()? {
+            match read_lockfile(&lockfile) {
+                Ok(packages) => crates.extend(
+                    packages
+                        .into_iter()
+                        .map(|package| (package.name, package.version)),
+                ),
+                Err(e) => tracing::warn!("Ignoring registered project: {:#}", e),
+            }
+        }
+        Ok(crates)
+    }
+
+    fn load(&self) -> Result<ProjectList> {
+        if !self.path.exists() {
+            return Ok(ProjectList::default());
+        }
+        let json = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid {}", self.path.display()))
+    }
+
+    /// Update the project list while holding its lock, so concurrent processes don't
+    /// lose each other's registrations
+    fn modify(&self, update: impl FnOnce(&mut ProjectList)) -> Result<()> {
+        let _lock = lock_file(&self.path.with_extension("lock"))?;
+        let mut list = self.load()?;
+        update(&mut list);
+        write_atomic(&self.path, serde_json::to_string_pretty(&list)?.as_bytes())
+    }
+}
+
+/// Get the absolute lockfile path of a project given by its directory or lockfile
+pub fn resolve_lockfile(project: &Path) -> Result<PathBuf> {
+    let expanded = shellexpand::full(&project.to_string_lossy())
+        .with_context(|| format!("Failed to expand path: {}", project.display()))?
+        .into_owned();
+    let project = std::path::absolute(&expanded)
+        .with_context(|| format!("Invalid project path: {expanded}"))?;
+
+    if project.file_name().is_some_and(|name| name == "Cargo.lock") {
+        Ok(project)
+    } else {
+        Ok(project.join("Cargo.lock"))
+    }
+}
+
+/// Read the packages pinned by a `Cargo.lock`
+pub fn read_lockfile(path: &Path) -> Result<Vec<LockedPackage>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let lock: CargoLock =
+        toml::from_str(&content).with_context(|| format!("Invalid lockfile {}", path.display()))?;
+    Ok(lock.package)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const LOCKFILE: &str = r#"
+version = 4
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde"]
+
+[[package]]
+name = "serde"
+version = "1.0.219"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    #[test]
+    fn test_registered_lockfiles_protect_their_crates() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let project = temp_dir.path().join("app");
+        fs::create_dir_all(&project)?;
+        fs::write(project.join("Cargo.lock"), LOCKFILE)?;
+
+        let registry = ProjectRegistry::new(temp_dir.path().join("projects.json"));
+        assert!(registry.locked_crates()?.is_empty());
+
+        let lockfile = registry.register(&project)?;
+        assert_eq!(lockfile, project.join("Cargo.lock"));
+        // Registering the lockfile itself is the same registration
+        registry.register(&lockfile)?;
+        assert_eq!(registry.lockfiles()?.len(), 1);
+
+        let crates = registry.locked_crates()?;
+        assert!(crates.contains(&("serde".to_string(), "1.0.219".to_string())));
+        assert!(crates.contains(&("app".to_string(), "0.1.0".to_string())));
+
+        let packages = read_lockfile(&lockfile)?;
+        let from_crates_io: Vec<_> = packages.iter().map(|p| p.is_crates_io()).collect();
+        assert_eq!(from_crates_io, [false, true]);
+
+        // A deleted project no longer protects anything
+        fs::remove_file(&lockfile)?;
+        assert!(registry.locked_crates()?.is_empty());
+
+        assert!(registry.unregister(&project)?);
+        assert!(!registry.unregister(&project)?);
+        assert!(registry.lockfiles()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_requires_a_lockfile() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let registry = ProjectRegistry::new(temp_dir.path().join("projects.json"));
+        assert!(registry.register(temp_dir.path()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+//! Sandboxed execution of cargo commands for untrusted crates
+//!
+//! Generating documentation runs every build script and proc-macro of the crate
+//! being documented. This module wraps the cargo invocations made by the
+//! documentation generator so that crates from untrusted sources run with a
+//! scrubbed environment, no network access, a read-only view of the file system,
+//! a private, mostly read-only `CARGO_HOME`, and optional resource limits.
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// The kind of source a cached crate was obtained from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// Crates downloaded from crates.io
+    #[value(name = "cratesio")]
+    CratesIo,
+    /// Crates cloned from a GitHub repository
+    #[value(name = "github")]
+    GitHub,
+    /// Crates copied from a local file system path
+    #[value(name = "local")]
+    Local,
+}
+
+impl SourceKind {
+    /// Map the `source` field stored in crate metadata to a source kind
+    ///
+    /// Unknown values are treated as crates.io so that they are never trusted by accident,
+    /// as are the third-party sources `vendor`, `archive` and `bundle`.
+    pub fn from_metadata_source(source: &str) -> Self {
+        match source {
+            "github" => Self::GitHub,
+            "local" => Self::Local,
+            _ => Self::CratesIo,
+        }
+    }
+}
+
+/// Resource limits applied to sandboxed commands
+#[derive(Debug, Clone, Default)]
+pub struct SandboxLimits {
+    /// Maximum CPU time in seconds
+    pub cpu_seconds: Option<u64>,
+    /// Maximum address space in bytes
+    pub memory_bytes: Option<u64>,
+    /// Maximum wall-clock time, enforced by the caller when awaiting the command
+    pub wall_time: Option<Duration>,
+}
+
+/// Configuration deciding which sources are built inside the sandbox
+#[derive(Debug, Clone, Default)]
+pub struct SandboxConfig {
+    sandboxed_sources: HashSet<SourceKind>,
+    limits: SandboxLimits,
+}
+
+impl SandboxConfig {
+    /// Create a sandbox configuration for the given source kinds
+    pub fn new(
+        sandboxed_sources: impl IntoIterator<Item = SourceKind>,
+        limits: SandboxLimits,
+    ) -> Self {
+        Self {
+            sandboxed_sources: sandboxed_sources.into_iter().collect(),
+            limits,
+        }
+    }
+
+    /// Check whether crates from the given source must be built in the sandbox
+    pub fn is_sandboxed(&self, kind: SourceKind) -> bool {
+        self.sandboxed_sources.contains(&kind)
+    }
+
+    /// Get the configured resource limits
+    pub fn limits(&self) -> &SandboxLimits {
+        &self.limits
+    }
+}
+
+/// Directories a sandboxed command is allowed to see or modify
+#[derive(Debug, Clone)]
+pub struct SandboxMounts {
+    /// Directory the command runs in, mounted read-only
+    pub source_dir: PathBuf,
+    /// Directories mounted read-write (build output)
+    pub writable_dirs: Vec<PathBuf>,
+    /// Private `CARGO_HOME` used instead of the user's one, shared by all builds
+    pub cargo_home: PathBuf,
+    /// Private `HOME` directory, empty at the start of every build
+    pub home_dir: PathBuf,
+}
+
+/// Parts of the private cargo home a sandboxed build may write to
+///
+/// All sandboxed builds share one cargo home, so the rest of it, in particular
+/// the unpacked sources in `registry/src`, is mounted read-only: a build script
+/// must not be able to change what later builds of other crates compile. Only
+/// `cargo fetch`, which runs no crate code, fills it.
+#[cfg(target_os = "linux")]
+const CARGO_HOME_WRITABLE: [&str; 2] = ["registry/cache", ".package-cache"];
+
+impl SandboxMounts {
+    /// Create the writable parts of the cargo home so they can be mounted
+    #[cfg(target_os = "linux")]
+    fn writable_cargo_home_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for part in CARGO_HOME_WRITABLE {
+            let path = self.cargo_home.join(part);
+            if part.starts_with('.') {
+                std::fs::create_dir_all(&self.cargo_home)?;
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)?;
+            } else {
+                std::fs::create_dir_all(&path)?;
+            }
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+/// Builds cargo commands that run isolated from the host
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    limits: SandboxLimits,
+    mounts: SandboxMounts,
+}
+
+impl Sandbox {
+    /// Create a sandbox with the given limits and mounts
+    pub fn new(limits: SandboxLimits, mounts: SandboxMounts) -> Self {
+        Self { limits, mounts }
+    }
+
+    /// Build a command that fetches dependencies for the source directory
+    ///
+    /// Fetching only downloads and unpacks crates, it never executes crate code,
+    /// so it runs outside the isolation but still with a scrubbed environment and
+    /// the private cargo home. Every later build can then run offline.
+    ///
+    /// Cargo reads `.cargo/config.toml` from the directory it runs in and its
+    /// parents, which in the crate's source could replace sources or set up
+    /// credential providers and proxies. The fetch therefore runs in the private
+    /// cargo home, which builds can't modify, and is pointed at the manifest.
+    pub fn fetch_command(&self) -> Result<Command> {
+        std::fs::create_dir_all(&self.mounts.cargo_home)?;
+        let mut command = Command::new("cargo");
+        command
+            .args(["+nightly", "fetch", "--manifest-path"])
+            .arg(self.mounts.source_dir.join("Cargo.toml"))
+            .current_dir(&self.mounts.cargo_home);
+        self.apply_env(&mut command);
+        Ok(command)
+    }
+
+    /// Build an isolated cargo command with the given arguments (including any `+toolchain`)
+    pub fn cargo_command<I, S>(&self, args: I) -> Result<Command>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut inner: Vec<std::ffi::OsString> = Vec::new();
+
+        if self.limits.cpu_seconds.is_some() || self.limits.memory_bytes.is_some() {
+            if find_in_path("prlimit").is_some() {
+                inner.push("prlimit".into());
+                if let Some(cpu) = self.limits.cpu_seconds {
+                    inner.push(format!("--cpu={cpu}").into());
+                }
+                if let Some(memory) = self.limits.memory_bytes {
+                    inner.push(format!("--as={memory}").into());
+                }
+                inner.push("--".into());
+            } else {
+                tracing::warn!("prlimit not found, CPU and memory limits will not be enforced");
+            }
+        }
+
+        inner.push("cargo".into());
+        inner.extend(args.into_iter().map(|a| a.as_ref().to_os_string()));
+
+        let mut command = self.isolation_command(&inner)?;
+        command.current_dir(&self.mounts.source_dir);
+        self.apply_env(&mut command);
+        command.env("CARGO_NET_OFFLINE", "true");
+        Ok(command)
+    }
+
+    /// Wrap the inner command line in bubblewrap
+    #[cfg(target_os = "linux")]
+    fn isolation_command(&self, inner: &[std::ffi::OsString]) -> Result<Command> {
+        if let Some(bwrap) = find_in_path("bwrap") {
+            let mut command = Command::new(bwrap);
+            command.args(["--ro-bind", "/", "/"]);
+            command.args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]);
+
+            // Hide the user's home directory (credentials, ssh keys, cargo config)
+            // and re-expose only the toolchain.
+            if let Some(home) = dirs::home_dir() {
+                command.arg("--tmpfs").arg(&home);
+            }
+            for toolchain_dir in toolchain_dirs() {
+                command
+                    .arg("--ro-bind")
+                    .arg(&toolchain_dir)
+                    .arg(&toolchain_dir);
+            }
+
+            command
+                .arg("--ro-bind")
+                .arg(&self.mounts.source_dir)
+                .arg(&self.mounts.source_dir);
+            for dir in &self.mounts.writable_dirs {
+                std::fs::create_dir_all(dir)?;
+                command.arg("--bind").arg(dir).arg(dir);
+            }
+            // Every build starts with an empty home, so nothing written to it, like a
+            // `.gitconfig`, reaches later builds or the fetch running outside the sandbox
+            std::fs::create_dir_all(&self.mounts.home_dir)?;
+            command.arg("--tmpfs").arg(&self.mounts.home_dir);
+
+            let cargo_home = &self.mounts.cargo_home;
+            std::fs::create_dir_all(cargo_home)?;
+            command.arg("--ro-bind").arg(cargo_home).arg(cargo_home);
+            for path in self.mounts.writable_cargo_home_paths()? {
+                command.arg("--bind").arg(&path).arg(&path);
+            }
+
+            command.args([
+                "--unshare-net",
+                "--unshare-pid",
+                "--unshare-ipc",
+                "--die-with-parent",
+                "--new-session",
+            ]);
+            command.arg("--chdir").arg(&self.mounts.source_dir);
+            command.arg("--").args(inner);
+            return Ok(command);
+        }
+
+        // Other tools can't hide the home directory or mount the file system
+        // read-only, so anything less than bwrap would only pretend to isolate
+        bail!("Sandboxed builds require 'bwrap' (bubblewrap) to be installed");
+    }
+
+    /// Wrap the inner command line in bubblewrap
+    #[cfg(not(target_os = "linux"))]
+    fn isolation_command(&self, _inner: &[std::ffi::OsString]) -> Result<Command> {
+        bail!("Sandboxed builds are only supported on Linux");
+    }
+
+    /// Replace the inherited environment with a minimal one
+    fn apply_env(&self, command: &mut Command) {
+        command.env_clear();
+        for key in ["PATH", "LANG", "LC_ALL", "TERM"] {
+            if let Some(value) = std::env::var_os(key) {
+                command.env(key, value);
+            }
+        }
+        if let Some(rustup_home) = rustup_home() {
+            command.env("RUSTUP_HOME", rustup_home);
+        }
+        command
+            .env("HOME", &self.mounts.home_dir)
+            .env("CARGO_HOME", &self.mounts.cargo_home)
+            .env("TMPDIR", "/tmp");
+    }
+}
+
+/// Locate the rustup home directory of the current user
+fn rustup_home() -> Option<PathBuf> {
+    std::env::var_os("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".rustup")))
+}
+
+/// Directories holding the toolchain that must stay visible inside the sandbox
+#[cfg(target_os = "linux")]
+fn toolchain_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(rustup_home) = rustup_home().filter(|p| p.exists()) {
+        dirs.push(rustup_home);
+    }
+    if let Some(cargo_bin) = find_in_path("cargo").and_then(|p| p.parent().map(Path::to_path_buf)) {
+        dirs.push(cargo_bin);
+    }
+    dirs
+}
+
+/// Find an executable in the directories listed in `PATH`
+pub fn find_in_path(binary: &str) -> Option<PathBuf> {
+    let path_var = std::env::var_os("PATH")?;
+    std::env::split_paths(&path_var)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_kind_from_metadata() {
+        assert_eq!(SourceKind::from_metadata_source("local"), SourceKind::Local);
+        assert_eq!(
+            SourceKind::from_metadata_source("github"),
+            SourceKind::GitHub
+        );
+        assert_eq!(
+            SourceKind::from_metadata_source("crates.io"),
+            SourceKind::CratesIo
+        );
+        assert_eq!(
+            SourceKind::from_metadata_source("archive"),
+            SourceKind::CratesIo
+        );
+        assert_eq!(
+            SourceKind::from_metadata_source("bundle"),
+            SourceKind::CratesIo
+        );
+        // Unknown sources are never trusted implicitly
+        assert_eq!(
+            SourceKind::from_metadata_source("something-else"),
+            SourceKind::CratesIo
+        );
+    }
+
+    #[test]
+    fn test_sandbox_config() {
+        let config = SandboxConfig::new(
+            [SourceKind::CratesIo, SourceKind::GitHub],
+            SandboxLimits::default(),
+        );
+        assert!(config.is_sandboxed(SourceKind::CratesIo));
+        assert!(config.is_sandboxed(SourceKind::GitHub));
+        assert!(!config.is_sandboxed(SourceKind::Local));
+
+        // Default configuration trusts everything
+        let config = SandboxConfig::default();
+        assert!(!config.is_sandboxed(SourceKind::CratesIo));
+    }
+
+    #[test]
+    fn test_fetch_command_scrubs_environment() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let source_dir = temp_dir.path().join("crates/foo/1.0.0/source");
+        let cargo_home = temp_dir.path().join("sandbox/cargo-home");
+        let home_dir = temp_dir.path().join("sandbox/home");
+        let sandbox = Sandbox::new(
+            SandboxLimits::default(),
+            SandboxMounts {
+                source_dir: source_dir.clone(),
+                writable_dirs: vec![],
+                cargo_home: cargo_home.clone(),
+                home_dir: home_dir.clone(),
+            },
+        );
+        let command = sandbox.fetch_command()?;
+        let envs: Vec<_> = command.get_envs().collect();
+
+        assert!(envs.contains(&(OsStr::new("CARGO_HOME"), Some(cargo_home.as_os_str()))));
+        assert!(envs.contains(&(OsStr::new("HOME"), Some(home_dir.as_os_str()))));
+
+        // The crate's own `.cargo/config.toml` must not configure the fetch
+        assert_eq!(command.get_current_dir(), Some(cargo_home.as_path()));
+        let manifest = source_dir.join("Cargo.toml");
+        assert!(command.get_args().any(|arg| arg == manifest.as_os_str()));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_writable_cargo_home_paths() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let cargo_home = temp_dir.path().join("cargo-home");
+        let mounts = SandboxMounts {
+            source_dir: temp_dir.path().join("source"),
+            writable_dirs: vec![],
+            cargo_home: cargo_home.clone(),
+            home_dir: temp_dir.path().join("home"),
+        };
+
+        // A lock left by an earlier fetch is kept as it is
+        std::fs::create_dir_all(&cargo_home)?;
+        std::fs::write(cargo_home.join(".package-cache"), "lock")?;
+
+        let paths = mounts.writable_cargo_home_paths()?;
+        assert_eq!(
+            paths,
+            [
+                cargo_home.join("registry/cache"),
+                cargo_home.join(".package-cache")
+            ]
+        );
+        assert!(cargo_home.join("registry/cache").is_dir());
+        assert_eq!(
+            std::fs::read_to_string(cargo_home.join(".package-cache"))?,
+            "lock"
+        );
+        assert!(
+            !paths
+                .iter()
+                .any(|path| path.starts_with(cargo_home.join("registry/src")))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,859 @@
+//! Persistent search index over all cached crates
+//!
+//! Every cached crate version and workspace member with documentation gets a
+//! segment in `search/`, holding the name, path, kind and a summary of each item
+//! together with an inverted index of the words in its documentation. Segments
+//! are written when `CrateCache` finishes generating docs and deleted when an
+//! entry is removed, so the index is maintained incrementally and survives
+//! restarts: searching never has to parse a `docs.json`.
+//!
+//! Each segment records the fingerprint of the `docs.json` it was built from.
+//! [`SearchIndex::sync`] compares those against the cache and rebuilds or drops
+//! segments that no longer match, which also picks up entries added by other
+//! processes sharing the cache directory or cached before the index existed.
+//! The server syncs once at startup rather than on every search.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::storage::CacheStorage;
+use crate::cache::utils::write_atomic;
+use crate::docs::DocIndex;
+use crate::docs::cache::DocsFingerprint;
+use crate::docs::query::{ItemInfo, sort_by_relevance};
+
+/// File extension of segment files
+const SEGMENT_EXTENSION: &str = "json.gz";
+
+/// Maximum length of the documentation summary stored per item
+const SUMMARY_LEN: usize = 200;
+
+/// Memory budget for segments kept loaded between searches, measured as their JSON size
+const LOADED_SEGMENTS_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// A searchable item of a segment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchEntry {
+    pub id: u32,
+    pub name: String,
+    pub search_name: String,
+    pub kind: String,
+    pub path: Vec<String>,
+    pub visibility: String,
+    pub summary: Option<String>,
+}
+
+/// Searchable items of one crate version or workspace member
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchSegment {
+    pub crate_name: String,
+    pub version: String,
+    pub member: Option<String>,
+    fingerprint: DocsFingerprint,
+    items: Vec<SearchEntry>,
+    /// Lowercased words of item paths and documentation, mapped to the positions
+    /// of the items containing them
+    terms: BTreeMap<String, Vec<u32>>,
+}
+
+/// A cross-crate search result
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub crate_name: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub path: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// How well the item matched, lower is better
+    #[serde(skip)]
+    rank: u8,
+}
+
+/// Outcome of reconciling the index with the cache
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SyncStats {
+    pub rebuilt: usize,
+    pub removed: usize,
+}
+
+impl SearchSegment {
+    /// Build a segment from the binary index of a documentation file
+    fn from_index(
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+        fingerprint: DocsFingerprint,
+        index: &DocIndex,
+    ) -> Result<Self> {
+        let mut items = Vec::new();
+        let mut terms: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+
+        for entry in index.entries() {
+            let (Some(name), Some(search_name)) = (entry.name, entry.search_name) else {
+                continue;
+            };
+
+            let position = items.len() as u32;
+            let path = index.path(&entry);
+            let docs = index.item(&entry.id)?.and_then(|item| item.docs);
+
+            let mut words: HashSet<String> = path.iter().map(|part| part.to_lowercase()).collect();
+            if let Some(docs) = &docs {
+                words.extend(tokenize(docs));
+            }
+            for word in words {
+                terms.entry(word).or_default().push(position);
+            }
+
+            items.push(SearchEntry {
+                id: entry.id.0,
+                name: name.to_string(),
+                search_name: search_name.to_string(),
+                kind: entry.kind.to_string(),
+                path,
+                visibility: entry.visibility.to_string(),
+                summary: docs.as_deref().and_then(summarize),
+            });
+        }
+
+        Ok(Self {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            member: member.map(String::from),
+            fingerprint,
+            items,
+            terms,
+        })
+    }
+
+    /// Search item names like [`crate::docs::DocQuery::search_items`]
+    ///
+    /// Items are returned without documentation, in the same order.
+    pub fn search_items(&self, pattern: &str) -> Vec<ItemInfo> {
+        let pattern_lower = pattern.to_lowercase();
+        let mut items: Vec<ItemInfo> = self
+            .items
+            .iter()
+            .filter(|entry| entry.search_name.contains(&pattern_lower))
+            .map(|entry| ItemInfo {
+                id: entry.id.to_string(),
+                name: entry.name.clone(),
+                kind: entry.kind.clone(),
+                path: entry.path.clone(),
+                docs: None,
+                visibility: entry.visibility.clone(),
+                member: None,
+            })
+            .collect();
+
+        sort_by_relevance(&mut items, &pattern_lower);
+        items
+    }
+
+    /// Search names, paths and documentation
+    ///
+    /// Items whose name contains the whole pattern rank first. Otherwise every
+    /// word of the pattern must occur in the name or as a prefix of a word of the
+    /// item's path or documentation.
+    fn search(
+        &self,
+        pattern_lower: &str,
+        words: &[String],
+        kind_filter: Option<&str>,
+    ) -> Vec<SearchHit> {
+        // Positions of the items containing each pattern word in their path or docs
+        let postings: Vec<HashSet<u32>> = words
+            .iter()
+            .map(|word| {
+                self.terms
+                    .range::<str, _>((Bound::Included(word.as_str()), Bound::Unbounded))
+                    .take_while(|(term, _)| term.starts_with(word.as_str()))
+                    .flat_map(|(_, positions)| positions.iter().copied())
+                    .collect()
+            })
+            .collect();
+
+        let mut hits = Vec::new();
+        for (position, entry) in self.items.iter().enumerate() {
+            if kind_filter.is_some_and(|kind| entry.kind != kind) {
+                continue;
+            }
+
+            let rank = if entry.search_name == pattern_lower {
+                0
+            } else if entry.search_name.starts_with(pattern_lower) {
+                1
+            } else if entry.search_name.contains(pattern_lower) {
+                2
+            } else if !words.is_empty()
+                && words.iter().zip(&postings).all(|(word, positions)| {
+                    entry.search_name.contains(word.as_str())
+                        || positions.contains(&(position as u32))
+                })
+            {
+                3
+            } else {
+                continue;
+            };
+
+            hits.push(SearchHit {
+                crate_name: self.crate_name.clone(),
+                version: self.version.clone(),
+                member: self.member.clone(),
+                id: entry.id.to_string(),
+                name: entry.name.clone(),
+                kind: entry.kind.clone(),
+                path: entry.path.clone(),
+                summary: entry.summary.clone(),
+                rank,
+            });
+        }
+        hits
+    }
+}
+
+#[derive(Debug)]
+struct LoadedSegment {
+    modified: Option<SystemTime>,
+    segment: Arc<SearchSegment>,
+    weight: u64,
+    last_used: u64,
+}
+
+/// Segments read from disk, keyed by file and revalidated by modification time
+///
+/// Least recently used segments are dropped once their total size exceeds the
+/// budget, like [`crate::docs::DocQueryCache`] does for parsed documentation.
+#[derive(Debug, Default)]
+struct LoadedSegments {
+    segments: HashMap<PathBuf, LoadedSegment>,
+    used_bytes: u64,
+    clock: u64,
+}
+
+impl LoadedSegments {
+    /// Get the in-memory copy of a segment if its file is unchanged
+    fn get(&mut self, path: &Path, modified: Option<SystemTime>) -> Option<Arc<SearchSegment>> {
+        self.clock += 1;
+        let now = self.clock;
+        let loaded = self.segments.get_mut(path)?;
+        if loaded.modified != modified {
+            return None;
+        }
+        loaded.last_used = now;
+        Some(loaded.segment.clone())
+    }
+
+    /// Keep a segment of `weight` bytes, dropping the least recently used ones to stay within `capacity_bytes`
+    fn insert(&mut self, path: PathBuf, loaded: LoadedSegment, capacity_bytes: u64) {
+        self.remove(&path);
+        if loaded.weight > capacity_bytes {
+            return;
+        }
+
+        while self.used_bytes + loaded.weight > capacity_bytes {
+            let Some(oldest) = self
+                .segments
+                .iter()
+                .min_by_key(|(_, segment)| segment.last_used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+
+        self.clock += 1;
+        self.used_bytes += loaded.weight;
+        self.segments.insert(
+            path,
+            LoadedSegment {
+                last_used: self.clock,
+                ..loaded
+            },
+        );
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(removed) = self.segments.remove(path) {
+            self.used_bytes -= removed.weight;
+        }
+    }
+}
+
+/// Search index over every cached crate, stored as one segment file per cache entry
+#[derive(Debug)]
+pub struct SearchIndex {
+    dir: PathBuf,
+    loaded: Mutex<LoadedSegments>,
+    capacity_bytes: u64,
+}
+
+impl SearchIndex {
+    /// Create an index storing its segments in `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            loaded: Mutex::default(),
+            capacity_bytes: LOADED_SEGMENTS_MAX_BYTES,
+        }
+    }
+
+    /// Index the documentation of a crate version or workspace member
+    ///
+    /// Uses the binary docs index at `index_path`, writing it first if it is missing.
+    pub fn update(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+        docs_path: &Path,
+        index_path: &Path,
+    ) -> Result<()> {
+        let fingerprint = DocsFingerprint::of(docs_path)?;
+        let index = match DocIndex::open(index_path, fingerprint) {
+            Ok(index) => index,
+            Err(_) => {
+                DocIndex::write(docs_path, index_path)?;
+                DocIndex::open(index_path, fingerprint)?
+            }
+        };
+
+        let segment = SearchSegment::from_index(crate_name, version, member, fingerprint, &index)?;
+
+        let json = serde_json::to_vec(&segment).context("Failed to serialize search segment")?;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let bytes = encoder
+            .write_all(&json)
+            .and_then(|_| encoder.finish())
+            .context("Failed to compress search segment")?;
+
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create directory: {}", self.dir.display()))?;
+        let path = self.segment_path(crate_name, version, member);
+        write_atomic(&path, &bytes)?;
+
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        self.loaded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                path,
+                LoadedSegment {
+                    modified,
+                    segment: Arc::new(segment),
+                    weight: json.len() as u64,
+                    last_used: 0,
+                },
+                self.capacity_bytes,
+            );
+        Ok(())
+    }
+
+    /// Remove the segments of a crate version and all of its workspace members
+    pub fn remove_crate(&self, crate_name: &str, version: &str) -> Result<()> {
+        let crate_key = segment_key(crate_name, version, None);
+        let member_prefix = format!("{crate_key}@");
+        let crate_file = format!("{crate_key}.{SEGMENT_EXTENSION}");
+
+        for path in self.segment_files()? {
+            let file_name = file_name(&path);
+            if file_name == crate_file || file_name.starts_with(&member_prefix) {
+                self.remove_segment(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the segment of a crate version or member if it matches the current documentation
+    pub fn segment(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+        fingerprint: DocsFingerprint,
+    ) -> Option<Arc<SearchSegment>> {
+        let path = self.segment_path(crate_name, version, member);
+        match self.load(&path) {
+            Ok(segment) if segment.fingerprint == fingerprint => Some(segment),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::debug!("No usable search segment at {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Search all indexed crates by name, path and documentation
+    pub fn search(&self, pattern: &str, kind_filter: Option<&str>) -> Result<Vec<SearchHit>> {
+        let pattern_lower = pattern.trim().to_lowercase();
+        if pattern_lower.is_empty() {
+            return Ok(Vec::new());
+        }
+        let words = tokenize(&pattern_lower);
+
+        let mut hits = Vec::new();
+        for path in self.segment_files()? {
+            match self.load(&path) {
+                Ok(segment) => hits.extend(segment.search(&pattern_lower, &words, kind_filter)),
+                Err(e) => tracing::warn!("Skipping search segment {}: {}", path.display(), e),
+            }
+        }
+
+        hits.sort_by(|a, b| {
+            a.rank
+                .cmp(&b.rank)
+                .then_with(|| a.name.len().cmp(&b.name.len()))
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.crate_name.cmp(&b.crate_name))
+                .then_with(|| a.version.cmp(&b.version))
+                .then_with(|| a.member.cmp(&b.member))
+        });
+        Ok(hits)
+    }
+
+    /// Number of segments in the index
+    pub fn segment_count(&self) -> Result<usize> {
+        Ok(self.segment_files()?.len())
+    }
+
+    /// Reconcile the index with the cache
+    ///
+    /// Segments are rebuilt when missing or built from different documentation,
+    /// and removed when their cache entry no longer has documentation.
+    pub fn sync(&self, storage: &CacheStorage) -> Result<SyncStats> {
+        let mut stats = SyncStats::default();
+        let mut expected = HashSet::new();
+
+        for metadata in storage.list_cached_crates()? {
+            let (name, version) = (&metadata.name, &metadata.version);
+
+            let mut entries = vec![(
+                None,
+                storage.docs_path(name, version),
+                storage.docs_index_path(name, version),
+            )];
+            for member in storage.list_workspace_members(name, version)? {
+                let docs_path = storage.member_docs_path(name, version, &member);
+                let index_path = storage.member_docs_index_path(name, version, &member);
+                entries.push((Some(member), docs_path, index_path));
+            }
+
+            for (member, docs_path, index_path) in entries {
+                let Ok(fingerprint) = DocsFingerprint::of(&docs_path) else {
+                    continue;
+                };
+                let member = member.as_deref();
+                expected.insert(self.segment_path(name, version, member));
+
+                if self.segment(name, version, member, fingerprint).is_some() {
+                    continue;
+                }
+                match self.update(name, version, member, &docs_path, &index_path) {
+                    Ok(()) => stats.rebuilt += 1,
+                    Err(e) => {
+                        tracing::warn!("Failed to index {}: {}", docs_path.display(), e);
+                    }
+                }
+            }
+        }
+
+        for path in self.segment_files()? {
+            if !expected.contains(&path) {
+                self.remove_segment(&path)?;
+                stats.removed += 1;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Load a segment, reusing the in-memory copy while the file is unchanged
+    fn load(&self, path: &Path) -> Result<Arc<SearchSegment>> {
+        let modified = fs::metadata(path)
+            .with_context(|| format!("Failed to read metadata of {}", path.display()))?
+            .modified()
+            .ok();
+
+        if let Some(segment) = self
+            .loaded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(path, modified)
+        {
+            return Ok(segment);
+        }
+
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open search segment: {}", path.display()))?;
+        let mut json = Vec::new();
+        GzDecoder::new(file)
+            .read_to_end(&mut json)
+            .with_context(|| format!("Failed to decompress search segment: {}", path.display()))?;
+        let segment: Arc<SearchSegment> = Arc::new(
+            serde_json::from_slice(&json)
+                .with_context(|| format!("Failed to parse search segment: {}", path.display()))?,
+        );
+
+        self.loaded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                path.to_path_buf(),
+                LoadedSegment {
+                    modified,
+                    segment: segment.clone(),
+                    weight: json.len() as u64,
+                    last_used: 0,
+                },
+                self.capacity_bytes,
+            );
+        Ok(segment)
+    }
+
+    /// Delete a segment file and forget its in-memory copy
+    fn remove_segment(&self, path: &Path) -> Result<()> {
+        self.loaded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(path);
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)
+                .with_context(|| format!("Failed to remove search segment: {}", path.display())),
+            _ => Ok(()),
+        }
+    }
+
+    /// List the segment files on disk
+    fn segment_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        if !self.dir.exists() {
+            return Ok(files);
+        }
+
+        let suffix = format!(".{SEGMENT_EXTENSION}");
+        for entry in fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read directory: {}", self.dir.display()))?
+        {
+            let path = entry?.path();
+            if file_name(&path).ends_with(&suffix) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn segment_path(&self, crate_name: &str, version: &str, member: Option<&str>) -> PathBuf {
+        self.dir.join(format!(
+            "{}.{SEGMENT_EXTENSION}",
+            segment_key(crate_name, version, member)
+        ))
+    }
+}
+
+/// Build the file stem of a segment
+///
+/// Crate and member names never contain `@`, which separates the components.
+fn segment_key(crate_name: &str, version: &str, member: Option<&str>) -> String {
+    let key = match member {
+        Some(member) => format!("{crate_name}@{version}@{member}"),
+        None => format!("{crate_name}@{version}"),
+    };
+    // GitHub versions are branch or tag names, which may contain slashes
+    key.replace(['/', '\\'], "_")
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Split text into lowercased words of at least two characters
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| word.chars().nth(1).is_some())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// First paragraph of documentation, shortened to [`SUMMARY_LEN`] characters
+fn summarize(docs: &str) -> Option<String> {
+    let paragraph = docs.trim().split("\n\n").next()?.trim();
+    if paragraph.is_empty() {
+        return None;
+    }
+
+    let mut summary: String = paragraph
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if let Some((end, _)) = summary.char_indices().nth(SUMMARY_LEN) {
+        summary.truncate(end);
+        summary.push('…');
+    }
+    Some(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docs::index::INDEX_FILE_NAME;
+    use rustdoc_types::{
+        Crate, FORMAT_VERSION, Function, FunctionHeader, FunctionSignature, Generics, Id, Item,
+        ItemEnum, ItemKind, ItemSummary, Target, Visibility,
+    };
+    use tempfile::TempDir;
+
+    fn function(id: u32, name: &str, docs: &str) -> Item {
+        Item {
+            id: Id(id),
+            crate_id: 0,
+            name: Some(name.to_string()),
+            span: None,
+            visibility: Visibility::Public,
+            docs: Some(docs.to_string()),
+            links: Default::default(),
+            attrs: Vec::new(),
+            deprecation: None,
+            inner: ItemEnum::Function(Function {
+                sig: FunctionSignature {
+                    inputs: Vec::new(),
+                    output: None,
+                    is_c_variadic: false,
+                },
+                generics: Generics {
+                    params: Vec::new(),
+                    where_predicates: Vec::new(),
+                },
+                header: FunctionHeader {
+                    is_const: false,
+                    is_unsafe: false,
+                    is_async: false,
+                    abi: rustdoc_types::Abi::Rust,
+                },
+                has_body: true,
+            }),
+        }
+    }
+
+    /// Write a docs.json with the given functions and return its path
+    fn write_docs(dir: &Path, crate_name: &str, functions: &[(&str, &str)]) -> Result<PathBuf> {
+        let mut crate_data = Crate {
+            root: Id(0),
+            crate_version: Some("1.0.0".to_string()),
+            includes_private: false,
+            index: Default::default(),
+            paths: Default::default(),
+            external_crates: Default::default(),
+            target: Target {
+                triple: String::new(),
+                target_features: Vec::new(),
+            },
+            format_version: FORMAT_VERSION,
+        };
+        for (i, (name, docs)) in functions.iter().enumerate() {
+            let id = i as u32 + 1;
+            crate_data.index.insert(Id(id), function(id, name, docs));
+            crate_data.paths.insert(
+                Id(id),
+                ItemSummary {
+                    crate_id: 0,
+                    path: vec![crate_name.to_string(), name.to_string()],
+                    kind: ItemKind::Function,
+                },
+            );
+        }
+
+        let docs_dir = dir.join(crate_name);
+        fs::create_dir_all(&docs_dir)?;
+        let docs_path = docs_dir.join("docs.json");
+        fs::write(&docs_path, serde_json::to_vec(&crate_data)?)?;
+        Ok(docs_path)
+    }
+
+    #[test]
+    fn test_search_across_crates() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let index = SearchIndex::new(temp_dir.path().join("search"));
+
+        let serde_docs = write_docs(
+            temp_dir.path(),
+            "serde",
+            &[("to_string", "Serialize the value as a JSON string.")],
+        )?;
+        let toml_docs = write_docs(
+            temp_dir.path(),
+            "toml",
+            &[
+                ("to_string", "Serialize as TOML."),
+                ("from_str", "Parse a string into a value."),
+            ],
+        )?;
+        index.update(
+            "serde",
+            "1.0.0",
+            None,
+            &serde_docs,
+            &serde_docs.with_file_name(INDEX_FILE_NAME),
+        )?;
+        index.update(
+            "toml",
+            "0.8.0",
+            None,
+            &toml_docs,
+            &toml_docs.with_file_name(INDEX_FILE_NAME),
+        )?;
+
+        // Name matches rank before documentation matches
+        let hits = index.search("to_string", None)?;
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|hit| hit.name == "to_string"));
+
+        let hits = index.search("parse string", None)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].crate_name, "toml");
+        assert_eq!(hits[0].name, "from_str");
+
+        // Path components are searchable too
+        let hits = index.search("serde", None)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0].summary.as_deref(),
+            Some("Serialize the value as a JSON string.")
+        );
+
+        // A fresh index reads the persisted segments
+        let reopened = SearchIndex::new(temp_dir.path().join("search"));
+        assert_eq!(reopened.search("to_string", Some("function"))?.len(), 2);
+        assert!(reopened.search("to_string", Some("struct"))?.is_empty());
+
+        reopened.remove_crate("serde", "1.0.0")?;
+        let hits = reopened.search("to_string", None)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].crate_name, "toml");
+        Ok(())
+    }
+
+    #[test]
+    fn test_loaded_segments_stay_within_budget() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut index = SearchIndex::new(temp_dir.path().join("search"));
+        let serde_docs = write_docs(temp_dir.path(), "serde", &[("to_string", "Docs.")])?;
+        let toml_docs = write_docs(temp_dir.path(), "toml", &[("to_string", "Docs.")])?;
+        index.update(
+            "serde",
+            "1.0.0",
+            None,
+            &serde_docs,
+            &serde_docs.with_file_name(INDEX_FILE_NAME),
+        )?;
+        let weight = index.loaded.lock().unwrap().used_bytes;
+
+        // Room for a single segment
+        index.capacity_bytes = weight + weight / 2;
+        index.update(
+            "toml",
+            "0.8.0",
+            None,
+            &toml_docs,
+            &toml_docs.with_file_name(INDEX_FILE_NAME),
+        )?;
+        assert_eq!(index.search("to_string", None)?.len(), 2);
+
+        let loaded = index.loaded.lock().unwrap();
+        assert_eq!(loaded.segments.len(), 1);
+        assert!(loaded.used_bytes <= index.capacity_bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_indexes_read_only_layers() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let shared = CacheStorage::new(Some(temp_dir.path().join("shared")))?;
+        let docs_path = write_docs(temp_dir.path(), "serde", &[("to_string", "Docs.")])?;
+        fs::create_dir_all(shared.source_path("serde", "1.0.0"))?;
+        fs::copy(
+            &docs_path,
+            shared.crate_path("serde", "1.0.0").join("docs.json"),
+        )?;
+        shared.save_metadata("serde", "1.0.0")?;
+
+        let storage = CacheStorage::new(Some(temp_dir.path().join("personal")))?
+            .with_read_only_layers(vec![temp_dir.path().join("shared")])?;
+        let index = SearchIndex::new(storage.search_dir());
+        assert_eq!(index.sync(&storage)?.rebuilt, 1);
+        assert_eq!(index.search("to_string", None)?.len(), 1);
+
+        // The layer is left untouched and the next sync has nothing to do
+        assert!(
+            !shared
+                .crate_path("serde", "1.0.0")
+                .join(INDEX_FILE_NAME)
+                .exists()
+        );
+        assert_eq!(index.sync(&storage)?.rebuilt, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_segment_matches_fingerprint() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let index = SearchIndex::new(temp_dir.path().join("search"));
+        let docs_path = write_docs(temp_dir.path(), "serde", &[("to_string", "Docs.")])?;
+        index.update(
+            "serde",
+            "1.0.0",
+            None,
+            &docs_path,
+            &docs_path.with_file_name(INDEX_FILE_NAME),
+        )?;
+
+        let fingerprint = DocsFingerprint::of(&docs_path)?;
+        let segment = index
+            .segment("serde", "1.0.0", None, fingerprint)
+            .expect("segment should be current");
+        assert_eq!(segment.search_items("string")[0].name, "to_string");
+
+        // Regenerated documentation makes the segment stale
+        let docs_path = write_docs(
+            temp_dir.path(),
+            "serde",
+            &[("to_vec", "Much longer documentation.")],
+        )?;
+        let fingerprint = DocsFingerprint::of(&docs_path)?;
+        assert!(index.segment("serde", "1.0.0", None, fingerprint).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_is_first_paragraph() {
+        assert_eq!(
+            summarize("Short summary\nover two lines.\n\nDetails.").as_deref(),
+            Some("Short summary over two lines.")
+        );
+        assert_eq!(summarize("  \n"), None);
+        assert_eq!(
+            summarize(&"a".repeat(300)).unwrap().chars().count(),
+            SUMMARY_LEN + 1
+        );
+    }
+}
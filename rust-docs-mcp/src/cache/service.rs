@@ -1,11 +1,27 @@
+use crate::cache::bundle::{self, BundleWriter, ExportReport, ImportReport, OnConflict};
+use crate::cache::config::CacheConfig;
 use crate::cache::docgen::DocGenerator;
-use crate::cache::downloader::{CrateDownloader, CrateSource};
-use crate::cache::storage::CacheStorage;
+use crate::cache::downloader::{self, CrateDownloader, CrateSource, VendorReport};
+use crate::cache::gc::{self, AccessLog, GcQuota, GcReport};
+use crate::cache::link::{self, LinkChecks, LinkState};
+use crate::cache::locks::CrateLocks;
+use crate::cache::migrations;
+use crate::cache::prefetch::{
+    self, PrefetchJob, PrefetchJobs, PrefetchOptions, PrefetchStatus, PrefetchTarget,
+};
+use crate::cache::projects::{ProjectRegistry, read_lockfile, resolve_lockfile};
+use crate::cache::search_index::{SearchIndex, SyncStats};
+use crate::cache::storage::{CacheStorage, CrateMetadata};
 use crate::cache::transaction::CacheTransaction;
 use crate::cache::utils::CacheResponse;
-use crate::cache::workspace::WorkspaceHandler;
+use crate::cache::verify::{self, Issue, Repair, VerifyReport};
+use crate::cache::workspace::{Workspace, WorkspaceHandler, WorkspaceMember};
+use crate::deps::graph::DependencyGraph;
 use anyhow::{Context, Result, bail};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// Service for managing crate caching and documentation generation
 #[derive(Debug, Clone)]
@@ -13,104 +29,275 @@ pub struct CrateCache {
     pub(crate) storage: CacheStorage,
     downloader: CrateDownloader,
     doc_generator: DocGenerator,
+    locks: CrateLocks,
+    search_index: Arc<SearchIndex>,
+    projects: ProjectRegistry,
+    access_log: Arc<AccessLog>,
+    link_checks: Arc<LinkChecks>,
+    prefetch_jobs: Arc<PrefetchJobs>,
+    quota: GcQuota,
 }
 
 impl CrateCache {
     /// Create a new crate cache instance
-    pub fn new(cache_dir: Option<PathBuf>) -> Result<Self> {
-        let storage = CacheStorage::new(cache_dir)?;
+    pub fn new(cache_dir: Option<PathBuf>, config: CacheConfig) -> Result<Self> {
+        let storage =
+            CacheStorage::new(cache_dir)?.with_read_only_layers(config.read_only_layers.clone())?;
+        migrations::prepare(&storage)?;
+        let quota = config.quota;
         let downloader = CrateDownloader::new(storage.clone());
-        let doc_generator = DocGenerator::new(storage.clone());
+        let doc_generator = DocGenerator::new(storage.clone(), config);
+        let locks = CrateLocks::new(storage.locks_dir());
+        let search_index = Arc::new(SearchIndex::new(storage.search_dir()));
+        let projects = ProjectRegistry::new(storage.projects_path());
 
         Ok(Self {
             storage,
             downloader,
             doc_generator,
+            locks,
+            search_index,
+            projects,
+            access_log: Arc::default(),
+            link_checks: Arc::default(),
+            prefetch_jobs: Arc::default(),
+            quota,
         })
     }
 
     /// Ensure a crate's documentation is available, downloading and generating if necessary
+    ///
+    /// Returns the path of the generated `docs.json`.
     pub async fn ensure_crate_docs(
         &self,
         name: &str,
         version: &str,
         source: Option<&str>,
-    ) -> Result<rustdoc_types::Crate> {
-        // Check if docs already exist
+    ) -> Result<PathBuf> {
+        self.ensure_crate_docs_keeping(name, version, source, &HashSet::new())
+            .await
+    }
+
+    /// Like [`Self::ensure_crate_docs`], also keeping the crate versions in `keep`
+    /// cached when making room for the new crate
+    async fn ensure_crate_docs_keeping(
+        &self,
+        name: &str,
+        version: &str,
+        source: Option<&str>,
+        keep: &HashSet<(String, String)>,
+    ) -> Result<PathBuf> {
+        // Docs that already exist are served without taking the crate lock
+        if !self.storage.has_docs(name, version) {
+            let lock = self.locks.lock(name, version).await?;
+            self.build_crate_docs(name, version, source).await?;
+            drop(lock);
+            let mut keep = keep.clone();
+            keep.insert((name.to_string(), version.to_string()));
+            self.enforce_quota(&keep).await;
+        }
+
+        self.record_access(name, version);
+        Ok(self.storage.docs_path(name, version))
+    }
+
+    /// Download and document a crate unless another caller already did
+    ///
+    /// The caller must hold the crate lock.
+    async fn build_crate_docs(
+        &self,
+        name: &str,
+        version: &str,
+        source: Option<&str>,
+    ) -> Result<()> {
+        // Re-check after acquiring the lock: a concurrent identical request may
+        // have generated the docs while we were waiting
         if self.storage.has_docs(name, version) {
-            return self.load_docs(name, version).await;
+            return Ok(());
         }
 
         // Check if crate is downloaded but docs not generated
         if !self.storage.is_cached(name, version) {
-            self.download_or_copy_crate(name, version, source).await?;
+            self.downloader
+                .download_or_copy_crate(name, version, source)
+                .await?;
         }
 
         // Generate documentation
         self.generate_docs(name, version).await?;
-
-        // Load and return the generated docs
-        self.load_docs(name, version).await
+        self.remove_build_artifacts(name, version);
+        Ok(())
     }
 
     /// Ensure a workspace member's documentation is available
+    ///
+    /// The member is given by its package name or path. Returns the path of the
+    /// generated `docs.json`.
     pub async fn ensure_workspace_member_docs(
         &self,
         name: &str,
         version: &str,
         source: Option<&str>,
-        member_path: &str,
-    ) -> Result<rustdoc_types::Crate> {
+        member: &str,
+    ) -> Result<PathBuf> {
         // Check if docs already exist for this member
-        let member_name = WorkspaceHandler::extract_member_name(member_path);
+        let member_name = match self.storage.find_member(name, version, member) {
+            Some(info) if self.storage.has_member_docs(name, version, &info.name) => info.name,
+            _ => {
+                let lock = self.locks.lock(name, version).await?;
+                let info = self
+                    .build_member_docs(name, version, source, member)
+                    .await?;
+                self.remove_build_artifacts(name, version);
+                drop(lock);
+                self.enforce_quota(&HashSet::from([(name.to_string(), version.to_string())]))
+                    .await;
+                info.name
+            }
+        };
 
-        if self.storage.has_member_docs(name, version, member_name) {
-            return self.load_member_docs(name, version, member_name).await;
+        self.record_access(name, version);
+        Ok(self.storage.member_docs_path(name, version, &member_name))
+    }
+
+    /// Download a workspace and document one of its members unless another caller already did
+    ///
+    /// The caller must hold the crate lock.
+    async fn build_member_docs(
+        &self,
+        name: &str,
+        version: &str,
+        source: Option<&str>,
+        member: &str,
+    ) -> Result<WorkspaceMember> {
+        if let Some(info) = self.storage.find_member(name, version, member)
+            && self.storage.has_member_docs(name, version, &info.name)
+        {
+            return Ok(info);
         }
 
         // Check if crate is downloaded
         if !self.storage.is_cached(name, version) {
-            self.download_or_copy_crate(name, version, source).await?;
+            self.downloader
+                .download_or_copy_crate(name, version, source)
+                .await?;
         }
 
         // Generate documentation for the specific workspace member
-        self.generate_workspace_member_docs(name, version, member_path)
+        let info = self.find_workspace_member(name, version, member).await?;
+        self.generate_workspace_member_docs(name, version, &info)
             .await?;
+        Ok(info)
+    }
+
+    /// Resolve a `member` parameter, a package name or member path, of a cached workspace
+    pub async fn resolve_member(
+        &self,
+        name: &str,
+        version: &str,
+        member: &str,
+    ) -> Result<WorkspaceMember> {
+        match self.storage.find_member(name, version, member) {
+            Some(info) => Ok(info),
+            None => self.find_workspace_member(name, version, member).await,
+        }
+    }
+
+    /// Get the documented parts of a crate queried without selecting a member
+    ///
+    /// A workspace whose members are documented is queried as a whole: its root
+    /// package, if documented, and every documented member, given by package name.
+    /// Anything else is a single unnamed scope, the crate itself.
+    pub async fn workspace_scopes(&self, name: &str, version: &str) -> Result<Vec<Option<String>>> {
+        self.refresh_linked(name, version).await;
+
+        let mut members = self.storage.list_workspace_members(name, version)?;
+        members.retain(|member| self.storage.has_member_docs(name, version, member));
+        if members.is_empty() {
+            return Ok(vec![None]);
+        }
+
+        members.sort();
+        let root = self.storage.has_docs(name, version).then_some(None);
+        Ok(root
+            .into_iter()
+            .chain(members.into_iter().map(Some))
+            .collect())
+    }
+
+    /// Find the documented workspace member whose library is named `lib_name`
+    ///
+    /// Used to follow re-exports from one member into another.
+    pub fn find_library_member(
+        &self,
+        name: &str,
+        version: &str,
+        lib_name: &str,
+    ) -> Option<WorkspaceMember> {
+        let members = self.storage.list_workspace_members(name, version).ok()?;
+        members
+            .iter()
+            .filter(|member| self.storage.has_member_docs(name, version, member))
+            .filter_map(|member| self.storage.load_member_info(name, version, member).ok())
+            .find(|member| {
+                // Members documented before library names were recorded
+                let default_lib_name = || member.name.replace('-', "_");
+                member.lib_name.clone().unwrap_or_else(default_lib_name) == lib_name
+            })
+    }
 
-        // Load and return the generated docs
-        self.load_member_docs(name, version, member_name).await
+    /// Find a member in the workspace manifest of a cached crate's source
+    async fn find_workspace_member(
+        &self,
+        name: &str,
+        version: &str,
+        member: &str,
+    ) -> Result<WorkspaceMember> {
+        let source_path = self.storage.source_path(name, version);
+        let workspace = self
+            .resolve_workspace(&source_path)
+            .await?
+            .with_context(|| format!("{name}-{version} is not a workspace"))?;
+        match workspace.find_member(member) {
+            Some(info) => Ok(info.clone()),
+            None => bail!(
+                "Workspace member '{}' not found in {}-{}. Available members: {:?}",
+                member,
+                name,
+                version,
+                workspace
+                    .members
+                    .iter()
+                    .map(|m| format!("{} ({})", m.name, m.path))
+                    .collect::<Vec<_>>()
+            ),
+        }
     }
 
     /// Ensure documentation is available for a crate or workspace member
+    ///
+    /// Returns the path of the generated `docs.json`.
     pub async fn ensure_crate_or_member_docs(
         &self,
         name: &str,
         version: &str,
         member: Option<&str>,
-    ) -> Result<rustdoc_types::Crate> {
+    ) -> Result<PathBuf> {
+        self.refresh_linked(name, version).await;
+
         // If member is specified, use workspace member logic
-        if let Some(member_path) = member {
+        if let Some(member) = member {
             return self
-                .ensure_workspace_member_docs(name, version, None, member_path)
+                .ensure_workspace_member_docs(name, version, None, member)
                 .await;
         }
 
         // Check if crate is already downloaded
         if self.storage.is_cached(name, version) {
             let source_path = self.storage.source_path(name, version);
-            let cargo_toml_path = source_path.join("Cargo.toml");
-
-            // Check if it's a workspace
-            if cargo_toml_path.exists() && WorkspaceHandler::is_workspace(&cargo_toml_path)? {
-                // It's a workspace without member specified
-                let members = WorkspaceHandler::get_workspace_members(&cargo_toml_path)?;
-                bail!(
-                    "This is a workspace crate. Please specify a member using the 'member' parameter.\n\
-                    Available members: {:?}\n\
-                    Example: specify member=\"{}\"",
-                    members,
-                    members.first().unwrap_or(&"crates/example".to_string())
-                );
+            // A workspace with a root package is documented as that package
+            if let Some(workspace) = self.virtual_workspace(&source_path).await? {
+                bail!(member_required(&workspace));
             }
         }
 
@@ -118,8 +305,59 @@ impl CrateCache {
         self.ensure_crate_docs(name, version, None).await
     }
 
+    /// Cache the sources of every crate of a `cargo vendor` directory
+    ///
+    /// Crate versions that are already cached are kept. Documentation is generated
+    /// when a crate is first queried, like for crates downloaded by other tools.
+    pub async fn import_vendor_dir(&self, vendor_dir: &str) -> Result<VendorReport> {
+        let expanded = shellexpand::full(vendor_dir)
+            .with_context(|| format!("Failed to expand path: {vendor_dir}"))?;
+        let path = PathBuf::from(expanded.as_ref());
+        let scan = tokio::task::spawn_blocking(move || downloader::scan_vendor_dir(&path))
+            .await
+            .context("Vendor directory scan panicked")??;
+        if scan.crates.is_empty() && scan.ignored.is_empty() && scan.failed.is_empty() {
+            bail!("No vendored crates found in {vendor_dir}");
+        }
+
+        let mut report = VendorReport {
+            ignored: scan.ignored,
+            failed: scan.failed,
+            ..VendorReport::default()
+        };
+        for vendored in &scan.crates {
+            let label = format!("{}-{}", vendored.name, vendored.version);
+            let _lock = self.locks.lock(&vendored.name, &vendored.version).await?;
+            if self.storage.is_cached(&vendored.name, &vendored.version) {
+                report.skipped.push(label);
+                continue;
+            }
+            match self.downloader.copy_vendored(vendored).await {
+                Ok(_) => report.imported.push(label),
+                Err(e) => report.failed.push(format!("{label}: {e:#}")),
+            }
+        }
+
+        tracing::info!(
+            "Imported {} vendored crates, {} already cached, {} not from a registry, {} failed",
+            report.imported.len(),
+            report.skipped.len(),
+            report.ignored.len(),
+            report.failed.len()
+        );
+        let imported = scan
+            .crates
+            .iter()
+            .map(|vendored| (vendored.name.clone(), vendored.version.clone()))
+            .collect();
+        self.enforce_quota(&imported).await;
+        Ok(report)
+    }
+
     /// Download or copy a crate based on source type
-    pub async fn download_or_copy_crate(
+    ///
+    /// The caller must hold the crate lock.
+    async fn download_or_copy_crate(
         &self,
         name: &str,
         version: &str,
@@ -131,20 +369,126 @@ impl CrateCache {
     }
 
     /// Generate JSON documentation for a crate
+    ///
+    /// Crates in a read-only layer are copied into the cache directory first.
     pub async fn generate_docs(&self, name: &str, version: &str) -> Result<PathBuf> {
-        self.doc_generator.generate_docs(name, version).await
+        self.storage.copy_up(name, version)?;
+        let docs_path = self.doc_generator.generate_docs(name, version).await?;
+        self.update_search_index(name, version, None, &docs_path)
+            .await;
+        Ok(docs_path)
     }
 
-    /// Generate JSON documentation for a workspace member
+    /// Generate JSON documentation for a workspace member, see [`Self::generate_docs`]
     pub async fn generate_workspace_member_docs(
         &self,
         name: &str,
         version: &str,
-        member_path: &str,
+        member: &WorkspaceMember,
     ) -> Result<PathBuf> {
-        self.doc_generator
-            .generate_workspace_member_docs(name, version, member_path)
+        self.storage.copy_up(name, version)?;
+        let docs_path = self
+            .doc_generator
+            .generate_workspace_member_docs(name, version, member)
+            .await?;
+        self.update_search_index(name, version, Some(&member.name), &docs_path)
+            .await;
+        Ok(docs_path)
+    }
+
+    /// Delete a crate's build directory after a successful build and record its new size
+    ///
+    /// The caller must hold the crate lock, so no other build of the crate is using
+    /// the directory. Failures only cost disk space and are logged.
+    fn remove_build_artifacts(&self, name: &str, version: &str) {
+        if let Err(e) = self.storage.remove_build_artifacts(name, version) {
+            tracing::warn!(
+                "Failed to remove build artifacts of {}-{}: {}",
+                name,
+                version,
+                e
+            );
+        }
+        if let Err(e) = self.storage.update_sizes(name, version) {
+            tracing::debug!("Failed to update cache size of {}-{}: {}", name, version, e);
+        }
+    }
+
+    /// Add freshly generated documentation to the cross-crate search index
+    ///
+    /// The index is derived data, so failures are logged rather than failing the build.
+    async fn update_search_index(
+        &self,
+        name: &str,
+        version: &str,
+        member_name: Option<&str>,
+        docs_path: &Path,
+    ) {
+        let search_index = self.search_index.clone();
+        let (name, version) = (name.to_string(), version.to_string());
+        let member_name = member_name.map(String::from);
+        let docs_path = docs_path.to_path_buf();
+        let index_path = self.docs_index_path(&name, &version, member_name.as_deref());
+
+        let result = tokio::task::spawn_blocking(move || {
+            search_index.update(
+                &name,
+                &version,
+                member_name.as_deref(),
+                &docs_path,
+                &index_path,
+            )
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to update search index: {}", e),
+            Err(e) => tracing::warn!("Search index task failed: {}", e),
+        }
+    }
+
+    /// Get the binary docs index path of a crate or workspace member
+    pub fn docs_index_path(&self, name: &str, version: &str, member: Option<&str>) -> PathBuf {
+        match member {
+            Some(member) => self.storage.member_docs_index_path(name, version, member),
+            None => self.storage.docs_index_path(name, version),
+        }
+    }
+
+    /// Get the search index over all cached crates
+    pub fn search_index(&self) -> &SearchIndex {
+        &self.search_index
+    }
+
+    /// Bring the search index up to date with the cache
+    ///
+    /// Only entries whose documentation changed since they were indexed are rebuilt.
+    pub async fn sync_search_index(&self) -> Result<SyncStats> {
+        let search_index = self.search_index.clone();
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || search_index.sync(&storage))
             .await
+            .context("Search index task panicked")?
+    }
+
+    /// Bring the search index up to date in the background
+    ///
+    /// Called at startup to index documentation generated by other processes
+    /// sharing the cache, or before the index existed. Afterwards, building,
+    /// importing and removing crates keep the index current.
+    pub fn spawn_search_index_sync(&self) {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            match cache.sync_search_index().await {
+                Ok(stats) if stats.rebuilt + stats.removed > 0 => tracing::info!(
+                    "Search index synced: {} entries rebuilt, {} removed",
+                    stats.rebuilt,
+                    stats.removed
+                ),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to sync search index: {}", e),
+            }
+        });
     }
 
     /// Load documentation from cache
@@ -192,7 +536,498 @@ impl CrateCache {
 
     /// Remove a cached crate version
     pub async fn remove_crate(&self, name: &str, version: &str) -> Result<()> {
-        self.storage.remove_crate(name, version)
+        let _lock = self.locks.lock(name, version).await?;
+        self.storage.remove_crate(name, version)?;
+        self.search_index.remove_crate(name, version)
+    }
+
+    /// Pin or unpin a cached crate version, protecting it from garbage collection
+    pub async fn set_pinned(&self, name: &str, version: &str, pinned: bool) -> Result<()> {
+        let _lock = self.locks.lock(name, version).await?;
+        self.storage.set_pinned(name, version, pinned)
+    }
+
+    /// Get the registry of projects whose lockfiles protect crates from eviction
+    pub fn projects(&self) -> &ProjectRegistry {
+        &self.projects
+    }
+
+    /// Get the configured cache quota
+    pub fn quota(&self) -> GcQuota {
+        self.quota
+    }
+
+    /// Evict least recently used crate versions until the cache is within `quota`
+    ///
+    /// Pinned crates and crates referenced by registered projects are kept. Crates
+    /// that are being built or removed are skipped. With `dry_run`, nothing is
+    /// removed and the report lists what would be evicted.
+    pub async fn gc(&self, quota: GcQuota, dry_run: bool) -> Result<GcReport> {
+        self.gc_keeping(quota, dry_run, &HashSet::new()).await
+    }
+
+    /// Like [`Self::gc`], never evicting the crate versions in `keep`
+    async fn gc_keeping(
+        &self,
+        quota: GcQuota,
+        dry_run: bool,
+        keep: &HashSet<(String, String)>,
+    ) -> Result<GcReport> {
+        // Read-only layers neither count towards the quota nor can be evicted from
+        let mut crates = self.storage.list_cached_crates()?;
+        crates.retain(|metadata| !self.storage.is_read_only(&metadata.name, &metadata.version));
+        let referenced = self.projects.locked_crates()?;
+        let plan = gc::plan(&crates, &referenced, keep, quota);
+
+        let mut evicted = Vec::new();
+        let mut skipped_in_use = Vec::new();
+        for entry in plan.evict {
+            if dry_run {
+                evicted.push(entry);
+                continue;
+            }
+
+            let Some(_lock) = self.locks.try_lock(&entry.name, &entry.version)? else {
+                skipped_in_use.push(entry);
+                continue;
+            };
+            // The crate may have been pinned since the plan was made
+            if self
+                .storage
+                .load_metadata(&entry.name, &entry.version)
+                .is_ok_and(|metadata| metadata.pinned)
+            {
+                continue;
+            }
+
+            tracing::info!("Evicting {}-{} from the cache", entry.name, entry.version);
+            self.storage.remove_crate(&entry.name, &entry.version)?;
+            self.search_index
+                .remove_crate(&entry.name, &entry.version)?;
+            evicted.push(entry);
+        }
+
+        let evicted_bytes: u64 = evicted.iter().map(|entry| entry.size_bytes).sum();
+        let total_bytes_after = plan.total_bytes.saturating_sub(evicted_bytes);
+        let crates_after = plan.total_crates - evicted.len();
+        Ok(GcReport {
+            dry_run,
+            quota,
+            evicted,
+            skipped_in_use,
+            pinned: plan.pinned,
+            referenced_by_projects: plan.referenced,
+            total_bytes_before: plan.total_bytes,
+            total_bytes_after,
+            crates_before: plan.total_crates,
+            crates_after,
+            quota_met: !quota.is_exceeded(total_bytes_after, crates_after),
+        })
+    }
+
+    /// Run garbage collection with the configured quota, if there is one
+    ///
+    /// Called after new crates were cached, which are passed in `keep` so they are
+    /// never evicted before the caller reads them, even if they don't fit into the
+    /// quota on their own. Failures are logged, the crates that were just cached
+    /// are usable either way.
+    async fn enforce_quota(&self, keep: &HashSet<(String, String)>) {
+        if self.quota.is_unlimited() {
+            return;
+        }
+
+        match self.gc_keeping(self.quota, false, keep).await {
+            Ok(report) if !report.evicted.is_empty() => tracing::info!(
+                "Evicted {} crate versions to stay within the cache quota",
+                report.evicted.len()
+            ),
+            Ok(report) if !report.quota_met => {
+                tracing::warn!("Cache quota exceeded, but no crate versions can be evicted")
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Failed to enforce cache quota: {}", e),
+        }
+    }
+
+    /// Check cache entries for damage, optionally repairing what was found
+    ///
+    /// Checks a single crate's versions when `crate_name` is given, otherwise all
+    /// entries plus leftover update backups and staging directories. Entries that
+    /// are being built or removed are skipped.
+    pub async fn verify(&self, crate_name: Option<&str>, repair: bool) -> Result<VerifyReport> {
+        let mut report = VerifyReport {
+            repair,
+            ..VerifyReport::default()
+        };
+
+        let entries = self.storage.list_entries()?;
+        for (name, version) in entries {
+            if crate_name.is_some_and(|crate_name| crate_name != name) {
+                continue;
+            }
+            let Some(_lock) = self.locks.try_lock(&name, &version)? else {
+                report.skipped_in_use.push(format!("{name}-{version}"));
+                continue;
+            };
+
+            // Deserializing all docs of a crate is CPU bound
+            let storage = self.storage.clone();
+            let (entry_name, entry_version) = (name.clone(), version.clone());
+            let mut issues = tokio::task::spawn_blocking(move || {
+                verify::check_entry(&storage, &entry_name, &entry_version)
+            })
+            .await
+            .context("Verification task panicked")?;
+
+            if repair {
+                for issue in &mut issues {
+                    let result = self.repair_issue(&name, &version, issue).await;
+                    issue.repaired = result.is_ok();
+                    issue.repair_error = result.err().map(|e| format!("{e:#}"));
+                }
+                if issues.iter().any(|issue| issue.repaired)
+                    && self.storage.is_cached(&name, &version)
+                {
+                    self.remove_build_artifacts(&name, &version);
+                }
+            }
+            report.checked += 1;
+            report.issues.extend(issues);
+        }
+
+        if crate_name.is_none() {
+            let mut leftovers = verify::check_leftovers(&self.storage);
+            if repair {
+                for issue in &mut leftovers {
+                    let result = remove_path(&issue.path);
+                    issue.repaired = result.is_ok();
+                    issue.repair_error = result.err().map(|e| format!("{e:#}"));
+                }
+            }
+            report.issues.extend(leftovers);
+        }
+
+        Ok(report)
+    }
+
+    /// Carry out the repair of an issue found in a crate version
+    ///
+    /// The caller must hold the crate lock.
+    async fn repair_issue(&self, name: &str, version: &str, issue: &Issue) -> Result<()> {
+        tracing::info!("Repairing {}-{}: {}", name, version, issue.detail);
+        if !matches!(issue.repair, Repair::RemoveEntry | Repair::Remove) {
+            self.storage.copy_up(name, version)?;
+        }
+        match &issue.repair {
+            Repair::RemoveEntry => {
+                self.storage.remove_crate(name, version)?;
+                self.search_index.remove_crate(name, version)
+            }
+            Repair::RewriteMetadata => {
+                // The source is unknown once the metadata is lost
+                self.storage.save_metadata(name, version)
+            }
+            Repair::Remove => remove_path(&issue.path),
+            Repair::RegenerateDocs(None) => {
+                for path in [
+                    self.storage.docs_path(name, version),
+                    self.storage.docs_index_path(name, version),
+                    self.storage.dependencies_path(name, version),
+                ] {
+                    remove_path(&path)?;
+                }
+                self.generate_docs(name, version).await.map(|_| ())
+            }
+            Repair::RegenerateDocs(Some(member_name)) => {
+                let member = self.resolve_member(name, version, member_name).await?;
+                for path in [
+                    self.storage.member_docs_path(name, version, member_name),
+                    self.storage
+                        .member_docs_index_path(name, version, member_name),
+                    self.storage
+                        .member_dependencies_path(name, version, member_name),
+                ] {
+                    remove_path(&path)?;
+                }
+                self.generate_workspace_member_docs(name, version, &member)
+                    .await
+                    .map(|_| ())
+            }
+            Repair::RegenerateDependencies(None) => {
+                self.doc_generator
+                    .generate_dependencies(name, version)
+                    .await
+            }
+            Repair::RegenerateDependencies(Some(member_name)) => {
+                let member = self.resolve_member(name, version, member_name).await?;
+                self.doc_generator
+                    .generate_workspace_member_dependencies(name, version, &member)
+                    .await
+            }
+        }
+    }
+
+    /// Pack cached crate versions into a bundle at `output`
+    ///
+    /// Crates are given as `name` for all cached versions or `name@version`. With a
+    /// `lockfile`, its crates.io packages are exported as well, and with
+    /// `fetch_missing` those that are not cached yet are downloaded and documented
+    /// first. When neither is given, the whole cache is exported.
+    pub async fn export_bundle(
+        &self,
+        crates: &[String],
+        lockfile: Option<&Path>,
+        fetch_missing: bool,
+        output: &Path,
+    ) -> Result<ExportReport> {
+        let cached: Vec<(String, String)> = self
+            .storage
+            .list_cached_crates()?
+            .into_iter()
+            .map(|metadata| (metadata.name, metadata.version))
+            .collect();
+
+        let mut selected = Vec::new();
+        let mut missing = Vec::new();
+        if crates.is_empty() && lockfile.is_none() {
+            selected = cached.clone();
+        }
+        for spec in crates {
+            let matches: Vec<_> = match spec.split_once('@') {
+                Some((name, version)) => cached
+                    .iter()
+                    .filter(|(n, v)| n == name && v == version)
+                    .cloned()
+                    .collect(),
+                None => cached.iter().filter(|(n, _)| n == spec).cloned().collect(),
+            };
+            if matches.is_empty() {
+                missing.push(spec.clone());
+            }
+            selected.extend(matches);
+        }
+        if let Some(lockfile) = lockfile {
+            for package in read_lockfile(&resolve_lockfile(lockfile)?)? {
+                if !package.is_crates_io() {
+                    continue;
+                }
+                let key = (package.name, package.version);
+                if !cached.contains(&key) {
+                    if !fetch_missing {
+                        missing.push(format!("{}-{}", key.0, key.1));
+                        continue;
+                    }
+                    if let Err(e) = self.ensure_crate_docs(&key.0, &key.1, None).await {
+                        tracing::warn!("Failed to fetch {}-{}: {}", key.0, key.1, e);
+                        missing.push(format!("{}-{}", key.0, key.1));
+                        continue;
+                    }
+                }
+                selected.push(key);
+            }
+        }
+        selected.sort();
+        selected.dedup();
+
+        let manifest = bundle::manifest(&self.storage, &selected);
+        let mut writer = BundleWriter::create(output, &manifest)?;
+        for entry in manifest.crates.iter().cloned() {
+            let _lock = self.locks.lock(&entry.name, &entry.version).await?;
+            let storage = self.storage.clone();
+            writer = tokio::task::spawn_blocking(move || {
+                writer.append(&storage, &entry)?;
+                Ok::<_, anyhow::Error>(writer)
+            })
+            .await
+            .context("Export task panicked")??;
+        }
+        let size_bytes = tokio::task::spawn_blocking(move || writer.finish())
+            .await
+            .context("Export task panicked")??;
+
+        Ok(ExportReport {
+            path: output.to_path_buf(),
+            crates: manifest
+                .crates
+                .iter()
+                .map(|entry| format!("{}-{}", entry.name, entry.version))
+                .collect(),
+            missing,
+            size_bytes,
+        })
+    }
+
+    /// Merge the crate versions of a bundle into the cache
+    ///
+    /// Crate versions that are already cached are kept or replaced according to
+    /// `on_conflict`.
+    pub async fn import_bundle(
+        &self,
+        bundle_path: &Path,
+        on_conflict: OnConflict,
+    ) -> Result<ImportReport> {
+        let storage = self.storage.clone();
+        let path = bundle_path.to_path_buf();
+        let (manifest, staging) =
+            tokio::task::spawn_blocking(move || bundle::unpack(&storage, &path))
+                .await
+                .context("Import task panicked")??;
+
+        let mut report = ImportReport::default();
+        for entry in &manifest.crates {
+            let (name, version) = (&entry.name, &entry.version);
+            let key = format!("{name}-{version}");
+            let staged = bundle::staged_entry(staging.path(), entry);
+
+            let _lock = self.locks.lock(name, version).await?;
+            let exists = self.storage.is_cached(name, version);
+            if exists {
+                let keep = match on_conflict {
+                    OnConflict::Skip => true,
+                    OnConflict::Replace => false,
+                    OnConflict::Newer => {
+                        let cached_at = |path: PathBuf| {
+                            fs::read_to_string(path)
+                                .ok()
+                                .and_then(|json| serde_json::from_str::<CrateMetadata>(&json).ok())
+                                .map(|metadata| metadata.cached_at)
+                        };
+                        cached_at(self.storage.metadata_path(name, version))
+                            >= cached_at(staged.join("metadata.json"))
+                    }
+                };
+                if keep {
+                    report.skipped.push(key);
+                    continue;
+                }
+            }
+
+            CacheStorage::rewrite_dependency_paths(
+                &staged,
+                &entry.source_path,
+                &self.storage.source_path(name, version),
+            )?;
+            self.storage.install_entry(name, version, &staged)?;
+            if exists {
+                // Imported docs may be older than the index of the replaced ones
+                self.search_index.remove_crate(name, version)?;
+                report.replaced.push(key);
+            } else {
+                report.imported.push(key);
+            }
+        }
+        drop(staging);
+
+        if let Err(e) = self.sync_search_index().await {
+            tracing::warn!("Failed to update search index: {}", e);
+        }
+        let imported = manifest
+            .crates
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.version.clone()))
+            .collect();
+        self.enforce_quota(&imported).await;
+        Ok(report)
+    }
+
+    /// Bring a linked local crate up to date with its original directory
+    ///
+    /// Failures, e.g. because the code doesn't compile in the middle of an edit,
+    /// are logged and the previous documentation is served. The changes are
+    /// picked up again by the next check.
+    async fn refresh_linked(&self, name: &str, version: &str) {
+        if !self.storage.is_linked(name, version) || !self.link_checks.due(name, version) {
+            return;
+        }
+        let result = match self.locks.lock(name, version).await {
+            Ok(_lock) => self.sync_linked(name, version).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to refresh linked crate {}-{}: {:#}",
+                name,
+                version,
+                e
+            );
+        }
+    }
+
+    /// Copy changed files of a linked crate and regenerate the affected documentation
+    ///
+    /// The caller must hold the crate lock.
+    async fn sync_linked(&self, name: &str, version: &str) -> Result<()> {
+        let state = LinkState::load(&self.storage.link_path(name, version))?;
+        let (state, changes) = tokio::task::spawn_blocking(move || state.rescan())
+            .await
+            .context("Link check task panicked")??;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        tracing::info!(
+            "{} files of linked crate {}-{} changed, refreshing",
+            changes.len(),
+            name,
+            version
+        );
+
+        self.storage.copy_up(name, version)?;
+        let source_path = self.storage.source_path(name, version);
+        let (from, to) = (state.path.clone(), source_path.clone());
+        let changes =
+            tokio::task::spawn_blocking(move || changes.apply(&from, &to).map(|()| changes))
+                .await
+                .context("Link sync task panicked")??;
+
+        let workspace = self.resolve_workspace(&source_path).await?;
+        // The root package of a workspace is documented as the crate itself, a
+        // single package is affected by every change
+        let mut regenerate_root = workspace.is_none();
+        let mut result = Ok(());
+        if let Some(workspace) = &workspace {
+            let mut members = workspace.member_paths();
+            members.extend(workspace.root_package.iter().map(|root| root.path.clone()));
+            for member_path in link::affected_members(&source_path, &members, &changes) {
+                let is_root = workspace
+                    .root_package
+                    .as_ref()
+                    .is_some_and(|root| root.path == member_path);
+                if is_root {
+                    regenerate_root = true;
+                    continue;
+                }
+                let Some(member) = workspace.find_member(&member_path) else {
+                    continue;
+                };
+                if !self.storage.has_member_docs(name, version, &member.name) {
+                    continue;
+                }
+                result = self
+                    .generate_workspace_member_docs(name, version, member)
+                    .await
+                    .map(|_| ());
+                if result.is_err() {
+                    break;
+                }
+            }
+        }
+        if result.is_ok() && regenerate_root && self.storage.has_docs(name, version) {
+            result = self.generate_docs(name, version).await.map(|_| ());
+        }
+        self.remove_build_artifacts(name, version);
+        result?;
+
+        state.save(&self.storage.link_path(name, version))
+    }
+
+    /// Count a use of a crate's docs or source for garbage collection
+    fn record_access(&self, name: &str, version: &str) {
+        let Some(hits) = self.access_log.hit(name, version) else {
+            return;
+        };
+        if let Err(e) = self.storage.record_access(name, version, hits) {
+            tracing::debug!("Failed to record use of {}-{}: {}", name, version, e);
+        }
     }
 
     /// Get the source path for a crate
@@ -209,9 +1044,13 @@ impl CrateCache {
     ) -> Result<PathBuf> {
         // Check if crate is already downloaded
         if !self.storage.is_cached(name, version) {
-            self.download_or_copy_crate(name, version, source).await?;
+            let _lock = self.locks.lock(name, version).await?;
+            if !self.storage.is_cached(name, version) {
+                self.download_or_copy_crate(name, version, source).await?;
+            }
         }
 
+        self.record_access(name, version);
         Ok(self.storage.source_path(name, version))
     }
 
@@ -223,49 +1062,174 @@ impl CrateCache {
         member: Option<&str>,
         source: Option<&str>,
     ) -> Result<PathBuf> {
+        self.refresh_linked(name, version).await;
+
         // Ensure the crate source is downloaded
         let source_path = self.ensure_crate_source(name, version, source).await?;
 
         // If member is specified, return the member's source path
-        if let Some(member_path) = member {
-            let member_source_path = source_path.join(member_path);
-            let member_cargo_toml = member_source_path.join("Cargo.toml");
-
-            if !member_cargo_toml.exists() {
-                bail!(
-                    "Workspace member '{}' not found in {}-{}. \
-                    Make sure the member path is correct.",
-                    member_path,
-                    name,
-                    version
-                );
-            }
-
-            return Ok(member_source_path);
+        if let Some(member) = member {
+            let member = self.resolve_member(name, version, member).await?;
+            return Ok(source_path.join(member.path));
         }
 
         // Check if it's a workspace without member specified
-        let cargo_toml_path = source_path.join("Cargo.toml");
-        if cargo_toml_path.exists() && WorkspaceHandler::is_workspace(&cargo_toml_path)? {
-            let members = WorkspaceHandler::get_workspace_members(&cargo_toml_path)?;
-            bail!(
-                "This is a workspace crate. Please specify a member using the 'member' parameter.\n\
-                Available members: {:?}\n\
-                Example: specify member=\"{}\"",
-                members,
-                members.first().unwrap_or(&"crates/example".to_string())
-            );
+        if let Some(workspace) = self.virtual_workspace(&source_path).await? {
+            bail!(member_required(&workspace));
         }
 
         // Regular crate, return source path
         Ok(source_path)
     }
 
+    /// Start caching the dependencies of a cached crate in the background
+    ///
+    /// Follows the stored dependency graphs of the crate and of its documented
+    /// workspace members. Returns the number of dependencies found.
+    pub fn start_prefetch(
+        &self,
+        name: &str,
+        version: &str,
+        options: &PrefetchOptions,
+    ) -> Result<usize> {
+        let mut graphs = Vec::new();
+        if self.storage.has_docs(name, version) {
+            graphs.push((
+                self.storage.dependencies_path(name, version),
+                name.to_string(),
+            ));
+        }
+        for member in self.storage.list_workspace_members(name, version)? {
+            if self.storage.has_member_docs(name, version, &member) {
+                graphs.push((
+                    self.storage
+                        .member_dependencies_path(name, version, &member),
+                    member,
+                ));
+            }
+        }
+        if graphs.is_empty() {
+            bail!("{name}-{version} has no documented packages to prefetch dependencies of");
+        }
+
+        let mut targets: Vec<PrefetchTarget> = Vec::new();
+        for (path, root) in graphs {
+            let content =
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let metadata: serde_json::Value = serde_json::from_slice(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            let graph = DependencyGraph::from_metadata(&metadata)
+                .with_context(|| format!("Failed to read dependency graph {}", path.display()))?;
+            for target in prefetch::dependency_targets(&graph, &root, options)? {
+                match targets
+                    .iter_mut()
+                    .find(|t| t.name == target.name && t.version == target.version)
+                {
+                    Some(existing) => existing.depth = existing.depth.min(target.depth),
+                    None => targets.push(target),
+                }
+            }
+        }
+
+        let count = targets.len();
+        let key = format!("{name}-{version}");
+        if !self.prefetch_jobs.start(&key, targets) {
+            bail!("A prefetch of {key} is still running");
+        }
+        let cache = self.clone();
+        let root = (name.to_string(), version.to_string());
+        tokio::spawn(async move { cache.run_prefetch(&key, root).await });
+        Ok(count)
+    }
+
+    /// Cache the pending dependencies of a prefetch one at a time
+    ///
+    /// The crate `root` and the dependencies cached so far are kept when making
+    /// room for the next one.
+    async fn run_prefetch(&self, key: &str, root: (String, String)) {
+        let mut keep = HashSet::from([root]);
+        for (index, target) in self.prefetch_jobs.pending(key) {
+            self.prefetch_jobs
+                .set_status(key, index, PrefetchStatus::Caching);
+            let status = match self
+                .ensure_crate_docs_keeping(&target.name, &target.version, None, &keep)
+                .await
+            {
+                Ok(_) => {
+                    keep.insert((target.name.clone(), target.version.clone()));
+                    PrefetchStatus::Cached
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to prefetch {}-{}: {:#}",
+                        target.name,
+                        target.version,
+                        e
+                    );
+                    PrefetchStatus::Failed {
+                        error: format!("{e:#}"),
+                    }
+                }
+            };
+            self.prefetch_jobs.set_status(key, index, status);
+        }
+        self.prefetch_jobs.finish(key);
+    }
+
+    /// Progress of the latest prefetch of a crate version started by this server
+    pub fn prefetch_status(&self, name: &str, version: &str) -> Option<PrefetchJob> {
+        self.prefetch_jobs.get(&format!("{name}-{version}"))
+    }
+
+    /// Start a requested prefetch after a successful cache and report it in the response
+    fn attach_prefetch(
+        &self,
+        response: String,
+        name: &str,
+        version: &str,
+        options: &PrefetchOptions,
+    ) -> String {
+        let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&response) else {
+            return response;
+        };
+        if !matches!(
+            value["status"].as_str(),
+            Some("success" | "partial_success")
+        ) {
+            return response;
+        }
+
+        value["prefetch"] = match self.start_prefetch(name, version, options) {
+            Ok(dependencies) => serde_json::json!({
+                "status": "started",
+                "dependencies": dependencies,
+                "message": "Dependencies are cached in the background, use get_prefetch_status to follow progress",
+            }),
+            Err(e) => serde_json::json!({
+                "status": "error",
+                "error": format!("{e:#}"),
+            }),
+        };
+        serde_json::to_string(&value).unwrap_or(response)
+    }
+
     /// Load dependency information from cache
     pub async fn load_dependencies(&self, name: &str, version: &str) -> Result<serde_json::Value> {
         self.doc_generator.load_dependencies(name, version).await
     }
 
+    /// Load dependency information of a workspace member from cache
+    pub async fn load_member_dependencies(
+        &self,
+        name: &str,
+        version: &str,
+        member_name: &str,
+    ) -> Result<serde_json::Value> {
+        self.doc_generator
+            .load_member_dependencies(name, version, member_name)
+            .await
+    }
+
     /// Internal implementation for caching a crate during update
     async fn cache_crate_with_update_impl(
         &self,
@@ -275,6 +1239,12 @@ impl CrateCache {
         source_str: Option<&str>,
         source: &CrateSource,
     ) -> Result<CacheResponse> {
+        if source.all_members() {
+            return self
+                .cache_all_members(crate_name, version, source_str, true)
+                .await;
+        }
+
         // If members are specified, cache those specific workspace members
         if let Some(members) = members {
             let response = self
@@ -299,17 +1269,27 @@ impl CrateCache {
             .await?;
 
         // Check if it's a workspace
-        let cargo_toml_path = source_path.join("Cargo.toml");
-        if WorkspaceHandler::is_workspace(&cargo_toml_path)? {
-            // It's a workspace, get the members
-            let members = WorkspaceHandler::get_workspace_members(&cargo_toml_path)?;
-            Ok(self.generate_workspace_response(crate_name, version, members, source, true))
-        } else {
-            // Not a workspace, proceed with normal caching
-            self.ensure_crate_docs(crate_name, version, source_str)
-                .await?;
+        match self.resolve_workspace(&source_path).await? {
+            Some(workspace) if workspace.root_package.is_none() => {
+                Ok(self.generate_workspace_response(crate_name, version, &workspace, source, true))
+            }
+            Some(workspace) => {
+                self.build_crate_docs(crate_name, version, source_str)
+                    .await?;
+                Ok(CacheResponse::root_package_success(
+                    crate_name,
+                    version,
+                    workspace.member_paths(),
+                    true,
+                ))
+            }
+            None => {
+                // Not a workspace, proceed with normal caching
+                self.build_crate_docs(crate_name, version, source_str)
+                    .await?;
 
-            Ok(CacheResponse::success_updated(crate_name, version))
+                Ok(CacheResponse::success_updated(crate_name, version))
+            }
         }
     }
 
@@ -363,6 +1343,8 @@ impl CrateCache {
     }
 
     /// Handle caching workspace members
+    ///
+    /// Members are given by package name or path. The caller must hold the crate lock.
     async fn cache_workspace_members(
         &self,
         crate_name: &str,
@@ -371,44 +1353,40 @@ impl CrateCache {
         source_str: Option<&str>,
         updated: bool,
     ) -> CacheResponse {
-        use futures::future::join_all;
-
-        // Create futures for all member caching operations
-        let member_futures: Vec<_> = members
-            .iter()
-            .map(|member| {
-                let member_clone = member.clone();
-                async move {
-                    let result = self
-                        .ensure_workspace_member_docs(
-                            crate_name,
-                            version,
-                            source_str,
-                            &member_clone,
-                        )
-                        .await;
-                    (member_clone, result)
-                }
-            })
-            .collect();
-
-        // Execute all futures concurrently
-        let results_with_members = join_all(member_futures).await;
+        let workspace = match self
+            .download_workspace(crate_name, version, source_str)
+            .await
+        {
+            Ok(workspace) => workspace,
+            Err(e) => {
+                let errors = members
+                    .iter()
+                    .map(|member| format!("Failed to cache member {member}: {e}"))
+                    .collect();
+                return CacheResponse::members_partial(
+                    crate_name,
+                    version,
+                    members.to_vec(),
+                    Vec::new(),
+                    errors,
+                    updated,
+                );
+            }
+        };
 
-        // Collect results and errors
-        let mut results = Vec::new();
+        let mut selected = Vec::new();
         let mut errors = Vec::new();
-
-        for (member, result) in results_with_members {
-            match result {
-                Ok(_) => {
-                    results.push(format!("Successfully cached member: {member}"));
-                }
-                Err(e) => {
-                    errors.push(format!("Failed to cache member {member}: {e}"));
-                }
+        for member in members {
+            match workspace.find_member(member) {
+                Some(info) => selected.push((member.clone(), info.clone())),
+                None => errors.push(format!(
+                    "Failed to cache member {member}: not found in workspace. Available members: {:?}",
+                    workspace.member_paths()
+                )),
             }
         }
+        let (results, member_errors) = self.document_members(crate_name, version, &selected).await;
+        errors.extend(member_errors);
 
         if errors.is_empty() {
             CacheResponse::members_success(crate_name, version, members.to_vec(), results, updated)
@@ -424,12 +1402,177 @@ impl CrateCache {
         }
     }
 
+    /// Document the root package and all library members of a workspace
+    ///
+    /// The caller must hold the crate lock.
+    async fn cache_all_members(
+        &self,
+        crate_name: &str,
+        version: &str,
+        source_str: Option<&str>,
+        updated: bool,
+    ) -> Result<CacheResponse> {
+        let workspace = self
+            .download_workspace(crate_name, version, source_str)
+            .await?;
+
+        let mut results = Vec::new();
+        let mut errors = Vec::new();
+        if workspace.root_package.is_some() {
+            match self.build_crate_docs(crate_name, version, source_str).await {
+                Ok(()) => results.push(format!("Successfully cached root package: {crate_name}")),
+                Err(e) => errors.push(format!("Failed to cache root package {crate_name}: {e}")),
+            }
+        }
+
+        let selected: Vec<_> = workspace
+            .members
+            .iter()
+            .filter(|member| member.lib_name.is_some())
+            .map(|member| (member.name.clone(), member.clone()))
+            .collect();
+        let (member_results, member_errors) =
+            self.document_members(crate_name, version, &selected).await;
+        results.extend(member_results);
+        errors.extend(member_errors);
+
+        if results.is_empty() {
+            bail!("Failed to cache any workspace members: {:?}", errors);
+        }
+        let members = selected.into_iter().map(|(name, _)| name).collect();
+        Ok(if errors.is_empty() {
+            CacheResponse::members_success(crate_name, version, members, results, updated)
+        } else {
+            CacheResponse::members_partial(crate_name, version, members, results, errors, updated)
+        })
+    }
+
+    /// Download a workspace unless it is cached and resolve its members
+    ///
+    /// The caller must hold the crate lock.
+    async fn download_workspace(
+        &self,
+        name: &str,
+        version: &str,
+        source: Option<&str>,
+    ) -> Result<Workspace> {
+        if !self.storage.is_cached(name, version) {
+            self.download_or_copy_crate(name, version, source).await?;
+        }
+        let source_path = self.storage.source_path(name, version);
+        self.resolve_workspace(&source_path)
+            .await?
+            .with_context(|| format!("{name}-{version} is not a workspace"))
+    }
+
+    /// Document workspace members that aren't documented yet
+    ///
+    /// All library members are documented by one `cargo doc`, so they share a
+    /// single build of their dependencies. Members are paired with the name they
+    /// were requested by, which the returned result and error messages refer to.
+    /// The caller must hold the crate lock.
+    async fn document_members(
+        &self,
+        name: &str,
+        version: &str,
+        members: &[(String, WorkspaceMember)],
+    ) -> (Vec<String>, Vec<String>) {
+        let mut pending: Vec<WorkspaceMember> = Vec::new();
+        for (_, member) in members {
+            if !self.storage.has_member_docs(name, version, &member.name)
+                && !pending.iter().any(|p| p.name == member.name)
+            {
+                pending.push(member.clone());
+            }
+        }
+        let (libraries, others): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|member| member.lib_name.is_some());
+
+        let mut outcomes: HashMap<String, Result<(), String>> = HashMap::new();
+        if !libraries.is_empty() {
+            if let Err(e) = self.storage.copy_up(name, version) {
+                for member in &libraries {
+                    outcomes.insert(member.name.clone(), Err(e.to_string()));
+                }
+            } else {
+                match self
+                    .doc_generator
+                    .generate_workspace_docs(name, version, &libraries)
+                    .await
+                {
+                    Ok(results) => {
+                        for (member, result) in libraries.iter().zip(results) {
+                            let outcome = match result {
+                                Ok(docs_path) => {
+                                    self.update_search_index(
+                                        name,
+                                        version,
+                                        Some(&member.name),
+                                        &docs_path,
+                                    )
+                                    .await;
+                                    Ok(())
+                                }
+                                Err(e) => Err(e.to_string()),
+                            };
+                            outcomes.insert(member.name.clone(), outcome);
+                        }
+                    }
+                    Err(e) => {
+                        for member in &libraries {
+                            outcomes.insert(member.name.clone(), Err(e.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+        for member in &others {
+            let outcome = self
+                .generate_workspace_member_docs(name, version, member)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string());
+            outcomes.insert(member.name.clone(), outcome);
+        }
+        if outcomes.values().any(Result::is_ok) {
+            self.remove_build_artifacts(name, version);
+        }
+
+        let mut results = Vec::new();
+        let mut errors = Vec::new();
+        for (requested, member) in members {
+            match outcomes.get(&member.name) {
+                Some(Err(e)) => errors.push(format!("Failed to cache member {requested}: {e}")),
+                _ => results.push(format!("Successfully cached member: {requested}")),
+            }
+        }
+        (results, errors)
+    }
+
+    /// Resolve the workspace rooted at a source directory, see [`WorkspaceHandler::resolve`]
+    async fn resolve_workspace(&self, source_path: &Path) -> Result<Option<Workspace>> {
+        let source_path = source_path.to_path_buf();
+        tokio::task::spawn_blocking(move || WorkspaceHandler::resolve(&source_path))
+            .await
+            .context("Workspace resolution task panicked")?
+    }
+
+    /// Resolve a workspace that has no root package, so a member has to be selected
+    async fn virtual_workspace(&self, source_path: &Path) -> Result<Option<Workspace>> {
+        if !source_path.join("Cargo.toml").exists() {
+            return Ok(None);
+        }
+        let workspace = self.resolve_workspace(source_path).await?;
+        Ok(workspace.filter(|workspace| workspace.root_package.is_none()))
+    }
+
     /// Generate workspace detection response
     fn generate_workspace_response(
         &self,
         crate_name: &str,
         version: &str,
-        members: Vec<String>,
+        workspace: &Workspace,
         source: &CrateSource,
         updated: bool,
     ) -> CacheResponse {
@@ -439,7 +1582,7 @@ impl CrateCache {
             CrateSource::LocalPath(_) => "local",
         };
 
-        CacheResponse::workspace_detected(crate_name, version, members, source_type, updated)
+        CacheResponse::workspace_detected(crate_name, version, workspace, source_type, updated)
     }
 
     /// Handle update operation for a crate
@@ -451,6 +1594,9 @@ impl CrateCache {
         source_str: Option<&str>,
         source: &CrateSource,
     ) -> String {
+        // The update replaces the metadata, keep the usage statistics and pin
+        let previous = self.storage.load_metadata(crate_name, version).ok();
+
         // Create transaction for safe update
         let mut transaction = CacheTransaction::new(&self.storage, crate_name, version);
 
@@ -475,6 +1621,11 @@ impl CrateCache {
                     ))
                     .to_json();
                 }
+                if let Some(previous) = previous
+                    && let Err(e) = self.storage.restore_usage(&previous)
+                {
+                    tracing::debug!("Failed to restore usage of {crate_name}-{version}: {e}");
+                }
                 response.to_json()
             }
             Err(e) => {
@@ -507,22 +1658,29 @@ impl CrateCache {
         source_str: Option<&str>,
         updated: bool,
     ) -> Result<CacheResponse> {
-        let cargo_toml_path = source_path.join("Cargo.toml");
-
-        match WorkspaceHandler::is_workspace(&cargo_toml_path) {
-            Ok(true) => {
-                // It's a workspace, get the members
-                let members = WorkspaceHandler::get_workspace_members(&cargo_toml_path)
-                    .context("Failed to get workspace members")?;
-                Ok(self.generate_workspace_response(crate_name, version, members, source, updated))
+        match self.resolve_workspace(source_path).await {
+            Ok(Some(workspace)) if workspace.root_package.is_none() => {
+                Ok(self
+                    .generate_workspace_response(crate_name, version, &workspace, source, updated))
+            }
+            Ok(Some(workspace)) => {
+                self.cache_regular_crate(crate_name, version, source_str)
+                    .await?;
+                Ok(CacheResponse::root_package_success(
+                    crate_name,
+                    version,
+                    workspace.member_paths(),
+                    updated,
+                ))
             }
-            Ok(false) => {
+            Ok(None) => {
                 // Not a workspace, proceed with normal caching
                 self.cache_regular_crate(crate_name, version, source_str)
                     .await
             }
-            Err(_e) => {
+            Err(e) => {
                 // Error checking workspace status, try normal caching anyway
+                tracing::warn!("Failed to resolve workspace members: {}", e);
                 self.cache_regular_crate(crate_name, version, source_str)
                     .await
             }
@@ -536,7 +1694,7 @@ impl CrateCache {
         version: &str,
         source_str: Option<&str>,
     ) -> Result<CacheResponse> {
-        self.ensure_crate_docs(crate_name, version, source_str)
+        self.build_crate_docs(crate_name, version, source_str)
             .await
             .context("Failed to cache crate")?;
         Ok(CacheResponse::success(crate_name, version))
@@ -544,6 +1702,60 @@ impl CrateCache {
 
     /// Common method to cache a crate from any source
     pub async fn cache_crate_with_source(&self, source: CrateSource) -> String {
+        let prefetch = match source.prefetch() {
+            Ok(prefetch) => prefetch,
+            Err(e) => {
+                return CacheResponse::error(format!("Invalid prefetch options: {e:#}")).to_json();
+            }
+        };
+        let (crate_name, version, ..) = self.extract_source_params(&source);
+
+        // Fingerprint a linked directory before copying it, so changes made while
+        // it is being cached are picked up by the first refresh
+        let link = match &source {
+            CrateSource::LocalPath(params) if params.linked == Some(true) => {
+                let local_path = params.path.clone();
+                match tokio::task::spawn_blocking(move || LinkState::capture(&local_path)).await {
+                    Ok(Ok(state)) => {
+                        Some((params.crate_name.clone(), params.version.clone(), state))
+                    }
+                    Ok(Err(e)) => {
+                        return CacheResponse::error(format!("Failed to link local crate: {e:#}"))
+                            .to_json();
+                    }
+                    Err(e) => {
+                        return CacheResponse::error(format!("Failed to link local crate: {e}"))
+                            .to_json();
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let response = self.cache_crate_with_source_impl(source).await;
+
+        if let Some((name, version, state)) = link
+            && self.storage.is_cached(&name, &version)
+        {
+            let result = match self.locks.lock(&name, &version).await {
+                Ok(_lock) => state.save(&self.storage.link_path(&name, &version)),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                tracing::warn!("Failed to link {}-{}: {}", name, version, e);
+            }
+        }
+
+        self.enforce_quota(&HashSet::from([(crate_name.clone(), version.clone())]))
+            .await;
+        match prefetch {
+            Some(options) => self.attach_prefetch(response, &crate_name, &version, &options),
+            None => response,
+        }
+    }
+
+    /// Cache a crate from any source while holding its lock
+    async fn cache_crate_with_source_impl(&self, source: CrateSource) -> String {
         // Extract parameters from source
         let (crate_name, version, members, source_str, update) =
             self.extract_source_params(&source);
@@ -553,6 +1765,14 @@ impl CrateCache {
             return CacheResponse::error("Either branch or tag must be specified").to_json();
         }
 
+        // Everything below downloads or rebuilds this entry
+        let _lock = match self.locks.lock(&crate_name, &version).await {
+            Ok(lock) => lock,
+            Err(e) => {
+                return CacheResponse::error(format!("Failed to lock crate cache: {e}")).to_json();
+            }
+        };
+
         // Handle update logic if requested
         if update && self.storage.is_cached(&crate_name, &version) {
             return self
@@ -566,6 +1786,16 @@ impl CrateCache {
                 .await;
         }
 
+        if source.all_members() {
+            return match self
+                .cache_all_members(&crate_name, &version, source_str.as_deref(), false)
+                .await
+            {
+                Ok(response) => response.to_json(),
+                Err(e) => CacheResponse::error(format!("Failed to cache workspace: {e}")).to_json(),
+            };
+        }
+
         // If members are specified, cache those specific workspace members
         if let Some(members) = members {
             let response = self
@@ -608,3 +1838,68 @@ impl CrateCache {
         }
     }
 }
+
+/// Error message for a workspace queried without selecting one of its members
+fn member_required(workspace: &Workspace) -> String {
+    let members = workspace.member_paths();
+    let example = workspace
+        .default_members
+        .first()
+        .or(members.first())
+        .map_or("crates/example", String::as_str);
+    format!(
+        "This is a workspace crate. Please specify a member using the 'member' parameter.\n\
+        Available members: {members:?}\n\
+        Example: specify member=\"{example}\""
+    )
+}
+
+/// Remove a file or directory, succeeding if it is already gone
+fn remove_path(path: &Path) -> Result<()> {
+    let result = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    match result {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::utils::test_support::write;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_quota_keeps_crate_just_cached() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = CacheConfig {
+            quota: GcQuota {
+                max_bytes: 100,
+                max_crates: 0,
+            },
+            ..CacheConfig::default()
+        };
+        let cache = CrateCache::new(Some(temp_dir.path().to_path_buf()), config)?;
+
+        for name in ["old", "new"] {
+            let crate_path = cache.storage.crate_path(name, "1.0.0");
+            write(&crate_path, "source/src/lib.rs", "");
+            write(&crate_path, "dependencies.json", "{}");
+            fs::write(cache.storage.docs_path(name, "1.0.0"), vec![b' '; 500])?;
+            cache.storage.save_metadata(name, "1.0.0")?;
+        }
+
+        // The new crate doesn't fit into the quota even on its own
+        let keep = HashSet::from([("new".to_string(), "1.0.0".to_string())]);
+        cache.enforce_quota(&keep).await;
+        assert!(!cache.storage.is_cached("old", "1.0.0"));
+        assert!(cache.storage.has_docs("new", "1.0.0"));
+        Ok(())
+    }
+}
@@ -1,15 +1,28 @@
 use anyhow::{Context, Result, bail};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::cache::link::LINK_FILE_NAME;
+use crate::cache::migrations::FORMAT_FILE_NAME;
 use crate::cache::types::CrateIdentifier;
-use crate::cache::utils::copy_directory_contents;
+use crate::cache::utils::{copy_directory_contents, write_atomic};
+use crate::cache::workspace::WorkspaceMember;
+use crate::docs::DocIndex;
+use crate::docs::cache::DocsFingerprint;
+use crate::docs::index::INDEX_FILE_NAME;
 
 /// Manages the file system storage for cached crates and their documentation
+///
+/// Besides its own cache directory, the storage can look up crates in read-only
+/// layers: other cache directories, e.g. a team cache on a network mount, that
+/// are searched in order for entries missing from the cache directory. New
+/// entries and all modifications only ever go to the cache directory.
 #[derive(Debug, Clone)]
 pub struct CacheStorage {
     cache_dir: PathBuf,
+    read_only_layers: Vec<PathBuf>,
 }
 
 /// Metadata about a cached crate
@@ -24,8 +37,55 @@ pub struct CrateMetadata {
     pub source: String,
     #[serde(default)]
     pub source_path: Option<String>,
+    /// Breakdown of `size_bytes`, missing in metadata written by older versions
+    #[serde(default)]
+    pub sizes: CacheSizes,
+    /// When the crate's docs or source were last used, `None` if never used since caching
+    #[serde(default)]
+    pub last_accessed: Option<chrono::DateTime<chrono::Utc>>,
+    /// Number of times the crate's docs or source were used
+    #[serde(default)]
+    pub access_count: u64,
+    /// Pinned crates are never evicted by garbage collection
+    #[serde(default)]
+    pub pinned: bool,
+    /// Files and directories of a local source left out when copying it, see [`crate::cache::filter`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_paths: Vec<String>,
+    /// Checksum of the `.crate` file a vendored crate was unpacked from, as recorded by `cargo vendor`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+impl CrateMetadata {
+    /// Time of the last use, counting caching as a use
+    pub fn last_used(&self) -> chrono::DateTime<chrono::Utc> {
+        self.last_accessed
+            .unwrap_or(self.cached_at)
+            .max(self.cached_at)
+    }
+}
+
+/// Disk usage of a cached crate version, split by purpose
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct CacheSizes {
+    /// Source code, excluding build artifacts
+    pub source_bytes: u64,
+    /// Documentation, indexes, dependency and cache metadata, including workspace members
+    pub docs_bytes: u64,
+    /// Build artifacts left in `source/target`
+    pub build_bytes: u64,
 }
 
+/// File name of the compressed rustdoc JSON documentation
+pub const DOCS_FILE_NAME: &str = "docs.json.gz";
+
+/// File name of uncompressed documentation written by older versions
+pub const LEGACY_DOCS_FILE_NAME: &str = "docs.json";
+
+/// File name of the package identity of a cached workspace member
+const MEMBER_FILE_NAME: &str = "member.json";
+
 /// Default source for backward compatibility
 fn default_source() -> String {
     "crates.io".to_string()
@@ -44,7 +104,166 @@ impl CacheStorage {
 
         fs::create_dir_all(&cache_dir).context("Failed to create cache directory")?;
 
-        Ok(Self { cache_dir })
+        Ok(Self {
+            cache_dir,
+            read_only_layers: Vec::new(),
+        })
+    }
+
+    /// Look up crates missing from the cache directory in these read-only caches, in order
+    pub fn with_read_only_layers(mut self, layers: Vec<PathBuf>) -> Result<Self> {
+        for layer in layers {
+            if !layer.is_dir() {
+                bail!("Read-only cache layer {} does not exist", layer.display());
+            }
+            if layer != self.cache_dir && !self.read_only_layers.contains(&layer) {
+                self.read_only_layers.push(layer);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Get the writable cache directory
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Get the read-only layers below the cache directory
+    pub fn read_only_layers(&self) -> &[PathBuf] {
+        &self.read_only_layers
+    }
+
+    /// Get the cache directory and the read-only layers, in lookup order
+    fn layers(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.cache_dir.as_path())
+            .chain(self.read_only_layers.iter().map(PathBuf::as_path))
+    }
+
+    /// Get the cache directory or read-only layer holding a crate version
+    ///
+    /// Crates that are not cached anywhere belong to the cache directory.
+    pub fn layer_of(&self, name: &str, version: &str) -> &Path {
+        let relative = Path::new("crates").join(name).join(version);
+        self.layers()
+            .find(|layer| layer.join(&relative).exists())
+            .unwrap_or(&self.cache_dir)
+    }
+
+    /// Check if a crate version is only cached in a read-only layer
+    pub fn is_read_only(&self, name: &str, version: &str) -> bool {
+        self.layer_of(name, version) != self.cache_dir
+    }
+
+    /// Fail with a helpful message if a crate version can't be modified
+    fn ensure_writable(&self, name: &str, version: &str) -> Result<()> {
+        if self.is_read_only(name, version) {
+            bail!(
+                "{name}-{version} is cached in the read-only layer {}",
+                self.layer_of(name, version).display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Copy a crate version from a read-only layer into the cache directory
+    ///
+    /// Does nothing for crates that are not in a read-only layer. From then on the
+    /// copy shadows the read-only entry, so it can be modified. Build artifacts are
+    /// not copied.
+    pub fn copy_up(&self, name: &str, version: &str) -> Result<()> {
+        if !self.is_read_only(name, version) {
+            return Ok(());
+        }
+        tracing::info!(
+            "Copying {}-{} from read-only layer {}",
+            name,
+            version,
+            self.layer_of(name, version).display()
+        );
+
+        let entry_dir = self.crate_path(name, version);
+        let staging = self.create_temp_dir(&format!("{name}-{version}-copy-"))?;
+        let staged = staging.path().join("entry");
+        copy_directory_contents(&entry_dir, &staged)?;
+        let staged_build_dir = staged.join("source").join("target");
+        if staged_build_dir.exists() {
+            fs::remove_dir_all(&staged_build_dir)?;
+        }
+
+        let writable_source = self.writable_crate_path(name, version).join("source");
+        Self::rewrite_dependency_paths(
+            &staged,
+            &self.source_path(name, version),
+            &writable_source,
+        )?;
+        self.install_entry(name, version, &staged)?;
+
+        // The copy keeps its indexes next to its docs from now on
+        let layer_indexes = self
+            .layer_indexes_dir()
+            .join("crates")
+            .join(name)
+            .join(version);
+        if layer_indexes.exists() {
+            let _ = fs::remove_dir_all(&layer_indexes);
+        }
+        Ok(())
+    }
+
+    /// Get the directory holding the private cargo home and home used by sandboxed builds
+    pub fn sandbox_dir(&self) -> PathBuf {
+        self.cache_dir.join("sandbox")
+    }
+
+    /// Get the directory holding the cross-process lock files of cache entries
+    pub fn locks_dir(&self) -> PathBuf {
+        self.cache_dir.join("locks")
+    }
+
+    /// Get the default directory holding the target directories shared between builds
+    pub fn targets_dir(&self) -> PathBuf {
+        self.cache_dir.join("targets")
+    }
+
+    /// Get the directory in which downloads and builds are staged
+    pub fn temp_dir(&self) -> PathBuf {
+        self.cache_dir.join("tmp")
+    }
+
+    /// Get the directory holding the backups of crates being updated
+    ///
+    /// Backups live in the system temp directory, outside of the cache.
+    pub fn backup_root() -> PathBuf {
+        std::env::temp_dir().join("rust-docs-mcp-backup")
+    }
+
+    /// Get the file listing the projects registered with the cache
+    pub fn projects_path(&self) -> PathBuf {
+        self.cache_dir.join("projects.json")
+    }
+
+    /// Get the file recording the format version of the cache
+    pub fn format_path(&self) -> PathBuf {
+        self.cache_dir.join(FORMAT_FILE_NAME)
+    }
+
+    /// Get the directory holding the segments of the cross-crate search index
+    pub fn search_dir(&self) -> PathBuf {
+        self.cache_dir.join("search")
+    }
+
+    /// Create a uniquely named temporary directory inside the cache
+    ///
+    /// Staging downloads inside the cache directory keeps them on the same file
+    /// system as their final location, so they can be moved into place with a rename.
+    /// The directory is removed when the returned handle is dropped.
+    pub fn create_temp_dir(&self, prefix: &str) -> Result<tempfile::TempDir> {
+        let tmp_dir = self.temp_dir();
+        self.ensure_dir(&tmp_dir)?;
+        tempfile::Builder::new()
+            .prefix(prefix)
+            .tempdir_in(&tmp_dir)
+            .context("Failed to create temporary directory")
     }
 
     /// Get the path for a specific crate version
@@ -53,13 +272,20 @@ impl CacheStorage {
     }
 
     /// Get the path for a specific crate using CrateIdentifier
+    ///
+    /// This is the entry in the first layer that has the crate, see [`Self::layer_of`].
     pub fn crate_path_for_id(&self, crate_id: &CrateIdentifier) -> PathBuf {
-        self.cache_dir
+        self.layer_of(crate_id.name(), crate_id.version())
             .join("crates")
             .join(crate_id.name())
             .join(crate_id.version())
     }
 
+    /// Get the path of a crate version in the cache directory, ignoring read-only layers
+    fn writable_crate_path(&self, name: &str, version: &str) -> PathBuf {
+        self.cache_dir.join("crates").join(name).join(version)
+    }
+
     /// Get the path for a specific workspace member
     pub fn member_path(&self, name: &str, version: &str, member_name: &str) -> PathBuf {
         self.crate_path(name, version)
@@ -72,15 +298,76 @@ impl CacheStorage {
         self.crate_path(name, version).join("source")
     }
 
+    /// Get the build directory of a crate's source
+    pub fn build_dir(&self, name: &str, version: &str) -> PathBuf {
+        self.source_path(name, version).join("target")
+    }
+
     /// Get the documentation JSON path for a crate
     pub fn docs_path(&self, name: &str, version: &str) -> PathBuf {
-        self.crate_path(name, version).join("docs.json")
+        Self::docs_file(&self.crate_path(name, version))
     }
 
     /// Get the documentation JSON path for a workspace member
     pub fn member_docs_path(&self, name: &str, version: &str, member_name: &str) -> PathBuf {
-        self.member_path(name, version, member_name)
-            .join("docs.json")
+        Self::docs_file(&self.member_path(name, version, member_name))
+    }
+
+    /// Get the documentation file in an entry directory
+    ///
+    /// Documentation is stored compressed, but entries cached by older versions
+    /// keep their uncompressed `docs.json` until they are regenerated.
+    fn docs_file(dir: &Path) -> PathBuf {
+        let compressed = dir.join(DOCS_FILE_NAME);
+        let legacy = dir.join(LEGACY_DOCS_FILE_NAME);
+        if !compressed.exists() && legacy.exists() {
+            legacy
+        } else {
+            compressed
+        }
+    }
+
+    /// Get the binary documentation index path for a crate, see [`Self::index_path_of`]
+    pub fn docs_index_path(&self, name: &str, version: &str) -> PathBuf {
+        self.index_path_of(name, version, &self.docs_path(name, version))
+    }
+
+    /// Get the binary documentation index path for a workspace member
+    pub fn member_docs_index_path(&self, name: &str, version: &str, member_name: &str) -> PathBuf {
+        self.index_path_of(
+            name,
+            version,
+            &self.member_docs_path(name, version, member_name),
+        )
+    }
+
+    /// Get the path of the binary index of a documentation file
+    ///
+    /// The index lives next to the docs. Layers can't be written to, so when a
+    /// read-only entry has no index matching its docs, e.g. because the layer was
+    /// created before indexes existed, it is kept under `layer-indexes/` in the
+    /// cache directory instead.
+    fn index_path_of(&self, name: &str, version: &str, docs_path: &Path) -> PathBuf {
+        let index_path = docs_path.with_file_name(INDEX_FILE_NAME);
+        let layer = self.layer_of(name, version);
+        if layer == self.cache_dir {
+            return index_path;
+        }
+        let usable = DocsFingerprint::of(docs_path)
+            .and_then(|fingerprint| DocIndex::open(&index_path, fingerprint))
+            .is_ok();
+        if usable {
+            return index_path;
+        }
+        match index_path.strip_prefix(layer) {
+            Ok(relative) => self.layer_indexes_dir().join(relative),
+            Err(_) => index_path,
+        }
+    }
+
+    /// Get the directory holding the indexes of read-only entries, see [`Self::index_path_of`]
+    fn layer_indexes_dir(&self) -> PathBuf {
+        self.cache_dir.join("layer-indexes")
     }
 
     /// Get the metadata path for a crate
@@ -88,10 +375,10 @@ impl CacheStorage {
         self.crate_path(name, version).join("metadata.json")
     }
 
-    /// Get the metadata path for a workspace member
-    pub fn member_metadata_path(&self, name: &str, version: &str, member_name: &str) -> PathBuf {
+    /// Get the path of a workspace member's package name, id and path
+    pub fn member_info_path(&self, name: &str, version: &str, member_name: &str) -> PathBuf {
         self.member_path(name, version, member_name)
-            .join("metadata.json")
+            .join(MEMBER_FILE_NAME)
     }
 
     /// Get the dependencies path for a crate
@@ -110,24 +397,35 @@ impl CacheStorage {
             .join("dependencies.json")
     }
 
+    /// Get the link state of a crate cached from a local path in linked mode
+    pub fn link_path(&self, name: &str, version: &str) -> PathBuf {
+        self.crate_path(name, version).join(LINK_FILE_NAME)
+    }
+
+    /// Check if a crate is linked to the local directory it was cached from
+    pub fn is_linked(&self, name: &str, version: &str) -> bool {
+        self.link_path(name, version).exists()
+    }
+
     /// Check if a crate version is cached
     pub fn is_cached(&self, name: &str, version: &str) -> bool {
         self.crate_path(name, version).exists()
     }
 
     /// Check if documentation is generated for a crate
+    ///
+    /// Dependency information is written after the docs, so docs without it are
+    /// left over from an interrupted build and don't count.
     pub fn has_docs(&self, name: &str, version: &str) -> bool {
-        self.docs_path(name, version).exists()
+        self.docs_path(name, version).exists() && self.dependencies_path(name, version).exists()
     }
 
-    /// Check if a workspace member is cached
-    pub fn is_member_cached(&self, name: &str, version: &str, member_name: &str) -> bool {
-        self.member_path(name, version, member_name).exists()
-    }
-
-    /// Check if documentation is generated for a workspace member
+    /// Check if documentation is generated for a workspace member, see [`Self::has_docs`]
     pub fn has_member_docs(&self, name: &str, version: &str, member_name: &str) -> bool {
         self.member_docs_path(name, version, member_name).exists()
+            && self
+                .member_dependencies_path(name, version, member_name)
+                .exists()
     }
 
     /// Ensure a directory exists
@@ -162,7 +460,7 @@ impl CacheStorage {
 
     /// Save metadata for a crate
     pub fn save_metadata(&self, name: &str, version: &str) -> Result<()> {
-        self.save_metadata_with_source(name, version, "crates.io", None)
+        self.save_metadata_with_source(name, version, "crates.io", None, &[])
     }
 
     /// Save metadata for a crate with source information
@@ -172,9 +470,11 @@ impl CacheStorage {
         version: &str,
         source: &str,
         source_path: Option<&str>,
+        skipped_paths: &[String],
     ) -> Result<()> {
-        let crate_path = self.crate_path(name, version);
-        let size_bytes = self.calculate_dir_size(&crate_path)?;
+        let (size_bytes, sizes) = self.measure_sizes(name, version)?;
+        // Re-caching a crate keeps its usage statistics and pin
+        let previous = self.load_metadata(name, version).ok();
 
         let metadata = CrateMetadata {
             name: name.to_string(),
@@ -184,11 +484,184 @@ impl CacheStorage {
             size_bytes,
             source: source.to_string(),
             source_path: source_path.map(String::from),
+            sizes,
+            last_accessed: previous.as_ref().and_then(|m| m.last_accessed),
+            access_count: previous.as_ref().map_or(0, |m| m.access_count),
+            pinned: previous.is_some_and(|m| m.pinned),
+            skipped_paths: skipped_paths.to_vec(),
+            checksum: None,
         };
 
-        let metadata_path = self.metadata_path(name, version);
-        let json = serde_json::to_string_pretty(&metadata)?;
-        fs::write(metadata_path, json)?;
+        self.write_metadata(&metadata)
+    }
+
+    /// Record the checksum of the `.crate` file a crate was unpacked from
+    pub fn set_checksum(&self, name: &str, version: &str, checksum: &str) -> Result<()> {
+        let mut metadata = self.load_metadata(name, version)?;
+        metadata.checksum = Some(checksum.to_string());
+        self.write_metadata(&metadata)
+    }
+
+    /// Recompute the disk usage recorded in a crate's metadata
+    pub fn update_sizes(&self, name: &str, version: &str) -> Result<()> {
+        let mut metadata = self.load_metadata(name, version)?;
+        (metadata.size_bytes, metadata.sizes) = self.measure_sizes(name, version)?;
+        metadata.doc_generated = self.has_docs(name, version);
+        self.write_metadata(&metadata)
+    }
+
+    /// Record uses of a crate's docs or source in its metadata
+    ///
+    /// Uses of crates in read-only layers are not recorded.
+    pub fn record_access(&self, name: &str, version: &str, hits: u64) -> Result<()> {
+        if self.is_read_only(name, version) {
+            return Ok(());
+        }
+        let mut metadata = self.load_metadata(name, version)?;
+        metadata.last_accessed = Some(chrono::Utc::now());
+        metadata.access_count += hits;
+        self.write_metadata(&metadata)
+    }
+
+    /// Pin or unpin a crate, protecting it from garbage collection
+    pub fn set_pinned(&self, name: &str, version: &str, pinned: bool) -> Result<()> {
+        if self.is_read_only(name, version) {
+            bail!(
+                "{name}-{version} is cached in the read-only layer {}, which garbage collection never evicts from",
+                self.layer_of(name, version).display()
+            );
+        }
+        let mut metadata = self
+            .load_metadata(name, version)
+            .with_context(|| format!("{name}-{version} is not cached"))?;
+        metadata.pinned = pinned;
+        self.write_metadata(&metadata)
+    }
+
+    /// Copy usage statistics and pin from metadata of an earlier copy of a crate
+    pub fn restore_usage(&self, previous: &CrateMetadata) -> Result<()> {
+        let mut metadata = self.load_metadata(&previous.name, &previous.version)?;
+        metadata.last_accessed = previous.last_accessed;
+        metadata.access_count = previous.access_count;
+        metadata.pinned = previous.pinned;
+        self.write_metadata(&metadata)
+    }
+
+    /// Measure the total disk usage of a crate version and its breakdown
+    fn measure_sizes(&self, name: &str, version: &str) -> Result<(u64, CacheSizes)> {
+        let total = self.calculate_dir_size(&self.crate_path(name, version))?;
+        let source_total = self.calculate_dir_size(&self.source_path(name, version))?;
+        let build_bytes = self.calculate_dir_size(&self.build_dir(name, version))?;
+
+        Ok((
+            total,
+            CacheSizes {
+                source_bytes: source_total.saturating_sub(build_bytes),
+                docs_bytes: total.saturating_sub(source_total),
+                build_bytes,
+            },
+        ))
+    }
+
+    fn write_metadata(&self, metadata: &CrateMetadata) -> Result<()> {
+        self.ensure_writable(&metadata.name, &metadata.version)?;
+        let metadata_path = self.metadata_path(&metadata.name, &metadata.version);
+        let json = serde_json::to_string_pretty(metadata)?;
+        write_atomic(&metadata_path, json.as_bytes())?;
+        Ok(())
+    }
+
+    /// Delete the build artifacts of a crate, which are not needed once its docs exist
+    pub fn remove_build_artifacts(&self, name: &str, version: &str) -> Result<()> {
+        if self.is_read_only(name, version) {
+            return Ok(());
+        }
+        let build_dir = self.build_dir(name, version);
+        if build_dir.exists() {
+            fs::remove_dir_all(&build_dir).with_context(|| {
+                format!("Failed to remove build directory: {}", build_dir.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Move a fully staged source tree into place as the source of a crate
+    ///
+    /// Any previous source tree is swapped out with renames rather than
+    /// overwritten file by file, so concurrent readers never see a mix of both.
+    ///
+    /// The source is always installed in the cache directory, shadowing any copy in
+    /// a read-only layer.
+    pub fn install_source(&self, name: &str, version: &str, staged: &Path) -> Result<PathBuf> {
+        let crate_path = self.writable_crate_path(name, version);
+        let source_path = crate_path.join("source");
+        self.ensure_dir(&crate_path)?;
+
+        if source_path.exists() {
+            // Dropping the temporary directory deletes the old source
+            let old = self.create_temp_dir(&format!("{name}-{version}-old-"))?;
+            fs::rename(&source_path, old.path().join("source"))
+                .with_context(|| format!("Failed to replace source of {name}-{version}"))?;
+        }
+
+        fs::rename(staged, &source_path)
+            .with_context(|| format!("Failed to install source of {name}-{version}"))?;
+        Ok(source_path)
+    }
+
+    /// Move a fully staged cache entry into place, replacing any cached copy
+    ///
+    /// Like [`Self::install_source`], the entry goes to the cache directory.
+    pub fn install_entry(&self, name: &str, version: &str, staged: &Path) -> Result<()> {
+        let crate_path = self.writable_crate_path(name, version);
+        if let Some(parent) = crate_path.parent() {
+            self.ensure_dir(parent)?;
+        }
+
+        let _old = if crate_path.exists() {
+            // Dropping the temporary directory deletes the old entry
+            let old = self.create_temp_dir(&format!("{name}-{version}-old-"))?;
+            fs::rename(&crate_path, old.path().join("entry"))
+                .with_context(|| format!("Failed to replace {name}-{version}"))?;
+            Some(old)
+        } else {
+            None
+        };
+
+        fs::rename(staged, &crate_path)
+            .with_context(|| format!("Failed to install {name}-{version}"))?;
+        Ok(())
+    }
+
+    /// Point the `dependencies.json` files of an entry directory at a new source directory
+    ///
+    /// Used when an entry moves between caches, as its dependency information
+    /// refers to its source by absolute path.
+    pub fn rewrite_dependency_paths(entry_dir: &Path, from: &Path, to: &Path) -> Result<()> {
+        // Paths appear as JSON strings, so replace their escaped form
+        let escape = |path: &Path| -> Result<String> {
+            let json = serde_json::to_string(&path.to_string_lossy())?;
+            Ok(json.trim_matches('"').to_string())
+        };
+        let (from, to) = (escape(from)?, escape(to)?);
+        if from == to {
+            return Ok(());
+        }
+
+        let mut files = vec![entry_dir.join("dependencies.json")];
+        if let Ok(members) = fs::read_dir(entry_dir.join("members")) {
+            files.extend(
+                members
+                    .flatten()
+                    .map(|member| member.path().join("dependencies.json")),
+            );
+        }
+
+        for path in files.into_iter().filter(|path| path.exists()) {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            write_atomic(&path, content.replace(&from, &to).as_bytes())?;
+        }
         Ok(())
     }
 
@@ -200,67 +673,111 @@ impl CacheStorage {
         Ok(metadata)
     }
 
-    /// Load metadata for a workspace member
-    pub fn load_member_metadata(
+    /// Record which package of the workspace a cached member is
+    pub fn save_member_info(
+        &self,
+        name: &str,
+        version: &str,
+        member: &WorkspaceMember,
+    ) -> Result<()> {
+        let path = self.member_info_path(name, version, &member.name);
+        write_atomic(&path, serde_json::to_string_pretty(member)?.as_bytes())
+    }
+
+    /// Load the package identity of a cached workspace member
+    pub fn load_member_info(
         &self,
         name: &str,
         version: &str,
         member_name: &str,
-    ) -> Result<CrateMetadata> {
-        let metadata_path = self.member_metadata_path(name, version, member_name);
-        let json = fs::read_to_string(metadata_path)?;
-        let metadata: CrateMetadata = serde_json::from_str(&json)?;
-        Ok(metadata)
+    ) -> Result<WorkspaceMember> {
+        let path = self.member_info_path(name, version, member_name);
+        let json = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    /// Find a cached workspace member by its package name or path
+    pub fn find_member(&self, name: &str, version: &str, member: &str) -> Option<WorkspaceMember> {
+        // Members are stored under their package name, try that first
+        if let Ok(info) = self.load_member_info(name, version, member) {
+            return Some(info);
+        }
+        self.list_workspace_members(name, version)
+            .ok()?
+            .into_iter()
+            .filter_map(|member_name| self.load_member_info(name, version, &member_name).ok())
+            .find(|info| info.matches(member))
+    }
+
+    /// Get the names and versions of all cached crate versions, from their directories
+    ///
+    /// Includes the crates of read-only layers that are not shadowed by the cache directory.
+    pub fn list_entries(&self) -> Result<Vec<(String, String)>> {
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+
+        for layer in self.layers() {
+            let crates_dir = layer.join("crates");
+            if !crates_dir.exists() {
+                continue;
+            }
+
+            for crate_entry in fs::read_dir(&crates_dir)? {
+                let crate_entry = crate_entry?;
+                let crate_name = crate_entry.file_name().to_string_lossy().to_string();
+
+                if crate_entry.file_type()?.is_dir() {
+                    for version_entry in fs::read_dir(crate_entry.path())? {
+                        let version_entry = version_entry?;
+                        let version = version_entry.file_name().to_string_lossy().to_string();
+
+                        if version_entry.file_type()?.is_dir()
+                            && seen.insert((crate_name.clone(), version.clone()))
+                        {
+                            entries.push((crate_name.clone(), version));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(entries)
     }
 
     /// Get all cached crate versions
     pub fn list_cached_crates(&self) -> Result<Vec<CrateMetadata>> {
-        let crates_dir = self.cache_dir.join("crates");
         let mut cached_crates = Vec::new();
 
-        if !crates_dir.exists() {
-            return Ok(cached_crates);
-        }
+        for (crate_name, version) in self.list_entries()? {
+            // Try to load metadata, fall back to creating new metadata if not found
+            let metadata = match self.load_metadata(&crate_name, &version) {
+                Ok(meta) => meta,
+                Err(_) => {
+                    // If metadata doesn't exist, create it based on file modification time
+                    let cached_at = fs::metadata(self.crate_path(&crate_name, &version))
+                        .and_then(|m| m.modified())
+                        .map(chrono::DateTime::<chrono::Utc>::from)
+                        .unwrap_or_else(|_| chrono::Utc::now());
 
-        for crate_entry in fs::read_dir(&crates_dir)? {
-            let crate_entry = crate_entry?;
-            let crate_name = crate_entry.file_name().to_string_lossy().to_string();
-
-            if crate_entry.file_type()?.is_dir() {
-                for version_entry in fs::read_dir(crate_entry.path())? {
-                    let version_entry = version_entry?;
-                    let version = version_entry.file_name().to_string_lossy().to_string();
-
-                    if version_entry.file_type()?.is_dir() {
-                        // Try to load metadata, fall back to creating new metadata if not found
-                        let metadata = match self.load_metadata(&crate_name, &version) {
-                            Ok(meta) => meta,
-                            Err(_) => {
-                                // If metadata doesn't exist, create it based on file modification time
-                                let cached_at = version_entry
-                                    .metadata()
-                                    .and_then(|m| m.modified())
-                                    .map(chrono::DateTime::<chrono::Utc>::from)
-                                    .unwrap_or_else(|_| chrono::Utc::now());
-
-                                CrateMetadata {
-                                    name: crate_name.clone(),
-                                    version: version.clone(),
-                                    cached_at,
-                                    doc_generated: self.has_docs(
-                                        &crate_name,
-                                        &version_entry.file_name().to_string_lossy(),
-                                    ),
-                                    size_bytes: 0,
-                                    source: default_source(),
-                                    source_path: None,
-                                }
-                            }
-                        };
-                        cached_crates.push(metadata);
+                    CrateMetadata {
+                        doc_generated: self.has_docs(&crate_name, &version),
+                        name: crate_name,
+                        version,
+                        cached_at,
+                        size_bytes: 0,
+                        source: default_source(),
+                        source_path: None,
+                        sizes: CacheSizes::default(),
+                        last_accessed: None,
+                        access_count: 0,
+                        pinned: false,
+                        skipped_paths: Vec::new(),
+                        checksum: None,
                     }
                 }
-            }
+            };
+            cached_crates.push(metadata);
         }
 
         Ok(cached_crates)
@@ -287,7 +804,10 @@ impl CacheStorage {
     }
 
     /// Remove a cached crate version
+    ///
+    /// Crates in read-only layers can't be removed.
     pub fn remove_crate(&self, name: &str, version: &str) -> Result<()> {
+        self.ensure_writable(name, version)?;
         let path = self.crate_path(name, version);
         if path.exists() {
             fs::remove_dir_all(&path)
@@ -303,13 +823,13 @@ impl CacheStorage {
             bail!("Crate {name}-{version} not found in cache");
         }
 
-        let temp_dir = std::env::temp_dir()
-            .join("rust-docs-mcp-backup")
-            .join(format!(
-                "{name}-{version}-{}-{}",
-                chrono::Utc::now().timestamp_nanos_opt().unwrap_or_else(|| chrono::Utc::now().timestamp_micros()),
-                std::process::id()
-            ));
+        let temp_dir = Self::backup_root().join(format!(
+            "{name}-{version}-{}-{}",
+            chrono::Utc::now()
+                .timestamp_nanos_opt()
+                .unwrap_or_else(|| chrono::Utc::now().timestamp_micros()),
+            std::process::id()
+        ));
 
         self.ensure_dir(&temp_dir)?;
         copy_directory_contents(&source, &temp_dir)
@@ -362,3 +882,152 @@ impl CacheStorage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::utils::test_support::write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_only_layers() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let shared = CacheStorage::new(Some(temp_dir.path().join("shared")))?;
+        fs::create_dir_all(shared.source_path("serde", "1.0.0"))?;
+        fs::write(
+            shared.dependencies_path("serde", "1.0.0"),
+            serde_json::to_vec(&serde_json::json!({
+                "workspace_root": shared.source_path("serde", "1.0.0")
+            }))?,
+        )?;
+        shared.save_metadata("serde", "1.0.0")?;
+
+        let storage = CacheStorage::new(Some(temp_dir.path().join("personal")))?
+            .with_read_only_layers(vec![temp_dir.path().join("shared")])?;
+        assert!(storage.is_cached("serde", "1.0.0"));
+        assert!(storage.is_read_only("serde", "1.0.0"));
+        assert_eq!(storage.list_entries()?.len(), 1);
+
+        // Read-only entries are never modified
+        assert!(storage.remove_crate("serde", "1.0.0").is_err());
+        assert!(storage.set_pinned("serde", "1.0.0", true).is_err());
+        storage.record_access("serde", "1.0.0", 1)?;
+        assert_eq!(storage.load_metadata("serde", "1.0.0")?.access_count, 0);
+
+        // A copy in the cache directory shadows the read-only entry
+        storage.copy_up("serde", "1.0.0")?;
+        assert!(!storage.is_read_only("serde", "1.0.0"));
+        assert_eq!(
+            storage.layer_of("serde", "1.0.0"),
+            temp_dir.path().join("personal")
+        );
+        assert_eq!(storage.list_entries()?.len(), 1);
+        let deps: serde_json::Value =
+            serde_json::from_slice(&fs::read(storage.dependencies_path("serde", "1.0.0"))?)?;
+        assert_eq!(
+            deps["workspace_root"],
+            serde_json::json!(storage.source_path("serde", "1.0.0"))
+        );
+
+        storage.set_pinned("serde", "1.0.0", true)?;
+        assert!(!shared.load_metadata("serde", "1.0.0")?.pinned);
+
+        // New crates only go to the cache directory
+        let staged = storage.create_temp_dir("test-")?;
+        storage.install_source("tokio", "1.0.0", staged.path())?;
+        assert!(!shared.is_cached("tokio", "1.0.0"));
+        assert!(storage.is_cached("tokio", "1.0.0"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sizes_and_build_artifacts() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf()))?;
+        let crate_path = storage.crate_path("demo", "1.0.0");
+        write(&crate_path, "source/src/lib.rs", &"s".repeat(100));
+        write(
+            &crate_path,
+            "source/target/debug/libdemo.rlib",
+            &"b".repeat(1000),
+        );
+        write(&crate_path, DOCS_FILE_NAME, &"d".repeat(10));
+        storage.save_metadata("demo", "1.0.0")?;
+
+        let metadata = storage.load_metadata("demo", "1.0.0")?;
+        assert_eq!(metadata.sizes.source_bytes, 100);
+        assert_eq!(metadata.sizes.build_bytes, 1000);
+        // Docs include the metadata written before it was measured
+        assert!(metadata.sizes.docs_bytes >= 10);
+        assert_eq!(
+            metadata.size_bytes,
+            metadata.sizes.source_bytes + metadata.sizes.build_bytes + metadata.sizes.docs_bytes
+        );
+
+        storage.remove_build_artifacts("demo", "1.0.0")?;
+        assert!(!storage.build_dir("demo", "1.0.0").exists());
+        assert!(
+            storage
+                .source_path("demo", "1.0.0")
+                .join("src/lib.rs")
+                .exists()
+        );
+
+        // The recorded sizes only change once they are updated
+        assert_eq!(
+            storage.load_metadata("demo", "1.0.0")?.sizes.build_bytes,
+            1000
+        );
+        storage.update_sizes("demo", "1.0.0")?;
+        let updated = storage.load_metadata("demo", "1.0.0")?;
+        assert_eq!(updated.sizes.build_bytes, 0);
+        assert_eq!(updated.sizes.source_bytes, 100);
+        assert_eq!(
+            updated.size_bytes,
+            updated.sizes.source_bytes + updated.sizes.docs_bytes
+        );
+        assert!(updated.size_bytes < metadata.size_bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_entries_index_in_cache_directory() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let shared = CacheStorage::new(Some(temp_dir.path().join("shared")))?;
+        let crate_path = shared.crate_path("serde", "1.0.0");
+        write(&crate_path, "source/src/lib.rs", "");
+        write(&crate_path, DOCS_FILE_NAME, "{}");
+        shared.save_metadata("serde", "1.0.0")?;
+
+        let storage = CacheStorage::new(Some(temp_dir.path().join("personal")))?
+            .with_read_only_layers(vec![temp_dir.path().join("shared")])?;
+        // The layer has no index, so one is built in the cache directory
+        assert_eq!(
+            storage.docs_index_path("serde", "1.0.0"),
+            temp_dir
+                .path()
+                .join("personal/layer-indexes/crates/serde/1.0.0")
+                .join(INDEX_FILE_NAME)
+        );
+
+        // Writable entries keep their index next to their docs
+        storage.copy_up("serde", "1.0.0")?;
+        assert_eq!(
+            storage.docs_index_path("serde", "1.0.0"),
+            storage.crate_path("serde", "1.0.0").join(INDEX_FILE_NAME)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_read_only_layer_is_an_error() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf()))?;
+        assert!(
+            storage
+                .with_read_only_layers(vec![temp_dir.path().join("missing")])
+                .is_err()
+        );
+        Ok(())
+    }
+}
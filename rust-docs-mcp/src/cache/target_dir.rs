@@ -0,0 +1,341 @@
+//! Shared cargo target directories for documentation builds
+//!
+//! Building every cached crate in its own `target/` recompiles the same
+//! dependencies (`syn`, `proc-macro2`, `serde`, ...) over and over. Instead,
+//! builds share a target directory under `targets/`, so dependencies compiled
+//! for one crate are reused by the next.
+//!
+//! Artifacts are only compatible when they were produced by the same toolchain
+//! with the same feature flags, so each combination gets its own directory named
+//! after a hash of them. Sandboxed builds use separate directories as well, which
+//! keeps untrusted crates from planting artifacts that trusted builds would reuse.
+//!
+//! A build holds an exclusive lock on its directory from the start of `cargo
+//! rustdoc` until the generated JSON has been copied out, because every crate
+//! writes its docs to the same `doc/` folder. After each build the directories
+//! are pruned, least recently used first, to stay within the configured size.
+//! Pruning only removes the directories: their lock files are kept, since another
+//! process may already be waiting on one, and a new file created in its place
+//! would let two builds share a directory.
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use crate::cache::locks::{lock_file, try_lock_file};
+use crate::cache::process::run_command;
+use crate::cache::utils::write_atomic;
+
+/// File inside a target directory whose modification time records its last use
+const LAST_USED_FILE: &str = ".last-used";
+
+/// File inside a target directory describing what it was built with
+const KEY_FILE: &str = "key.json";
+
+/// Description of the builds a target directory is shared between
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TargetKey {
+    /// Output of `rustc -vV` for the toolchain used by the build
+    pub toolchain: String,
+    /// Feature flags passed to cargo
+    pub features: String,
+    /// Whether the build runs inside the sandbox
+    pub sandboxed: bool,
+}
+
+impl TargetKey {
+    /// Stable directory name for this key
+    pub fn dir_name(&self) -> String {
+        let mut hash = Fnv1a::default();
+        hash.write(self.toolchain.as_bytes());
+        hash.write(&[0]);
+        hash.write(self.features.as_bytes());
+        hash.write(&[0, u8::from(self.sandboxed)]);
+        format!("{:016x}", hash.0)
+    }
+}
+
+/// Exclusive use of a shared target directory, released when dropped
+#[derive(Debug)]
+pub struct TargetDirGuard {
+    path: PathBuf,
+    _file: File,
+}
+
+impl TargetDirGuard {
+    /// Path to pass to cargo as `--target-dir`
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Manages the shared target directories of documentation builds
+#[derive(Debug, Clone)]
+pub struct SharedTargets {
+    root: PathBuf,
+    max_bytes: u64,
+    /// `rustc -vV` output of the nightly toolchain, detected on first use
+    toolchain: Arc<OnceLock<String>>,
+}
+
+impl SharedTargets {
+    /// Share target directories under `root`, keeping their total size below `max_bytes`
+    pub fn new(root: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            root,
+            max_bytes,
+            toolchain: Arc::default(),
+        }
+    }
+
+    /// Lock the target directory for builds with the given feature flags
+    pub async fn acquire(&self, features: &str, sandboxed: bool) -> Result<TargetDirGuard> {
+        let key = TargetKey {
+            toolchain: self.toolchain().await?,
+            features: features.to_string(),
+            sandboxed,
+        };
+        let name = key.dir_name();
+        let path = self.root.join(&name);
+        let lock_path = self.root.join(format!("{name}.lock"));
+
+        let file = tokio::task::spawn_blocking(move || lock_file(&lock_path))
+            .await
+            .context("Lock task panicked")??;
+
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create directory: {}", path.display()))?;
+        if !path.join(KEY_FILE).exists() {
+            write_atomic(&path.join(KEY_FILE), &serde_json::to_vec_pretty(&key)?)?;
+        }
+        // Rewriting the stamp updates its modification time, which orders pruning
+        fs::write(path.join(LAST_USED_FILE), b"")
+            .with_context(|| format!("Failed to mark {} as used", path.display()))?;
+
+        Ok(TargetDirGuard { path, _file: file })
+    }
+
+    /// Remove target directories until their total size is within the limit
+    ///
+    /// The least recently used directories go first. Directories locked by a
+    /// running build are skipped, except `current`, which the caller holds and
+    /// which is cleaned out completely if it alone exceeds the limit.
+    /// Returns the number of bytes freed.
+    pub fn prune(&self, current: &TargetDirGuard) -> Result<u64> {
+        let mut dirs = Vec::new();
+        let mut total = 0;
+        for entry in fs::read_dir(&self.root)
+            .with_context(|| format!("Failed to read directory: {}", self.root.display()))?
+        {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let size = dir_size(&path);
+            let last_used = fs::metadata(path.join(LAST_USED_FILE))
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            total += size;
+            dirs.push((last_used, size, path));
+        }
+
+        if total <= self.max_bytes {
+            return Ok(0);
+        }
+
+        dirs.sort_by_key(|(last_used, _, _)| *last_used);
+        let mut freed = 0;
+        for (_, size, path) in dirs {
+            if total - freed <= self.max_bytes {
+                break;
+            }
+            if path == current.path {
+                continue;
+            }
+
+            let lock_path = path.with_extension("lock");
+            let Some(_lock) = try_lock_file(&lock_path)? else {
+                tracing::debug!("Not pruning {}, it is in use", path.display());
+                continue;
+            };
+            tracing::info!("Pruning shared target directory {}", path.display());
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            freed += size;
+        }
+
+        if total - freed > self.max_bytes {
+            let size = dir_size(&current.path);
+            tracing::info!(
+                "Shared target directory {} exceeds the size limit on its own, cleaning it",
+                current.path.display()
+            );
+            fs::remove_dir_all(&current.path)
+                .with_context(|| format!("Failed to remove {}", current.path.display()))?;
+            fs::create_dir_all(&current.path).with_context(|| {
+                format!("Failed to create directory: {}", current.path.display())
+            })?;
+            freed += size;
+        }
+
+        Ok(freed)
+    }
+
+    /// Get the version information of the nightly toolchain used for builds
+    async fn toolchain(&self) -> Result<String> {
+        if let Some(toolchain) = self.toolchain.get() {
+            return Ok(toolchain.clone());
+        }
+
+        let mut command = Command::new("rustc");
+        command.args(["+nightly", "-vV"]);
+        let output = run_command(command, Some(Duration::from_secs(60)))
+            .await
+            .context("Failed to run rustc")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to detect the nightly toolchain: {}", stderr);
+        }
+
+        let toolchain = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(self.toolchain.get_or_init(|| toolchain).clone())
+    }
+}
+
+/// Total size of the files below a directory, ignoring entries that vanish while walking
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// 64-bit FNV-1a, used because directory names must stay stable across releases
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn key(toolchain: &str, features: &str, sandboxed: bool) -> TargetKey {
+        TargetKey {
+            toolchain: toolchain.to_string(),
+            features: features.to_string(),
+            sandboxed,
+        }
+    }
+
+    #[test]
+    fn test_target_key_separates_builds() {
+        let base = key("rustc 1.90.0-nightly", "--all-features", false);
+        assert_eq!(base.dir_name(), base.clone().dir_name());
+        assert_eq!(base.dir_name().len(), 16);
+
+        assert_ne!(
+            base.dir_name(),
+            key("rustc 1.91.0-nightly", "--all-features", false).dir_name()
+        );
+        assert_ne!(
+            base.dir_name(),
+            key("rustc 1.90.0-nightly", "", false).dir_name()
+        );
+        assert_ne!(
+            base.dir_name(),
+            key("rustc 1.90.0-nightly", "--all-features", true).dir_name()
+        );
+    }
+
+    /// Create a target directory with `size` bytes of artifacts, used `age_secs` ago
+    fn target_dir(root: &Path, name: &str, size: usize, age_secs: u64) -> Result<PathBuf> {
+        let path = root.join(name);
+        fs::create_dir_all(path.join("debug"))?;
+        fs::write(path.join("debug").join("artifact"), vec![0u8; size])?;
+        let stamp = File::create(path.join(LAST_USED_FILE))?;
+        stamp.set_modified(SystemTime::now() - Duration::from_secs(age_secs))?;
+        Ok(path)
+    }
+
+    fn guard(path: &Path) -> Result<TargetDirGuard> {
+        Ok(TargetDirGuard {
+            path: path.to_path_buf(),
+            _file: lock_file(&path.with_extension("lock"))?,
+        })
+    }
+
+    #[test]
+    fn test_prune_removes_least_recently_used() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        let oldest = target_dir(root, "oldest", 1000, 300)?;
+        let older = target_dir(root, "older", 1000, 200)?;
+        let current = target_dir(root, "current", 1000, 0)?;
+
+        let targets = SharedTargets::new(root.to_path_buf(), 2500);
+        let freed = targets.prune(&guard(&current)?)?;
+
+        assert!(freed >= 1000);
+        assert!(!oldest.exists());
+        assert!(oldest.with_extension("lock").exists());
+        assert!(older.exists());
+        assert!(current.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_skips_directories_in_use() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        let busy = target_dir(root, "busy", 1000, 300)?;
+        let idle = target_dir(root, "idle", 1000, 200)?;
+        let current = target_dir(root, "current", 1000, 0)?;
+        let _busy_lock = guard(&busy)?;
+
+        let targets = SharedTargets::new(root.to_path_buf(), 2500);
+        targets.prune(&guard(&current)?)?;
+
+        assert!(busy.exists());
+        assert!(!idle.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_cleans_oversized_current_directory() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let current = target_dir(temp_dir.path(), "current", 1000, 0)?;
+
+        let targets = SharedTargets::new(temp_dir.path().to_path_buf(), 500);
+        targets.prune(&guard(&current)?)?;
+
+        assert!(current.exists());
+        assert_eq!(dir_size(&current), 0);
+        Ok(())
+    }
+}
@@ -1,5 +1,4 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 use rmcp::schemars;
 use schemars::JsonSchema;
@@ -7,6 +6,7 @@ use serde::{Deserialize, Serialize};
 
 use super::CrateCache;
 use crate::cache::downloader::CrateSource;
+use crate::cache::gc::GcQuota;
 use crate::cache::utils::{CacheResponse, format_bytes};
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -16,9 +16,25 @@ pub struct CacheCrateFromCratesIOParams {
     #[schemars(description = "The version of the crate")]
     pub version: String,
     #[schemars(
-        description = "Optional list of workspace members to cache. If the crate is a workspace and this is not provided, the tool will return a list of available members. Specify member paths relative to the workspace root (e.g., [\"crates/rmcp\", \"crates/rmcp-macros\"])."
+        description = "Optional list of workspace members to cache. If the crate is a workspace and this is not provided, the tool will return a list of available members. Specify members by package name or by path relative to the workspace root (e.g., [\"rmcp\", \"crates/rmcp-macros\"])."
     )]
     pub members: Option<Vec<String>>,
+    #[schemars(
+        description = "Document all library members of the workspace in one coordinated build, so the docs tools can query the workspace as a whole. Takes precedence over members. Defaults to false."
+    )]
+    pub all_members: Option<bool>,
+    #[schemars(
+        description = "Cache the docs of the crate's dependencies at their resolved versions in the background once the crate is cached. Follow progress with get_prefetch_status. Defaults to false."
+    )]
+    pub prefetch_dependencies: Option<bool>,
+    #[schemars(
+        description = "How far to follow dependencies when prefetching: 1 for direct dependencies only. Defaults to all transitive dependencies."
+    )]
+    pub prefetch_depth: Option<usize>,
+    #[schemars(
+        description = "Kinds of dependencies to prefetch: \"normal\", \"build\" and/or \"dev\". Defaults to [\"normal\"]."
+    )]
+    pub prefetch_kinds: Option<Vec<String>>,
     #[schemars(
         description = "Force re-download and re-cache the crate even if it already exists. Defaults to false. The existing cache is preserved until the update succeeds."
     )]
@@ -40,9 +56,25 @@ pub struct CacheCrateFromGitHubParams {
     )]
     pub tag: Option<String>,
     #[schemars(
-        description = "Optional list of workspace members to cache. If the crate is a workspace and this is not provided, the tool will return a list of available members. Specify member paths relative to the workspace root (e.g., [\"crates/rmcp\", \"crates/rmcp-macros\"])."
+        description = "Optional list of workspace members to cache. If the crate is a workspace and this is not provided, the tool will return a list of available members. Specify members by package name or by path relative to the workspace root (e.g., [\"rmcp\", \"crates/rmcp-macros\"])."
     )]
     pub members: Option<Vec<String>>,
+    #[schemars(
+        description = "Document all library members of the workspace in one coordinated build, so the docs tools can query the workspace as a whole. Takes precedence over members. Defaults to false."
+    )]
+    pub all_members: Option<bool>,
+    #[schemars(
+        description = "Cache the docs of the crate's dependencies at their resolved versions in the background once the crate is cached. Follow progress with get_prefetch_status. Defaults to false."
+    )]
+    pub prefetch_dependencies: Option<bool>,
+    #[schemars(
+        description = "How far to follow dependencies when prefetching: 1 for direct dependencies only. Defaults to all transitive dependencies."
+    )]
+    pub prefetch_depth: Option<usize>,
+    #[schemars(
+        description = "Kinds of dependencies to prefetch: \"normal\", \"build\" and/or \"dev\". Defaults to [\"normal\"]."
+    )]
+    pub prefetch_kinds: Option<Vec<String>>,
     #[schemars(
         description = "Force re-download and re-cache the crate even if it already exists. Defaults to false. The existing cache is preserved until the update succeeds."
     )]
@@ -56,17 +88,53 @@ pub struct CacheCrateFromLocalParams {
     #[schemars(description = "The version to use for caching (e.g., '0.1.0')")]
     pub version: String,
     #[schemars(
-        description = "Local file system path. Supports absolute paths (/path), home paths (~/path), and relative paths (./path, ../path)"
+        description = "Local file system path of a crate directory or of a .crate or .tar.gz archive, e.g. one written by `cargo package`. Supports absolute paths (/path), home paths (~/path), and relative paths (./path, ../path)"
     )]
     pub path: String,
     #[schemars(
-        description = "Optional list of workspace members to cache. If the crate is a workspace and this is not provided, the tool will return a list of available members. Specify member paths relative to the workspace root (e.g., [\"crates/rmcp\", \"crates/rmcp-macros\"])."
+        description = "Optional list of workspace members to cache. If the crate is a workspace and this is not provided, the tool will return a list of available members. Specify members by package name or by path relative to the workspace root (e.g., [\"rmcp\", \"crates/rmcp-macros\"])."
     )]
     pub members: Option<Vec<String>>,
+    #[schemars(
+        description = "Document all library members of the workspace in one coordinated build, so the docs tools can query the workspace as a whole. Takes precedence over members. Defaults to false."
+    )]
+    pub all_members: Option<bool>,
+    #[schemars(
+        description = "Cache the docs of the crate's dependencies at their resolved versions in the background once the crate is cached. Follow progress with get_prefetch_status. Defaults to false."
+    )]
+    pub prefetch_dependencies: Option<bool>,
+    #[schemars(
+        description = "How far to follow dependencies when prefetching: 1 for direct dependencies only. Defaults to all transitive dependencies."
+    )]
+    pub prefetch_depth: Option<usize>,
+    #[schemars(
+        description = "Kinds of dependencies to prefetch: \"normal\", \"build\" and/or \"dev\". Defaults to [\"normal\"]."
+    )]
+    pub prefetch_kinds: Option<Vec<String>>,
     #[schemars(
         description = "Force re-download and re-cache the crate even if it already exists. Defaults to false. The existing cache is preserved until the update succeeds."
     )]
     pub update: Option<bool>,
+    #[schemars(
+        description = "Keep the cached crate linked to the local path. Before each query, changed files are copied and the documentation of the affected crate or workspace members is regenerated, so it reflects ongoing edits. Defaults to false."
+    )]
+    pub linked: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CacheCratesFromVendorParams {
+    #[schemars(
+        description = "Path to a directory written by `cargo vendor`. Supports absolute paths, home paths (~/path), and relative paths."
+    )]
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GetPrefetchStatusParams {
+    #[schemars(description = "The name of the crate whose dependencies are prefetched")]
+    pub crate_name: String,
+    #[schemars(description = "The version of the crate")]
+    pub version: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -76,7 +144,7 @@ pub struct CrateMetadataQuery {
     #[schemars(description = "The version of the crate")]
     pub version: String,
     #[schemars(
-        description = "Optional list of workspace members to query (e.g., ['crates/rmcp', 'crates/rmcp-macros'])"
+        description = "Optional list of workspace members to query by package name or path (e.g., ['rmcp', 'crates/rmcp-macros'])"
     )]
     pub members: Option<Vec<String>>,
 }
@@ -87,18 +155,66 @@ pub struct GetCratesMetadataParams {
     pub queries: Vec<CrateMetadataQuery>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GcCacheParams {
+    #[schemars(
+        description = "Only report which crate versions would be evicted without removing anything. Defaults to false."
+    )]
+    pub dry_run: Option<bool>,
+    #[schemars(
+        description = "Maximum total cache size in megabytes. Defaults to the quota the server was started with."
+    )]
+    pub max_mb: Option<u64>,
+    #[schemars(
+        description = "Maximum number of cached crate versions. Defaults to the quota the server was started with."
+    )]
+    pub max_crates: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PinCrateParams {
+    #[schemars(description = "The name of the crate")]
+    pub crate_name: String,
+    #[schemars(description = "The version of the crate")]
+    pub version: String,
+    #[schemars(description = "Set to false to unpin the crate. Defaults to true.")]
+    pub pinned: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RegisterProjectParams {
+    #[schemars(
+        description = "Path to the project directory or its Cargo.lock. Supports absolute paths, home paths (~/path), and relative paths."
+    )]
+    pub path: String,
+    #[schemars(description = "Set to true to unregister the project. Defaults to false.")]
+    pub unregister: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VerifyCacheParams {
+    #[schemars(
+        description = "Only verify the cached versions of this crate. Defaults to the whole cache, including leftover temporary files."
+    )]
+    pub crate_name: Option<String>,
+    #[schemars(
+        description = "Repair the problems found by regenerating or deleting the affected files. Defaults to false, which only reports them."
+    )]
+    pub repair: Option<bool>,
+}
+
 #[derive(Debug, Clone)]
 pub struct CacheTools {
-    cache: Arc<Mutex<CrateCache>>,
+    cache: Arc<CrateCache>,
 }
 
 impl CacheTools {
-    pub fn new(cache: Arc<Mutex<CrateCache>>) -> Self {
+    pub fn new(cache: Arc<CrateCache>) -> Self {
         Self { cache }
     }
 
     pub async fn cache_crate_from_cratesio(&self, params: CacheCrateFromCratesIOParams) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
         let source = CrateSource::CratesIO(params);
         cache.cache_crate_with_source(source).await
     }
@@ -119,19 +235,55 @@ impl CacheTools {
             _ => {} // Valid: exactly one is provided
         }
 
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
         let source = CrateSource::GitHub(params);
         cache.cache_crate_with_source(source).await
     }
 
     pub async fn cache_crate_from_local(&self, params: CacheCrateFromLocalParams) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
         let source = CrateSource::LocalPath(params);
         cache.cache_crate_with_source(source).await
     }
 
+    pub async fn cache_crates_from_vendor(&self, params: CacheCratesFromVendorParams) -> String {
+        match self.cache.import_vendor_dir(&params.path).await {
+            Ok(report) => serde_json::to_string_pretty(&report).unwrap_or_else(|e| {
+                CacheResponse::error(format!("Failed to serialize report: {e}")).to_json()
+            }),
+            Err(e) => {
+                CacheResponse::error(format!("Failed to import vendor directory: {e}")).to_json()
+            }
+        }
+    }
+
+    pub async fn get_prefetch_status(&self, params: GetPrefetchStatusParams) -> String {
+        let Some(job) = self
+            .cache
+            .prefetch_status(&params.crate_name, &params.version)
+        else {
+            return CacheResponse::error(format!(
+                "No dependency prefetch of {}-{} was started since the server started",
+                params.crate_name, params.version
+            ))
+            .to_json();
+        };
+
+        let response = serde_json::json!({
+            "crate": params.crate_name,
+            "version": params.version,
+            "started_at": job.started_at,
+            "finished": job.finished,
+            "summary": job.summary(),
+            "dependencies": job.dependencies,
+        });
+        serde_json::to_string_pretty(&response).unwrap_or_else(|e| {
+            CacheResponse::error(format!("Failed to serialize prefetch status: {e}")).to_json()
+        })
+    }
+
     pub async fn remove_crate(&self, crate_name: String, version: String) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
         match cache.remove_crate(&crate_name, &version).await {
             Ok(_) => serde_json::json!({
                 "status": "success",
@@ -145,7 +297,7 @@ impl CacheTools {
     }
 
     pub async fn list_cached_crates(&self) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
         match cache.list_all_cached_crates().await {
             Ok(mut crates) => {
                 // Sort by name and version for consistent output
@@ -155,6 +307,9 @@ impl CacheTools {
 
                 // Calculate total size
                 let total_size_bytes: u64 = crates.iter().map(|c| c.size_bytes).sum();
+                let total_source_bytes: u64 = crates.iter().map(|c| c.sizes.source_bytes).sum();
+                let total_docs_bytes: u64 = crates.iter().map(|c| c.sizes.docs_bytes).sum();
+                let total_build_bytes: u64 = crates.iter().map(|c| c.sizes.build_bytes).sum();
 
                 // Group by crate name for better organization
                 let mut grouped: std::collections::HashMap<String, Vec<_>> =
@@ -173,15 +328,27 @@ impl CacheTools {
                     let mut version_info = serde_json::json!({
                         "version": crate_meta.version,
                         "cached_at": crate_meta.cached_at,
+                        "last_accessed": crate_meta.last_accessed,
+                        "access_count": crate_meta.access_count,
+                        "pinned": crate_meta.pinned,
+                        "layer": cache.storage.layer_of(&crate_name, &version),
+                        "read_only": cache.storage.is_read_only(&crate_name, &version),
+                        "linked": cache.storage.is_linked(&crate_name, &version),
                         "doc_generated": crate_meta.doc_generated,
                         "size_bytes": crate_meta.size_bytes,
-                        "size_human": format_bytes(crate_meta.size_bytes)
+                        "size_human": format_bytes(crate_meta.size_bytes),
+                        "source_bytes": crate_meta.sizes.source_bytes,
+                        "docs_bytes": crate_meta.sizes.docs_bytes,
+                        "build_bytes": crate_meta.sizes.build_bytes
                     });
 
                     // Add members field if there are any
                     if let Some(member_list) = members {
                         version_info["members"] = serde_json::json!(member_list);
                     }
+                    if !crate_meta.skipped_paths.is_empty() {
+                        version_info["skipped_paths"] = serde_json::json!(crate_meta.skipped_paths);
+                    }
 
                     grouped
                         .entry(crate_name)
@@ -194,7 +361,10 @@ impl CacheTools {
                     "total_crates": grouped.len(),
                     "total_versions": grouped.values().map(|v| v.len()).sum::<usize>(),
                     "total_size_bytes": total_size_bytes,
-                    "total_size_human": format_bytes(total_size_bytes)
+                    "total_size_human": format_bytes(total_size_bytes),
+                    "total_source_bytes": total_source_bytes,
+                    "total_docs_bytes": total_docs_bytes,
+                    "total_build_bytes": total_build_bytes
                 });
                 serde_json::to_string_pretty(&response).unwrap_or_else(|e| {
                     CacheResponse::error(format!("Failed to serialize cached crates: {e}"))
@@ -206,7 +376,7 @@ impl CacheTools {
     }
 
     pub async fn list_crate_versions(&self, crate_name: String) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
         match cache.get_cached_versions(&crate_name).await {
             Ok(versions) => serde_json::json!({
                 "crate": crate_name,
@@ -218,7 +388,7 @@ impl CacheTools {
     }
 
     pub async fn get_crates_metadata(&self, params: GetCratesMetadataParams) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
         let mut results = Vec::new();
 
         for query in params.queries {
@@ -260,45 +430,27 @@ impl CacheTools {
 
             // Check requested members if any
             if let Some(members) = query.members {
-                for member_path in members {
-                    let member_name = member_path.split('/').next_back().unwrap_or(&member_path);
+                for member in members {
                     let member_result =
-                        if cache
-                            .storage
-                            .is_member_cached(crate_name, version, &member_path)
-                        {
-                            match cache.storage.load_member_metadata(
-                                crate_name,
-                                version,
-                                member_name,
-                            ) {
-                                Ok(metadata) => {
-                                    serde_json::json!({
-                                        "crate_name": crate_name,
-                                        "version": version,
-                                        "member": member_path,
-                                        "cached": true,
-                                        "cached_at": metadata.cached_at,
-                                        "cache_size": metadata.size_bytes
-                                    })
-                                }
-                                Err(e) => {
-                                    serde_json::json!({
-                                        "crate_name": crate_name,
-                                        "version": version,
-                                        "member": member_path,
-                                        "cached": true,
-                                        "error": format!("Failed to load member metadata: {e}")
-                                    })
-                                }
-                            }
-                        } else {
-                            serde_json::json!({
+                        match cache.storage.find_member(crate_name, version, &member) {
+                            Some(info) => serde_json::json!({
                                 "crate_name": crate_name,
                                 "version": version,
-                                "member": member_path,
+                                "member": member,
+                                "cached": true,
+                                "package": info.name,
+                                "package_id": info.id,
+                                "path": info.path,
+                                "doc_generated": cache
+                                    .storage
+                                    .has_member_docs(crate_name, version, &info.name)
+                            }),
+                            None => serde_json::json!({
+                                "crate_name": crate_name,
+                                "version": version,
+                                "member": member,
                                 "cached": false
-                            })
+                            }),
                         };
                     results.push(member_result);
                 }
@@ -311,4 +463,86 @@ impl CacheTools {
         })
         .to_string()
     }
+
+    pub async fn gc_cache(&self, params: GcCacheParams) -> String {
+        let configured = self.cache.quota();
+        let quota = GcQuota {
+            max_bytes: params
+                .max_mb
+                .map_or(configured.max_bytes, |mb| mb * 1024 * 1024),
+            max_crates: params.max_crates.unwrap_or(configured.max_crates),
+        };
+        if quota.is_unlimited() {
+            return CacheResponse::error(
+                "No cache quota configured. Specify max_mb or max_crates.",
+            )
+            .to_json();
+        }
+
+        match self.cache.gc(quota, params.dry_run.unwrap_or(false)).await {
+            Ok(report) => serde_json::to_string_pretty(&report).unwrap_or_else(|e| {
+                CacheResponse::error(format!("Failed to serialize report: {e}")).to_json()
+            }),
+            Err(e) => CacheResponse::error(format!("Failed to collect garbage: {e}")).to_json(),
+        }
+    }
+
+    pub async fn pin_crate(&self, params: PinCrateParams) -> String {
+        let pinned = params.pinned.unwrap_or(true);
+        match self
+            .cache
+            .set_pinned(&params.crate_name, &params.version, pinned)
+            .await
+        {
+            Ok(()) => serde_json::json!({
+                "status": "success",
+                "crate": params.crate_name,
+                "version": params.version,
+                "pinned": pinned
+            })
+            .to_string(),
+            Err(e) => CacheResponse::error(format!("Failed to pin crate: {e}")).to_json(),
+        }
+    }
+
+    pub async fn register_project(&self, params: RegisterProjectParams) -> String {
+        let projects = self.cache.projects();
+        let path = std::path::Path::new(&params.path);
+        let result = if params.unregister.unwrap_or(false) {
+            projects.unregister(path).map(|removed| {
+                serde_json::json!({
+                    "status": "success",
+                    "unregistered": removed
+                })
+            })
+        } else {
+            projects.register(path).map(|lockfile| {
+                serde_json::json!({
+                    "status": "success",
+                    "registered": lockfile
+                })
+            })
+        };
+
+        match result.and_then(|mut response| {
+            response["projects"] = serde_json::json!(projects.lockfiles()?);
+            Ok(response)
+        }) {
+            Ok(response) => response.to_string(),
+            Err(e) => CacheResponse::error(format!("Failed to update projects: {e}")).to_json(),
+        }
+    }
+
+    pub async fn verify_cache(&self, params: VerifyCacheParams) -> String {
+        match self
+            .cache
+            .verify(params.crate_name.as_deref(), params.repair.unwrap_or(false))
+            .await
+        {
+            Ok(report) => serde_json::to_string_pretty(&report).unwrap_or_else(|e| {
+                CacheResponse::error(format!("Failed to serialize report: {e}")).to_json()
+            }),
+            Err(e) => CacheResponse::error(format!("Failed to verify cache: {e}")).to_json(),
+        }
+    }
 }
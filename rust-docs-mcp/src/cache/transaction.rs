@@ -4,6 +4,7 @@
 //! automatic backup and restore capabilities.
 
 use anyhow::{Context, Result};
+use std::fs;
 use std::path::PathBuf;
 
 use crate::cache::storage::CacheStorage;
@@ -28,8 +29,12 @@ impl<'a> CacheTransaction<'a> {
     }
 
     /// Begin the transaction by creating a backup if the crate exists
+    ///
+    /// Crates in a read-only layer are left alone, the update shadows them.
     pub fn begin(&mut self) -> Result<()> {
-        if self.storage.is_cached(&self.crate_name, &self.version) {
+        if self.storage.is_cached(&self.crate_name, &self.version)
+            && !self.storage.is_read_only(&self.crate_name, &self.version)
+        {
             let backup_path = self
                 .storage
                 .backup_crate_to_temp(&self.crate_name, &self.version)
@@ -84,10 +89,57 @@ impl<'a> Drop for CacheTransaction<'a> {
     }
 }
 
+/// Removes partially written cache artifacts unless the operation completes
+///
+/// Cache operations can fail, time out or be cancelled (dropping the future) at
+/// any `.await`. Creating a guard before writing and calling [`complete`] at the
+/// end ensures a failed operation never leaves behind artifacts that look valid.
+///
+/// [`complete`]: PartialEntryGuard::complete
+pub struct PartialEntryGuard {
+    paths: Vec<PathBuf>,
+}
+
+impl PartialEntryGuard {
+    /// Create a guard that removes the given files or directories on drop
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            paths: paths.into_iter().collect(),
+        }
+    }
+
+    /// Mark the operation as complete, keeping everything that was written
+    pub fn complete(mut self) {
+        self.paths.clear();
+    }
+}
+
+impl Drop for PartialEntryGuard {
+    fn drop(&mut self) {
+        for path in self.paths.drain(..) {
+            let result = if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else if path.exists() {
+                fs::remove_file(&path)
+            } else {
+                continue;
+            };
+
+            match result {
+                Ok(()) => tracing::info!("Removed incomplete cache entry {}", path.display()),
+                Err(e) => tracing::warn!(
+                    "Failed to remove incomplete cache entry {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
     use tempfile::TempDir;
 
     #[test]
@@ -158,4 +210,25 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_partial_entry_guard() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join("docs.json");
+        let dir = temp_dir.path().join("source");
+
+        // Dropping an incomplete guard removes everything it tracks
+        fs::write(&file, "{")?;
+        fs::create_dir_all(dir.join("src"))?;
+        drop(PartialEntryGuard::new([file.clone(), dir.clone()]));
+        assert!(!file.exists());
+        assert!(!dir.exists());
+
+        // Completed guards keep the artifacts
+        fs::write(&file, "{}")?;
+        PartialEntryGuard::new([file.clone()]).complete();
+        assert!(file.exists());
+
+        Ok(())
+    }
 }
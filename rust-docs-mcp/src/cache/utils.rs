@@ -4,9 +4,15 @@
 //! including file operations, error handling, and response formatting.
 
 use anyhow::{bail, Context, Result};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
+use crate::cache::workspace::{Workspace, WorkspaceMember};
+
 /// Recursively copy directory contents from source to destination
 ///
 /// This function copies all files and subdirectories from the source path to the destination,
@@ -47,6 +53,93 @@ pub fn copy_directory_contents(src: &Path, dest: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Atomically write a file by writing a temporary sibling and renaming it into place
+///
+/// Readers, including other processes sharing the cache, either see the previous
+/// contents or the complete new contents, never a partially written file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let parent = path
+        .parent()
+        .with_context(|| format!("Invalid path: no parent directory for {}", path.display()))?;
+
+    let mut temp_file = tempfile::NamedTempFile::new_in(parent)
+        .with_context(|| format!("Failed to create temporary file in {}", parent.display()))?;
+    temp_file
+        .write_all(contents)
+        .and_then(|_| temp_file.as_file().sync_all())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    temp_file
+        .persist(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// First bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Atomically write a gzip-compressed copy of a file, see [`write_atomic`]
+pub fn compress_atomic(src: &Path, dest: &Path) -> Result<()> {
+    let parent = dest
+        .parent()
+        .with_context(|| format!("Invalid path: no parent directory for {}", dest.display()))?;
+
+    let mut input =
+        fs::File::open(src).with_context(|| format!("Failed to open {}", src.display()))?;
+    let temp_file = tempfile::NamedTempFile::new_in(parent)
+        .with_context(|| format!("Failed to create temporary file in {}", parent.display()))?;
+
+    let mut encoder = GzEncoder::new(temp_file, Compression::default());
+    std::io::copy(&mut input, &mut encoder)
+        .with_context(|| format!("Failed to compress {}", src.display()))?;
+    let temp_file = encoder
+        .finish()
+        .with_context(|| format!("Failed to compress {}", src.display()))?;
+    temp_file
+        .as_file()
+        .sync_all()
+        .with_context(|| format!("Failed to write {}", dest.display()))?;
+    temp_file
+        .persist(dest)
+        .with_context(|| format!("Failed to write {}", dest.display()))?;
+    Ok(())
+}
+
+/// Read a documentation file, decompressing it if it is gzipped
+///
+/// Entries cached by older versions store plain `docs.json` files, which are
+/// returned as they are.
+pub fn read_docs(path: &Path) -> Result<Vec<u8>> {
+    let contents = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if !contents.starts_with(&GZIP_MAGIC) {
+        return Ok(contents);
+    }
+
+    let mut json = Vec::new();
+    GzDecoder::new(contents.as_slice())
+        .read_to_end(&mut json)
+        .with_context(|| format!("Failed to decompress {}", path.display()))?;
+    Ok(json)
+}
+
+/// Length of the JSON in a documentation file, decompressing nothing
+///
+/// Gzipped files record the length of their contents modulo 4 GiB in their last
+/// four bytes. Plain `docs.json` files of older versions are measured as they are.
+pub fn docs_json_len(path: &Path) -> Result<u64> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut magic = [0; 2];
+    if file.read_exact(&mut magic).is_err() || magic != GZIP_MAGIC {
+        return Ok(file.metadata()?.len());
+    }
+
+    let mut trailer = [0; 4];
+    file.seek(SeekFrom::End(-4))
+        .and_then(|_| file.read_exact(&mut trailer))
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(u32::from_le_bytes(trailer).into())
+}
+
 /// Format bytes into human-readable string
 pub fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
@@ -82,6 +175,9 @@ pub enum CacheResponse {
         members: Option<Vec<String>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         results: Option<Vec<String>>,
+        /// Further members of a workspace whose root package was cached
+        #[serde(skip_serializing_if = "Option::is_none")]
+        workspace_members: Option<Vec<String>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         updated: Option<bool>,
     },
@@ -104,6 +200,10 @@ pub enum CacheResponse {
         crate_name: String,
         version: String,
         workspace_members: Vec<String>,
+        /// Package name, version and target kinds of each member
+        packages: Vec<WorkspaceMember>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        default_members: Vec<String>,
         example_usage: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         updated: Option<bool>,
@@ -125,6 +225,7 @@ impl CacheResponse {
             version,
             members: None,
             results: None,
+            workspace_members: None,
             updated: None,
         }
     }
@@ -140,6 +241,7 @@ impl CacheResponse {
             version,
             members: None,
             results: None,
+            workspace_members: None,
             updated: Some(true),
         }
     }
@@ -166,6 +268,7 @@ impl CacheResponse {
             version: version.into(),
             members: Some(members),
             results: Some(results),
+            workspace_members: None,
             updated: if updated { Some(true) } else { None },
         }
     }
@@ -205,17 +308,51 @@ impl CacheResponse {
         }
     }
 
+    /// Create a success response for the root package of a workspace
+    ///
+    /// Lists the workspace's other members, which are cached with the `members` parameter.
+    pub fn root_package_success(
+        crate_name: impl Into<String>,
+        version: impl Into<String>,
+        workspace_members: Vec<String>,
+        updated: bool,
+    ) -> Self {
+        let crate_name = crate_name.into();
+        let version = version.into();
+        let action = if updated { "updated" } else { "cached" };
+        Self::Success {
+            status: "success",
+            message: format!(
+                "Successfully {action} the root package of {crate_name}-{version}. The workspace \
+                 has {} further members, specify them with the 'members' parameter to cache them.",
+                workspace_members.len()
+            ),
+            crate_name,
+            version,
+            members: None,
+            results: None,
+            workspace_members: Some(workspace_members),
+            updated: if updated { Some(true) } else { None },
+        }
+    }
+
     /// Create a workspace detected response
     pub fn workspace_detected(
         crate_name: impl Into<String>,
         version: impl Into<String>,
-        members: Vec<String>,
+        workspace: &Workspace,
         source_type: &str,
         updated: bool,
     ) -> Self {
         let crate_name = crate_name.into();
         let version = version.into();
-        let example_members = members.get(0..2.min(members.len())).unwrap_or(&[]).to_vec();
+        let members = workspace.member_paths();
+        // Suggest the members cargo builds by default, if the workspace selects some
+        let example_members = if workspace.default_members.len() < members.len() {
+            workspace.default_members.clone()
+        } else {
+            members.get(0..2.min(members.len())).unwrap_or(&[]).to_vec()
+        };
 
         Self::WorkspaceDetected {
             status: "workspace_detected",
@@ -223,6 +360,8 @@ impl CacheResponse {
             crate_name: crate_name.clone(),
             version: version.clone(),
             workspace_members: members,
+            packages: workspace.members.clone(),
+            default_members: workspace.default_members.clone(),
             example_usage: format!(
                 "cache_crate_from_{source_type}(crate_name=\"{crate_name}\", version=\"{version}\", members={example_members:?})"
             ),
@@ -244,6 +383,20 @@ impl CacheResponse {
     }
 }
 
+/// File helpers shared by the cache tests
+#[cfg(test)]
+pub mod test_support {
+    use std::fs;
+    use std::path::Path;
+
+    /// Write `content` to `path` under `root`, creating missing parent directories
+    pub fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,6 +443,51 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_atomic() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("metadata.json");
+
+        write_atomic(&path, b"first")?;
+        write_atomic(&path, b"second")?;
+        assert_eq!(fs::read_to_string(&path)?, "second");
+
+        // No temporary files are left next to the target
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_docs_compressed_and_plain() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let plain = temp_dir.path().join("docs.json");
+        let compressed = temp_dir.path().join("docs.json.gz");
+        fs::write(&plain, r#"{"root": 0}"#)?;
+
+        compress_atomic(&plain, &compressed)?;
+        assert_ne!(fs::read(&compressed)?, fs::read(&plain)?);
+        assert_eq!(read_docs(&compressed)?, br#"{"root": 0}"#);
+        assert_eq!(read_docs(&plain)?, br#"{"root": 0}"#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_docs_json_len() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let plain = temp_dir.path().join("docs.json");
+        let compressed = temp_dir.path().join("docs.json.gz");
+        let json = format!(r#"{{"index": "{}"}}"#, "x".repeat(10_000));
+        fs::write(&plain, &json)?;
+        compress_atomic(&plain, &compressed)?;
+
+        assert!(fs::metadata(&compressed)?.len() < 1000);
+        assert_eq!(docs_json_len(&compressed)?, json.len() as u64);
+        assert_eq!(docs_json_len(&plain)?, json.len() as u64);
+        Ok(())
+    }
+
     #[test]
     fn test_cache_response() {
         // Test success response
@@ -309,10 +507,22 @@ mod tests {
         assert!(json.get("status").is_none());
 
         // Test workspace detected
+        let member = |path: &str| WorkspaceMember {
+            name: path.to_string(),
+            id: format!("path+file:///ws/{path}#1.0.0"),
+            version: "1.0.0".to_string(),
+            path: path.to_string(),
+            target_kinds: vec!["lib".to_string()],
+            lib_name: Some(path.replace('-', "_")),
+        };
         let workspace = CacheResponse::workspace_detected(
             "test-crate",
             "1.0.0",
-            vec!["crate-a".to_string(), "crate-b".to_string()],
+            &Workspace {
+                root_package: None,
+                members: vec![member("crate-a"), member("crate-b")],
+                default_members: vec!["crate-a".to_string(), "crate-b".to_string()],
+            },
             "cratesio",
             false,
         );
@@ -324,6 +534,8 @@ mod tests {
             json["workspace_members"],
             serde_json::json!(["crate-a", "crate-b"])
         );
+        assert_eq!(json["packages"][1]["name"], "crate-b");
+        assert!(json.get("default_members").is_some());
 
         // Test members success
         let members = CacheResponse::members_success(
@@ -0,0 +1,621 @@
+//! Integrity checks for the crate cache
+//!
+//! Cache entries can end up broken in ways the normal code paths don't notice:
+//! a crash between writing the docs and the dependency information, docs
+//! generated by a rustdoc whose JSON format is no longer understood, metadata
+//! that was truncated, or a cache directory that was moved so the paths in
+//! `dependencies.json` point nowhere. This module walks the cache, reports such
+//! problems as [`Issue`]s and names the [`Repair`] for each of them. Repairs
+//! themselves are carried out by [`CrateCache`](crate::cache::CrateCache),
+//! which owns the locks and the documentation generator.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::cache::storage::CacheStorage;
+use crate::cache::utils::read_docs;
+use crate::cache::workspace::WorkspaceHandler;
+use crate::docs::DocIndex;
+use crate::docs::cache::DocsFingerprint;
+
+/// Age after which an entry of the staging directory is considered abandoned
+pub const STALE_TEMP_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Kind of problem found in the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// The entry has no source, so nothing in it can be rebuilt
+    MissingSource,
+    MissingMetadata,
+    InvalidMetadata,
+    /// The docs can't be read or deserialized
+    InvalidDocs,
+    /// The docs were generated for a different rustdoc JSON format version
+    StaleDocsFormat,
+    /// The binary docs index doesn't match the docs
+    InvalidDocsIndex,
+    /// Docs exist without dependency information, left over from an interrupted build
+    MissingDependencies,
+    InvalidDependencies,
+    /// The dependency information was generated for a different source location or manifest
+    MismatchedDependencies,
+    /// A `members/` directory that belongs to no workspace member or holds no docs
+    OrphanedMember,
+    /// A backup of an update whose process is gone
+    LeftoverBackup,
+    /// A staging directory of a download or build that never finished
+    LeftoverTempDir,
+}
+
+/// How an issue is repaired
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "action", content = "member")]
+pub enum Repair {
+    /// Remove the whole crate version from the cache
+    RemoveEntry,
+    /// Write fresh metadata for the crate version
+    RewriteMetadata,
+    /// Delete the docs of the crate or a member and generate them again
+    RegenerateDocs(Option<String>),
+    /// Run `cargo metadata` again for the crate or a member
+    RegenerateDependencies(Option<String>),
+    /// Delete the file or directory at the issue's path
+    Remove,
+}
+
+/// A problem found in the cache
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crate_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+    pub path: PathBuf,
+    pub detail: String,
+    pub repair: Repair,
+    /// Whether the repair was carried out
+    pub repaired: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair_error: Option<String>,
+}
+
+/// Outcome of verifying the cache
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub repair: bool,
+    /// Number of crate versions checked
+    pub checked: usize,
+    /// Crate versions skipped because an operation on them was in progress
+    pub skipped_in_use: Vec<String>,
+    pub issues: Vec<Issue>,
+}
+
+/// Documentation of a crate or one of its workspace members
+struct DocTarget {
+    member: Option<String>,
+    docs_path: PathBuf,
+    index_path: PathBuf,
+    deps_path: PathBuf,
+    manifest_path: PathBuf,
+}
+
+/// Collects the issues of one crate version
+struct EntryChecker<'a> {
+    storage: &'a CacheStorage,
+    name: &'a str,
+    version: &'a str,
+    issues: Vec<Issue>,
+}
+
+impl EntryChecker<'_> {
+    fn report(
+        &mut self,
+        kind: IssueKind,
+        member: Option<&str>,
+        path: &Path,
+        detail: impl Into<String>,
+        repair: Repair,
+    ) {
+        self.issues.push(Issue {
+            kind,
+            crate_name: Some(self.name.to_string()),
+            version: Some(self.version.to_string()),
+            member: member.map(String::from),
+            path: path.to_path_buf(),
+            detail: detail.into(),
+            repair,
+            repaired: false,
+            repair_error: None,
+        });
+    }
+
+    fn check_metadata(&mut self) {
+        let path = self.storage.metadata_path(self.name, self.version);
+        if !path.exists() {
+            self.report(
+                IssueKind::MissingMetadata,
+                None,
+                &path,
+                "metadata.json is missing",
+                Repair::RewriteMetadata,
+            );
+            return;
+        }
+
+        match self.storage.load_metadata(self.name, self.version) {
+            Ok(metadata) if metadata.name == self.name && metadata.version == self.version => {}
+            Ok(metadata) => self.report(
+                IssueKind::InvalidMetadata,
+                None,
+                &path,
+                format!(
+                    "metadata.json describes {}-{}",
+                    metadata.name, metadata.version
+                ),
+                Repair::RewriteMetadata,
+            ),
+            Err(e) => self.report(
+                IssueKind::InvalidMetadata,
+                None,
+                &path,
+                format!("metadata.json can't be parsed: {e}"),
+                Repair::RewriteMetadata,
+            ),
+        }
+    }
+
+    fn check_docs(&mut self, target: &DocTarget) {
+        let member = target.member.as_deref();
+        if !target.docs_path.exists() {
+            // Docs are generated on first use, so missing docs are not a problem
+            return;
+        }
+
+        if let Some((kind, detail)) = docs_problem(&target.docs_path) {
+            self.report(
+                kind,
+                member,
+                &target.docs_path,
+                detail,
+                Repair::RegenerateDocs(target.member.clone()),
+            );
+            // Regenerating the docs rewrites the index and dependencies as well
+            return;
+        }
+
+        if target.index_path.exists() {
+            let index = DocsFingerprint::of(&target.docs_path)
+                .and_then(|fingerprint| DocIndex::open(&target.index_path, fingerprint));
+            if let Err(e) = index {
+                self.report(
+                    IssueKind::InvalidDocsIndex,
+                    member,
+                    &target.index_path,
+                    format!("docs index is unusable and will be rebuilt: {e}"),
+                    Repair::Remove,
+                );
+            }
+        }
+
+        let repair = Repair::RegenerateDependencies(target.member.clone());
+        if !target.deps_path.exists() {
+            self.report(
+                IssueKind::MissingDependencies,
+                member,
+                &target.deps_path,
+                "docs exist without dependencies.json, the build was interrupted",
+                repair,
+            );
+            return;
+        }
+        let source_path = self.storage.source_path(self.name, self.version);
+        if let Some((kind, detail)) =
+            dependencies_problem(&target.deps_path, &source_path, &target.manifest_path)
+        {
+            self.report(kind, member, &target.deps_path, detail, repair);
+        }
+    }
+
+    fn check_members(&mut self) {
+        let members = match self.storage.list_workspace_members(self.name, self.version) {
+            Ok(members) => members,
+            Err(e) => {
+                tracing::debug!(
+                    "Failed to list members of {}-{}: {}",
+                    self.name,
+                    self.version,
+                    e
+                );
+                return;
+            }
+        };
+        if members.is_empty() {
+            return;
+        }
+
+        let source_path = self.storage.source_path(self.name, self.version);
+        // Without the workspace members, cached members can't be told from orphaned ones
+        let workspace_members = match WorkspaceHandler::resolve(&source_path) {
+            Ok(workspace) => workspace.map(|w| w.members).unwrap_or_default(),
+            Err(e) => {
+                tracing::debug!(
+                    "Failed to resolve workspace members of {}-{}: {}",
+                    self.name,
+                    self.version,
+                    e
+                );
+                return;
+            }
+        };
+        for member_name in members {
+            let member_dir = self
+                .storage
+                .member_path(self.name, self.version, &member_name);
+            let Some(member) = workspace_members.iter().find(|m| m.name == member_name) else {
+                self.report(
+                    IssueKind::OrphanedMember,
+                    Some(&member_name),
+                    &member_dir,
+                    "no workspace member with this package name exists in the source",
+                    Repair::Remove,
+                );
+                continue;
+            };
+
+            let target = DocTarget {
+                docs_path: self
+                    .storage
+                    .member_docs_path(self.name, self.version, &member_name),
+                index_path: self.storage.member_docs_index_path(
+                    self.name,
+                    self.version,
+                    &member_name,
+                ),
+                deps_path: self.storage.member_dependencies_path(
+                    self.name,
+                    self.version,
+                    &member_name,
+                ),
+                manifest_path: source_path.join(&member.path).join("Cargo.toml"),
+                member: Some(member_name.clone()),
+            };
+            if !target.docs_path.exists() {
+                self.report(
+                    IssueKind::OrphanedMember,
+                    Some(&member_name),
+                    &member_dir,
+                    "member directory holds no documentation",
+                    Repair::Remove,
+                );
+                continue;
+            }
+            self.check_docs(&target);
+        }
+    }
+}
+
+/// Check a cached crate version and its workspace members
+///
+/// Reads and deserializes all documentation, so this is slow for large crates.
+pub fn check_entry(storage: &CacheStorage, name: &str, version: &str) -> Vec<Issue> {
+    let mut checker = EntryChecker {
+        storage,
+        name,
+        version,
+        issues: Vec::new(),
+    };
+
+    let source_path = storage.source_path(name, version);
+    if !source_path.exists() {
+        checker.report(
+            IssueKind::MissingSource,
+            None,
+            &storage.crate_path(name, version),
+            "the source is missing, the entry can't be used or rebuilt",
+            Repair::RemoveEntry,
+        );
+        return checker.issues;
+    }
+
+    checker.check_metadata();
+    checker.check_docs(&DocTarget {
+        member: None,
+        docs_path: storage.docs_path(name, version),
+        index_path: storage.docs_index_path(name, version),
+        deps_path: storage.dependencies_path(name, version),
+        manifest_path: source_path.join("Cargo.toml"),
+    });
+    checker.check_members();
+    checker.issues
+}
+
+/// Find update backups and staging directories that were left behind
+pub fn check_leftovers(storage: &CacheStorage) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let leftover = |kind, path: PathBuf, detail: String| Issue {
+        kind,
+        crate_name: None,
+        version: None,
+        member: None,
+        path,
+        detail,
+        repair: Repair::Remove,
+        repaired: false,
+        repair_error: None,
+    };
+
+    for path in dir_entries(&CacheStorage::backup_root()) {
+        // Backups are named `{name}-{version}-{timestamp}-{pid}`
+        let pid = path
+            .file_name()
+            .and_then(|name| name.to_str()?.rsplit('-').next()?.parse::<u32>().ok());
+        let in_use = match pid {
+            Some(pid) => pid == std::process::id() || process_alive(pid, &path),
+            None => !is_older_than(&path, STALE_TEMP_AGE),
+        };
+        if !in_use {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            issues.push(leftover(
+                IssueKind::LeftoverBackup,
+                path.clone(),
+                format!("backup {name} of an update that never finished"),
+            ));
+        }
+    }
+
+    for path in dir_entries(&storage.temp_dir()) {
+        if is_older_than(&path, STALE_TEMP_AGE) {
+            issues.push(leftover(
+                IssueKind::LeftoverTempDir,
+                path,
+                "staging directory of a download or build that never finished".to_string(),
+            ));
+        }
+    }
+
+    issues
+}
+
+/// Describe what is wrong with a docs file, if anything
+fn docs_problem(path: &Path) -> Option<(IssueKind, String)> {
+    #[derive(Deserialize)]
+    struct FormatVersion {
+        format_version: u32,
+    }
+
+    let bytes = match read_docs(path) {
+        Ok(bytes) => bytes,
+        Err(e) => return Some((IssueKind::InvalidDocs, format!("{e:#}"))),
+    };
+    match serde_json::from_slice::<FormatVersion>(&bytes) {
+        Ok(FormatVersion { format_version }) if format_version != rustdoc_types::FORMAT_VERSION => {
+            return Some((
+                IssueKind::StaleDocsFormat,
+                format!(
+                    "docs use rustdoc JSON format {format_version}, expected {}",
+                    rustdoc_types::FORMAT_VERSION
+                ),
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return Some((
+                IssueKind::InvalidDocs,
+                format!("docs have no format version: {e}"),
+            ));
+        }
+    }
+
+    serde_json::from_slice::<rustdoc_types::Crate>(&bytes)
+        .err()
+        .map(|e| {
+            (
+                IssueKind::InvalidDocs,
+                format!("docs can't be deserialized: {e}"),
+            )
+        })
+}
+
+/// Describe what is wrong with a `dependencies.json`, if anything
+///
+/// The file must be `cargo metadata` output for the cached source and must be
+/// newer than the manifest it was generated from.
+fn dependencies_problem(
+    path: &Path,
+    source_path: &Path,
+    manifest_path: &Path,
+) -> Option<(IssueKind, String)> {
+    #[derive(Deserialize)]
+    struct CargoMetadata {
+        workspace_root: PathBuf,
+    }
+
+    let metadata = match fs::read(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| Ok(serde_json::from_slice::<CargoMetadata>(&bytes)?))
+    {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            return Some((
+                IssueKind::InvalidDependencies,
+                format!("dependencies.json can't be parsed: {e}"),
+            ));
+        }
+    };
+
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if canonical(&metadata.workspace_root) != canonical(source_path) {
+        return Some((
+            IssueKind::MismatchedDependencies,
+            format!(
+                "dependencies were generated for {}",
+                metadata.workspace_root.display()
+            ),
+        ));
+    }
+
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    if let (Some(deps), Some(manifest)) = (modified(path), modified(manifest_path))
+        && manifest > deps
+    {
+        return Some((
+            IssueKind::MismatchedDependencies,
+            "Cargo.toml changed after the dependencies were generated".to_string(),
+        ));
+    }
+    None
+}
+
+/// Paths of the entries of a directory, empty if it can't be read
+fn dir_entries(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default()
+}
+
+fn is_older_than(path: &Path, age: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|elapsed| elapsed > age)
+}
+
+/// Whether the process that created `path` is still running
+#[cfg(unix)]
+fn process_alive(pid: u32, _path: &Path) -> bool {
+    use nix::errno::Errno;
+    use nix::sys::signal::kill;
+    use nix::unistd::Pid;
+
+    // Signal 0 only checks whether the process exists
+    !matches!(kill(Pid::from_raw(pid as i32), None), Err(Errno::ESRCH))
+}
+
+/// Whether the process that created `path` is still running
+#[cfg(not(unix))]
+fn process_alive(_pid: u32, path: &Path) -> bool {
+    !is_older_than(path, STALE_TEMP_AGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::utils::compress_atomic;
+    use anyhow::Result;
+    use tempfile::TempDir;
+
+    /// Cache a crate with valid docs and dependencies, returning the storage
+    fn cached_crate(temp_dir: &TempDir) -> Result<CacheStorage> {
+        let storage = CacheStorage::new(Some(temp_dir.path().to_path_buf()))?;
+        let source = storage.source_path("demo", "1.0.0");
+        fs::create_dir_all(&source)?;
+        fs::write(
+            source.join("Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"1.0.0\"\n",
+        )?;
+
+        let docs = serde_json::json!({
+            "root": 0,
+            "crate_version": "1.0.0",
+            "includes_private": false,
+            "index": {},
+            "paths": {},
+            "external_crates": {},
+            "target": { "triple": "x86_64-unknown-linux-gnu", "target_features": [] },
+            "format_version": rustdoc_types::FORMAT_VERSION
+        });
+        let plain = temp_dir.path().join("demo.json");
+        fs::write(&plain, serde_json::to_vec(&docs)?)?;
+        compress_atomic(&plain, &storage.docs_path("demo", "1.0.0"))?;
+
+        let deps = serde_json::json!({ "workspace_root": source, "packages": [] });
+        fs::write(
+            storage.dependencies_path("demo", "1.0.0"),
+            serde_json::to_vec(&deps)?,
+        )?;
+        storage.save_metadata("demo", "1.0.0")?;
+        Ok(storage)
+    }
+
+    fn kinds(issues: &[Issue]) -> Vec<IssueKind> {
+        issues.iter().map(|issue| issue.kind).collect()
+    }
+
+    #[test]
+    fn test_valid_entry_has_no_issues() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = cached_crate(&temp_dir)?;
+        assert!(storage.has_docs("demo", "1.0.0"));
+        assert_eq!(kinds(&check_entry(&storage, "demo", "1.0.0")), []);
+        Ok(())
+    }
+
+    #[test]
+    fn test_detects_broken_files() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = cached_crate(&temp_dir)?;
+
+        fs::write(storage.metadata_path("demo", "1.0.0"), "{ truncated")?;
+        fs::remove_file(storage.dependencies_path("demo", "1.0.0"))?;
+        fs::create_dir_all(storage.member_path("demo", "1.0.0", "gone"))?;
+
+        // Docs without dependencies are an interrupted build, not generated docs
+        assert!(!storage.has_docs("demo", "1.0.0"));
+        let issues = check_entry(&storage, "demo", "1.0.0");
+        assert_eq!(
+            kinds(&issues),
+            [
+                IssueKind::InvalidMetadata,
+                IssueKind::MissingDependencies,
+                IssueKind::OrphanedMember
+            ]
+        );
+        assert_eq!(issues[1].repair, Repair::RegenerateDependencies(None));
+        Ok(())
+    }
+
+    #[test]
+    fn test_detects_stale_and_corrupt_docs() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = cached_crate(&temp_dir)?;
+        let docs_path = storage.docs_path("demo", "1.0.0");
+
+        let stale = temp_dir.path().join("stale.json");
+        fs::write(&stale, r#"{"format_version": 1}"#)?;
+        compress_atomic(&stale, &docs_path)?;
+        assert_eq!(
+            kinds(&check_entry(&storage, "demo", "1.0.0")),
+            [IssueKind::StaleDocsFormat]
+        );
+
+        fs::write(&docs_path, b"\x1f\x8b not really gzip")?;
+        let issues = check_entry(&storage, "demo", "1.0.0");
+        assert_eq!(kinds(&issues), [IssueKind::InvalidDocs]);
+        assert_eq!(issues[0].repair, Repair::RegenerateDocs(None));
+        Ok(())
+    }
+
+    #[test]
+    fn test_detects_dependencies_of_another_location() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let storage = cached_crate(&temp_dir)?;
+
+        let deps = serde_json::json!({ "workspace_root": "/somewhere/else", "packages": [] });
+        fs::write(
+            storage.dependencies_path("demo", "1.0.0"),
+            serde_json::to_vec(&deps)?,
+        )?;
+        assert_eq!(
+            kinds(&check_entry(&storage, "demo", "1.0.0")),
+            [IssueKind::MismatchedDependencies]
+        );
+        Ok(())
+    }
+}
@@ -1,40 +1,109 @@
 //! Workspace handling utilities for Rust crates
 //!
 //! This module provides functionality for detecting and managing Rust workspace crates,
-//! including member detection and metadata extraction.
+//! including member detection and metadata extraction. Members are resolved by
+//! `cargo metadata`, which expands `members` globs and applies `exclude`,
+//! `default-members` and implicit path dependency members the way cargo does.
+//!
+//! Members are identified by their package name, which is unique within a
+//! workspace, and `member` parameters accept either that name or the member's
+//! path relative to the workspace root.
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
 use toml::Value;
 
 /// Workspace-related utilities
 pub struct WorkspaceHandler;
 
-impl WorkspaceHandler {
-    /// Check if a Cargo.toml represents a virtual manifest (workspace without [package])
-    pub fn is_workspace(cargo_toml_path: &Path) -> Result<bool> {
-        let content = fs::read_to_string(cargo_toml_path).with_context(|| {
-            format!("Failed to read Cargo.toml at {}", cargo_toml_path.display())
-        })?;
+/// A workspace resolved by `cargo metadata`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    /// The package of a non-virtual workspace's root manifest
+    pub root_package: Option<WorkspaceMember>,
+    /// Members other than the root package, ordered by path
+    pub members: Vec<WorkspaceMember>,
+    /// Paths of the members built when no package is selected
+    pub default_members: Vec<String>,
+}
 
-        let parsed: Value = toml::from_str(&content).with_context(|| {
-            format!(
-                "Failed to parse Cargo.toml at {}",
-                cargo_toml_path.display()
-            )
-        })?;
+impl Workspace {
+    /// Paths of the members other than the root package
+    pub fn member_paths(&self) -> Vec<String> {
+        self.members.iter().map(|m| m.path.clone()).collect()
+    }
 
-        // A virtual manifest has [workspace] but no [package]
-        let has_workspace = parsed.get("workspace").is_some();
-        let has_package = parsed.get("package").is_some();
+    /// Find a member other than the root package by its package name or path
+    pub fn find_member(&self, member: &str) -> Option<&WorkspaceMember> {
+        self.members.iter().find(|m| m.matches(member))
+    }
+}
 
-        Ok(has_workspace && !has_package)
+/// A package of a workspace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceMember {
+    /// Package name, which names the member's cache directory
+    pub name: String,
+    /// Package id assigned by cargo
+    pub id: String,
+    pub version: String,
+    /// Directory of the package relative to the workspace root, `.` for the root package
+    pub path: String,
+    /// Kinds of the package's targets, e.g. `lib`, `proc-macro` or `bin`
+    pub target_kinds: Vec<String>,
+    /// Crate name of the library target, which names its rustdoc JSON file
+    #[serde(default)]
+    pub lib_name: Option<String>,
+}
+
+impl WorkspaceMember {
+    /// Check whether a `member` parameter, a package name or member path, refers to this member
+    pub fn matches(&self, member: &str) -> bool {
+        self.name == member || self.path == normalize_member_path(member)
     }
+}
+
+/// The parts of `cargo metadata --no-deps` output needed to resolve members
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+    workspace_members: Vec<String>,
+    #[serde(default)]
+    workspace_default_members: Option<Vec<String>>,
+    workspace_root: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    id: String,
+    name: String,
+    version: String,
+    manifest_path: PathBuf,
+    targets: Vec<MetadataTarget>,
+}
+
+#[derive(Deserialize)]
+struct MetadataTarget {
+    name: String,
+    kind: Vec<String>,
+}
 
-    /// Get workspace members from a workspace Cargo.toml
-    pub fn get_workspace_members(cargo_toml_path: &Path) -> Result<Vec<String>> {
-        let content = fs::read_to_string(cargo_toml_path).with_context(|| {
+/// Target kinds of libraries, which can be depended on and re-exported
+pub const LIBRARY_KINDS: &[&str] = &["lib", "rlib", "dylib", "proc-macro"];
+
+impl WorkspaceHandler {
+    /// Resolve the workspace rooted at `source_path`
+    ///
+    /// Both virtual manifests and packages whose `[workspace]` has further members
+    /// are workspaces. Returns `None` if the manifest has no `[workspace]` section,
+    /// which is recognized without running cargo, or the workspace consists of the
+    /// root package only.
+    pub fn resolve(source_path: &Path) -> Result<Option<Workspace>> {
+        let cargo_toml_path = source_path.join("Cargo.toml");
+        let content = fs::read_to_string(&cargo_toml_path).with_context(|| {
             format!("Failed to read Cargo.toml at {}", cargo_toml_path.display())
         })?;
 
@@ -44,64 +113,135 @@ impl WorkspaceHandler {
                 cargo_toml_path.display()
             )
         })?;
+        if parsed.get("workspace").is_none() {
+            return Ok(None);
+        }
 
-        let workspace = parsed
-            .get("workspace")
-            .ok_or_else(|| anyhow!("No [workspace] section found in Cargo.toml"))?;
-
-        let members = workspace
-            .get("members")
-            .and_then(|m| m.as_array())
-            .ok_or_else(|| anyhow!("No members array found in [workspace] section"))?;
-
-        let mut member_list = Vec::new();
-        for member in members {
-            if let Some(member_str) = member.as_str() {
-                // Expand glob patterns
-                if member_str.contains('*') {
-                    // For now, we'll skip glob patterns and handle them later if needed
-                    // In the real implementation, we'd expand these patterns
-                    if member_str == "examples/*" {
-                        // Skip examples for now as requested
-                        continue;
-                    }
-                } else {
-                    member_list.push(member_str.to_string());
-                }
-            }
+        let metadata = Self::cargo_metadata(&cargo_toml_path)?;
+        let workspace = Self::from_metadata(metadata)?;
+        Ok((!workspace.members.is_empty()).then_some(workspace))
+    }
+
+    /// Run `cargo metadata` without resolving dependencies, which needs no network access
+    fn cargo_metadata(cargo_toml_path: &Path) -> Result<Metadata> {
+        let output = Command::new("cargo")
+            .args([
+                "metadata",
+                "--no-deps",
+                "--offline",
+                "--format-version",
+                "1",
+            ])
+            .arg("--manifest-path")
+            .arg(cargo_toml_path)
+            .output()
+            .context("Failed to run cargo metadata")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!(
+                "Failed to resolve workspace members of {}: {}",
+                cargo_toml_path.display(),
+                stderr.trim()
+            );
         }
 
-        Ok(member_list)
+        serde_json::from_slice(&output.stdout).context("Failed to parse cargo metadata output")
     }
 
-    /// Get the package name from a Cargo.toml file
-    pub fn get_package_name(cargo_toml_path: &Path) -> Result<String> {
-        let content = fs::read_to_string(cargo_toml_path).with_context(|| {
-            format!("Failed to read Cargo.toml at {}", cargo_toml_path.display())
-        })?;
+    fn from_metadata(metadata: Metadata) -> Result<Workspace> {
+        let root = &metadata.workspace_root;
+        let mut root_package = None;
+        let mut members = Vec::new();
+        let mut member_paths = Vec::new();
 
-        let parsed: Value = toml::from_str(&content).with_context(|| {
-            format!(
-                "Failed to parse Cargo.toml at {}",
-                cargo_toml_path.display()
-            )
-        })?;
+        for package in &metadata.packages {
+            if !metadata.workspace_members.contains(&package.id) {
+                continue;
+            }
+            let package_dir = package.manifest_path.parent().unwrap_or(root);
+            let relative = package_dir.strip_prefix(root).with_context(|| {
+                format!(
+                    "Workspace member {} is outside of the workspace root {}",
+                    package_dir.display(),
+                    root.display()
+                )
+            })?;
+            let path = relative_path(relative);
 
-        let package = parsed
-            .get("package")
-            .ok_or_else(|| anyhow!("No [package] section found in Cargo.toml"))?;
+            let member = WorkspaceMember {
+                name: package.name.clone(),
+                id: package.id.clone(),
+                version: package.version.clone(),
+                path: path.clone(),
+                target_kinds: package
+                    .targets
+                    .iter()
+                    .flat_map(|target| target.kind.iter().cloned())
+                    .fold(Vec::new(), |mut kinds, kind| {
+                        if !kinds.contains(&kind) {
+                            kinds.push(kind);
+                        }
+                        kinds
+                    }),
+                lib_name: package
+                    .targets
+                    .iter()
+                    .find(|target| {
+                        target
+                            .kind
+                            .iter()
+                            .any(|kind| LIBRARY_KINDS.contains(&kind.as_str()))
+                    })
+                    .map(|target| target.name.replace('-', "_")),
+            };
+            member_paths.push((package.id.as_str(), path.clone()));
+            if path == "." {
+                root_package = Some(member);
+            } else {
+                members.push(member);
+            }
+        }
+        members.sort_by(|a, b| a.path.cmp(&b.path));
 
-        let name = package
-            .get("name")
-            .and_then(|n| n.as_str())
-            .ok_or_else(|| anyhow!("No 'name' field found in [package] section"))?;
+        // Cargo before 1.71 doesn't report default members, all members are built then
+        let default_ids = metadata
+            .workspace_default_members
+            .as_ref()
+            .unwrap_or(&metadata.workspace_members);
+        let mut default_members: Vec<String> = member_paths
+            .into_iter()
+            .filter(|(id, _)| default_ids.iter().any(|default| default == id))
+            .map(|(_, path)| path)
+            .collect();
+        default_members.sort();
 
-        Ok(name.to_string())
+        Ok(Workspace {
+            root_package,
+            members,
+            default_members,
+        })
     }
+}
+
+/// Normalize a member path given by a user, e.g. `./crates/core/` to `crates/core`
+fn normalize_member_path(path: &str) -> String {
+    relative_path(Path::new(path))
+}
 
-    /// Extract member name from a member path
-    pub fn extract_member_name(member_path: &str) -> &str {
-        member_path.split('/').next_back().unwrap_or(member_path)
+/// Format a path relative to the workspace root with `/` separators, `.` if it is empty
+fn relative_path(path: &Path) -> String {
+    let parts: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join("/")
     }
 }
 
@@ -110,58 +250,89 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
-    #[test]
-    fn test_extract_member_name() {
-        assert_eq!(WorkspaceHandler::extract_member_name("crates/rmcp"), "rmcp");
-        assert_eq!(WorkspaceHandler::extract_member_name("rmcp"), "rmcp");
-        assert_eq!(
-            WorkspaceHandler::extract_member_name("path/to/deep/crate"),
-            "crate"
-        );
+    fn write_package(dir: &Path, manifest: &str) -> Result<()> {
+        fs::create_dir_all(dir.join("src"))?;
+        fs::write(dir.join("Cargo.toml"), manifest)?;
+        fs::write(dir.join("src").join("lib.rs"), "")?;
+        Ok(())
+    }
+
+    fn package(name: &str) -> String {
+        format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n")
     }
 
     #[test]
     fn test_workspace_detection() -> Result<()> {
         let temp_dir = TempDir::new()?;
 
-        // Test virtual manifest (workspace without package)
-        let workspace_toml = temp_dir.path().join("workspace.toml");
-        fs::write(
-            &workspace_toml,
-            r#"
-[workspace]
-members = ["crate-a", "crate-b"]
-"#,
-        )?;
-        assert!(WorkspaceHandler::is_workspace(&workspace_toml)?);
-
         // Test regular crate (has package)
-        let crate_toml = temp_dir.path().join("crate.toml");
-        fs::write(
-            &crate_toml,
-            r#"
-[package]
-name = "my-crate"
-version = "0.1.0"
-"#,
+        let single = temp_dir.path().join("single");
+        write_package(&single, &package("my-crate"))?;
+        assert_eq!(WorkspaceHandler::resolve(&single)?, None);
+
+        // A package that only opts out of an enclosing workspace has no members
+        let detached = temp_dir.path().join("detached");
+        write_package(
+            &detached,
+            &format!("{}\n[workspace]\n", package("detached")),
         )?;
-        assert!(!WorkspaceHandler::is_workspace(&crate_toml)?);
+        assert_eq!(WorkspaceHandler::resolve(&detached)?, None);
 
         // Test workspace with package (not a virtual manifest)
-        let mixed_toml = temp_dir.path().join("mixed.toml");
+        let mixed = temp_dir.path().join("mixed");
+        write_package(
+            &mixed,
+            &format!(
+                "{}\n[workspace]\nmembers = [\"sub-crate\"]\n",
+                package("my-crate")
+            ),
+        )?;
+        write_package(&mixed.join("sub-crate"), &package("sub-crate"))?;
+        let workspace = WorkspaceHandler::resolve(&mixed)?.unwrap();
+        assert_eq!(workspace.root_package.as_ref().unwrap().name, "my-crate");
+        assert_eq!(workspace.member_paths(), ["sub-crate"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_virtual_workspace() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
         fs::write(
-            &mixed_toml,
+            root.join("Cargo.toml"),
             r#"
-[package]
-name = "my-crate"
-version = "0.1.0"
-
 [workspace]
-members = ["sub-crate"]
+members = ["crates/*", "tools/cli"]
+exclude = ["crates/experimental"]
+default-members = ["crates/core"]
 "#,
         )?;
-        assert!(!WorkspaceHandler::is_workspace(&mixed_toml)?);
+        write_package(&root.join("crates/core"), &package("demo-core"))?;
+        write_package(&root.join("crates/macros"), &package("demo-macros"))?;
+        write_package(&root.join("crates/experimental"), &package("experimental"))?;
+        write_package(&root.join("tools/cli"), &package("demo-cli"))?;
+        fs::write(root.join("tools/cli/src/main.rs"), "fn main() {}")?;
 
+        let workspace = WorkspaceHandler::resolve(root)?.unwrap();
+        assert_eq!(workspace.root_package, None);
+        assert_eq!(
+            workspace.member_paths(),
+            ["crates/core", "crates/macros", "tools/cli"]
+        );
+        assert_eq!(workspace.default_members, ["crates/core"]);
+
+        let core = &workspace.members[0];
+        assert_eq!(core.name, "demo-core");
+        assert_eq!(core.version, "0.1.0");
+        assert_eq!(core.target_kinds, ["lib"]);
+        assert_eq!(core.lib_name.as_deref(), Some("demo_core"));
+        assert_eq!(workspace.members[2].target_kinds, ["lib", "bin"]);
+
+        // Members are found by package name or path
+        for member in ["demo-macros", "crates/macros", "./crates/macros/"] {
+            assert_eq!(workspace.find_member(member).unwrap().name, "demo-macros");
+        }
+        assert!(workspace.find_member("macros").is_none());
         Ok(())
     }
 }
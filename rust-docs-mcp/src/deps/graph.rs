@@ -0,0 +1,599 @@
+//! Resolved dependency graph of a crate
+//!
+//! `dependencies.json` holds the output of `cargo metadata`: every package with
+//! its declared dependencies and features, and under `resolve` the graph cargo
+//! resolved, with the features it enabled on each package. [`DependencyGraph`]
+//! indexes both, so dependency queries can walk the graph in either direction.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use anyhow::{Context, Result, bail};
+use rmcp::schemars;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cache::workspace::LIBRARY_KINDS;
+
+/// Upper bound on the partial paths explored when searching paths between packages
+const MAX_PARTIAL_PATHS: usize = 10_000;
+
+/// Kind of a dependency, as named in `cargo metadata`
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+    Normal,
+    Build,
+    Dev,
+}
+
+impl DependencyKind {
+    pub fn parse(kind: &str) -> Result<Self> {
+        match kind {
+            "normal" => Ok(Self::Normal),
+            "build" => Ok(Self::Build),
+            "dev" => Ok(Self::Dev),
+            _ => bail!("Unknown dependency kind '{kind}', expected normal, build or dev"),
+        }
+    }
+}
+
+/// One way a package depends on another, a dependency may be declared several times
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct EdgeKind {
+    pub kind: DependencyKind,
+    /// Platform the dependency is limited to, e.g. `cfg(windows)`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// A package of the resolved graph
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// Where cargo resolved the package from, `None` for local packages
+    pub source: Option<String>,
+    /// Name of the package's library as used in code, e.g. `serde_json`
+    pub lib_name: String,
+    /// Features cargo enabled on the package
+    pub features: Vec<String>,
+    /// Features the package declares, with the features and dependencies each enables
+    pub declared_features: BTreeMap<String, Vec<String>>,
+}
+
+/// Dependency of a package on another
+#[derive(Debug, Clone)]
+pub struct Edge {
+    /// Index of the dependency
+    pub to: usize,
+    /// Name the dependent declares the dependency under, its package name unless renamed
+    pub name: String,
+    /// Version requirement of the declaration, e.g. `^1.0`
+    pub requirement: Option<String>,
+    pub kinds: Vec<EdgeKind>,
+    /// Features of the dependency this edge enables, including `default`
+    pub features: Vec<String>,
+    /// Whether the dependency is optional, enabled by a feature of the dependent
+    pub optional: bool,
+}
+
+impl Edge {
+    /// Whether the edge is declared with one of `kinds`
+    pub fn has_kind(&self, kinds: &[DependencyKind]) -> bool {
+        self.kinds
+            .iter()
+            .any(|edge_kind| kinds.contains(&edge_kind.kind))
+    }
+}
+
+/// Packages and dependencies resolved by cargo, indexed in both directions
+#[derive(Debug)]
+pub struct DependencyGraph {
+    packages: Vec<Package>,
+    dependencies: Vec<Vec<Edge>>,
+    /// Package and position in its dependencies of every edge into a package
+    dependents: Vec<Vec<(usize, usize)>>,
+}
+
+impl DependencyGraph {
+    /// Build the graph from `cargo metadata` output
+    pub fn from_metadata(metadata: &Value) -> Result<Self> {
+        let package_values: Vec<&Value> = metadata["packages"]
+            .as_array()
+            .context("No packages in dependency metadata")?
+            .iter()
+            .filter(|package| package["id"].is_string())
+            .collect();
+        let nodes = metadata["resolve"]["nodes"]
+            .as_array()
+            .context("No resolved dependency graph in dependency metadata")?;
+
+        let ids: HashMap<&str, usize> = package_values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, package)| Some((package["id"].as_str()?, index)))
+            .collect();
+        let mut packages: Vec<Package> = package_values
+            .iter()
+            .map(|package| Package {
+                name: package["name"].as_str().unwrap_or_default().to_string(),
+                version: package["version"].as_str().unwrap_or_default().to_string(),
+                source: package["source"].as_str().map(String::from),
+                lib_name: library_name(package),
+                features: Vec::new(),
+                declared_features: package["features"]
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(feature, values)| (feature.clone(), strings(values)))
+                    .collect(),
+            })
+            .collect();
+        for node in nodes {
+            if let Some(&index) = node["id"].as_str().and_then(|id| ids.get(id)) {
+                packages[index].features = strings(&node["features"]);
+            }
+        }
+
+        let mut dependencies = vec![Vec::new(); packages.len()];
+        let mut dependents = vec![Vec::new(); packages.len()];
+        for node in nodes {
+            let Some(&from) = node["id"].as_str().and_then(|id| ids.get(id)) else {
+                continue;
+            };
+            for dep in node["deps"].as_array().into_iter().flatten() {
+                let Some(&to) = dep["pkg"].as_str().and_then(|id| ids.get(id)) else {
+                    continue;
+                };
+                // Cargo before 1.41 doesn't report dependency kinds
+                let kinds = match dep["dep_kinds"].as_array() {
+                    Some(kinds) => kinds
+                        .iter()
+                        .filter_map(|kind| {
+                            let dependency_kind = match kind["kind"].as_str() {
+                                None => DependencyKind::Normal,
+                                Some(kind) => DependencyKind::parse(kind).ok()?,
+                            };
+                            Some(EdgeKind {
+                                kind: dependency_kind,
+                                target: kind["target"].as_str().map(String::from),
+                            })
+                        })
+                        .collect(),
+                    None => vec![EdgeKind {
+                        kind: DependencyKind::Normal,
+                        target: None,
+                    }],
+                };
+                let declaration = declaration(
+                    package_values[from],
+                    &packages[from].features,
+                    &packages[to],
+                    package_values[to],
+                    dep["name"].as_str().unwrap_or_default(),
+                );
+
+                dependents[to].push((from, dependencies[from].len()));
+                dependencies[from].push(Edge {
+                    to,
+                    name: declaration.name,
+                    requirement: declaration.requirement,
+                    kinds,
+                    features: declaration.features,
+                    optional: declaration.optional,
+                });
+            }
+        }
+
+        Ok(Self {
+            packages,
+            dependencies,
+            dependents,
+        })
+    }
+
+    pub fn package(&self, index: usize) -> &Package {
+        &self.packages[index]
+    }
+
+    pub fn dependencies(&self, index: usize) -> &[Edge] {
+        &self.dependencies[index]
+    }
+
+    /// Packages depending on a package, with the edge from each
+    pub fn dependents(&self, index: usize) -> impl Iterator<Item = (usize, &Edge)> {
+        self.dependents[index]
+            .iter()
+            .map(|&(from, position)| (from, &self.dependencies[from][position]))
+    }
+
+    /// Packages reachable from a package through any dependencies, itself first, breadth first
+    pub fn reachable(&self, root: usize) -> Vec<usize> {
+        let mut order = vec![root];
+        let mut seen = HashSet::from([root]);
+        let mut next = 0;
+        while let Some(&index) = order.get(next) {
+            for edge in self.dependencies(index) {
+                if seen.insert(edge.to) {
+                    order.push(edge.to);
+                }
+            }
+            next += 1;
+        }
+        order
+    }
+
+    /// Find the local package, such as the cached crate or a workspace member, named `name`
+    pub fn local_package(&self, name: &str) -> Option<usize> {
+        self.packages
+            .iter()
+            .position(|package| package.name == name && package.source.is_none())
+    }
+
+    /// Find all versions of a package by its package or library name, ignoring case
+    pub fn find(&self, name: &str) -> Vec<usize> {
+        let name = name.to_lowercase();
+        (0..self.packages.len())
+            .filter(|&index| {
+                let package = &self.packages[index];
+                package.name.to_lowercase() == name || package.lib_name.to_lowercase() == name
+            })
+            .collect()
+    }
+
+    /// Names of packages whose name contains `name` or is contained in it, ignoring case
+    pub fn similar_names(&self, name: &str) -> Vec<String> {
+        let name = name.to_lowercase().replace('_', "-");
+        let names: BTreeSet<&str> = self
+            .packages
+            .iter()
+            .map(|package| package.name.as_str())
+            .filter(|package| {
+                let package = package.to_lowercase().replace('_', "-");
+                package.contains(&name) || name.contains(&package)
+            })
+            .collect();
+        names.into_iter().map(String::from).collect()
+    }
+
+    /// Find the package providing the library `lib_name` to the package `root`
+    ///
+    /// Normal dependencies are walked breadth first from `root`, so a direct
+    /// dependency wins over another version of the same library deeper in the graph.
+    pub fn find_library(&self, root: usize, lib_name: &str) -> Option<usize> {
+        let mut seen = HashSet::from([root]);
+        let mut queue = VecDeque::from([root]);
+        while let Some(index) = queue.pop_front() {
+            // Only normal dependencies are linked into the documented library
+            for edge in self.dependencies(index) {
+                if !edge.has_kind(&[DependencyKind::Normal]) || !seen.insert(edge.to) {
+                    continue;
+                }
+                if self.packages[edge.to].lib_name == lib_name {
+                    return Some(edge.to);
+                }
+                queue.push_back(edge.to);
+            }
+        }
+        None
+    }
+
+    /// Find the paths of dependencies leading from one package to another, shortest first
+    ///
+    /// Returns at most `max_paths` paths, each listing the packages from `from` to
+    /// `to`, and whether there are more.
+    pub fn paths(&self, from: usize, to: usize, max_paths: usize) -> (Vec<Vec<usize>>, bool) {
+        // Only packages depending on `to`, directly or not, lead there
+        let mut leads_to = HashSet::from([to]);
+        let mut queue = VecDeque::from([to]);
+        while let Some(index) = queue.pop_front() {
+            for (dependent, _) in self.dependents(index) {
+                if leads_to.insert(dependent) {
+                    queue.push_back(dependent);
+                }
+            }
+        }
+        if !leads_to.contains(&from) {
+            return (Vec::new(), false);
+        }
+
+        let mut paths = Vec::new();
+        let mut queue = VecDeque::from([vec![from]]);
+        while let Some(path) = queue.pop_front() {
+            let last = path[path.len() - 1];
+            if last == to {
+                if paths.len() == max_paths {
+                    return (paths, true);
+                }
+                paths.push(path);
+                continue;
+            }
+            if queue.len() > MAX_PARTIAL_PATHS {
+                return (paths, true);
+            }
+            for edge in self.dependencies(last) {
+                if leads_to.contains(&edge.to) && !path.contains(&edge.to) {
+                    let mut next = path.clone();
+                    next.push(edge.to);
+                    queue.push_back(next);
+                }
+            }
+        }
+        (paths, false)
+    }
+
+    /// Get the edge from a package to one of its dependencies
+    pub fn edge(&self, from: usize, to: usize) -> Option<&Edge> {
+        self.dependencies(from).iter().find(|edge| edge.to == to)
+    }
+}
+
+/// How a dependent declares one of its resolved dependencies
+struct Declaration {
+    name: String,
+    requirement: Option<String>,
+    features: Vec<String>,
+    optional: bool,
+}
+
+/// Find the declaration of a resolved dependency in its dependent's manifest
+///
+/// The features of the dependency the dependent enables are the features listed
+/// with the dependency, `default` unless default features are disabled, and
+/// features forwarded by the dependent's enabled features, e.g.
+/// `tls = ["reqwest/native-tls"]`.
+fn declaration(
+    dependent: &Value,
+    enabled: &[String],
+    dependency: &Package,
+    dependency_value: &Value,
+    extern_name: &str,
+) -> Declaration {
+    let mut features = BTreeSet::new();
+    let mut optional = false;
+    let mut requirement = None;
+    // Names the dependency is declared under, as used in the dependent's features
+    let mut keys = HashSet::new();
+    for declared in dependent["dependencies"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|declared| declared["name"].as_str() == Some(dependency.name.as_str()))
+    {
+        let rename = declared["rename"].as_str();
+        let declared_extern_name = rename
+            .map(|rename| rename.replace('-', "_"))
+            .unwrap_or_else(|| dependency.lib_name.clone());
+        if declared_extern_name != extern_name {
+            continue;
+        }
+
+        keys.insert(rename.unwrap_or(&dependency.name));
+        optional |= declared["optional"].as_bool().unwrap_or(false);
+        if requirement.is_none() {
+            requirement = declared["req"].as_str().map(String::from);
+        }
+        features.extend(strings(&declared["features"]));
+        if declared["uses_default_features"].as_bool().unwrap_or(true)
+            && dependency_value["features"].get("default").is_some()
+        {
+            features.insert("default".to_string());
+        }
+    }
+
+    for feature in enabled {
+        for value in strings(&dependent["features"][feature]) {
+            if let Some((key, feature)) = value.split_once('/')
+                && keys.contains(key.trim_end_matches('?'))
+            {
+                features.insert(feature.to_string());
+            }
+        }
+    }
+    // Every declaration found has the same name, as the extern name derives from it
+    let name = keys
+        .into_iter()
+        .next()
+        .unwrap_or(&dependency.name)
+        .to_string();
+    Declaration {
+        name,
+        requirement,
+        features: features.into_iter().collect(),
+        optional,
+    }
+}
+
+/// Name of a package's library as used in code, e.g. `serde_json`
+fn library_name(package: &Value) -> String {
+    package["targets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|target| {
+            target["kind"].as_array().into_iter().flatten().any(|kind| {
+                kind.as_str()
+                    .is_some_and(|kind| LIBRARY_KINDS.contains(&kind))
+            })
+        })
+        .and_then(|target| target["name"].as_str())
+        .or_else(|| package["name"].as_str())
+        .unwrap_or_default()
+        .replace('-', "_")
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.as_str().map(String::from))
+        .collect()
+}
+
+/// Builders for `cargo metadata` output shared by the dependency tests
+#[cfg(test)]
+pub mod test_support {
+    use serde_json::Value;
+
+    pub const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+    /// Package with a single library target and no declared dependencies or features
+    pub fn package(id: &str, name: &str, version: &str, source: Option<&str>) -> Value {
+        serde_json::json!({
+            "id": id,
+            "name": name,
+            "version": version,
+            "source": source,
+            "targets": [{ "name": name, "kind": ["lib"] }],
+            "dependencies": [],
+            "features": {},
+        })
+    }
+
+    /// Resolved dependency on the package with id `pkg`, used in code as `name`
+    pub fn dep(pkg: &str, name: &str, kind: Option<&str>) -> Value {
+        target_dep(pkg, name, kind, None)
+    }
+
+    /// Like [`dep`], limited to the platform `target`
+    pub fn target_dep(pkg: &str, name: &str, kind: Option<&str>, target: Option<&str>) -> Value {
+        serde_json::json!({
+            "pkg": pkg,
+            "name": name,
+            "dep_kinds": [{ "kind": kind, "target": target }],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::{CRATES_IO, dep, package};
+    use super::*;
+
+    /// `app` uses bytes 1 directly, and bytes 0.5 and openssl through `old-http`,
+    /// whose `tls` feature `app` enables
+    fn metadata() -> Value {
+        let mut app = package("app", "app", "0.1.0", None);
+        app["dependencies"] = serde_json::json!([
+            { "name": "old-http", "rename": null, "features": ["tls"], "uses_default_features": false, "kind": null },
+            { "name": "bytes", "rename": null, "features": [], "uses_default_features": true, "kind": null },
+            { "name": "cc", "rename": null, "features": [], "uses_default_features": true, "kind": "build" },
+        ]);
+        let mut old_http = package("old-http", "old-http", "0.1.0", Some(CRATES_IO));
+        old_http["dependencies"] = serde_json::json!([
+            { "name": "bytes", "rename": null, "features": [], "uses_default_features": false, "kind": null },
+            { "name": "openssl", "rename": null, "features": [], "uses_default_features": true, "kind": null, "optional": true },
+        ]);
+        old_http["features"] = serde_json::json!({
+            "default": [],
+            "tls": ["dep:openssl", "openssl?/vendored"],
+        });
+        let mut bytes = package("bytes-1", "bytes", "1.7.1", Some(CRATES_IO));
+        bytes["features"] = serde_json::json!({ "default": ["std"], "std": [] });
+
+        serde_json::json!({
+            "packages": [
+                app,
+                old_http,
+                package("bytes-0.5", "bytes", "0.5.6", Some(CRATES_IO)),
+                bytes,
+                package("openssl", "openssl", "0.10.66", Some(CRATES_IO)),
+                package("cc", "cc", "1.1.0", Some(CRATES_IO)),
+            ],
+            "resolve": {
+                "nodes": [
+                    { "id": "app", "features": [], "deps": [
+                        dep("old-http", "old_http", None),
+                        dep("bytes-1", "bytes", None),
+                        dep("cc", "cc", Some("build")),
+                    ]},
+                    { "id": "old-http", "features": ["tls"], "deps": [
+                        dep("bytes-0.5", "bytes", None),
+                        dep("openssl", "openssl", None),
+                    ]},
+                    { "id": "bytes-0.5", "features": [], "deps": [] },
+                    { "id": "bytes-1", "features": ["default", "std"], "deps": [] },
+                    { "id": "openssl", "features": ["vendored"], "deps": [dep("cc", "cc", Some("build"))] },
+                    { "id": "cc", "features": [], "deps": [] },
+                ]
+            }
+        })
+    }
+
+    fn names(graph: &DependencyGraph, path: &[usize]) -> Vec<String> {
+        path.iter()
+            .map(|&index| graph.package(index).name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_find_library() -> Result<()> {
+        let graph = DependencyGraph::from_metadata(&metadata())?;
+        let app = graph.local_package("app").unwrap();
+
+        let bytes = graph.find_library(app, "bytes").unwrap();
+        assert_eq!(graph.package(bytes).version, "1.7.1");
+        let old_http = graph.find_library(app, "old_http").unwrap();
+        assert_eq!(graph.package(old_http).name, "old-http");
+
+        // Build dependencies aren't linked into the library
+        assert!(graph.find_library(app, "cc").is_none());
+        assert!(graph.find_library(app, "core").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dependents_and_paths() -> Result<()> {
+        let graph = DependencyGraph::from_metadata(&metadata())?;
+        let app = graph.local_package("app").unwrap();
+        assert_eq!(graph.find("Bytes").len(), 2);
+
+        let cc = graph.find("cc")[0];
+        let dependents: Vec<_> = graph
+            .dependents(cc)
+            .map(|(from, edge)| (graph.package(from).name.as_str(), edge.kinds[0].kind))
+            .collect();
+        assert_eq!(
+            dependents,
+            [
+                ("app", DependencyKind::Build),
+                ("openssl", DependencyKind::Build)
+            ]
+        );
+
+        let (paths, truncated) = graph.paths(app, cc, 10);
+        assert!(!truncated);
+        let paths: Vec<_> = paths.iter().map(|path| names(&graph, path)).collect();
+        assert_eq!(
+            paths,
+            [vec!["app", "cc"], vec!["app", "old-http", "openssl", "cc"]]
+        );
+        let (paths, truncated) = graph.paths(app, cc, 1);
+        assert_eq!(paths.len(), 1);
+        assert!(truncated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_edge_features() -> Result<()> {
+        let graph = DependencyGraph::from_metadata(&metadata())?;
+        let app = graph.local_package("app").unwrap();
+        let old_http = graph.find("old-http")[0];
+        let openssl = graph.find("openssl")[0];
+        let bytes = graph.find_library(app, "bytes").unwrap();
+
+        // Listed features, without the disabled defaults
+        assert_eq!(graph.edge(app, old_http).unwrap().features, ["tls"]);
+        assert_eq!(graph.edge(app, bytes).unwrap().features, ["default"]);
+        // Forwarded by the enabled `tls` feature
+        let openssl_edge = graph.edge(old_http, openssl).unwrap();
+        assert_eq!(openssl_edge.features, ["vendored"]);
+        assert!(openssl_edge.optional);
+        assert!(!graph.edge(app, bytes).unwrap().optional);
+        Ok(())
+    }
+}
@@ -1,9 +1,15 @@
+pub mod graph;
+pub mod report;
 pub mod tools;
+pub mod tree;
 
 use rmcp::schemars;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::deps::graph::{DependencyGraph, EdgeKind};
+use crate::deps::tree::{DependencyTreeNode, TreeOptions, dependency_tree};
+
 /// Response for dependency information
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DependencyInfo {
@@ -13,8 +19,8 @@ pub struct DependencyInfo {
     /// Direct dependencies of the crate
     pub direct_dependencies: Vec<Dependency>,
 
-    /// Full dependency tree (only included if requested)
-    pub dependency_tree: Option<serde_json::Value>,
+    /// Nested dependency tree (only included if requested)
+    pub dependency_tree: Option<DependencyTreeNode>,
 
     /// Total number of dependencies (direct + transitive)
     pub total_dependencies: usize,
@@ -52,12 +58,169 @@ pub struct Dependency {
     pub target: Option<String>,
 }
 
+/// Response for reverse dependency queries
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ReverseDependencyInfo {
+    /// The crate name and version being queried
+    pub crate_info: CrateIdentifier,
+
+    /// The dependency asked about
+    pub dependency: String,
+
+    /// Every version of the dependency in the resolved graph
+    pub matches: Vec<ReverseDependencyMatch>,
+}
+
+/// One resolved version of a dependency and why it is in the graph
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ReverseDependencyMatch {
+    pub name: String,
+    pub version: String,
+
+    /// Where cargo resolved the package from, absent for local packages
+    pub source: Option<String>,
+
+    /// Features cargo enabled on the package
+    pub features: Vec<String>,
+
+    /// Packages depending on it directly, with the features each one enables
+    pub dependents: Vec<DependencyEdge>,
+
+    /// Chains of dependencies from the crate to the package, shortest first.
+    /// Each step lists the features the previous package enables on it.
+    pub paths: Vec<Vec<DependencyEdge>>,
+
+    /// Whether more paths exist than are listed
+    pub paths_truncated: bool,
+}
+
+/// A package at one end of a dependency
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DependencyEdge {
+    pub name: String,
+    pub version: String,
+
+    /// How the dependency is declared, empty for the start of a path
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<EdgeKind>,
+
+    /// Features the dependency enables
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+}
+
+/// Find which packages pull `dependency` into the graph of the local package `root`
+///
+/// The dependency is matched by package or library name, ignoring case, and
+/// optionally by exact version. At most `max_paths` paths are listed for each match.
+pub fn find_reverse_dependencies(
+    graph: &DependencyGraph,
+    root: &str,
+    dependency: &str,
+    dependency_version: Option<&str>,
+    max_paths: usize,
+) -> anyhow::Result<ReverseDependencyInfo> {
+    let root_index = graph
+        .local_package(root)
+        .ok_or_else(|| anyhow::anyhow!("Package {root} not found in metadata"))?;
+
+    let found = graph.find(dependency);
+    let matching: Vec<usize> = found
+        .iter()
+        .copied()
+        .filter(|&index| {
+            dependency_version.is_none_or(|version| graph.package(index).version == version)
+        })
+        .collect();
+    if matching.is_empty() {
+        if let Some(version) = dependency_version
+            && !found.is_empty()
+        {
+            let versions: Vec<&str> = found
+                .iter()
+                .map(|&index| graph.package(index).version.as_str())
+                .collect();
+            anyhow::bail!(
+                "{dependency} {version} is not in the dependency graph of {root}, \
+                 resolved versions: {}",
+                versions.join(", ")
+            );
+        }
+        let similar = graph.similar_names(dependency);
+        if similar.is_empty() {
+            anyhow::bail!("{dependency} is not in the dependency graph of {root}");
+        }
+        anyhow::bail!(
+            "{dependency} is not in the dependency graph of {root}, did you mean: {}?",
+            similar.join(", ")
+        );
+    }
+
+    let matches = matching
+        .into_iter()
+        .map(|index| {
+            let package = graph.package(index);
+            let dependents = graph
+                .dependents(index)
+                .map(|(from, edge)| DependencyEdge {
+                    name: graph.package(from).name.clone(),
+                    version: graph.package(from).version.clone(),
+                    kinds: edge.kinds.clone(),
+                    features: edge.features.clone(),
+                })
+                .collect();
+            let (paths, paths_truncated) = graph.paths(root_index, index, max_paths);
+            let paths = paths
+                .iter()
+                .map(|path| {
+                    path.iter()
+                        .enumerate()
+                        .map(|(position, &step)| {
+                            let edge = position
+                                .checked_sub(1)
+                                .and_then(|previous| graph.edge(path[previous], step));
+                            DependencyEdge {
+                                name: graph.package(step).name.clone(),
+                                version: graph.package(step).version.clone(),
+                                kinds: edge.map(|edge| edge.kinds.clone()).unwrap_or_default(),
+                                features: edge
+                                    .map(|edge| edge.features.clone())
+                                    .unwrap_or_default(),
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+
+            ReverseDependencyMatch {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                source: package.source.clone(),
+                features: package.features.clone(),
+                dependents,
+                paths,
+                paths_truncated,
+            }
+        })
+        .collect();
+
+    let root_package = graph.package(root_index);
+    Ok(ReverseDependencyInfo {
+        crate_info: CrateIdentifier {
+            name: root_package.name.clone(),
+            version: root_package.version.clone(),
+        },
+        dependency: dependency.to_string(),
+        matches,
+    })
+}
+
 /// Process cargo metadata output to extract dependency information
 pub fn process_cargo_metadata(
     metadata: &serde_json::Value,
     crate_name: &str,
     crate_version: &str,
-    include_tree: bool,
+    tree: Option<&TreeOptions>,
     filter: Option<&str>,
 ) -> anyhow::Result<DependencyInfo> {
     // Find the package in the metadata
@@ -142,10 +305,12 @@ pub fn process_cargo_metadata(
             version: crate_version.to_string(),
         },
         direct_dependencies,
-        dependency_tree: if include_tree {
-            Some(metadata["resolve"].clone())
-        } else {
-            None
+        dependency_tree: match tree {
+            Some(options) => {
+                let graph = DependencyGraph::from_metadata(metadata)?;
+                Some(dependency_tree(&graph, crate_name, options)?)
+            }
+            None => None,
         },
         total_dependencies,
     })
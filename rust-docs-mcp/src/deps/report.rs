@@ -0,0 +1,397 @@
+//! Duplicate versions and feature unification in a resolved dependency graph
+//!
+//! Cargo builds every version of a package required by incompatible
+//! requirements, and builds a single version with the union of the features
+//! its dependents ask for. The report answers the questions usually asked of
+//! `cargo tree -d` and `cargo tree -e features`: which packages come in several
+//! versions and who requires each, which features of the crate's dependencies
+//! are only on because another package asks for them, and which features turn
+//! on the optional dependencies in the graph.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Context, Result};
+use rmcp::schemars;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::deps::CrateIdentifier;
+use crate::deps::graph::{DependencyGraph, Package};
+
+/// Response for dependency reports
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DependencyReport {
+    /// The crate name and version being queried
+    pub crate_info: CrateIdentifier,
+
+    /// Packages resolved in more than one version
+    pub duplicates: Vec<DuplicateDependency>,
+
+    /// Direct dependencies with features the crate itself doesn't enable
+    pub unified_features: Vec<UnifiedFeatures>,
+
+    /// Optional dependencies in the graph and the features activating them
+    pub optional_dependencies: Vec<OptionalDependency>,
+}
+
+/// A package resolved in several versions
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DuplicateDependency {
+    pub name: String,
+    pub versions: Vec<DuplicateVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DuplicateVersion {
+    pub version: String,
+
+    /// Where cargo resolved the version from, absent for local packages
+    pub source: Option<String>,
+
+    /// Packages depending on this version
+    pub required_by: Vec<Requirement>,
+}
+
+/// A package requiring a dependency
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Requirement {
+    pub name: String,
+    pub version: String,
+
+    /// Version requirement the package declares, e.g. `^0.5`
+    pub requirement: Option<String>,
+}
+
+/// A direct dependency whose features are unified with those asked for elsewhere
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct UnifiedFeatures {
+    pub name: String,
+    pub version: String,
+
+    /// Features the crate enables on the dependency, directly or through other features
+    pub requested: Vec<String>,
+
+    /// Features enabled on the dependency that the crate doesn't enable
+    pub added: Vec<AddedFeature>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AddedFeature {
+    pub feature: String,
+
+    /// Packages whose dependency enables the feature
+    pub enabled_by: Vec<CrateIdentifier>,
+}
+
+/// An optional dependency turned on in the graph
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct OptionalDependency {
+    /// Package declaring the optional dependency
+    pub name: String,
+    pub version: String,
+
+    pub dependency: String,
+    pub dependency_version: String,
+
+    /// Enabled features of the package activating the dependency
+    pub activated_by: Vec<String>,
+}
+
+/// Report duplicate versions and feature unification below the local package `root`
+pub fn dependency_report(graph: &DependencyGraph, root: &str) -> Result<DependencyReport> {
+    let root = graph
+        .local_package(root)
+        .with_context(|| format!("Package {root} not found in dependency metadata"))?;
+    let reachable = graph.reachable(root);
+
+    Ok(DependencyReport {
+        crate_info: identifier(graph.package(root)),
+        duplicates: duplicates(graph, &reachable),
+        unified_features: unified_features(graph, root),
+        optional_dependencies: optional_dependencies(graph, &reachable),
+    })
+}
+
+fn duplicates(graph: &DependencyGraph, reachable: &[usize]) -> Vec<DuplicateDependency> {
+    let mut by_name: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for &index in reachable {
+        by_name
+            .entry(graph.package(index).name.as_str())
+            .or_default()
+            .push(index);
+    }
+
+    by_name
+        .into_iter()
+        .filter(|(_, versions)| versions.len() > 1)
+        .map(|(name, mut versions)| {
+            versions.sort_by_cached_key(|&index| {
+                semver::Version::parse(&graph.package(index).version).ok()
+            });
+            let versions = versions
+                .into_iter()
+                .map(|index| DuplicateVersion {
+                    version: graph.package(index).version.clone(),
+                    source: graph.package(index).source.clone(),
+                    required_by: graph
+                        .dependents(index)
+                        .filter(|(from, _)| reachable.contains(from))
+                        .map(|(from, edge)| Requirement {
+                            name: graph.package(from).name.clone(),
+                            version: graph.package(from).version.clone(),
+                            requirement: edge.requirement.clone(),
+                        })
+                        .collect(),
+                })
+                .collect();
+            DuplicateDependency {
+                name: name.to_string(),
+                versions,
+            }
+        })
+        .collect()
+}
+
+fn unified_features(graph: &DependencyGraph, root: usize) -> Vec<UnifiedFeatures> {
+    let mut unified = Vec::new();
+    for edge in graph.dependencies(root) {
+        let package = graph.package(edge.to);
+        let requested = feature_closure(package, &edge.features);
+        let added: Vec<AddedFeature> = package
+            .features
+            .iter()
+            .filter(|feature| !requested.contains(*feature))
+            .map(|feature| AddedFeature {
+                feature: feature.clone(),
+                enabled_by: graph
+                    .dependents(edge.to)
+                    .filter(|(from, dependent_edge)| {
+                        *from != root
+                            && feature_closure(package, &dependent_edge.features).contains(feature)
+                    })
+                    .map(|(from, _)| identifier(graph.package(from)))
+                    .collect(),
+            })
+            .collect();
+        if added.is_empty() {
+            continue;
+        }
+
+        unified.push(UnifiedFeatures {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            requested: requested.into_iter().collect(),
+            added,
+        });
+    }
+    unified
+}
+
+fn optional_dependencies(graph: &DependencyGraph, reachable: &[usize]) -> Vec<OptionalDependency> {
+    let mut optional = Vec::new();
+    for &index in reachable {
+        let package = graph.package(index);
+        for edge in graph
+            .dependencies(index)
+            .iter()
+            .filter(|edge| edge.optional)
+        {
+            let activated_by = package
+                .features
+                .iter()
+                .filter(|feature| {
+                    // Optional dependencies without `dep:` references get a feature of their name
+                    **feature == edge.name
+                        || package
+                            .declared_features
+                            .get(*feature)
+                            .into_iter()
+                            .flatten()
+                            .any(|value| activates(value, &edge.name))
+                })
+                .cloned()
+                .collect();
+
+            let dependency = graph.package(edge.to);
+            optional.push(OptionalDependency {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                dependency: dependency.name.clone(),
+                dependency_version: dependency.version.clone(),
+                activated_by,
+            });
+        }
+    }
+    optional
+}
+
+/// Whether a value of a feature's list turns on the optional dependency `name`
+///
+/// `dep:name`, `name` and `name/feature` do, `name?/feature` only forwards a
+/// feature if the dependency is turned on by something else.
+fn activates(value: &str, name: &str) -> bool {
+    let key = value.split_once('/').map_or(value, |(key, _)| key);
+    value.strip_prefix("dep:") == Some(name) || key == name
+}
+
+/// Features of a package turned on by enabling `features`, including those they enable in turn
+fn feature_closure(package: &Package, features: &[String]) -> BTreeSet<String> {
+    let mut enabled = BTreeSet::new();
+    let mut stack: Vec<&str> = features.iter().map(String::as_str).collect();
+    while let Some(feature) = stack.pop() {
+        if !enabled.insert(feature.to_string()) {
+            continue;
+        }
+        for value in package.declared_features.get(feature).into_iter().flatten() {
+            if value.starts_with("dep:") {
+                continue;
+            }
+            match value.split_once('/') {
+                // `name/feature` also turns on the feature named after an optional dependency
+                Some((key, _)) => {
+                    if package.declared_features.contains_key(key) {
+                        stack.push(key);
+                    }
+                }
+                None => stack.push(value),
+            }
+        }
+    }
+    enabled
+}
+
+fn identifier(package: &Package) -> CrateIdentifier {
+    CrateIdentifier {
+        name: package.name.clone(),
+        version: package.version.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deps::graph::test_support::{CRATES_IO, dep, package};
+    use serde_json::Value;
+
+    fn declared(name: &str, req: &str, features: &[&str], optional: bool) -> Value {
+        serde_json::json!({
+            "name": name,
+            "req": req,
+            "rename": null,
+            "features": features,
+            "uses_default_features": false,
+            "kind": null,
+            "optional": optional,
+        })
+    }
+
+    /// `app` uses bytes 1 and `http`, which uses bytes 0.5, turns on the `std`
+    /// feature of `serde` and, through its `tls` feature, `openssl`
+    fn graph() -> DependencyGraph {
+        let mut app = package("app", "app", "0.1.0", None);
+        app["dependencies"] = serde_json::json!([
+            declared("http", "^0.2", &["tls"], false),
+            declared("bytes", "^1", &[], false),
+            declared("serde", "^1", &["derive"], false),
+        ]);
+        let mut http = package("http", "http", "0.2.0", Some(CRATES_IO));
+        http["dependencies"] = serde_json::json!([
+            declared("bytes", "^0.5", &[], false),
+            declared("serde", "^1", &["std"], false),
+            declared("openssl", "^0.10", &[], true),
+        ]);
+        http["features"] = serde_json::json!({ "tls": ["dep:openssl"] });
+        let mut serde = package("serde", "serde", "1.0.210", Some(CRATES_IO));
+        serde["features"] = serde_json::json!({
+            "derive": [],
+            "std": ["alloc"],
+            "alloc": [],
+        });
+
+        let metadata = serde_json::json!({
+            "packages": [
+                app,
+                http,
+                package("bytes-0.5", "bytes", "0.5.6", Some(CRATES_IO)),
+                package("bytes-1", "bytes", "1.7.1", Some(CRATES_IO)),
+                serde,
+                package("openssl", "openssl", "0.10.66", Some(CRATES_IO)),
+            ],
+            "resolve": {
+                "nodes": [
+                    { "id": "app", "features": [], "deps": [
+                        dep("http", "http", None),
+                        dep("bytes-1", "bytes", None),
+                        dep("serde", "serde", None),
+                    ]},
+                    { "id": "http", "features": ["tls"], "deps": [
+                        dep("bytes-0.5", "bytes", None),
+                        dep("serde", "serde", None),
+                        dep("openssl", "openssl", None),
+                    ]},
+                    { "id": "bytes-0.5", "features": [], "deps": [] },
+                    { "id": "bytes-1", "features": [], "deps": [] },
+                    { "id": "serde", "features": ["alloc", "derive", "std"], "deps": [] },
+                    { "id": "openssl", "features": [], "deps": [] },
+                ]
+            }
+        });
+        DependencyGraph::from_metadata(&metadata).unwrap()
+    }
+
+    #[test]
+    fn test_dependency_report() -> Result<()> {
+        let report = dependency_report(&graph(), "app")?;
+
+        assert_eq!(report.duplicates.len(), 1);
+        let bytes = &report.duplicates[0];
+        assert_eq!(bytes.name, "bytes");
+        let versions: Vec<_> = bytes
+            .versions
+            .iter()
+            .map(|version| {
+                let required_by = &version.required_by[0];
+                (
+                    version.version.as_str(),
+                    required_by.name.as_str(),
+                    required_by.requirement.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            versions,
+            [
+                ("0.5.6", "http", Some("^0.5")),
+                ("1.7.1", "app", Some("^1"))
+            ]
+        );
+
+        assert_eq!(report.unified_features.len(), 1);
+        let serde = &report.unified_features[0];
+        assert_eq!(serde.requested, ["derive"]);
+        let added: Vec<_> = serde
+            .added
+            .iter()
+            .map(|added| (added.feature.as_str(), added.enabled_by[0].name.as_str()))
+            .collect();
+        assert_eq!(added, [("alloc", "http"), ("std", "http")]);
+
+        assert_eq!(report.optional_dependencies.len(), 1);
+        let openssl = &report.optional_dependencies[0];
+        assert_eq!(
+            (openssl.name.as_str(), openssl.dependency.as_str()),
+            ("http", "openssl")
+        );
+        assert_eq!(openssl.activated_by, ["tls"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_activates() {
+        assert!(activates("dep:openssl", "openssl"));
+        assert!(activates("openssl", "openssl"));
+        assert!(activates("openssl/vendored", "openssl"));
+        assert!(!activates("openssl?/vendored", "openssl"));
+        assert!(!activates("openssl-sys", "openssl"));
+    }
+}
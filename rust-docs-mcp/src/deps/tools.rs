@@ -1,12 +1,18 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
+use anyhow::Context;
 use rmcp::schemars;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::cache::CrateCache;
-use crate::deps::process_cargo_metadata;
+use crate::deps::graph::{DependencyGraph, DependencyKind};
+use crate::deps::report::dependency_report;
+use crate::deps::tree::{TargetPlatform, TreeOptions};
+use crate::deps::{ReverseDependencyInfo, find_reverse_dependencies, process_cargo_metadata};
+
+/// Paths listed for each match of a reverse dependency query by default
+const DEFAULT_MAX_PATHS: usize = 10;
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct GetDependenciesParams {
@@ -18,26 +24,79 @@ pub struct GetDependenciesParams {
         description = "Include the full dependency tree (default: false, only shows direct dependencies)"
     )]
     pub include_tree: Option<bool>,
+    #[schemars(
+        description = "How deep to render the dependency tree: 1 for direct dependencies only. Defaults to the whole tree. Packages whose dependencies were already shown are marked duplicate, like (*) in cargo tree."
+    )]
+    pub max_depth: Option<usize>,
+    #[schemars(
+        description = "Kinds of dependencies to include in the tree: \"normal\", \"build\" and/or \"dev\". Defaults to all three."
+    )]
+    pub kinds: Option<Vec<String>>,
+    #[schemars(
+        description = "Target triple to evaluate platform-specific dependencies of the tree against (e.g., 'x86_64-pc-windows-msvc'), or 'host'. Defaults to showing dependencies of all platforms."
+    )]
+    pub target: Option<String>,
     #[schemars(description = "Filter dependencies by name (partial match)")]
     pub filter: Option<String>,
     #[schemars(
-        description = "For workspace crates, specify the member path (e.g., 'crates/rmcp')"
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp')"
+    )]
+    pub member: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GetReverseDependenciesParams {
+    #[schemars(description = "The name of the crate")]
+    pub crate_name: String,
+    #[schemars(description = "The version of the crate")]
+    pub version: String,
+    #[schemars(
+        description = "The dependency to explain, by package or library name (e.g., 'openssl' or 'serde_json')"
+    )]
+    pub dependency: String,
+    #[schemars(
+        description = "Only explain this resolved version of the dependency (default: all versions)"
+    )]
+    pub dependency_version: Option<String>,
+    #[schemars(
+        description = "Maximum number of dependency paths to list for each version (default: 10)"
+    )]
+    pub max_paths: Option<usize>,
+    #[schemars(
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp')"
+    )]
+    pub member: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GetDependencyReportParams {
+    #[schemars(description = "The name of the crate")]
+    pub crate_name: String,
+    #[schemars(description = "The version of the crate")]
+    pub version: String,
+    #[schemars(
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp')"
     )]
     pub member: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct DepsTools {
-    cache: Arc<Mutex<CrateCache>>,
+    cache: Arc<CrateCache>,
 }
 
 impl DepsTools {
-    pub fn new(cache: Arc<Mutex<CrateCache>>) -> Self {
+    pub fn new(cache: Arc<CrateCache>) -> Self {
         Self { cache }
     }
 
     pub async fn get_dependencies(&self, params: GetDependenciesParams) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
+
+        let tree = match tree_options(&params).await {
+            Ok(tree) => tree,
+            Err(e) => return format!(r#"{{"error": "Invalid dependency tree options: {e:#}"}}"#),
+        };
 
         // First ensure the crate is cached
         match cache
@@ -60,7 +119,7 @@ impl DepsTools {
                             &metadata,
                             &params.crate_name,
                             &params.version,
-                            params.include_tree.unwrap_or(false),
+                            tree.as_ref(),
                             params.filter.as_deref(),
                         ) {
                             Ok(dep_info) => {
@@ -90,4 +149,115 @@ impl DepsTools {
             }
         }
     }
+
+    pub async fn get_reverse_dependencies(&self, params: GetReverseDependenciesParams) -> String {
+        match self.reverse_dependencies(&params).await {
+            Ok(info) => serde_json::to_string_pretty(&info).unwrap_or_else(|e| {
+                format!(r#"{{"error": "Failed to serialize reverse dependencies: {e}"}}"#)
+            }),
+            Err(e) => format!(r#"{{"error": "Failed to find reverse dependencies: {e:#}"}}"#),
+        }
+    }
+
+    async fn reverse_dependencies(
+        &self,
+        params: &GetReverseDependenciesParams,
+    ) -> anyhow::Result<ReverseDependencyInfo> {
+        let (graph, root) = self
+            .dependency_graph(
+                &params.crate_name,
+                &params.version,
+                params.member.as_deref(),
+            )
+            .await?;
+        find_reverse_dependencies(
+            &graph,
+            &root,
+            &params.dependency,
+            params.dependency_version.as_deref(),
+            params.max_paths.unwrap_or(DEFAULT_MAX_PATHS),
+        )
+    }
+
+    pub async fn get_dependency_report(&self, params: GetDependencyReportParams) -> String {
+        let report = match self
+            .dependency_graph(
+                &params.crate_name,
+                &params.version,
+                params.member.as_deref(),
+            )
+            .await
+        {
+            Ok((graph, root)) => dependency_report(&graph, &root),
+            Err(e) => Err(e),
+        };
+        match report {
+            Ok(report) => serde_json::to_string_pretty(&report).unwrap_or_else(|e| {
+                format!(r#"{{"error": "Failed to serialize dependency report: {e}"}}"#)
+            }),
+            Err(e) => format!(r#"{{"error": "Failed to build dependency report: {e:#}"}}"#),
+        }
+    }
+
+    /// Load the resolved dependency graph of a crate or workspace member
+    ///
+    /// Returns the graph with the package name of the crate or member in it.
+    async fn dependency_graph(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+    ) -> anyhow::Result<(DependencyGraph, String)> {
+        let cache = &self.cache;
+        cache
+            .ensure_crate_or_member_docs(crate_name, version, member)
+            .await
+            .context("Failed to cache crate")?;
+
+        let (metadata, root) = match member {
+            Some(member) => {
+                let member = cache.resolve_member(crate_name, version, member).await?;
+                let metadata = cache
+                    .load_member_dependencies(crate_name, version, &member.name)
+                    .await;
+                (metadata, member.name)
+            }
+            None => {
+                let metadata = cache.load_dependencies(crate_name, version).await;
+                (metadata, crate_name.to_string())
+            }
+        };
+        let metadata = metadata
+            .with_context(|| format!("Dependencies not available for {crate_name}-{version}"))?;
+
+        Ok((DependencyGraph::from_metadata(&metadata)?, root))
+    }
+}
+
+/// Options of the dependency tree, if one is requested
+async fn tree_options(params: &GetDependenciesParams) -> anyhow::Result<Option<TreeOptions>> {
+    if params.include_tree != Some(true) {
+        return Ok(None);
+    }
+
+    let kinds = match &params.kinds {
+        Some(kinds) => kinds
+            .iter()
+            .map(|kind| DependencyKind::parse(kind))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => vec![
+            DependencyKind::Normal,
+            DependencyKind::Build,
+            DependencyKind::Dev,
+        ],
+    };
+    let platform = match &params.target {
+        Some(target) => Some(TargetPlatform::detect(target).await?),
+        None => None,
+    };
+    Ok(Some(TreeOptions {
+        max_depth: params.max_depth,
+        kinds,
+        platform,
+    }))
 }
@@ -0,0 +1,337 @@
+//! Nested rendering of a resolved dependency graph, like `cargo tree`
+//!
+//! The tree starts at a local package and lists each dependency with the kind
+//! and platform it is declared for. Like `cargo tree`, a package whose
+//! dependencies were already listed further up is shown once more without them
+//! and marked as a duplicate, which keeps large graphs small.
+
+use std::collections::HashSet;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use cargo_platform::{Cfg, Platform};
+use rmcp::schemars;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::process::run_command;
+use crate::deps::graph::{DependencyGraph, DependencyKind};
+
+/// Which parts of the graph a tree shows
+#[derive(Debug, Clone)]
+pub struct TreeOptions {
+    /// Maximum distance from the root, 1 for direct dependencies only, `None` for all
+    pub max_depth: Option<usize>,
+    /// Kinds of dependencies to show
+    pub kinds: Vec<DependencyKind>,
+    /// Platform to evaluate target-specific dependencies against, `None` to show all
+    pub platform: Option<TargetPlatform>,
+}
+
+/// A target triple and the `cfg` values rustc sets for it
+#[derive(Debug, Clone)]
+pub struct TargetPlatform {
+    pub triple: String,
+    cfgs: Vec<Cfg>,
+}
+
+impl TargetPlatform {
+    pub fn new(triple: &str, cfgs: Vec<Cfg>) -> Self {
+        Self {
+            triple: triple.to_string(),
+            cfgs,
+        }
+    }
+
+    /// Ask rustc for the `cfg` values of a target triple, or of the host for `host`
+    pub async fn detect(target: &str) -> Result<Self> {
+        let triple = if target == "host" {
+            host_triple().await?
+        } else {
+            target.to_string()
+        };
+
+        let mut command = Command::new("rustc");
+        command.args(["--print", "cfg", "--target", &triple]);
+        let output = run_command(command, Some(Duration::from_secs(30)))
+            .await
+            .context("Failed to run rustc")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Unknown target {triple}: {}", stderr.trim());
+        }
+
+        let cfgs = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| Cfg::from_str(line).ok())
+            .collect();
+        Ok(Self::new(&triple, cfgs))
+    }
+
+    /// Whether a dependency limited to `target`, a triple or `cfg(..)` expression, applies
+    ///
+    /// Expressions cargo couldn't parse either are kept rather than hidden.
+    pub fn matches(&self, target: &str) -> bool {
+        Platform::from_str(target)
+            .map_or(true, |platform| platform.matches(&self.triple, &self.cfgs))
+    }
+}
+
+/// Target triple of the host, from `rustc -vV`
+async fn host_triple() -> Result<String> {
+    let mut command = Command::new("rustc");
+    command.arg("-vV");
+    let output = run_command(command, Some(Duration::from_secs(30)))
+        .await
+        .context("Failed to run rustc")?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_string())
+        .context("rustc didn't report its host target")
+}
+
+/// A package in a dependency tree
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DependencyTreeNode {
+    pub name: String,
+    pub version: String,
+
+    /// How the parent depends on the package, absent for the root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<DependencyKind>,
+
+    /// Features cargo enabled on the package
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+
+    /// Whether the parent declares the dependency optional
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+
+    /// Platform the dependency is limited to, e.g. `cfg(windows)`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+
+    /// Dependencies of the package
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<DependencyTreeNode>,
+
+    /// The package's dependencies are listed where it first appears, like `(*)` in `cargo tree`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate: bool,
+
+    /// The package has dependencies below the maximum depth that aren't shown
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// Render the dependency tree of the local package `root`
+pub fn dependency_tree(
+    graph: &DependencyGraph,
+    root: &str,
+    options: &TreeOptions,
+) -> Result<DependencyTreeNode> {
+    let root = graph
+        .local_package(root)
+        .with_context(|| format!("Package {root} not found in dependency metadata"))?;
+
+    let mut expanded = HashSet::new();
+    let mut node = tree_node(graph, root);
+    fill(graph, root, 0, options, &mut expanded, &mut node);
+    Ok(node)
+}
+
+fn tree_node(graph: &DependencyGraph, index: usize) -> DependencyTreeNode {
+    let package = graph.package(index);
+    DependencyTreeNode {
+        name: package.name.clone(),
+        version: package.version.clone(),
+        kind: None,
+        features: package.features.clone(),
+        optional: false,
+        target: None,
+        dependencies: Vec::new(),
+        duplicate: false,
+        truncated: false,
+    }
+}
+
+/// List the shown dependencies of the package `index` under its node, depth first
+fn fill(
+    graph: &DependencyGraph,
+    index: usize,
+    depth: usize,
+    options: &TreeOptions,
+    expanded: &mut HashSet<usize>,
+    node: &mut DependencyTreeNode,
+) {
+    let mut children = Vec::new();
+    for edge in graph.dependencies(index) {
+        let kinds: Vec<_> = edge
+            .kinds
+            .iter()
+            .filter(|edge_kind| options.kinds.contains(&edge_kind.kind))
+            .filter(|edge_kind| match (&options.platform, &edge_kind.target) {
+                (Some(platform), Some(target)) => platform.matches(target),
+                _ => true,
+            })
+            .collect();
+        let Some(kind) = kinds.iter().map(|edge_kind| edge_kind.kind).min() else {
+            continue;
+        };
+
+        let mut child = tree_node(graph, edge.to);
+        child.kind = Some(kind);
+        child.optional = edge.optional;
+        // A dependency declared for all platforms isn't limited by its other declarations
+        if kinds.iter().all(|edge_kind| edge_kind.target.is_some()) {
+            child.target = kinds[0].target.clone();
+        }
+        children.push((edge.to, child));
+    }
+    if children.is_empty() {
+        return;
+    }
+    if options
+        .max_depth
+        .is_some_and(|max_depth| depth >= max_depth)
+    {
+        node.truncated = true;
+        return;
+    }
+    if !expanded.insert(index) {
+        node.duplicate = true;
+        return;
+    }
+
+    for (child_index, mut child) in children {
+        fill(graph, child_index, depth + 1, options, expanded, &mut child);
+        node.dependencies.push(child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deps::graph::test_support::{CRATES_IO, dep, package, target_dep};
+
+    /// `app` depends on `http` and `bytes`, `http` on `bytes` too, `winapi` on
+    /// Windows only and `cc` to build
+    fn graph() -> DependencyGraph {
+        let metadata = serde_json::json!({
+            "packages": [
+                package("app", "app", "1.0.0", None),
+                package("http", "http", "1.0.0", Some(CRATES_IO)),
+                package("bytes", "bytes", "1.0.0", Some(CRATES_IO)),
+                package("itoa", "itoa", "1.0.0", Some(CRATES_IO)),
+                package("winapi", "winapi", "1.0.0", Some(CRATES_IO)),
+                package("cc", "cc", "1.0.0", Some(CRATES_IO)),
+            ],
+            "resolve": {
+                "nodes": [
+                    { "id": "app", "features": [], "deps": [
+                        dep("http", "http", None),
+                        dep("bytes", "bytes", None),
+                        target_dep("winapi", "winapi", None, Some("cfg(windows)")),
+                        dep("cc", "cc", Some("build")),
+                    ]},
+                    { "id": "http", "features": [], "deps": [
+                        dep("bytes", "bytes", None),
+                        dep("itoa", "itoa", None),
+                    ]},
+                    { "id": "bytes", "features": ["std"], "deps": [dep("itoa", "itoa", None)] },
+                    { "id": "itoa", "features": [], "deps": [] },
+                    { "id": "winapi", "features": [], "deps": [] },
+                    { "id": "cc", "features": [], "deps": [] },
+                ]
+            }
+        });
+        DependencyGraph::from_metadata(&metadata).unwrap()
+    }
+
+    fn render(node: &DependencyTreeNode, depth: usize, lines: &mut Vec<String>) {
+        let mut line = format!("{}{}", "  ".repeat(depth), node.name);
+        if node.duplicate {
+            line.push_str(" (*)");
+        }
+        if node.truncated {
+            line.push_str(" ...");
+        }
+        lines.push(line);
+        for child in &node.dependencies {
+            render(child, depth + 1, lines);
+        }
+    }
+
+    fn lines(options: &TreeOptions) -> Result<Vec<String>> {
+        let tree = dependency_tree(&graph(), "app", options)?;
+        let mut lines = Vec::new();
+        render(&tree, 0, &mut lines);
+        Ok(lines)
+    }
+
+    #[test]
+    fn test_dependency_tree() -> Result<()> {
+        let all = TreeOptions {
+            max_depth: None,
+            kinds: vec![DependencyKind::Normal, DependencyKind::Build],
+            platform: None,
+        };
+        assert_eq!(
+            lines(&all)?,
+            [
+                "app",
+                "  http",
+                "    bytes",
+                "      itoa",
+                "    itoa",
+                "  bytes (*)",
+                "  winapi",
+                "  cc",
+            ]
+        );
+
+        let tree = dependency_tree(&graph(), "app", &all)?;
+        assert_eq!(tree.dependencies[2].target.as_deref(), Some("cfg(windows)"));
+        assert_eq!(tree.dependencies[3].kind, Some(DependencyKind::Build));
+        assert_eq!(tree.dependencies[0].dependencies[0].features, ["std"]);
+
+        let direct = TreeOptions {
+            max_depth: Some(1),
+            kinds: vec![DependencyKind::Normal],
+            platform: None,
+        };
+        assert_eq!(
+            lines(&direct)?,
+            ["app", "  http ...", "  bytes ...", "  winapi"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_target_platform() -> Result<()> {
+        let linux = TargetPlatform::new(
+            "x86_64-unknown-linux-gnu",
+            ["unix", "target_os=\"linux\""]
+                .iter()
+                .map(|cfg| Cfg::from_str(cfg))
+                .collect::<Result<_, _>>()?,
+        );
+        assert!(linux.matches("cfg(unix)"));
+        assert!(linux.matches("cfg(all(unix, not(windows)))"));
+        assert!(linux.matches("x86_64-unknown-linux-gnu"));
+        assert!(!linux.matches("cfg(windows)"));
+        assert!(!linux.matches("x86_64-pc-windows-msvc"));
+
+        let options = TreeOptions {
+            max_depth: Some(1),
+            kinds: vec![DependencyKind::Normal],
+            platform: Some(linux),
+        };
+        assert_eq!(lines(&options)?, ["app", "  http ...", "  bytes ..."]);
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+//! In-memory cache of parsed crate documentation
+//!
+//! Parsing `docs.json` dominates the cost of every documentation query, and for
+//! large crates it takes seconds. Parsed [`DocQuery`] instances are kept in a
+//! least-recently-used cache bounded by an approximate memory budget. Entries are
+//! validated against the modification time and size of their `docs.json`, so an
+//! updated, regenerated or removed cache entry is never served stale.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::docs::DocQuery;
+
+/// Identifies the documentation of a crate version or one of its workspace members
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DocQueryKey {
+    crate_name: String,
+    version: String,
+    member: Option<String>,
+}
+
+impl DocQueryKey {
+    /// Create a key for a crate version, or for one of its members when `member` is set
+    pub fn new(crate_name: &str, version: &str, member: Option<&str>) -> Self {
+        Self {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            member: member.map(String::from),
+        }
+    }
+}
+
+/// State of a `docs.json` file a cached query was built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocsFingerprint {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl DocsFingerprint {
+    /// Read the fingerprint of a documentation file
+    pub fn of(docs_path: &Path) -> Result<Self> {
+        let metadata = std::fs::metadata(docs_path)
+            .with_context(|| format!("Failed to read metadata of {}", docs_path.display()))?;
+        Ok(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+
+    /// Size of the documentation file in bytes
+    pub fn docs_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Modification time of the documentation file in nanoseconds since the epoch, 0 if unknown
+    pub fn modified_nanos(&self) -> u64 {
+        self.modified
+            .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    query: Arc<DocQuery>,
+    fingerprint: DocsFingerprint,
+    weight: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<DocQueryKey, Entry>,
+    used_bytes: u64,
+    clock: u64,
+}
+
+/// Memory-bounded LRU cache of parsed documentation
+///
+/// Each entry is inserted with an estimate of the memory it keeps alive: the size
+/// of `docs.json` for fully parsed documentation, or the size of the index tables
+/// for queries backed by a memory-mapped index.
+#[derive(Debug)]
+pub struct DocQueryCache {
+    capacity_bytes: u64,
+    inner: Mutex<Inner>,
+}
+
+impl DocQueryCache {
+    /// Create a cache holding at most `capacity_bytes` of documentation, 0 disables caching
+    pub fn new(capacity_bytes: u64) -> Self {
+        Self {
+            capacity_bytes,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Get a cached query if it was built from documentation matching `fingerprint`
+    pub fn get(&self, key: &DocQueryKey, fingerprint: DocsFingerprint) -> Option<Arc<DocQuery>> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.clock += 1;
+        let now = inner.clock;
+
+        match inner.entries.get_mut(key) {
+            Some(entry) if entry.fingerprint == fingerprint => {
+                entry.last_used = now;
+                Some(entry.query.clone())
+            }
+            Some(_) => {
+                // The documentation changed on disk, drop the stale entry
+                if let Some(stale) = inner.entries.remove(key) {
+                    inner.used_bytes -= stale.weight;
+                }
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Store a query costing `weight` bytes, evicting the least recently used entries to stay within budget
+    pub fn insert(
+        &self,
+        key: DocQueryKey,
+        fingerprint: DocsFingerprint,
+        weight: u64,
+        query: Arc<DocQuery>,
+    ) {
+        // Documentation larger than the whole budget is never cached
+        if weight > self.capacity_bytes {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.clock += 1;
+        let now = inner.clock;
+
+        if let Some(previous) = inner.entries.remove(&key) {
+            inner.used_bytes -= previous.weight;
+        }
+
+        while inner.used_bytes + weight > self.capacity_bytes {
+            let Some(oldest) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(evicted) = inner.entries.remove(&oldest) {
+                tracing::debug!("Evicting parsed docs of {:?} from memory", oldest);
+                inner.used_bytes -= evicted.weight;
+            }
+        }
+
+        inner.used_bytes += weight;
+        inner.entries.insert(
+            key,
+            Entry {
+                query,
+                fingerprint,
+                weight,
+                last_used: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustdoc_types::{Crate, FORMAT_VERSION, Id, Target};
+
+    fn empty_query() -> Arc<DocQuery> {
+        Arc::new(DocQuery::new(Crate {
+            root: Id(0),
+            crate_version: None,
+            includes_private: false,
+            index: Default::default(),
+            paths: Default::default(),
+            external_crates: Default::default(),
+            target: Target {
+                triple: String::new(),
+                target_features: Vec::new(),
+            },
+            format_version: FORMAT_VERSION,
+        }))
+    }
+
+    fn fingerprint(len: u64) -> DocsFingerprint {
+        DocsFingerprint {
+            modified: Some(SystemTime::UNIX_EPOCH),
+            len,
+        }
+    }
+
+    #[test]
+    fn test_get_checks_fingerprint() {
+        let cache = DocQueryCache::new(100);
+        let key = DocQueryKey::new("serde", "1.0.0", None);
+        cache.insert(key.clone(), fingerprint(10), 10, empty_query());
+
+        assert!(cache.get(&key, fingerprint(10)).is_some());
+        // A changed docs.json invalidates the entry
+        assert!(cache.get(&key, fingerprint(11)).is_none());
+        assert!(cache.get(&key, fingerprint(10)).is_none());
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let cache = DocQueryCache::new(100);
+        let serde = DocQueryKey::new("serde", "1.0.0", None);
+        let tokio = DocQueryKey::new("tokio", "1.0.0", None);
+        let member = DocQueryKey::new("tokio", "1.0.0", Some("tokio-util"));
+
+        cache.insert(serde.clone(), fingerprint(40), 40, empty_query());
+        cache.insert(tokio.clone(), fingerprint(40), 40, empty_query());
+        // Touch serde so that tokio becomes the least recently used entry
+        assert!(cache.get(&serde, fingerprint(40)).is_some());
+
+        cache.insert(member.clone(), fingerprint(40), 40, empty_query());
+        assert!(cache.get(&serde, fingerprint(40)).is_some());
+        assert!(cache.get(&tokio, fingerprint(40)).is_none());
+        assert!(cache.get(&member, fingerprint(40)).is_some());
+    }
+
+    #[test]
+    fn test_disabled_cache_stores_nothing() {
+        let cache = DocQueryCache::new(0);
+        let key = DocQueryKey::new("serde", "1.0.0", None);
+        cache.insert(key.clone(), fingerprint(10), 10, empty_query());
+        assert!(cache.get(&key, fingerprint(10)).is_none());
+    }
+}
@@ -0,0 +1,628 @@
+//! Compact binary index of rustdoc JSON
+//!
+//! `docs.json` has to be deserialized in full before anything can be looked up
+//! in it. Next to it the documentation generator writes `docs.idx`, which stores
+//! everything needed to list and search items in fixed-size tables, and every item
+//! as a separately compressed JSON record. The index is memory-mapped at query
+//! time: listing and searching only touch the tables, and detail lookups decode
+//! only the items they need.
+//!
+//! Layout, with all integers little-endian:
+//!
+//! | Section        | Contents                                                  |
+//! |----------------|-----------------------------------------------------------|
+//! | header         | magic, versions, `docs.json` fingerprint, section sizes   |
+//! | string offsets | `string_count + 1` u64 offsets into the string data       |
+//! | string data    | interned UTF-8 strings                                    |
+//! | paths          | u32 string ids of item path components                    |
+//! | items          | fixed-size item records, sorted by item id                |
+//! | externals      | fixed-size records of items of other crates, by item id   |
+//! | blob           | deflate-compressed JSON of every item                     |
+
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use memmap2::Mmap;
+use rustdoc_types::{Crate, FORMAT_VERSION, Id, Item, ItemSummary};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use crate::cache::utils::{read_docs, write_atomic};
+use crate::docs::cache::DocsFingerprint;
+use crate::docs::query::{ExternalItem, item_kind, summary_kind, visibility_string};
+
+/// File name of the index, stored next to `docs.json`
+pub const INDEX_FILE_NAME: &str = "docs.idx";
+
+const MAGIC: &[u8; 8] = b"RDMCIDX\0";
+/// Version of the index layout, bump whenever it changes
+const INDEX_VERSION: u32 = 3;
+const HEADER_SIZE: usize = 56;
+const RECORD_SIZE: usize = 37;
+const EXTERNAL_RECORD_SIZE: usize = 20;
+/// Marker for absent string ids
+const NONE: u32 = u32::MAX;
+
+/// Item kinds, stored in records as their position in this table
+const KINDS: &[&str] = &[
+    "module",
+    "struct",
+    "enum",
+    "function",
+    "trait",
+    "impl",
+    "type_alias",
+    "constant",
+    "static",
+    "macro",
+    "extern_crate",
+    "use",
+    "union",
+    "field",
+    "variant",
+    "trait_alias",
+    "proc_macro",
+    "primitive",
+    "assoc_const",
+    "assoc_type",
+    "extern_type",
+];
+
+/// Table-level information about one item, read without decoding the item itself
+#[derive(Debug, Clone, Copy)]
+pub struct IndexEntry<'a> {
+    pub id: Id,
+    /// Name of the item, falling back to the last path component
+    pub name: Option<&'a str>,
+    /// Lowercased name used for case-insensitive search
+    pub search_name: Option<&'a str>,
+    pub kind: &'static str,
+    pub visibility: &'a str,
+    path_start: usize,
+    path_len: usize,
+    blob_offset: usize,
+    blob_len: usize,
+}
+
+/// Memory-mapped documentation index
+#[derive(Debug)]
+pub struct DocIndex {
+    mmap: Mmap,
+    string_count: usize,
+    item_count: usize,
+    external_count: usize,
+    string_offsets_at: usize,
+    string_data_at: usize,
+    paths_at: usize,
+    items_at: usize,
+    externals_at: usize,
+    blob_at: usize,
+}
+
+impl DocIndex {
+    /// Build the index for a documentation file and write it atomically to `index_path`
+    pub fn write(docs_path: &Path, index_path: &Path) -> Result<()> {
+        // The index is tied to the file as stored, which is usually compressed. The
+        // fingerprint is taken before reading, so docs replaced meanwhile read as stale.
+        let fingerprint = DocsFingerprint::of(docs_path)?;
+        let json = read_docs(docs_path)?;
+        let crate_data: Crate =
+            serde_json::from_slice(&json).context("Failed to parse documentation JSON")?;
+        Self::write_crate(&crate_data, fingerprint, index_path)
+    }
+
+    /// Build the index for already parsed documentation and write it atomically to `index_path`
+    pub fn write_crate(
+        crate_data: &Crate,
+        fingerprint: DocsFingerprint,
+        index_path: &Path,
+    ) -> Result<()> {
+        let bytes = build(crate_data, fingerprint)?;
+        if let Some(parent) = index_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        write_atomic(index_path, &bytes)
+    }
+
+    /// Open an index, checking that it was built from the `docs.json` with `fingerprint`
+    ///
+    /// Both the length and the modification time are compared: regenerated docs
+    /// often have exactly the same length as before, e.g. after a small edit.
+    pub fn open(index_path: &Path, fingerprint: DocsFingerprint) -> Result<Self> {
+        let file = File::open(index_path)
+            .with_context(|| format!("Failed to open {}", index_path.display()))?;
+        // SAFETY: index files are only ever replaced through an atomic rename and
+        // never modified in place, so the mapped contents cannot change under us.
+        let mmap = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("Failed to map {}", index_path.display()))?;
+
+        if mmap.len() < HEADER_SIZE || &mmap[..8] != MAGIC {
+            bail!("Not a documentation index: {}", index_path.display());
+        }
+        let header = Reader(&mmap);
+        if header.u32(8) != INDEX_VERSION || header.u32(12) != FORMAT_VERSION {
+            bail!("Documentation index uses an unsupported format version");
+        }
+        if header.u64(16) != fingerprint.docs_len()
+            || header.u64(48) != fingerprint.modified_nanos()
+        {
+            bail!("Documentation index is out of date");
+        }
+
+        let string_count = header.u32(24) as usize;
+        let path_len = header.u32(28) as usize;
+        let item_count = header.u32(32) as usize;
+        let string_data_len = header.u64(36) as usize;
+        let external_count = header.u32(44) as usize;
+
+        let string_offsets_at = HEADER_SIZE;
+        let string_data_at = string_offsets_at + (string_count + 1) * 8;
+        let paths_at = string_data_at + string_data_len;
+        let items_at = paths_at + path_len * 4;
+        let externals_at = items_at + item_count * RECORD_SIZE;
+        let blob_at = externals_at + external_count * EXTERNAL_RECORD_SIZE;
+        if blob_at > mmap.len() {
+            bail!("Documentation index is truncated");
+        }
+
+        Ok(Self {
+            mmap,
+            string_count,
+            item_count,
+            external_count,
+            string_offsets_at,
+            string_data_at,
+            paths_at,
+            items_at,
+            externals_at,
+            blob_at,
+        })
+    }
+
+    /// Number of bytes of tables read by listing and searching
+    pub fn table_size(&self) -> u64 {
+        self.blob_at as u64
+    }
+
+    /// Iterate over all items in id order
+    pub fn entries(&self) -> impl Iterator<Item = IndexEntry<'_>> {
+        (0..self.item_count).map(|position| self.entry_at(position))
+    }
+
+    /// Look up the table entry of an item
+    pub fn entry(&self, id: &Id) -> Option<IndexEntry<'_>> {
+        let reader = Reader(&self.mmap);
+        let (mut low, mut high) = (0, self.item_count);
+        while low < high {
+            let mid = (low + high) / 2;
+            let mid_id = reader.u32(self.items_at + mid * RECORD_SIZE);
+            match mid_id.cmp(&id.0) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(self.entry_at(mid)),
+            }
+        }
+        None
+    }
+
+    /// Get the full path of an item
+    pub fn path(&self, entry: &IndexEntry<'_>) -> Vec<String> {
+        self.path_at(entry.path_start, entry.path_len)
+    }
+
+    /// Look up an item of another crate named in the documentation
+    pub fn external(&self, id: &Id) -> Option<ExternalItem> {
+        let reader = Reader(&self.mmap);
+        let (mut low, mut high) = (0, self.external_count);
+        while low < high {
+            let mid = (low + high) / 2;
+            let at = self.externals_at + mid * EXTERNAL_RECORD_SIZE;
+            match reader.u32(at).cmp(&id.0) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => {
+                    return Some(ExternalItem {
+                        crate_name: self.string(reader.u32(at + 4))?.to_string(),
+                        kind: self.string(reader.u32(at + 8))?.to_string(),
+                        path: self
+                            .path_at(reader.u32(at + 12) as usize, reader.u32(at + 16) as usize),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Decode a single item
+    pub fn item(&self, id: &Id) -> Result<Option<Item>> {
+        let Some(entry) = self.entry(id) else {
+            return Ok(None);
+        };
+
+        let start = self.blob_at + entry.blob_offset;
+        let compressed = self
+            .mmap
+            .get(start..start + entry.blob_len)
+            .context("Documentation index is truncated")?;
+        let item = serde_json::from_reader(DeflateDecoder::new(compressed))
+            .with_context(|| format!("Failed to decode item {}", id.0))?;
+        Ok(Some(item))
+    }
+
+    fn path_at(&self, start: usize, len: usize) -> Vec<String> {
+        let reader = Reader(&self.mmap);
+        (0..len)
+            .filter_map(|i| {
+                let string_id = reader.u32(self.paths_at + (start + i) * 4);
+                self.string(string_id).map(String::from)
+            })
+            .collect()
+    }
+
+    fn entry_at(&self, position: usize) -> IndexEntry<'_> {
+        let reader = Reader(&self.mmap);
+        let at = self.items_at + position * RECORD_SIZE;
+        IndexEntry {
+            id: Id(reader.u32(at)),
+            name: self.string(reader.u32(at + 4)),
+            search_name: self.string(reader.u32(at + 8)),
+            kind: KINDS
+                .get(self.mmap[at + 12] as usize)
+                .copied()
+                .unwrap_or("unknown"),
+            visibility: self.string(reader.u32(at + 13)).unwrap_or_default(),
+            path_start: reader.u32(at + 17) as usize,
+            path_len: reader.u32(at + 21) as usize,
+            blob_offset: reader.u64(at + 25) as usize,
+            blob_len: reader.u32(at + 33) as usize,
+        }
+    }
+
+    fn string(&self, string_id: u32) -> Option<&str> {
+        let string_id = string_id as usize;
+        if string_id >= self.string_count {
+            return None;
+        }
+        let reader = Reader(&self.mmap);
+        let start = reader.u64(self.string_offsets_at + string_id * 8) as usize;
+        let end = reader.u64(self.string_offsets_at + (string_id + 1) * 8) as usize;
+        let bytes = self
+            .mmap
+            .get(self.string_data_at + start..self.string_data_at + end)?;
+        std::str::from_utf8(bytes).ok()
+    }
+}
+
+/// Little-endian reads from validated positions of the index
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn u32(&self, at: usize) -> u32 {
+        self.0
+            .get(at..at + 4)
+            .map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn u64(&self, at: usize) -> u64 {
+        self.0
+            .get(at..at + 8)
+            .map_or(0, |b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+}
+
+/// Interns strings so that repeated names and path components are stored once
+#[derive(Default)]
+struct StringTable {
+    ids: HashMap<String, u32>,
+    offsets: Vec<u64>,
+    data: Vec<u8>,
+}
+
+impl StringTable {
+    fn intern(&mut self, s: &str) -> Result<u32> {
+        if let Some(&id) = self.ids.get(s) {
+            return Ok(id);
+        }
+        let id = to_u32(self.offsets.len())?;
+        self.offsets.push(self.data.len() as u64);
+        self.data.extend_from_slice(s.as_bytes());
+        self.ids.insert(s.to_string(), id);
+        Ok(id)
+    }
+}
+
+/// Serialize the index of a crate
+fn build(crate_data: &Crate, fingerprint: DocsFingerprint) -> Result<Vec<u8>> {
+    let mut strings = StringTable::default();
+    let mut paths: Vec<u32> = Vec::new();
+    let mut records: Vec<u8> = Vec::with_capacity(crate_data.index.len() * RECORD_SIZE);
+    let mut blob: Vec<u8> = Vec::new();
+
+    let mut ids: Vec<&Id> = crate_data.index.keys().collect();
+    ids.sort_by_key(|id| id.0);
+
+    for id in ids {
+        let item = &crate_data.index[id];
+        let path = crate_data
+            .paths
+            .get(id)
+            .map(|summary| summary.path.as_slice())
+            .unwrap_or_default();
+        let name = item
+            .name
+            .as_deref()
+            .or_else(|| path.last().map(String::as_str));
+
+        let (name_id, search_name_id) = match name {
+            Some(name) => (strings.intern(name)?, strings.intern(&name.to_lowercase())?),
+            None => (NONE, NONE),
+        };
+        let kind = item_kind(&item.inner);
+        let kind_code = KINDS.iter().position(|k| *k == kind).unwrap_or(0) as u8;
+        let visibility_id = strings.intern(&visibility_string(&item.visibility))?;
+
+        let path_start = to_u32(paths.len())?;
+        for component in path {
+            paths.push(strings.intern(component)?);
+        }
+
+        let blob_offset = blob.len() as u64;
+        let mut encoder = DeflateEncoder::new(&mut blob, Compression::default());
+        serde_json::to_writer(&mut encoder, item)?;
+        encoder.finish()?;
+        let blob_len = to_u32(blob.len() - blob_offset as usize)?;
+
+        records.extend_from_slice(&id.0.to_le_bytes());
+        records.extend_from_slice(&name_id.to_le_bytes());
+        records.extend_from_slice(&search_name_id.to_le_bytes());
+        records.push(kind_code);
+        records.extend_from_slice(&visibility_id.to_le_bytes());
+        records.extend_from_slice(&path_start.to_le_bytes());
+        records.extend_from_slice(&to_u32(path.len())?.to_le_bytes());
+        records.extend_from_slice(&blob_offset.to_le_bytes());
+        records.extend_from_slice(&blob_len.to_le_bytes());
+    }
+
+    // Items of other crates only have a path summary
+    let mut externals: Vec<(&Id, &ItemSummary)> = crate_data
+        .paths
+        .iter()
+        .filter(|(id, summary)| summary.crate_id != 0 && !crate_data.index.contains_key(id))
+        .collect();
+    externals.sort_by_key(|(id, _)| id.0);
+    let mut external_records: Vec<u8> = Vec::with_capacity(externals.len() * EXTERNAL_RECORD_SIZE);
+    let mut external_count = 0;
+    for (id, summary) in externals {
+        let Some(external_crate) = crate_data.external_crates.get(&summary.crate_id) else {
+            continue;
+        };
+        let path_start = to_u32(paths.len())?;
+        for component in &summary.path {
+            paths.push(strings.intern(component)?);
+        }
+
+        external_records.extend_from_slice(&id.0.to_le_bytes());
+        external_records.extend_from_slice(&strings.intern(&external_crate.name)?.to_le_bytes());
+        external_records
+            .extend_from_slice(&strings.intern(&summary_kind(&summary.kind))?.to_le_bytes());
+        external_records.extend_from_slice(&path_start.to_le_bytes());
+        external_records.extend_from_slice(&to_u32(summary.path.len())?.to_le_bytes());
+        external_count += 1;
+    }
+    strings.offsets.push(strings.data.len() as u64);
+
+    let mut out = Vec::with_capacity(
+        HEADER_SIZE
+            + strings.offsets.len() * 8
+            + strings.data.len()
+            + records.len()
+            + external_records.len()
+            + blob.len(),
+    );
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&fingerprint.docs_len().to_le_bytes());
+    out.extend_from_slice(&to_u32(strings.offsets.len() - 1)?.to_le_bytes());
+    out.extend_from_slice(&to_u32(paths.len())?.to_le_bytes());
+    out.extend_from_slice(&to_u32(crate_data.index.len())?.to_le_bytes());
+    out.extend_from_slice(&(strings.data.len() as u64).to_le_bytes());
+    out.extend_from_slice(&to_u32(external_count)?.to_le_bytes());
+    out.extend_from_slice(&fingerprint.modified_nanos().to_le_bytes());
+    debug_assert_eq!(out.len(), HEADER_SIZE);
+
+    for offset in &strings.offsets {
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+    out.extend_from_slice(&strings.data);
+    for string_id in &paths {
+        out.extend_from_slice(&string_id.to_le_bytes());
+    }
+    out.extend_from_slice(&records);
+    out.extend_from_slice(&external_records);
+    out.extend_from_slice(&blob);
+    Ok(out)
+}
+
+fn to_u32(n: usize) -> Result<u32> {
+    u32::try_from(n).context("Documentation is too large to index")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustdoc_types::{
+        ExternalCrate, Generics, ItemEnum, ItemKind, Module, Struct, StructKind, Target, Visibility,
+    };
+    use tempfile::TempDir;
+
+    fn item(id: u32, name: Option<&str>, inner: ItemEnum) -> Item {
+        Item {
+            id: Id(id),
+            crate_id: 0,
+            name: name.map(String::from),
+            span: None,
+            visibility: Visibility::Public,
+            docs: Some(format!("Docs of item {id}")),
+            links: Default::default(),
+            attrs: Vec::new(),
+            deprecation: None,
+            inner,
+        }
+    }
+
+    fn sample_crate() -> Crate {
+        let mut crate_data = Crate {
+            root: Id(0),
+            crate_version: Some("1.0.0".to_string()),
+            includes_private: false,
+            index: Default::default(),
+            paths: Default::default(),
+            external_crates: Default::default(),
+            target: Target {
+                triple: String::new(),
+                target_features: Vec::new(),
+            },
+            format_version: FORMAT_VERSION,
+        };
+
+        crate_data.index.insert(
+            Id(0),
+            item(
+                0,
+                Some("sample"),
+                ItemEnum::Module(Module {
+                    is_crate: true,
+                    items: vec![Id(1), Id(2)],
+                    is_stripped: false,
+                }),
+            ),
+        );
+        for (id, name) in [(1, "Config"), (2, "ConfigBuilder")] {
+            crate_data.index.insert(
+                Id(id),
+                item(
+                    id,
+                    Some(name),
+                    ItemEnum::Struct(Struct {
+                        kind: StructKind::Unit,
+                        generics: Generics {
+                            params: Vec::new(),
+                            where_predicates: Vec::new(),
+                        },
+                        impls: Vec::new(),
+                    }),
+                ),
+            );
+        }
+        crate_data.paths.insert(
+            Id(1),
+            ItemSummary {
+                crate_id: 0,
+                path: vec!["sample".to_string(), "Config".to_string()],
+                kind: ItemKind::Struct,
+            },
+        );
+        crate_data.paths.insert(
+            Id(9),
+            ItemSummary {
+                crate_id: 3,
+                path: vec![
+                    "bytes".to_string(),
+                    "bytes".to_string(),
+                    "Bytes".to_string(),
+                ],
+                kind: ItemKind::Struct,
+            },
+        );
+        crate_data.external_crates.insert(
+            3,
+            ExternalCrate {
+                name: "bytes".to_string(),
+                html_root_url: None,
+            },
+        );
+
+        crate_data
+    }
+
+    /// Write a placeholder `docs.json` and return its fingerprint
+    fn docs_fingerprint(temp_dir: &TempDir, contents: &[u8]) -> Result<DocsFingerprint> {
+        let docs_path = temp_dir.path().join("docs.json");
+        std::fs::write(&docs_path, contents)?;
+        DocsFingerprint::of(&docs_path)
+    }
+
+    #[test]
+    fn test_index_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let index_path = temp_dir.path().join(INDEX_FILE_NAME);
+        let fingerprint = docs_fingerprint(&temp_dir, b"{}")?;
+        DocIndex::write_crate(&sample_crate(), fingerprint, &index_path)?;
+
+        let index = DocIndex::open(&index_path, fingerprint)?;
+        let names: Vec<_> = index.entries().filter_map(|e| e.name).collect();
+        assert_eq!(names, ["sample", "Config", "ConfigBuilder"]);
+
+        let config = index.entry(&Id(1)).unwrap();
+        assert_eq!(config.kind, "struct");
+        assert_eq!(config.search_name, Some("config"));
+        assert_eq!(config.visibility, "public");
+        assert_eq!(index.path(&config), ["sample", "Config"]);
+        assert!(index.entry(&Id(7)).is_none());
+
+        let item = index.item(&Id(2))?.unwrap();
+        assert_eq!(item.name.as_deref(), Some("ConfigBuilder"));
+        assert_eq!(item.docs.as_deref(), Some("Docs of item 2"));
+
+        let bytes = index.external(&Id(9)).unwrap();
+        assert_eq!(bytes.crate_name, "bytes");
+        assert_eq!(bytes.kind, "struct");
+        assert_eq!(bytes.path, ["bytes", "bytes", "Bytes"]);
+        assert!(index.external(&Id(1)).is_none());
+        assert!(index.entry(&Id(9)).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_index_is_rejected() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let index_path = temp_dir.path().join(INDEX_FILE_NAME);
+        let fingerprint = docs_fingerprint(&temp_dir, b"{}")?;
+        DocIndex::write_crate(&sample_crate(), fingerprint, &index_path)?;
+        assert!(DocIndex::open(&index_path, fingerprint).is_ok());
+
+        // docs.json was regenerated with a different size
+        let resized = docs_fingerprint(&temp_dir, b"{ }")?;
+        assert!(DocIndex::open(&index_path, resized).is_err());
+
+        std::fs::write(&index_path, b"not an index")?;
+        assert!(DocIndex::open(&index_path, fingerprint).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_length_regeneration_is_stale() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let index_path = temp_dir.path().join(INDEX_FILE_NAME);
+        let fingerprint = docs_fingerprint(&temp_dir, b"[1]")?;
+        DocIndex::write_crate(&sample_crate(), fingerprint, &index_path)?;
+
+        // Regenerated docs of the same length, written later
+        let docs_path = temp_dir.path().join("docs.json");
+        std::fs::write(&docs_path, b"[2]")?;
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&docs_path)?
+            .set_modified(later)?;
+        let regenerated = DocsFingerprint::of(&docs_path)?;
+        assert_eq!(regenerated.docs_len(), fingerprint.docs_len());
+
+        assert!(DocIndex::open(&index_path, regenerated).is_err());
+        Ok(())
+    }
+}
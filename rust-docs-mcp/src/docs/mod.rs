@@ -1,4 +1,9 @@
+pub mod cache;
+pub mod index;
 pub mod query;
+pub mod references;
 pub mod tools;
 
+pub use cache::DocQueryCache;
+pub use index::DocIndex;
 pub use query::DocQuery;
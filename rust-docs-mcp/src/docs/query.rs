@@ -1,13 +1,32 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
 use anyhow::{Context, Result};
 use rmcp::schemars;
-use rustdoc_types::{Crate, Id, Item, ItemEnum};
+use rustdoc_types::{Crate, Id, Item, ItemEnum, ItemKind};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::docs::index::{DocIndex, IndexEntry};
+use crate::docs::references;
+
 /// Query interface for rustdoc JSON data
 #[derive(Debug)]
 pub struct DocQuery {
-    crate_data: Crate,
+    source: DocSource,
+}
+
+/// Where a query reads its documentation from
+#[derive(Debug)]
+enum DocSource {
+    /// Fully deserialized rustdoc JSON
+    Json {
+        crate_data: Crate,
+        /// Lowercased searchable name of every item, built once so searches avoid re-deriving it
+        search_names: Vec<(Id, String)>,
+    },
+    /// Memory-mapped binary index, items are decoded on demand
+    Index(DocIndex),
 }
 
 /// Simplified item information for API responses
@@ -19,6 +38,60 @@ pub struct ItemInfo {
     pub path: Vec<String>,
     pub docs: Option<String>,
     pub visibility: String,
+    /// Workspace member defining the item, set when a whole workspace is queried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+}
+
+/// Item of another crate that a `use` item re-exports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReexportTarget {
+    /// Library name of the crate defining the item
+    pub crate_name: String,
+    /// Full path of the item, starting with the crate name
+    pub path: Vec<String>,
+}
+
+/// Item of another crate named in the documentation, e.g. in a signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalItem {
+    /// Library name of the crate defining the item
+    pub crate_name: String,
+    /// Full path of the item, starting with the crate name
+    pub path: Vec<String>,
+    pub kind: String,
+}
+
+/// Item named in the signature, fields, variants or methods of another item
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ItemReference {
+    /// ID in the documentation the referring item was read from. Pass it to
+    /// `get_item_details` with the same crate, version and member to follow the
+    /// reference, into dependencies too.
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub path: Vec<String>,
+    /// Whether the item is defined in another crate
+    pub external: bool,
+    /// Crate defining an external item: its package name once resolved through
+    /// `dependencies.json`, otherwise its library name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crate_name: Option<String>,
+    /// Resolved version of the crate defining an external item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Workspace member defining an external item, for items of other members
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+}
+
+/// Dependency an item was fetched from
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DependencyOrigin {
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    pub version: String,
 }
 
 /// Source location information
@@ -49,27 +122,84 @@ pub struct DetailedItem {
     pub variants: Option<Vec<ItemInfo>>,
     pub methods: Option<Vec<ItemInfo>>,
     pub source_location: Option<SourceLocation>,
+    /// Items named in the signature, fields, variants or methods
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<ItemReference>,
+    /// Dependency of the queried crate the item was fetched from, whose crate name
+    /// and version apply to the IDs in these details
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependency: Option<DependencyOrigin>,
 }
 
 impl DocQuery {
     /// Create a new query interface for a crate's documentation
     pub fn new(crate_data: Crate) -> Self {
-        Self { crate_data }
+        let search_names = crate_data
+            .index
+            .iter()
+            .filter_map(|(id, item)| {
+                // First check if item has a direct name
+                let item_name = if let Some(name) = &item.name {
+                    Some(name)
+                } else {
+                    // Fall back to using the last component of the path
+                    crate_data
+                        .paths
+                        .get(id)
+                        .and_then(|path_summary| path_summary.path.last())
+                };
+                item_name.map(|name| (*id, name.to_lowercase()))
+            })
+            .collect();
+
+        Self {
+            source: DocSource::Json {
+                crate_data,
+                search_names,
+            },
+        }
+    }
+
+    /// Create a query interface backed by a binary documentation index
+    ///
+    /// Listing and searching read only the index tables and return items without
+    /// their documentation, see [`DocQuery::load_docs`].
+    pub fn from_index(index: DocIndex) -> Self {
+        Self {
+            source: DocSource::Index(index),
+        }
     }
 
     /// List all items in the crate, optionally filtered by kind
     pub fn list_items(&self, kind_filter: Option<&str>) -> Vec<ItemInfo> {
         let mut items = Vec::new();
 
-        for (id, item) in &self.crate_data.index {
-            if let Some(filter) = &kind_filter
-                && self.get_item_kind_string(&item.inner) != *filter
-            {
-                continue;
+        match &self.source {
+            DocSource::Json { crate_data, .. } => {
+                for (id, item) in &crate_data.index {
+                    if let Some(filter) = &kind_filter
+                        && item_kind(&item.inner) != *filter
+                    {
+                        continue;
+                    }
+
+                    if let Some(info) = self.item_to_info(id, item) {
+                        items.push(info);
+                    }
+                }
             }
+            DocSource::Index(index) => {
+                for entry in index.entries() {
+                    if let Some(filter) = &kind_filter
+                        && entry.kind != *filter
+                    {
+                        continue;
+                    }
 
-            if let Some(info) = self.item_to_info(id, item) {
-                items.push(info);
+                    if let Some(info) = entry_to_info(index, &entry) {
+                        items.push(info);
+                    }
+                }
             }
         }
 
@@ -83,46 +213,74 @@ impl DocQuery {
         let pattern_lower = pattern.to_lowercase();
         let mut items = Vec::new();
 
-        for (id, item) in &self.crate_data.index {
-            // First check if item has a direct name
-            let item_name = if let Some(name) = &item.name {
-                Some(name.clone())
-            } else if let Some(path_summary) = self.crate_data.paths.get(id) {
-                // Fall back to using the last component of the path
-                path_summary.path.last().cloned()
-            } else {
-                None
-            };
+        match &self.source {
+            DocSource::Json {
+                crate_data,
+                search_names,
+            } => {
+                for (id, name) in search_names {
+                    if name.contains(&pattern_lower)
+                        && let Some(item) = crate_data.index.get(id)
+                        && let Some(info) = self.item_to_info(id, item)
+                    {
+                        items.push(info);
+                    }
+                }
+            }
+            DocSource::Index(index) => {
+                for entry in index.entries() {
+                    if entry
+                        .search_name
+                        .is_some_and(|name| name.contains(&pattern_lower))
+                        && let Some(info) = entry_to_info(index, &entry)
+                    {
+                        items.push(info);
+                    }
+                }
+            }
+        }
 
-            if let Some(name) = item_name
-                && name.to_lowercase().contains(&pattern_lower)
-                && let Some(info) = self.item_to_info(id, item)
+        sort_by_relevance(&mut items, &pattern_lower);
+        items
+    }
+
+    /// Fill in the documentation of items returned without it
+    ///
+    /// Items listed or found through the binary index carry no documentation, which
+    /// is only decoded here for the items actually returned to the caller.
+    pub fn load_docs(&self, items: &mut [ItemInfo]) {
+        if !matches!(self.source, DocSource::Index(_)) {
+            return;
+        }
+
+        for info in items {
+            if let Ok(id) = info.id.parse::<u32>()
+                && let Some(item) = self.item(&Id(id))
             {
-                items.push(info);
+                info.docs = item.docs.clone();
             }
         }
+    }
 
-        items.sort_by(|a, b| {
-            // Sort by relevance (exact match first, then prefix match, then contains)
-            let a_exact = a.name.to_lowercase() == pattern_lower;
-            let b_exact = b.name.to_lowercase() == pattern_lower;
-            let a_prefix = a.name.to_lowercase().starts_with(&pattern_lower);
-            let b_prefix = b.name.to_lowercase().starts_with(&pattern_lower);
-
-            b_exact
-                .cmp(&a_exact)
-                .then_with(|| b_prefix.cmp(&a_prefix))
-                .then_with(|| a.name.len().cmp(&b.name.len()))
-                .then_with(|| a.name.cmp(&b.name))
-        });
-
-        items
+    /// Get an item by ID, decoding it from the index if necessary
+    fn item(&self, id: &Id) -> Option<Cow<'_, Item>> {
+        match &self.source {
+            DocSource::Json { crate_data, .. } => crate_data.index.get(id).map(Cow::Borrowed),
+            DocSource::Index(index) => match index.item(id) {
+                Ok(item) => item.map(Cow::Owned),
+                Err(e) => {
+                    tracing::warn!("Failed to decode item {} from index: {}", id.0, e);
+                    None
+                }
+            },
+        }
     }
 
     /// Get detailed information about a specific item by ID
     pub fn get_item_details(&self, item_id: u32) -> Result<DetailedItem> {
         let id = Id(item_id);
-        let item = self.crate_data.index.get(&id).context("Item not found")?;
+        let item = self.item(&id).context("Item not found")?;
+        let item = item.as_ref();
 
         let info = self
             .item_to_info(&id, item)
@@ -136,6 +294,8 @@ impl DocQuery {
             variants: None,
             methods: None,
             source_location: self.get_item_source_location(item),
+            references: self.get_item_references(item),
+            dependency: None,
         };
 
         // Add type-specific information
@@ -168,25 +328,94 @@ impl DocQuery {
     /// Get documentation for a specific item
     pub fn get_item_docs(&self, item_id: u32) -> Result<Option<String>> {
         let id = Id(item_id);
-        let item = self.crate_data.index.get(&id).context("Item not found")?;
+        let item = self.item(&id).context("Item not found")?;
 
         Ok(item.docs.clone())
     }
 
+    /// Get the item of another crate re-exported by a `use` item
+    ///
+    /// Returns `None` for other items and for re-exports within this crate.
+    pub fn reexport_target(&self, item_id: u32) -> Option<ReexportTarget> {
+        let item = self.item(&Id(item_id))?;
+        let ItemEnum::Use(import) = &item.inner else {
+            return None;
+        };
+        if let Some(id) = &import.id
+            && self.item(id).is_some()
+        {
+            return None;
+        }
+
+        // Prefer the canonical path of the item over the path written in the `use`
+        let canonical_path = import
+            .id
+            .and_then(|id| self.external_item(id.0))
+            .map(|external| external.path);
+        let path =
+            canonical_path.unwrap_or_else(|| import.source.split("::").map(String::from).collect());
+        let crate_name = path.first()?.clone();
+        if matches!(crate_name.as_str(), "crate" | "self" | "super") {
+            return None;
+        }
+        Some(ReexportTarget { crate_name, path })
+    }
+
+    /// Get an item of another crate named in the documentation
+    ///
+    /// Returns `None` for items of this crate.
+    pub fn external_item(&self, item_id: u32) -> Option<ExternalItem> {
+        let id = Id(item_id);
+        match &self.source {
+            DocSource::Json { crate_data, .. } => {
+                if crate_data.index.contains_key(&id) {
+                    return None;
+                }
+                let summary = crate_data
+                    .paths
+                    .get(&id)
+                    .filter(|summary| summary.crate_id != 0)?;
+                let external_crate = crate_data.external_crates.get(&summary.crate_id)?;
+                Some(ExternalItem {
+                    crate_name: external_crate.name.clone(),
+                    path: summary.path.clone(),
+                    kind: summary_kind(&summary.kind),
+                })
+            }
+            DocSource::Index(index) => index.external(&id),
+        }
+    }
+
+    /// Find an item by its full path, falling back to an item of the same name
+    ///
+    /// Used to resolve re-exports, whose path may name a public re-export rather
+    /// than the module the item is defined in.
+    pub fn find_item(&self, path: &[String]) -> Option<u32> {
+        let name = path.last()?;
+        let items = self.list_items(None);
+        items
+            .iter()
+            .find(|info| info.path == path)
+            .or_else(|| {
+                items.iter().find(|info| {
+                    info.name == *name && !matches!(info.kind.as_str(), "use" | "impl")
+                })
+            })
+            .and_then(|info| info.id.parse().ok())
+    }
+
     /// Helper to convert an Item to ItemInfo
     fn item_to_info(&self, id: &Id, item: &Item) -> Option<ItemInfo> {
         // Get name from item or from paths
+        let path = self.get_item_path(id);
         let name = if let Some(name) = &item.name {
             name.clone()
-        } else if let Some(path_summary) = self.crate_data.paths.get(id) {
-            path_summary.path.last()?.clone()
         } else {
-            return None;
+            path.last()?.clone()
         };
 
-        let kind = self.get_item_kind_string(&item.inner);
-        let path = self.get_item_path(id);
-        let visibility = self.get_visibility_string(&item.visibility);
+        let kind = item_kind(&item.inner).to_string();
+        let visibility = visibility_string(&item.visibility);
 
         Some(ItemInfo {
             id: id.0.to_string(),
@@ -195,55 +424,83 @@ impl DocQuery {
             path,
             docs: item.docs.clone(),
             visibility,
+            member: None,
         })
     }
 
-    /// Get the kind of an item as a string
-    fn get_item_kind_string(&self, inner: &ItemEnum) -> String {
-        use ItemEnum::*;
-        match inner {
-            Module(_) => "module",
-            Struct(_) => "struct",
-            Enum(_) => "enum",
-            Function(_) => "function",
-            Trait(_) => "trait",
-            Impl(_) => "impl",
-            TypeAlias(_) => "type_alias",
-            Constant { .. } => "constant",
-            Static(_) => "static",
-            Macro(_) => "macro",
-            ExternCrate { .. } => "extern_crate",
-            Use(_) => "use",
-            Union(_) => "union",
-            StructField(_) => "field",
-            Variant(_) => "variant",
-            TraitAlias(_) => "trait_alias",
-            ProcMacro(_) => "proc_macro",
-            Primitive(_) => "primitive",
-            AssocConst { .. } => "assoc_const",
-            AssocType { .. } => "assoc_type",
-            ExternType => "extern_type",
+    /// Get the items named in the types of an item and of its fields, variants and methods
+    fn get_item_references(&self, item: &Item) -> Vec<ItemReference> {
+        let mut ids = Vec::new();
+        references::item_ids(&item.inner, &mut ids);
+        for member_id in references::member_ids(&item.inner) {
+            let Some(member) = self.item(&member_id) else {
+                continue;
+            };
+            references::item_ids(&member.inner, &mut ids);
+            // Fields of struct and tuple variants
+            for field_id in references::member_ids(&member.inner) {
+                if let Some(field) = self.item(&field_id) {
+                    references::item_ids(&field.inner, &mut ids);
+                }
+            }
         }
-        .to_string()
+
+        let mut seen = HashSet::new();
+        ids.into_iter()
+            .filter(|id| seen.insert(*id))
+            .filter_map(|id| self.item_reference(&id))
+            .collect()
     }
 
-    /// Get the full path of an item
-    fn get_item_path(&self, id: &Id) -> Vec<String> {
-        if let Some(summary) = self.crate_data.paths.get(id) {
-            summary.path.clone()
-        } else {
-            Vec::new()
+    /// Describe a referenced item without decoding it
+    fn item_reference(&self, id: &Id) -> Option<ItemReference> {
+        let local = match &self.source {
+            DocSource::Json { crate_data, .. } => crate_data
+                .index
+                .get(id)
+                .and_then(|item| self.item_to_info(id, item)),
+            DocSource::Index(index) => index
+                .entry(id)
+                .and_then(|entry| entry_to_info(index, &entry)),
+        };
+        if let Some(info) = local {
+            return Some(ItemReference {
+                id: info.id,
+                name: info.name,
+                kind: info.kind,
+                path: info.path,
+                external: false,
+                crate_name: None,
+                version: None,
+                member: None,
+            });
         }
+
+        let external = self.external_item(id.0)?;
+        Some(ItemReference {
+            id: id.0.to_string(),
+            name: external.path.last()?.clone(),
+            kind: external.kind,
+            path: external.path,
+            external: true,
+            crate_name: Some(external.crate_name),
+            version: None,
+            member: None,
+        })
     }
 
-    /// Get visibility as a string
-    fn get_visibility_string(&self, vis: &rustdoc_types::Visibility) -> String {
-        use rustdoc_types::Visibility::*;
-        match vis {
-            Public => "public".to_string(),
-            Default => "default".to_string(),
-            Crate => "crate".to_string(),
-            Restricted { parent, .. } => format!("restricted({})", parent.0),
+    /// Get the full path of an item
+    fn get_item_path(&self, id: &Id) -> Vec<String> {
+        match &self.source {
+            DocSource::Json { crate_data, .. } => crate_data
+                .paths
+                .get(id)
+                .map(|summary| summary.path.clone())
+                .unwrap_or_default(),
+            DocSource::Index(index) => index
+                .entry(id)
+                .map(|entry| index.path(&entry))
+                .unwrap_or_default(),
         }
     }
 
@@ -296,8 +553,8 @@ impl DocQuery {
                 .enumerate()
                 .filter_map(|(i, field_id)| {
                     if let Some(field_id) = field_id {
-                        let item = self.crate_data.index.get(field_id)?;
-                        let mut info = self.item_to_info(field_id, item)?;
+                        let item = self.item(field_id)?;
+                        let mut info = self.item_to_info(field_id, &item)?;
                         if info.name.is_empty() {
                             info.name = i.to_string();
                         }
@@ -310,6 +567,7 @@ impl DocQuery {
                             path: Vec::new(),
                             docs: None,
                             visibility: "private".to_string(),
+                            member: None,
                         })
                     }
                 })
@@ -321,8 +579,8 @@ impl DocQuery {
                 let mut field_infos: Vec<ItemInfo> = fields
                     .iter()
                     .filter_map(|field_id| {
-                        let item = self.crate_data.index.get(field_id)?;
-                        self.item_to_info(field_id, item)
+                        let item = self.item(field_id)?;
+                        self.item_to_info(field_id, &item)
                     })
                     .collect();
 
@@ -334,6 +592,7 @@ impl DocQuery {
                         path: Vec::new(),
                         docs: None,
                         visibility: "private".to_string(),
+                        member: None,
                     });
                 }
 
@@ -348,8 +607,8 @@ impl DocQuery {
             .variants
             .iter()
             .filter_map(|variant_id| {
-                let item = self.crate_data.index.get(variant_id)?;
-                self.item_to_info(variant_id, item)
+                let item = self.item(variant_id)?;
+                self.item_to_info(variant_id, &item)
             })
             .collect();
 
@@ -361,6 +620,7 @@ impl DocQuery {
                 path: Vec::new(),
                 docs: None,
                 visibility: "private".to_string(),
+                member: None,
             });
         }
 
@@ -372,8 +632,8 @@ impl DocQuery {
         items
             .iter()
             .filter_map(|item_id| {
-                let item = self.crate_data.index.get(item_id)?;
-                self.item_to_info(item_id, item)
+                let item = self.item(item_id)?;
+                self.item_to_info(item_id, &item)
             })
             .collect()
     }
@@ -383,8 +643,8 @@ impl DocQuery {
         items
             .iter()
             .filter_map(|item_id| {
-                let item = self.crate_data.index.get(item_id)?;
-                self.item_to_info(item_id, item)
+                let item = self.item(item_id)?;
+                self.item_to_info(item_id, &item)
             })
             .collect()
     }
@@ -409,7 +669,7 @@ impl DocQuery {
         context_lines: usize,
     ) -> Result<SourceInfo> {
         let id = Id(item_id);
-        let item = self.crate_data.index.get(&id).context("Item not found")?;
+        let item = self.item(&id).context("Item not found")?;
 
         let span = item.span.as_ref().context("Item has no source span")?;
         let source_path = base_path.join(&span.filename);
@@ -446,3 +706,298 @@ impl DocQuery {
         })
     }
 }
+
+/// Get the kind of an item as a string
+pub fn item_kind(inner: &ItemEnum) -> &'static str {
+    use ItemEnum::*;
+    match inner {
+        Module(_) => "module",
+        Struct(_) => "struct",
+        Enum(_) => "enum",
+        Function(_) => "function",
+        Trait(_) => "trait",
+        Impl(_) => "impl",
+        TypeAlias(_) => "type_alias",
+        Constant { .. } => "constant",
+        Static(_) => "static",
+        Macro(_) => "macro",
+        ExternCrate { .. } => "extern_crate",
+        Use(_) => "use",
+        Union(_) => "union",
+        StructField(_) => "field",
+        Variant(_) => "variant",
+        TraitAlias(_) => "trait_alias",
+        ProcMacro(_) => "proc_macro",
+        Primitive(_) => "primitive",
+        AssocConst { .. } => "assoc_const",
+        AssocType { .. } => "assoc_type",
+        ExternType => "extern_type",
+    }
+}
+
+/// Get the kind of an item summary as a string, e.g. `struct` or `type_alias`
+pub fn summary_kind(kind: &ItemKind) -> String {
+    serde_json::to_value(kind)
+        .ok()
+        .and_then(|kind| kind.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// Sort search results by relevance to a lowercased pattern
+///
+/// Exact matches come first, then prefix matches, then other matches, shorter names first.
+pub fn sort_by_relevance(items: &mut [ItemInfo], pattern_lower: &str) {
+    items.sort_by(|a, b| {
+        let a_exact = a.name.to_lowercase() == pattern_lower;
+        let b_exact = b.name.to_lowercase() == pattern_lower;
+        let a_prefix = a.name.to_lowercase().starts_with(pattern_lower);
+        let b_prefix = b.name.to_lowercase().starts_with(pattern_lower);
+
+        b_exact
+            .cmp(&a_exact)
+            .then_with(|| b_prefix.cmp(&a_prefix))
+            .then_with(|| a.name.len().cmp(&b.name.len()))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+/// Get visibility as a string
+pub fn visibility_string(vis: &rustdoc_types::Visibility) -> String {
+    use rustdoc_types::Visibility::*;
+    match vis {
+        Public => "public".to_string(),
+        Default => "default".to_string(),
+        Crate => "crate".to_string(),
+        Restricted { parent, .. } => format!("restricted({})", parent.0),
+    }
+}
+
+/// Convert an index entry to ItemInfo without decoding the item
+fn entry_to_info(index: &DocIndex, entry: &IndexEntry<'_>) -> Option<ItemInfo> {
+    Some(ItemInfo {
+        id: entry.id.0.to_string(),
+        name: entry.name?.to_string(),
+        kind: entry.kind.to_string(),
+        path: index.path(entry),
+        docs: None,
+        visibility: entry.visibility.to_string(),
+        member: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustdoc_types::{
+        Abi, ExternalCrate, FORMAT_VERSION, Function, FunctionHeader, FunctionSignature,
+        GenericArg, GenericArgs, GenericBound, Generics, ItemKind, ItemSummary, Module, Path,
+        Target, TraitBoundModifier, Type, Use, Visibility,
+    };
+
+    fn item(id: u32, name: &str, inner: ItemEnum) -> Item {
+        Item {
+            id: Id(id),
+            crate_id: 0,
+            name: Some(name.to_string()),
+            span: None,
+            visibility: Visibility::Public,
+            docs: None,
+            links: Default::default(),
+            attrs: Vec::new(),
+            deprecation: None,
+            inner,
+        }
+    }
+
+    fn summary(crate_id: u32, path: &[&str], kind: ItemKind) -> ItemSummary {
+        ItemSummary {
+            crate_id,
+            path: path.iter().map(|segment| segment.to_string()).collect(),
+            kind,
+        }
+    }
+
+    fn use_item(id: u32, source: &str, target: u32) -> Item {
+        let name = source.rsplit("::").next().unwrap();
+        item(
+            id,
+            name,
+            ItemEnum::Use(Use {
+                source: source.to_string(),
+                name: name.to_string(),
+                id: Some(Id(target)),
+                is_glob: false,
+            }),
+        )
+    }
+
+    fn module(id: u32, name: &str, items: Vec<Id>) -> Item {
+        item(
+            id,
+            name,
+            ItemEnum::Module(Module {
+                is_crate: id == 0,
+                items,
+                is_stripped: false,
+            }),
+        )
+    }
+
+    /// A facade crate `app` re-exporting `World` of `app_ecs` and its own `Config`
+    fn facade_crate() -> Crate {
+        let mut crate_data = Crate {
+            root: Id(0),
+            crate_version: Some("1.0.0".to_string()),
+            includes_private: false,
+            index: Default::default(),
+            paths: Default::default(),
+            external_crates: Default::default(),
+            target: Target {
+                triple: String::new(),
+                target_features: Vec::new(),
+            },
+            format_version: FORMAT_VERSION,
+        };
+        crate_data
+            .index
+            .insert(Id(0), module(0, "app", vec![Id(1), Id(2), Id(3)]));
+        crate_data
+            .index
+            .insert(Id(1), use_item(1, "app_ecs::prelude::World", 10));
+        crate_data.index.insert(Id(2), module(2, "config", vec![]));
+        crate_data
+            .index
+            .insert(Id(3), use_item(3, "self::config", 2));
+        crate_data
+            .paths
+            .insert(Id(0), summary(0, &["app"], ItemKind::Module));
+        crate_data
+            .paths
+            .insert(Id(2), summary(0, &["app", "config"], ItemKind::Module));
+        crate_data.paths.insert(
+            Id(10),
+            summary(1, &["app_ecs", "world", "World"], ItemKind::Struct),
+        );
+        crate_data.external_crates.insert(
+            1,
+            ExternalCrate {
+                name: "app_ecs".to_string(),
+                html_root_url: None,
+            },
+        );
+        crate_data
+    }
+
+    #[test]
+    fn test_reexport_target() {
+        let query = DocQuery::new(facade_crate());
+
+        let target = query.reexport_target(1).unwrap();
+        assert_eq!(target.crate_name, "app_ecs");
+        assert_eq!(target.path, ["app_ecs", "world", "World"]);
+
+        // Re-exports within the crate and other items aren't followed
+        assert!(query.reexport_target(3).is_none());
+        assert!(query.reexport_target(2).is_none());
+    }
+
+    #[test]
+    fn test_find_item() {
+        let query = DocQuery::new(facade_crate());
+        let path = |segments: &[&str]| -> Vec<String> {
+            segments.iter().map(|segment| segment.to_string()).collect()
+        };
+
+        assert_eq!(query.find_item(&path(&["app", "config"])), Some(2));
+        // A path through a re-exporting module falls back to the item's name
+        assert_eq!(
+            query.find_item(&path(&["app", "prelude", "config"])),
+            Some(2)
+        );
+        assert_eq!(query.find_item(&path(&["app", "missing"])), None);
+    }
+
+    fn resolved_path(name: &str, id: u32, args: Option<GenericArgs>) -> Path {
+        Path {
+            path: name.to_string(),
+            id: Id(id),
+            args: args.map(Box::new),
+        }
+    }
+
+    #[test]
+    fn test_external_references() {
+        // fn send(body: impl Into<Bytes>) -> Config, with Into from core and Bytes from bytes
+        let mut crate_data = facade_crate();
+        let into_bytes = GenericBound::TraitBound {
+            trait_: resolved_path(
+                "Into",
+                20,
+                Some(GenericArgs::AngleBracketed {
+                    args: vec![GenericArg::Type(Type::ResolvedPath(resolved_path(
+                        "Bytes", 21, None,
+                    )))],
+                    constraints: Vec::new(),
+                }),
+            ),
+            generic_params: Vec::new(),
+            modifier: TraitBoundModifier::None,
+        };
+        let send = ItemEnum::Function(Function {
+            sig: FunctionSignature {
+                inputs: vec![("body".to_string(), Type::ImplTrait(vec![into_bytes]))],
+                output: Some(Type::ResolvedPath(resolved_path("Config", 2, None))),
+                is_c_variadic: false,
+            },
+            generics: Generics {
+                params: Vec::new(),
+                where_predicates: Vec::new(),
+            },
+            header: FunctionHeader {
+                is_const: false,
+                is_unsafe: false,
+                is_async: false,
+                abi: Abi::Rust,
+            },
+            has_body: true,
+        });
+        crate_data.index.insert(Id(4), item(4, "send", send));
+        crate_data.paths.insert(
+            Id(20),
+            summary(2, &["core", "convert", "Into"], ItemKind::Trait),
+        );
+        crate_data.paths.insert(
+            Id(21),
+            summary(3, &["bytes", "bytes", "Bytes"], ItemKind::Struct),
+        );
+        for (crate_id, name) in [(2, "core"), (3, "bytes")] {
+            crate_data.external_crates.insert(
+                crate_id,
+                ExternalCrate {
+                    name: name.to_string(),
+                    html_root_url: None,
+                },
+            );
+        }
+        let query = DocQuery::new(crate_data);
+
+        let references = query.get_item_details(4).unwrap().references;
+        let summary: Vec<_> = references
+            .iter()
+            .map(|r| (r.id.as_str(), r.external, r.crate_name.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("20", true, Some("core")),
+                ("21", true, Some("bytes")),
+                ("2", false, None),
+            ]
+        );
+        assert_eq!(references[1].kind, "struct");
+
+        let bytes = query.external_item(21).unwrap();
+        assert_eq!(bytes.path, ["bytes", "bytes", "Bytes"]);
+        assert!(query.external_item(2).is_none());
+    }
+}
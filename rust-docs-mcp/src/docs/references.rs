@@ -0,0 +1,216 @@
+//! Items named in the types of an item
+//!
+//! Signatures refer to other items through resolved paths. Their IDs point into
+//! [`rustdoc_types::Crate::index`] for items of the documented crate, and into
+//! [`rustdoc_types::Crate::paths`] only for items of other crates, such as the
+//! `Bytes` of a parameter `impl Into<Bytes>`.
+
+use rustdoc_types::{
+    AssocItemConstraintKind, FunctionSignature, GenericArg, GenericArgs, GenericBound,
+    GenericParamDef, GenericParamDefKind, Generics, Id, ItemEnum, Path, StructKind, Term, Type,
+    VariantKind, WherePredicate,
+};
+
+/// Collect the IDs of the items named in the types of an item, in order of appearance
+pub fn item_ids(inner: &ItemEnum, ids: &mut Vec<Id>) {
+    match inner {
+        ItemEnum::Union(union) => generics_ids(&union.generics, ids),
+        ItemEnum::Struct(s) => generics_ids(&s.generics, ids),
+        ItemEnum::StructField(type_) => type_ids(type_, ids),
+        ItemEnum::Enum(e) => generics_ids(&e.generics, ids),
+        ItemEnum::Function(function) => {
+            signature_ids(&function.sig, ids);
+            generics_ids(&function.generics, ids);
+        }
+        ItemEnum::Trait(t) => {
+            generics_ids(&t.generics, ids);
+            bound_ids(&t.bounds, ids);
+        }
+        ItemEnum::TraitAlias(alias) => {
+            generics_ids(&alias.generics, ids);
+            bound_ids(&alias.params, ids);
+        }
+        ItemEnum::Impl(i) => {
+            if let Some(trait_) = &i.trait_ {
+                path_ids(trait_, ids);
+            }
+            type_ids(&i.for_, ids);
+            generics_ids(&i.generics, ids);
+        }
+        ItemEnum::TypeAlias(alias) => {
+            type_ids(&alias.type_, ids);
+            generics_ids(&alias.generics, ids);
+        }
+        ItemEnum::Constant { type_, .. } | ItemEnum::AssocConst { type_, .. } => {
+            type_ids(type_, ids)
+        }
+        ItemEnum::Static(s) => type_ids(&s.type_, ids),
+        ItemEnum::AssocType {
+            generics,
+            bounds,
+            type_,
+        } => {
+            if let Some(type_) = type_ {
+                type_ids(type_, ids);
+            }
+            bound_ids(bounds, ids);
+            generics_ids(generics, ids);
+        }
+        _ => {}
+    }
+}
+
+/// IDs of the fields, variants and associated items listed with an item's details
+pub fn member_ids(inner: &ItemEnum) -> Vec<Id> {
+    match inner {
+        ItemEnum::Struct(s) => match &s.kind {
+            StructKind::Unit => Vec::new(),
+            StructKind::Tuple(fields) => fields.iter().flatten().copied().collect(),
+            StructKind::Plain { fields, .. } => fields.clone(),
+        },
+        ItemEnum::Union(union) => union.fields.clone(),
+        ItemEnum::Enum(e) => e.variants.clone(),
+        ItemEnum::Variant(variant) => match &variant.kind {
+            VariantKind::Plain => Vec::new(),
+            VariantKind::Tuple(fields) => fields.iter().flatten().copied().collect(),
+            VariantKind::Struct { fields, .. } => fields.clone(),
+        },
+        ItemEnum::Trait(t) => t.items.clone(),
+        ItemEnum::Impl(i) => i.items.clone(),
+        _ => Vec::new(),
+    }
+}
+
+fn type_ids(type_: &Type, ids: &mut Vec<Id>) {
+    match type_ {
+        Type::ResolvedPath(path) => path_ids(path, ids),
+        Type::DynTrait(dyn_trait) => {
+            for poly_trait in &dyn_trait.traits {
+                param_ids(&poly_trait.generic_params, ids);
+                path_ids(&poly_trait.trait_, ids);
+            }
+        }
+        Type::FunctionPointer(pointer) => {
+            param_ids(&pointer.generic_params, ids);
+            signature_ids(&pointer.sig, ids);
+        }
+        Type::Tuple(types) => types.iter().for_each(|type_| type_ids(type_, ids)),
+        Type::Slice(type_)
+        | Type::Array { type_, .. }
+        | Type::Pat { type_, .. }
+        | Type::RawPointer { type_, .. }
+        | Type::BorrowedRef { type_, .. } => type_ids(type_, ids),
+        Type::ImplTrait(bounds) => bound_ids(bounds, ids),
+        Type::QualifiedPath {
+            args,
+            self_type,
+            trait_,
+            ..
+        } => {
+            type_ids(self_type, ids);
+            if let Some(trait_) = trait_ {
+                path_ids(trait_, ids);
+            }
+            args_ids(args, ids);
+        }
+        Type::Generic(_) | Type::Primitive(_) | Type::Infer => {}
+    }
+}
+
+fn path_ids(path: &Path, ids: &mut Vec<Id>) {
+    ids.push(path.id);
+    if let Some(args) = &path.args {
+        args_ids(args, ids);
+    }
+}
+
+fn args_ids(args: &GenericArgs, ids: &mut Vec<Id>) {
+    match args {
+        GenericArgs::AngleBracketed { args, constraints } => {
+            for arg in args {
+                if let GenericArg::Type(type_) = arg {
+                    type_ids(type_, ids);
+                }
+            }
+            for constraint in constraints {
+                args_ids(&constraint.args, ids);
+                match &constraint.binding {
+                    AssocItemConstraintKind::Equality(Term::Type(type_)) => type_ids(type_, ids),
+                    AssocItemConstraintKind::Equality(Term::Constant(_)) => {}
+                    AssocItemConstraintKind::Constraint(bounds) => bound_ids(bounds, ids),
+                }
+            }
+        }
+        GenericArgs::Parenthesized { inputs, output } => {
+            inputs.iter().for_each(|type_| type_ids(type_, ids));
+            if let Some(output) = output {
+                type_ids(output, ids);
+            }
+        }
+        GenericArgs::ReturnTypeNotation => {}
+    }
+}
+
+fn bound_ids(bounds: &[GenericBound], ids: &mut Vec<Id>) {
+    for bound in bounds {
+        if let GenericBound::TraitBound {
+            trait_,
+            generic_params,
+            ..
+        } = bound
+        {
+            param_ids(generic_params, ids);
+            path_ids(trait_, ids);
+        }
+    }
+}
+
+fn param_ids(params: &[GenericParamDef], ids: &mut Vec<Id>) {
+    for param in params {
+        match &param.kind {
+            GenericParamDefKind::Lifetime { .. } => {}
+            GenericParamDefKind::Type {
+                bounds, default, ..
+            } => {
+                bound_ids(bounds, ids);
+                if let Some(default) = default {
+                    type_ids(default, ids);
+                }
+            }
+            GenericParamDefKind::Const { type_, .. } => type_ids(type_, ids),
+        }
+    }
+}
+
+fn generics_ids(generics: &Generics, ids: &mut Vec<Id>) {
+    param_ids(&generics.params, ids);
+    for predicate in &generics.where_predicates {
+        match predicate {
+            WherePredicate::BoundPredicate {
+                type_,
+                bounds,
+                generic_params,
+            } => {
+                param_ids(generic_params, ids);
+                type_ids(type_, ids);
+                bound_ids(bounds, ids);
+            }
+            WherePredicate::LifetimePredicate { .. } => {}
+            WherePredicate::EqPredicate { lhs, rhs } => {
+                type_ids(lhs, ids);
+                if let Term::Type(rhs) = rhs {
+                    type_ids(rhs, ids);
+                }
+            }
+        }
+    }
+}
+
+fn signature_ids(sig: &FunctionSignature, ids: &mut Vec<Id>) {
+    for (_, type_) in &sig.inputs {
+        type_ids(type_, ids);
+    }
+    if let Some(output) = &sig.output {
+        type_ids(output, ids);
+    }
+}
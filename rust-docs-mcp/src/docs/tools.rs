@@ -1,16 +1,48 @@
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
+use anyhow::{Context, bail};
 use rmcp::schemars;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::cache::CrateCache;
-use crate::docs::DocQuery;
+use crate::cache::prefetch::is_crates_io_source;
+use crate::cache::utils::docs_json_len;
+use crate::deps::graph::DependencyGraph;
+use crate::docs::cache::{DocQueryKey, DocsFingerprint};
+use crate::docs::query::{
+    DependencyOrigin, DetailedItem, ExternalItem, ItemInfo, ItemReference, sort_by_relevance,
+};
+use crate::docs::{DocIndex, DocQuery, DocQueryCache};
 
 /// Maximum size for response in bytes (roughly 25k tokens * 4 bytes/token)
 const MAX_RESPONSE_SIZE: usize = 100_000;
 
+/// Libraries of the standard distribution, which are never cached
+const STANDARD_LIBRARIES: &[&str] = &["std", "core", "alloc", "proc_macro", "test"];
+
+/// Documentation of a crate version or one of its workspace members
+#[derive(Debug, Clone)]
+struct DocScope {
+    crate_name: String,
+    version: String,
+    member: Option<String>,
+}
+
+/// Where a library used by a crate is defined
+#[derive(Debug, Clone)]
+enum LibrarySource {
+    /// Another documented member of the same workspace, by package name
+    Member(String),
+    /// A dependency at its resolved version
+    Package {
+        name: String,
+        version: String,
+        source: Option<String>,
+    },
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ListItemsParams {
     #[schemars(description = "The name of the crate")]
@@ -24,7 +56,7 @@ pub struct ListItemsParams {
     #[schemars(description = "Starting position for pagination (default: 0)")]
     pub offset: Option<usize>,
     #[schemars(
-        description = "For workspace crates, specify the member path (e.g., 'crates/rmcp')"
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp'). If omitted, all documented members of the workspace are queried and each item reports its member."
     )]
     pub member: Option<String>,
 }
@@ -48,7 +80,7 @@ pub struct SearchItemsParams {
     #[schemars(description = "Optional filter by module path prefix")]
     pub path_filter: Option<String>,
     #[schemars(
-        description = "For workspace crates, specify the member path (e.g., 'crates/rmcp')"
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp'). If omitted, all documented members of the workspace are queried and each item reports its member."
     )]
     pub member: Option<String>,
 }
@@ -72,21 +104,37 @@ pub struct SearchItemsPreviewParams {
     #[schemars(description = "Optional filter by module path prefix")]
     pub path_filter: Option<String>,
     #[schemars(
-        description = "For workspace crates, specify the member path (e.g., 'crates/rmcp')"
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp'). If omitted, all documented members of the workspace are queried and each item reports its member."
     )]
     pub member: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SearchAllCratesParams {
+    #[schemars(
+        description = "Words to search for in item names, module paths and documentation of all cached crates"
+    )]
+    pub pattern: String,
+    #[schemars(description = "Optional filter by item kind (e.g., 'function', 'struct', 'enum')")]
+    pub kind_filter: Option<String>,
+    #[schemars(description = "Maximum number of items to return (default: 50)")]
+    pub limit: Option<usize>,
+    #[schemars(description = "Starting position for pagination (default: 0)")]
+    pub offset: Option<usize>,
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct GetItemDetailsParams {
     #[schemars(description = "The name of the crate")]
     pub crate_name: String,
     #[schemars(description = "The version of the crate")]
     pub version: String,
-    #[schemars(description = "The numeric ID of the item")]
+    #[schemars(
+        description = "The numeric ID of the item. IDs listed in the 'references' of other details work too: items of dependencies are fetched from the dependency's docs at the version the crate resolves it to, caching them if necessary."
+    )]
     pub item_id: u32,
     #[schemars(
-        description = "For workspace crates, specify the member path (e.g., 'crates/rmcp')"
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp'), as reported with the item. Re-exports of items of other documented members are followed into that member."
     )]
     pub member: Option<String>,
 }
@@ -100,7 +148,7 @@ pub struct GetItemDocsParams {
     #[schemars(description = "The numeric ID of the item")]
     pub item_id: u32,
     #[schemars(
-        description = "For workspace crates, specify the member path (e.g., 'crates/rmcp')"
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp'), as reported with the item. Re-exports of items of other documented members are followed into that member."
     )]
     pub member: Option<String>,
 }
@@ -118,19 +166,464 @@ pub struct GetItemSourceParams {
     )]
     pub context_lines: Option<usize>,
     #[schemars(
-        description = "For workspace crates, specify the member path (e.g., 'crates/rmcp')"
+        description = "For workspace crates, specify the member by package name or path (e.g., 'rmcp' or 'crates/rmcp'), as reported with the item. Re-exports of items of other documented members are followed into that member."
     )]
     pub member: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct DocsTools {
-    cache: Arc<Mutex<CrateCache>>,
+    cache: Arc<CrateCache>,
+    queries: Arc<DocQueryCache>,
 }
 
 impl DocsTools {
-    pub fn new(cache: Arc<Mutex<CrateCache>>) -> Self {
-        Self { cache }
+    pub fn new(cache: Arc<CrateCache>, doc_cache_bytes: u64) -> Self {
+        Self {
+            cache,
+            queries: Arc::new(DocQueryCache::new(doc_cache_bytes)),
+        }
+    }
+
+    /// Get the package name of a workspace member given by its package name or path
+    async fn member_name(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+    ) -> anyhow::Result<Option<String>> {
+        let Some(member) = member else {
+            return Ok(None);
+        };
+        let member = self
+            .cache
+            .resolve_member(crate_name, version, member)
+            .await?;
+        Ok(Some(member.name))
+    }
+
+    /// Get a query interface for a crate or workspace member, reusing parsed docs from memory
+    async fn doc_query(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+    ) -> anyhow::Result<Arc<DocQuery>> {
+        let docs_path = self
+            .cache
+            .ensure_crate_or_member_docs(crate_name, version, member)
+            .await?;
+        let member = self.member_name(crate_name, version, member).await?;
+        let member = member.as_deref();
+
+        let key = DocQueryKey::new(crate_name, version, member);
+        let fingerprint = DocsFingerprint::of(&docs_path)?;
+        if let Some(query) = self.queries.get(&key, fingerprint) {
+            return Ok(query);
+        }
+
+        // Prefer the binary index written next to docs.json
+        let index_path = self.cache.docs_index_path(crate_name, version, member);
+        let (query, weight) = match DocIndex::open(&index_path, fingerprint) {
+            Ok(index) => {
+                let weight = index.table_size();
+                (DocQuery::from_index(index), weight)
+            }
+            Err(e) => {
+                tracing::debug!("No usable docs index at {}: {}", index_path.display(), e);
+                // Parsed docs take more memory than even the decompressed JSON, which
+                // is a far better estimate than the size of the compressed file
+                let json_len = docs_json_len(&docs_path).unwrap_or(fingerprint.docs_len());
+                let crate_data = match member {
+                    Some(member_name) => {
+                        self.cache
+                            .load_member_docs(crate_name, version, member_name)
+                            .await?
+                    }
+                    None => self.cache.load_docs(crate_name, version).await?,
+                };
+
+                // Docs cached before indexes existed get their index now. Building it
+                // is CPU bound, keep it off the async workers.
+                tokio::task::spawn_blocking(move || {
+                    match DocIndex::write_crate(&crate_data, fingerprint, &index_path)
+                        .and_then(|_| DocIndex::open(&index_path, fingerprint))
+                    {
+                        Ok(index) => {
+                            let weight = index.table_size();
+                            (DocQuery::from_index(index), weight)
+                        }
+                        Err(e) => {
+                            tracing::warn!("Failed to index {}: {}", index_path.display(), e);
+                            (DocQuery::new(crate_data), json_len)
+                        }
+                    }
+                })
+                .await?
+            }
+        };
+
+        let query = Arc::new(query);
+        self.queries.insert(key, fingerprint, weight, query.clone());
+        Ok(query)
+    }
+
+    /// Get query interfaces for a crate, a workspace member or a whole workspace
+    ///
+    /// Without a member, a workspace with documented members is queried as a
+    /// whole, see [`CrateCache::workspace_scopes`]. Each query is paired with the
+    /// member to report with its items, which is only set in that case.
+    async fn scoped_queries(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+    ) -> anyhow::Result<Vec<(Option<String>, Arc<DocQuery>)>> {
+        if member.is_some() {
+            let query = self.doc_query(crate_name, version, member).await?;
+            return Ok(vec![(None, query)]);
+        }
+
+        let mut queries = Vec::new();
+        for scope in self.cache.workspace_scopes(crate_name, version).await? {
+            let query = self
+                .doc_query(crate_name, version, scope.as_deref())
+                .await?;
+            queries.push((scope, query));
+        }
+        Ok(queries)
+    }
+
+    /// Fill in the documentation of items collected from several queries
+    fn load_docs(queries: &[(Option<String>, Arc<DocQuery>)], items: &mut [ItemInfo]) {
+        for (member, query) in queries {
+            for item in items.iter_mut().filter(|item| item.member == *member) {
+                query.load_docs(std::slice::from_mut(item));
+            }
+        }
+    }
+
+    /// Follow a re-export of an item of another workspace member into that member
+    ///
+    /// Returns the member's package name, its query interface and the item's ID
+    /// there. Chains of re-exports, e.g. from a facade crate through an internal
+    /// crate, are followed up to a few levels.
+    async fn follow_reexport(
+        &self,
+        crate_name: &str,
+        version: &str,
+        query: &Arc<DocQuery>,
+        item_id: u32,
+    ) -> Option<(String, Arc<DocQuery>, u32)> {
+        const MAX_REEXPORT_DEPTH: usize = 8;
+
+        let mut resolved = None;
+        let (mut query, mut item_id) = (query.clone(), item_id);
+        for _ in 0..MAX_REEXPORT_DEPTH {
+            let Some(target) = query.reexport_target(item_id) else {
+                break;
+            };
+            let Some(member) =
+                self.cache
+                    .find_library_member(crate_name, version, &target.crate_name)
+            else {
+                break;
+            };
+            let target_query = match self
+                .doc_query(crate_name, version, Some(&member.name))
+                .await
+            {
+                Ok(target_query) => target_query,
+                Err(e) => {
+                    tracing::debug!("Failed to follow re-export into {}: {}", member.name, e);
+                    break;
+                }
+            };
+            let Some(target_id) = target_query.find_item(&target.path) else {
+                break;
+            };
+            resolved = Some((member.name, target_query.clone(), target_id));
+            (query, item_id) = (target_query, target_id);
+        }
+        resolved
+    }
+
+    /// Get the details of an item, following re-exports and references into other crates
+    ///
+    /// Returns the details together with the documentation they were read from.
+    async fn item_details(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+        query: &Arc<DocQuery>,
+        item_id: u32,
+    ) -> anyhow::Result<(DocScope, DetailedItem)> {
+        if let Some((member, target, item_id)) = self
+            .follow_reexport(crate_name, version, query, item_id)
+            .await
+        {
+            let mut details = target.get_item_details(item_id)?;
+            details.info.member = Some(member.clone());
+            let scope = DocScope {
+                crate_name: crate_name.to_string(),
+                version: version.to_string(),
+                member: Some(member),
+            };
+            return Ok((scope, details));
+        }
+
+        let member = self.member_name(crate_name, version, member).await?;
+        if let Some(external) = query.external_item(item_id) {
+            return self
+                .external_details(crate_name, version, member.as_deref(), &external)
+                .await;
+        }
+
+        let details = query.get_item_details(item_id)?;
+        let scope = DocScope {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            member,
+        };
+        Ok((scope, details))
+    }
+
+    /// Fetch the details of an item of another crate from that crate's docs
+    ///
+    /// The crate is another documented member of the workspace or a dependency at
+    /// the version resolved in `dependencies.json`, whose docs are cached on demand.
+    async fn external_details(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+        external: &ExternalItem,
+    ) -> anyhow::Result<(DocScope, DetailedItem)> {
+        let path = external.path.join("::");
+        if STANDARD_LIBRARIES.contains(&external.crate_name.as_str()) {
+            bail!("{path} is part of the standard library, whose docs are not cached");
+        }
+
+        let graph = self.dependency_graph(crate_name, version, member).await;
+        let source = self
+            .library_source(
+                crate_name,
+                version,
+                member,
+                graph.as_ref(),
+                &external.crate_name,
+            )
+            .with_context(|| {
+                format!(
+                    "{path} is defined in {}, which is not a dependency of {}",
+                    external.crate_name,
+                    member.unwrap_or(crate_name)
+                )
+            })?;
+        let scope = match source {
+            LibrarySource::Member(member) => DocScope {
+                crate_name: crate_name.to_string(),
+                version: version.to_string(),
+                member: Some(member),
+            },
+            LibrarySource::Package {
+                name,
+                version,
+                source,
+            } => {
+                if !source.as_deref().is_some_and(is_crates_io_source)
+                    && !self.cache.storage.has_docs(&name, &version)
+                {
+                    bail!(
+                        "{path} is defined in {name}-{version}, which is not from crates.io. \
+                         Cache it first to follow references into it."
+                    );
+                }
+                DocScope {
+                    crate_name: name,
+                    version,
+                    member: None,
+                }
+            }
+        };
+
+        let query = self
+            .doc_query(&scope.crate_name, &scope.version, scope.member.as_deref())
+            .await?;
+        let item_id = query.find_item(&external.path).with_context(|| {
+            format!(
+                "{path} not found in the docs of {}-{}",
+                scope.crate_name, scope.version
+            )
+        })?;
+        let mut details = query.get_item_details(item_id)?;
+        match &scope.member {
+            Some(member) => details.info.member = Some(member.clone()),
+            None => {
+                details.dependency = Some(DependencyOrigin {
+                    crate_name: scope.crate_name.clone(),
+                    version: scope.version.clone(),
+                })
+            }
+        }
+        Ok((scope, details))
+    }
+
+    /// Load the resolved dependency graph of a crate or workspace member
+    async fn dependency_graph(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+    ) -> Option<DependencyGraph> {
+        let metadata = match member {
+            Some(member) => {
+                self.cache
+                    .load_member_dependencies(crate_name, version, member)
+                    .await
+            }
+            None => self.cache.load_dependencies(crate_name, version).await,
+        };
+        metadata
+            .and_then(|metadata| DependencyGraph::from_metadata(&metadata))
+            .inspect_err(|e| tracing::debug!("No dependency graph for {}: {}", crate_name, e))
+            .ok()
+    }
+
+    /// Find where a library used by a crate or workspace member is defined
+    ///
+    /// Libraries of other documented members of the workspace are found among the
+    /// members, all others in the crate's dependency graph.
+    fn library_source(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+        graph: Option<&DependencyGraph>,
+        lib_name: &str,
+    ) -> Option<LibrarySource> {
+        if let Some(library_member) = self
+            .cache
+            .find_library_member(crate_name, version, lib_name)
+            && Some(library_member.name.as_str()) != member
+        {
+            return Some(LibrarySource::Member(library_member.name));
+        }
+
+        let graph = graph?;
+        let root = graph.local_package(member.unwrap_or(crate_name))?;
+        let package = graph.package(graph.find_library(root, lib_name)?);
+        Some(LibrarySource::Package {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            source: package.source.clone(),
+        })
+    }
+
+    /// Fill in the crate and version defining each external reference of an item
+    async fn resolve_references(&self, scope: &DocScope, references: &mut [ItemReference]) {
+        if !references.iter().any(|reference| reference.external) {
+            return;
+        }
+
+        let member = scope.member.as_deref();
+        let graph = self
+            .dependency_graph(&scope.crate_name, &scope.version, member)
+            .await;
+        let mut sources: HashMap<String, Option<LibrarySource>> = HashMap::new();
+        for reference in references.iter_mut().filter(|reference| reference.external) {
+            let Some(lib_name) = reference.crate_name.clone() else {
+                continue;
+            };
+            let source = sources.entry(lib_name).or_insert_with_key(|lib_name| {
+                self.library_source(
+                    &scope.crate_name,
+                    &scope.version,
+                    member,
+                    graph.as_ref(),
+                    lib_name,
+                )
+            });
+            match source {
+                Some(LibrarySource::Member(member)) => {
+                    reference.crate_name = Some(scope.crate_name.clone());
+                    reference.version = Some(scope.version.clone());
+                    reference.member = Some(member.clone());
+                }
+                Some(LibrarySource::Package { name, version, .. }) => {
+                    reference.crate_name = Some(name.clone());
+                    reference.version = Some(version.clone());
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Search item names of a crate, workspace member or whole workspace for a preview
+    async fn preview_items(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+        pattern: &str,
+    ) -> anyhow::Result<Vec<ItemInfo>> {
+        if member.is_some() {
+            return self
+                .preview_scope(crate_name, version, member, pattern)
+                .await;
+        }
+
+        let scopes = self.cache.workspace_scopes(crate_name, version).await?;
+        if let [scope] = scopes.as_slice() {
+            return self
+                .preview_scope(crate_name, version, scope.as_deref(), pattern)
+                .await;
+        }
+
+        let mut items = Vec::new();
+        for scope in scopes {
+            let mut scope_items = self
+                .preview_scope(crate_name, version, scope.as_deref(), pattern)
+                .await?;
+            for item in &mut scope_items {
+                item.member = scope.clone();
+            }
+            items.extend(scope_items);
+        }
+        sort_by_relevance(&mut items, &pattern.to_lowercase());
+        Ok(items)
+    }
+
+    /// Search item names of a crate or workspace member for a preview
+    ///
+    /// Served from the persistent search index when it is current, which avoids
+    /// loading the documentation at all.
+    async fn preview_scope(
+        &self,
+        crate_name: &str,
+        version: &str,
+        member: Option<&str>,
+        pattern: &str,
+    ) -> anyhow::Result<Vec<ItemInfo>> {
+        let docs_path = self
+            .cache
+            .ensure_crate_or_member_docs(crate_name, version, member)
+            .await?;
+
+        let fingerprint = DocsFingerprint::of(&docs_path)?;
+        let member_name = self.member_name(crate_name, version, member).await?;
+        if let Some(segment) = self.cache.search_index().segment(
+            crate_name,
+            version,
+            member_name.as_deref(),
+            fingerprint,
+        ) {
+            return Ok(segment.search_items(pattern));
+        }
+
+        let query = self.doc_query(crate_name, version, member).await?;
+        Ok(query.search_items(pattern))
     }
 
     /// Helper to check if a response might exceed size limits
@@ -139,25 +632,35 @@ impl DocsTools {
     }
 
     pub async fn list_crate_items(&self, params: ListItemsParams) -> String {
-        let cache = self.cache.lock().await;
-        match cache
-            .ensure_crate_or_member_docs(
+        match self
+            .scoped_queries(
                 &params.crate_name,
                 &params.version,
                 params.member.as_deref(),
             )
             .await
         {
-            Ok(crate_data) => {
-                let query = DocQuery::new(crate_data);
-                let items = query.list_items(params.kind_filter.as_deref());
+            Ok(queries) => {
+                let mut items = Vec::new();
+                for (member, query) in &queries {
+                    let mut member_items = query.list_items(params.kind_filter.as_deref());
+                    for item in &mut member_items {
+                        item.member = member.clone();
+                    }
+                    items.extend(member_items);
+                }
+                if queries.len() > 1 {
+                    items.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| a.name.cmp(&b.name)));
+                }
 
                 let total_count = items.len();
                 let limit = params.limit.unwrap_or(100);
                 let offset = params.offset.unwrap_or(0);
 
                 // Apply pagination
-                let paginated_items: Vec<_> = items.into_iter().skip(offset).take(limit).collect();
+                let mut paginated_items: Vec<_> =
+                    items.into_iter().skip(offset).take(limit).collect();
+                Self::load_docs(&queries, &mut paginated_items);
 
                 let response = serde_json::json!({
                     "items": paginated_items,
@@ -179,18 +682,26 @@ impl DocsTools {
     }
 
     pub async fn search_items(&self, params: SearchItemsParams) -> String {
-        let cache = self.cache.lock().await;
-        match cache
-            .ensure_crate_or_member_docs(
+        match self
+            .scoped_queries(
                 &params.crate_name,
                 &params.version,
                 params.member.as_deref(),
             )
             .await
         {
-            Ok(crate_data) => {
-                let query = DocQuery::new(crate_data);
-                let mut items = query.search_items(&params.pattern);
+            Ok(queries) => {
+                let mut items = Vec::new();
+                for (member, query) in &queries {
+                    let mut member_items = query.search_items(&params.pattern);
+                    for item in &mut member_items {
+                        item.member = member.clone();
+                    }
+                    items.extend(member_items);
+                }
+                if queries.len() > 1 {
+                    sort_by_relevance(&mut items, &params.pattern.to_lowercase());
+                }
 
                 // Apply kind filter if provided
                 if let Some(kind_filter) = &params.kind_filter {
@@ -212,6 +723,7 @@ impl DocsTools {
                 // Apply pagination
                 let mut paginated_items: Vec<_> =
                     items.into_iter().skip(offset).take(limit).collect();
+                Self::load_docs(&queries, &mut paginated_items);
 
                 // Check response size and truncate if necessary
                 let mut actual_limit = limit;
@@ -268,19 +780,16 @@ impl DocsTools {
     }
 
     pub async fn search_items_preview(&self, params: SearchItemsPreviewParams) -> String {
-        let cache = self.cache.lock().await;
-        match cache
-            .ensure_crate_or_member_docs(
+        match self
+            .preview_items(
                 &params.crate_name,
                 &params.version,
                 params.member.as_deref(),
+                &params.pattern,
             )
             .await
         {
-            Ok(crate_data) => {
-                let query = DocQuery::new(crate_data);
-                let mut items = query.search_items(&params.pattern);
-
+            Ok(mut items) => {
                 // Apply kind filter if provided
                 if let Some(kind_filter) = &params.kind_filter {
                     items.retain(|item| item.kind == *kind_filter);
@@ -304,12 +813,16 @@ impl DocsTools {
                     .skip(offset)
                     .take(limit)
                     .map(|item| {
-                        serde_json::json!({
+                        let mut preview = serde_json::json!({
                             "id": item.id,
                             "name": item.name,
                             "kind": item.kind,
                             "path": item.path,
-                        })
+                        });
+                        if let Some(member) = item.member {
+                            preview["member"] = serde_json::json!(member);
+                        }
+                        preview
                     })
                     .collect();
 
@@ -332,22 +845,64 @@ impl DocsTools {
         }
     }
 
+    pub async fn search_all_crates(&self, params: SearchAllCratesParams) -> String {
+        let search_index = self.cache.search_index();
+        match search_index.search(&params.pattern, params.kind_filter.as_deref()) {
+            Ok(hits) => {
+                let total_count = hits.len();
+                let limit = params.limit.unwrap_or(50);
+                let offset = params.offset.unwrap_or(0);
+
+                // Apply pagination
+                let paginated_hits: Vec<_> = hits.into_iter().skip(offset).take(limit).collect();
+
+                let response = serde_json::json!({
+                    "items": paginated_hits,
+                    "indexed_entries": search_index.segment_count().unwrap_or(0),
+                    "pagination": {
+                        "total": total_count,
+                        "limit": limit,
+                        "offset": offset,
+                        "has_more": offset + paginated_hits.len() < total_count
+                    }
+                });
+
+                serde_json::to_string_pretty(&response)
+                    .unwrap_or_else(|e| format!(r#"{{"error": "Failed to serialize items: {e}"}}"#))
+            }
+            Err(e) => {
+                format!(r#"{{"error": "Failed to search cached crates: {e}"}}"#)
+            }
+        }
+    }
+
     pub async fn get_item_details(&self, params: GetItemDetailsParams) -> String {
-        let cache = self.cache.lock().await;
-        match cache
-            .ensure_crate_or_member_docs(
+        match self
+            .doc_query(
                 &params.crate_name,
                 &params.version,
                 params.member.as_deref(),
             )
             .await
         {
-            Ok(crate_data) => {
-                let query = DocQuery::new(crate_data);
-                match query.get_item_details(params.item_id) {
-                    Ok(details) => serde_json::to_string_pretty(&details).unwrap_or_else(|e| {
-                        format!(r#"{{"error": "Failed to serialize details: {e}"}}"#)
-                    }),
+            Ok(query) => {
+                let details = self
+                    .item_details(
+                        &params.crate_name,
+                        &params.version,
+                        params.member.as_deref(),
+                        &query,
+                        params.item_id,
+                    )
+                    .await;
+                match details {
+                    Ok((scope, mut details)) => {
+                        self.resolve_references(&scope, &mut details.references)
+                            .await;
+                        serde_json::to_string_pretty(&details).unwrap_or_else(|e| {
+                            format!(r#"{{"error": "Failed to serialize details: {e}"}}"#)
+                        })
+                    }
                     Err(e) => format!(r#"{{"error": "Item not found: {e}"}}"#),
                 }
             }
@@ -358,29 +913,38 @@ impl DocsTools {
     }
 
     pub async fn get_item_docs(&self, params: GetItemDocsParams) -> String {
-        let cache = self.cache.lock().await;
-        match cache
-            .ensure_crate_or_member_docs(
+        match self
+            .doc_query(
                 &params.crate_name,
                 &params.version,
                 params.member.as_deref(),
             )
             .await
         {
-            Ok(crate_data) => {
-                let query = DocQuery::new(crate_data);
-                match query.get_item_docs(params.item_id) {
+            Ok(query) => {
+                let (member, docs) = match self
+                    .follow_reexport(&params.crate_name, &params.version, &query, params.item_id)
+                    .await
+                {
+                    Some((member, target, item_id)) => {
+                        (Some(member), target.get_item_docs(item_id))
+                    }
+                    None => (None, query.get_item_docs(params.item_id)),
+                };
+                let mut response = match docs {
                     Ok(Some(docs)) => serde_json::json!({
                         "documentation": docs
-                    })
-                    .to_string(),
+                    }),
                     Ok(None) => serde_json::json!({
                         "documentation": null,
                         "message": "No documentation available for this item"
-                    })
-                    .to_string(),
-                    Err(e) => format!(r#"{{"error": "Failed to get docs: {e}"}}"#),
+                    }),
+                    Err(e) => return format!(r#"{{"error": "Failed to get docs: {e}"}}"#),
+                };
+                if let Some(member) = member {
+                    response["member"] = serde_json::json!(member);
                 }
+                response.to_string()
             }
             Err(e) => {
                 format!(r#"{{"error": "Failed to get crate docs: {e}"}}"#)
@@ -389,24 +953,40 @@ impl DocsTools {
     }
 
     pub async fn get_item_source(&self, params: GetItemSourceParams) -> String {
-        let cache = self.cache.lock().await;
+        let cache = &self.cache;
         let source_base_path = cache.get_source_path(&params.crate_name, &params.version);
 
-        match cache
-            .ensure_crate_or_member_docs(
+        match self
+            .doc_query(
                 &params.crate_name,
                 &params.version,
                 params.member.as_deref(),
             )
             .await
         {
-            Ok(crate_data) => {
-                let query = DocQuery::new(crate_data);
+            Ok(query) => {
                 let context_lines = params.context_lines.unwrap_or(3);
 
-                match query.get_item_source(params.item_id, &source_base_path, context_lines) {
-                    Ok(source_info) => {
-                        serde_json::to_string_pretty(&source_info).unwrap_or_else(|e| {
+                // Spans of all members are relative to the workspace root
+                let (member, source_info) = match self
+                    .follow_reexport(&params.crate_name, &params.version, &query, params.item_id)
+                    .await
+                {
+                    Some((member, target, item_id)) => (
+                        Some(member),
+                        target.get_item_source(item_id, &source_base_path, context_lines),
+                    ),
+                    None => (
+                        None,
+                        query.get_item_source(params.item_id, &source_base_path, context_lines),
+                    ),
+                };
+                match source_info.and_then(|info| Ok(serde_json::to_value(info)?)) {
+                    Ok(mut response) => {
+                        if let Some(member) = member {
+                            response["member"] = serde_json::json!(member);
+                        }
+                        serde_json::to_string_pretty(&response).unwrap_or_else(|e| {
                             format!(r#"{{"error": "Failed to serialize source info: {e}"}}"#)
                         })
                     }
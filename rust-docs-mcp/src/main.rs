@@ -3,6 +3,7 @@ use clap::{Parser, Subcommand};
 use rmcp::{ServiceExt, transport::stdio};
 use std::path::PathBuf;
 use std::process;
+use std::time::Duration;
 use tracing_subscriber::EnvFilter;
 
 mod analysis;
@@ -10,6 +11,11 @@ mod cache;
 mod deps;
 mod docs;
 mod service;
+use cache::CacheConfig;
+use cache::CrateCache;
+use cache::bundle::OnConflict;
+use cache::gc::GcQuota;
+use cache::sandbox::{SandboxConfig, SandboxLimits, SourceKind};
 use service::RustDocsService;
 
 /// MCP server for querying Rust crate documentation with offline caching
@@ -20,6 +26,58 @@ struct Args {
     #[arg(long, env = "RUST_DOCS_MCP_CACHE_DIR")]
     cache_dir: Option<PathBuf>,
 
+    /// Maximum time in seconds a single documentation build may take (0 disables the limit)
+    #[arg(long, env = "RUST_DOCS_MCP_BUILD_TIMEOUT_SECS", default_value_t = 1800)]
+    build_timeout_secs: u64,
+
+    /// Memory in megabytes for keeping parsed documentation between queries (0 disables it)
+    #[arg(long, env = "RUST_DOCS_MCP_DOC_CACHE_MB", default_value_t = 512)]
+    doc_cache_mb: u64,
+
+    /// Directory for build artifacts shared between crates (defaults to targets/ in the cache directory)
+    #[arg(long, env = "RUST_DOCS_MCP_SHARED_TARGET_DIR")]
+    shared_target_dir: Option<PathBuf>,
+
+    /// Size limit in megabytes for shared build artifacts (0 builds every crate separately)
+    #[arg(
+        long,
+        env = "RUST_DOCS_MCP_SHARED_TARGET_MAX_MB",
+        default_value_t = 20480
+    )]
+    shared_target_max_mb: u64,
+
+    /// Build crates from these sources inside a sandbox without network access (e.g. cratesio,github)
+    #[arg(long, env = "RUST_DOCS_MCP_SANDBOX", value_enum, value_delimiter = ',')]
+    sandbox: Vec<SourceKind>,
+
+    /// CPU time limit in seconds for sandboxed builds
+    #[arg(long, env = "RUST_DOCS_MCP_SANDBOX_CPU_SECS")]
+    sandbox_cpu_secs: Option<u64>,
+
+    /// Memory limit in megabytes for sandboxed builds
+    #[arg(long, env = "RUST_DOCS_MCP_SANDBOX_MEMORY_MB")]
+    sandbox_memory_mb: Option<u64>,
+
+    /// Wall-clock time limit in seconds for sandboxed builds
+    #[arg(long, env = "RUST_DOCS_MCP_SANDBOX_TIMEOUT_SECS")]
+    sandbox_timeout_secs: Option<u64>,
+
+    /// Maximum total size of the cache in megabytes, enforced by evicting least recently used crates (0 disables it)
+    #[arg(long, env = "RUST_DOCS_MCP_CACHE_MAX_MB", default_value_t = 0)]
+    cache_max_mb: u64,
+
+    /// Maximum number of cached crate versions, enforced by evicting least recently used crates (0 disables it)
+    #[arg(long, env = "RUST_DOCS_MCP_CACHE_MAX_CRATES", default_value_t = 0)]
+    cache_max_crates: usize,
+
+    /// Read-only cache directory to look up crates missing from the cache, may be repeated (e.g. a shared team cache)
+    #[arg(
+        long = "read-only-cache",
+        env = "RUST_DOCS_MCP_READ_ONLY_CACHES",
+        value_delimiter = ','
+    )]
+    read_only_caches: Vec<PathBuf>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -35,6 +93,63 @@ enum Commands {
         #[arg(long)]
         force: bool,
     },
+    /// Evict least recently used crates until the cache is within its quota
+    Gc {
+        /// Only report what would be evicted
+        #[arg(long)]
+        dry_run: bool,
+        /// Maximum total size in megabytes, overriding --cache-max-mb
+        #[arg(long)]
+        max_mb: Option<u64>,
+        /// Maximum number of crate versions, overriding --cache-max-crates
+        #[arg(long)]
+        max_crates: Option<usize>,
+    },
+    /// Pack cached crates into a bundle that can be imported into another cache
+    #[command(group(
+        clap::ArgGroup::new("selection")
+            .required(true)
+            .multiple(true)
+            .args(["crates", "lockfile", "all"])
+    ))]
+    Export {
+        /// Path of the bundle to write
+        output: PathBuf,
+        /// Crate to export as NAME (all cached versions) or NAME@VERSION, may be repeated
+        #[arg(long = "crate")]
+        crates: Vec<String>,
+        /// Export the crates.io packages of this Cargo.lock or project directory
+        #[arg(long)]
+        lockfile: Option<PathBuf>,
+        /// Download and document packages of the lockfile that are not cached yet
+        #[arg(long, requires = "lockfile")]
+        fetch_missing: bool,
+        /// Export every cached crate
+        #[arg(long, conflicts_with_all = ["crates", "lockfile"])]
+        all: bool,
+    },
+    /// Merge the crates of a bundle into the cache
+    Import {
+        /// Path of the bundle to import
+        bundle: PathBuf,
+        /// What to do with crates that are already cached
+        #[arg(long, value_enum, default_value_t = OnConflict::Skip)]
+        on_conflict: OnConflict,
+    },
+    /// Cache the sources of every crate of a `cargo vendor` directory
+    Vendor {
+        /// Path of the vendor directory
+        dir: PathBuf,
+    },
+    /// Check cached crates for damaged entries and optionally repair them
+    Verify {
+        /// Only verify the cached versions of this crate
+        #[arg(long = "crate")]
+        crate_name: Option<String>,
+        /// Regenerate or delete broken files instead of only reporting them
+        #[arg(long)]
+        repair: bool,
+    },
 }
 
 #[tokio::main]
@@ -42,9 +157,32 @@ async fn main() -> Result<()> {
     // Parse command line arguments
     let args = Args::parse();
 
+    let quota = GcQuota {
+        max_bytes: args.cache_max_mb * 1024 * 1024,
+        max_crates: args.cache_max_crates,
+    };
+
+    let config = CacheConfig {
+        sandbox: SandboxConfig::new(
+            args.sandbox.clone(),
+            SandboxLimits {
+                cpu_seconds: args.sandbox_cpu_secs,
+                memory_bytes: args.sandbox_memory_mb.map(|mb| mb * 1024 * 1024),
+                wall_time: args.sandbox_timeout_secs.map(Duration::from_secs),
+            },
+        ),
+        build_timeout: (args.build_timeout_secs > 0)
+            .then(|| Duration::from_secs(args.build_timeout_secs)),
+        doc_cache_bytes: args.doc_cache_mb * 1024 * 1024,
+        shared_target_dir: args.shared_target_dir,
+        shared_target_max_bytes: args.shared_target_max_mb * 1024 * 1024,
+        quota,
+        read_only_layers: args.read_only_caches,
+    };
+
     // Handle subcommands
     if let Some(command) = args.command {
-        return handle_command(command).await;
+        return handle_command(command, args.cache_dir, config).await;
     }
 
     // Initialize tracing to stderr to avoid conflicts with stdio transport
@@ -59,8 +197,12 @@ async fn main() -> Result<()> {
         tracing::info!("Using custom cache directory: {}", cache_dir.display());
     }
 
+    if !args.sandbox.is_empty() {
+        tracing::info!("Sandboxing builds for sources: {:?}", args.sandbox);
+    }
+
     // Create the service with optional cache directory
-    let rust_docs_service = RustDocsService::new(args.cache_dir)?;
+    let rust_docs_service = RustDocsService::new(args.cache_dir, config)?;
 
     // Serve using stdio transport
     let service = rust_docs_service.serve(stdio()).await.inspect_err(|e| {
@@ -72,10 +214,75 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn handle_command(command: Commands) -> Result<()> {
+async fn handle_command(
+    command: Commands,
+    cache_dir: Option<PathBuf>,
+    config: CacheConfig,
+) -> Result<()> {
+    let quota = config.quota;
     match command {
         Commands::Install { target_dir, force } => install_executable(target_dir, force).await,
+        Commands::Gc {
+            dry_run,
+            max_mb,
+            max_crates,
+        } => {
+            let quota = GcQuota {
+                max_bytes: max_mb.map_or(quota.max_bytes, |mb| mb * 1024 * 1024),
+                max_crates: max_crates.unwrap_or(quota.max_crates),
+            };
+            collect_garbage(CrateCache::new(cache_dir, config)?, quota, dry_run).await
+        }
+        Commands::Export {
+            output,
+            crates,
+            lockfile,
+            fetch_missing,
+            all: _,
+        } => {
+            let cache = CrateCache::new(cache_dir, config)?;
+            let report = cache
+                .export_bundle(&crates, lockfile.as_deref(), fetch_missing, &output)
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            Ok(())
+        }
+        Commands::Import {
+            bundle,
+            on_conflict,
+        } => {
+            let cache = CrateCache::new(cache_dir, config)?;
+            let report = cache.import_bundle(&bundle, on_conflict).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            Ok(())
+        }
+        Commands::Vendor { dir } => {
+            let cache = CrateCache::new(cache_dir, config)?;
+            let report = cache.import_vendor_dir(&dir.to_string_lossy()).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            Ok(())
+        }
+        Commands::Verify { crate_name, repair } => {
+            let cache = CrateCache::new(cache_dir, config)?;
+            verify_cache(cache, crate_name.as_deref(), repair).await
+        }
+    }
+}
+
+async fn collect_garbage(cache: CrateCache, quota: GcQuota, dry_run: bool) -> Result<()> {
+    if quota.is_unlimited() {
+        anyhow::bail!("No cache quota configured. Use --max-mb or --max-crates.");
     }
+
+    let report = cache.gc(quota, dry_run).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+async fn verify_cache(cache: CrateCache, crate_name: Option<&str>, repair: bool) -> Result<()> {
+    let report = cache.verify(crate_name, repair).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
 }
 
 async fn install_executable(target_dir: Option<PathBuf>, force: bool) -> Result<()> {
@@ -1,6 +1,7 @@
+use std::future::Future;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
 use anyhow::Result;
 use rmcp::{
@@ -11,11 +12,13 @@ use rmcp::{
 
 use crate::analysis::tools::AnalysisTools;
 use crate::cache::{
-    CrateCache,
+    CacheConfig, CrateCache,
     tools::{
         CacheCrateFromCratesIOParams, CacheCrateFromGitHubParams, CacheCrateFromLocalParams,
-        CacheTools,
+        CacheCratesFromVendorParams, CacheTools, GcCacheParams, GetPrefetchStatusParams,
+        PinCrateParams, RegisterProjectParams, VerifyCacheParams,
     },
+    utils::CacheResponse,
 };
 use crate::deps::tools::DepsTools;
 use crate::docs::tools::DocsTools;
@@ -30,12 +33,14 @@ pub struct RustDocsService {
 
 #[tool(tool_box)]
 impl RustDocsService {
-    pub fn new(cache_dir: Option<PathBuf>) -> Result<Self> {
-        let cache = Arc::new(Mutex::new(CrateCache::new(cache_dir)?));
+    pub fn new(cache_dir: Option<PathBuf>, config: CacheConfig) -> Result<Self> {
+        let doc_cache_bytes = config.doc_cache_bytes;
+        let cache = Arc::new(CrateCache::new(cache_dir, config)?);
+        cache.spawn_search_index_sync();
 
         Ok(Self {
             cache_tools: CacheTools::new(cache.clone()),
-            docs_tools: DocsTools::new(cache.clone()),
+            docs_tools: DocsTools::new(cache.clone(), doc_cache_bytes),
             deps_tools: DepsTools::new(cache.clone()),
             analysis_tools: AnalysisTools::new(cache),
         })
@@ -50,8 +55,9 @@ impl RustDocsService {
     pub async fn cache_crate_from_cratesio(
         &self,
         #[tool(aggr)] params: CacheCrateFromCratesIOParams,
+        ct: CancellationToken,
     ) -> String {
-        self.cache_tools.cache_crate_from_cratesio(params).await
+        cancellable(ct, self.cache_tools.cache_crate_from_cratesio(params)).await
     }
 
     #[tool(
@@ -60,18 +66,41 @@ impl RustDocsService {
     pub async fn cache_crate_from_github(
         &self,
         #[tool(aggr)] params: CacheCrateFromGitHubParams,
+        ct: CancellationToken,
     ) -> String {
-        self.cache_tools.cache_crate_from_github(params).await
+        cancellable(ct, self.cache_tools.cache_crate_from_github(params)).await
     }
 
     #[tool(
-        description = "Cache a specific crate version from a local file system path. Supports absolute paths, home paths (~), and relative paths. The specified directory must contain a Cargo.toml file."
+        description = "Cache a specific crate version from a local file system path. Supports absolute paths, home paths (~), and relative paths. The path must be a directory containing a Cargo.toml file, or a .crate or .tar.gz archive such as the ones written by `cargo package`."
     )]
     pub async fn cache_crate_from_local(
         &self,
         #[tool(aggr)] params: CacheCrateFromLocalParams,
+        ct: CancellationToken,
     ) -> String {
-        self.cache_tools.cache_crate_from_local(params).await
+        cancellable(ct, self.cache_tools.cache_crate_from_local(params)).await
+    }
+
+    #[tool(
+        description = "Cache the sources of every crate in a `cargo vendor` directory, using the name and version from each crate's Cargo.toml. Crate versions that are already cached are kept. Useful for importing dependencies into an offline or air-gapped cache; documentation is generated when a crate is first queried."
+    )]
+    pub async fn cache_crates_from_vendor(
+        &self,
+        #[tool(aggr)] params: CacheCratesFromVendorParams,
+        ct: CancellationToken,
+    ) -> String {
+        cancellable(ct, self.cache_tools.cache_crates_from_vendor(params)).await
+    }
+
+    #[tool(
+        description = "Report the progress of the background dependency prefetch started by caching a crate with prefetch_dependencies=true: the status of each dependency (pending, caching, cached, skipped or failed) and a summary."
+    )]
+    pub async fn get_prefetch_status(
+        &self,
+        #[tool(aggr)] params: GetPrefetchStatusParams,
+    ) -> String {
+        self.cache_tools.get_prefetch_status(params).await
     }
 
     #[tool(
@@ -90,7 +119,7 @@ impl RustDocsService {
     }
 
     #[tool(
-        description = "List all locally cached crates with their versions and sizes. Use to see what crates are available offline and how much disk space they use. Shows cache metadata including when each crate was cached."
+        description = "List all locally cached crates with their versions and sizes, split into source, documentation and build artifacts. Use to see what crates are available offline and how much disk space they use. Shows cache metadata including when each crate was cached."
     )]
     pub async fn list_cached_crates(&self) -> String {
         self.cache_tools.list_cached_crates().await
@@ -118,76 +147,148 @@ impl RustDocsService {
         self.cache_tools.get_crates_metadata(params).await
     }
 
+    #[tool(
+        description = "Free disk space by evicting the least recently used cached crate versions until the cache is within a size or crate count quota. Pinned crates and crates used by registered projects are never evicted. Use dry_run to preview what would be removed."
+    )]
+    pub async fn gc_cache(&self, #[tool(aggr)] params: GcCacheParams) -> String {
+        self.cache_tools.gc_cache(params).await
+    }
+
+    #[tool(
+        description = "Pin a cached crate version so garbage collection never evicts it, or unpin it again with pinned=false."
+    )]
+    pub async fn pin_crate(&self, #[tool(aggr)] params: PinCrateParams) -> String {
+        self.cache_tools.pin_crate(params).await
+    }
+
+    #[tool(
+        description = "Register a local Cargo project so that garbage collection keeps every crate version in its Cargo.lock, or unregister it with unregister=true. Returns the lockfiles of all registered projects."
+    )]
+    pub async fn register_project(&self, #[tool(aggr)] params: RegisterProjectParams) -> String {
+        self.cache_tools.register_project(params).await
+    }
+
+    #[tool(
+        description = "Check cached crates for damaged entries: missing or unparsable metadata, docs that fail to deserialize or use an outdated rustdoc format, dependency information that is missing or doesn't match the source, orphaned workspace member directories and leftover backups. Set repair=true to fix the problems by regenerating or deleting the affected files."
+    )]
+    pub async fn verify_cache(
+        &self,
+        #[tool(aggr)] params: VerifyCacheParams,
+        ct: CancellationToken,
+    ) -> String {
+        cancellable(ct, self.cache_tools.verify_cache(params)).await
+    }
+
     // Docs tools
     #[tool(
-        description = "List all items in a crate's documentation. Use when browsing a crate's contents without a specific search term. Returns full item details including documentation. For large crates, consider using search_items_preview for a lighter response that only includes names and types. For workspace crates, specify the member parameter with the member path (e.g., 'crates/rmcp')."
+        description = "List all items in a crate's documentation. Use when browsing a crate's contents without a specific search term. Returns full item details including documentation. For large crates, consider using search_items_preview for a lighter response that only includes names and types. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
     )]
     pub async fn list_crate_items(
         &self,
         #[tool(aggr)] params: crate::docs::tools::ListItemsParams,
+        ct: CancellationToken,
     ) -> String {
-        self.docs_tools.list_crate_items(params).await
+        cancellable(ct, self.docs_tools.list_crate_items(params)).await
     }
 
     #[tool(
-        description = "Search for items by name pattern in a crate. Use when looking for specific functions, types, or modules. Returns FULL details including documentation. WARNING: May exceed token limits for large results. Use search_items_preview first for exploration, then get_item_details for specific items. For workspace crates, specify the member parameter with the member path (e.g., 'crates/rmcp')."
+        description = "Search for items by name pattern in a crate. Use when looking for specific functions, types, or modules. Returns FULL details including documentation. WARNING: May exceed token limits for large results. Use search_items_preview first for exploration, then get_item_details for specific items. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
     )]
     pub async fn search_items(
         &self,
         #[tool(aggr)] params: crate::docs::tools::SearchItemsParams,
+        ct: CancellationToken,
     ) -> String {
-        self.docs_tools.search_items(params).await
+        cancellable(ct, self.docs_tools.search_items(params)).await
     }
 
     #[tool(
-        description = "Search for items by name pattern in a crate - PREVIEW MODE. Use this FIRST when searching to avoid token limits. Returns only id, name, kind, and path. Once you find items of interest, use get_item_details to fetch full documentation. This is the recommended search method for exploration. For workspace crates, specify the member parameter with the member path (e.g., 'crates/rmcp')."
+        description = "Search for items by name pattern in a crate - PREVIEW MODE. Use this FIRST when searching to avoid token limits. Returns only id, name, kind, and path. Once you find items of interest, use get_item_details to fetch full documentation. This is the recommended search method for exploration. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
     )]
     pub async fn search_items_preview(
         &self,
         #[tool(aggr)] params: crate::docs::tools::SearchItemsPreviewParams,
+        ct: CancellationToken,
     ) -> String {
-        self.docs_tools.search_items_preview(params).await
+        cancellable(ct, self.docs_tools.search_items_preview(params)).await
     }
 
     #[tool(
-        description = "Get detailed information about a specific item by ID. Use after search_items_preview to fetch full details including documentation, signatures, fields, methods, etc. The item_id comes from search results. This is the recommended way to get complete information about a specific item. For workspace crates, specify the member parameter with the member path (e.g., 'crates/rmcp')."
+        description = "Search names, module paths and documentation text of ALL cached crates at once. Use when you don't know which crate provides something, e.g. 'parse toml' or 'retry backoff'. Returns crate, version, member, id, name, kind, path and a one-line summary for each match; pass them to get_item_details for full documentation. Only crates that are already cached are searched."
+    )]
+    pub async fn search_all_crates(
+        &self,
+        #[tool(aggr)] params: crate::docs::tools::SearchAllCratesParams,
+        ct: CancellationToken,
+    ) -> String {
+        cancellable(ct, self.docs_tools.search_all_crates(params)).await
+    }
+
+    #[tool(
+        description = "Get detailed information about a specific item by ID. Use after search_items_preview to fetch full details including documentation, signatures, fields, methods, etc. The item_id comes from search results. This is the recommended way to get complete information about a specific item. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
     )]
     pub async fn get_item_details(
         &self,
         #[tool(aggr)] params: crate::docs::tools::GetItemDetailsParams,
+        ct: CancellationToken,
     ) -> String {
-        self.docs_tools.get_item_details(params).await
+        cancellable(ct, self.docs_tools.get_item_details(params)).await
     }
 
     #[tool(
-        description = "Get ONLY the documentation string for a specific item. Use when you need just the docs without other details. More efficient than get_item_details if you only need the documentation text. Returns null if no documentation exists. For workspace crates, specify the member parameter with the member path (e.g., 'crates/rmcp')."
+        description = "Get ONLY the documentation string for a specific item. Use when you need just the docs without other details. More efficient than get_item_details if you only need the documentation text. Returns null if no documentation exists. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
     )]
     pub async fn get_item_docs(
         &self,
         #[tool(aggr)] params: crate::docs::tools::GetItemDocsParams,
+        ct: CancellationToken,
     ) -> String {
-        self.docs_tools.get_item_docs(params).await
+        cancellable(ct, self.docs_tools.get_item_docs(params)).await
     }
 
     #[tool(
-        description = "Get the source code for a specific item. Returns the actual source code with optional context lines. Use after finding items of interest to view their implementation. The source location is also included in get_item_details responses. For workspace crates, specify the member parameter with the member path (e.g., 'crates/rmcp')."
+        description = "Get the source code for a specific item. Returns the actual source code with optional context lines. Use after finding items of interest to view their implementation. The source location is also included in get_item_details responses. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
     )]
     pub async fn get_item_source(
         &self,
         #[tool(aggr)] params: crate::docs::tools::GetItemSourceParams,
+        ct: CancellationToken,
     ) -> String {
-        self.docs_tools.get_item_source(params).await
+        cancellable(ct, self.docs_tools.get_item_source(params)).await
     }
 
     // Deps tools
     #[tool(
-        description = "Get dependency information for a crate. Returns direct dependencies by default, with option to include full dependency tree. Use this to understand what a crate depends on, check for version conflicts, or explore the dependency graph. For workspace crates, specify the member parameter with the member path (e.g., 'crates/rmcp')."
+        description = "Get dependency information for a crate. Returns direct dependencies by default, with option to include full dependency tree. Use this to understand what a crate depends on, check for version conflicts, or explore the dependency graph. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
     )]
     pub async fn get_dependencies(
         &self,
         #[tool(aggr)] params: crate::deps::tools::GetDependenciesParams,
+        ct: CancellationToken,
     ) -> String {
-        self.deps_tools.get_dependencies(params).await
+        cancellable(ct, self.deps_tools.get_dependencies(params)).await
+    }
+
+    #[tool(
+        description = "Explain why a dependency is in a crate's dependency graph, like `cargo tree -i`. Finds every resolved version of the dependency by package or library name and returns the packages depending on it directly, the dependency paths from the crate to it, and the features each dependency along the way enables. Use this to find out which dependency pulled in a crate such as openssl. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
+    )]
+    pub async fn get_reverse_dependencies(
+        &self,
+        #[tool(aggr)] params: crate::deps::tools::GetReverseDependenciesParams,
+        ct: CancellationToken,
+    ) -> String {
+        cancellable(ct, self.deps_tools.get_reverse_dependencies(params)).await
+    }
+
+    #[tool(
+        description = "Report on version duplication and feature unification in a crate's dependency graph, like `cargo tree -d` and `cargo tree -e features`. Lists packages resolved in several versions with the packages requiring each version, features of the crate's direct dependencies that are only enabled because another package asks for them, and the optional dependencies in the graph with the features activating them. For workspace crates, specify the member parameter with the member's package name or path (e.g., 'rmcp' or 'crates/rmcp')."
+    )]
+    pub async fn get_dependency_report(
+        &self,
+        #[tool(aggr)] params: crate::deps::tools::GetDependencyReportParams,
+        ct: CancellationToken,
+    ) -> String {
+        cancellable(ct, self.deps_tools.get_dependency_report(params)).await
     }
 
     // Analysis tools
@@ -197,8 +298,20 @@ impl RustDocsService {
     pub async fn structure(
         &self,
         #[tool(aggr)] params: crate::analysis::tools::AnalyzeCrateStructureParams,
+        ct: CancellationToken,
     ) -> String {
-        self.analysis_tools.structure(params).await
+        cancellable(ct, self.analysis_tools.structure(params)).await
+    }
+}
+
+/// Run a tool future, aborting it when the client cancels the request
+///
+/// Dropping the future kills any running build process tree and removes
+/// partially written cache entries.
+async fn cancellable(ct: CancellationToken, future: impl Future<Output = String>) -> String {
+    tokio::select! {
+        result = future => result,
+        _ = ct.cancelled() => CacheResponse::error("Request was cancelled").to_json(),
     }
 }
 
@@ -215,7 +328,7 @@ impl ServerHandler for RustDocsService {
                 ..Default::default()
             },
             instructions: Some(
-                "MCP server for analyzing crate structure and querying documentation, dependencies and source code. Use the structure tool to get a high-level overview of the crate's organization before narrowing down your search. Use list_cached_crates to see what crates are already cached and to easily find the crate or member from a workspace crate instead of guessing. Common workflow: search_items_preview to find items quickly by symbol name, then get_item_details to fetch full documentation. Use get_item_source to view the actual source code of items. Use get_dependencies to understand a crate's dependency graph.".to_string(),
+                "MCP server for analyzing crate structure and querying documentation, dependencies and source code. Use the structure tool to get a high-level overview of the crate's organization before narrowing down your search. Use list_cached_crates to see what crates are already cached and to easily find the crate or member from a workspace crate instead of guessing. Use search_all_crates to find items across every cached crate when you don't know which crate to look in. Common workflow: search_items_preview to find items quickly by symbol name, then get_item_details to fetch full documentation. Use get_item_source to view the actual source code of items. Use get_dependencies to understand a crate's dependency graph.".to_string(),
             ),
             ..Default::default()
         }